@@ -19,6 +19,7 @@ lazy_static! {
 }
 
 mod account;
+mod accounts;
 mod attachments;
 mod auth;
 mod calendar_events;
@@ -27,28 +28,37 @@ mod comments;
 mod common;
 mod convo;
 mod device;
+mod drafts;
 mod invitation;
+mod link_previews;
 mod message;
 mod news;
 mod notifications;
+mod pinned_messages;
 mod pins;
+mod polls;
 mod profile;
 mod receipt;
 mod room;
 mod rsvp;
+mod scheduled;
 mod search;
 mod settings;
 mod spaces;
+mod starred_messages;
 mod stream;
 mod tasks;
+mod templates;
 mod typing;
 mod utils;
 mod verification;
 
 pub use account::Account;
+pub use accounts::{AccountRegistry, StoredAccount};
 pub use acter_core::{
     events::{news::NewsContent, Colorize, ObjRef, RefDetails, UtcDateTime},
     models::{ActerModel, Color as EfkColor, Tag, TextMessageContent},
+    outbox::{OutboxFailure, OutboxSent, PendingEvent},
 };
 pub use attachments::{Attachment, AttachmentDraft, AttachmentsManager};
 pub use auth::{
@@ -57,27 +67,35 @@ pub use auth::{
     register_with_token, register_with_token_under_config, sanitize_user,
 };
 pub use calendar_events::{CalendarEvent, CalendarEventDraft, CalendarEventUpdateBuilder};
-pub use client::{Client, ClientStateBuilder, HistoryLoadState, SyncState};
-pub use comments::{Comment, CommentDraft, CommentsManager};
+pub use client::{Client, ClientStateBuilder, ConnectionState, HistoryLoadState, SyncState};
+pub use comments::{Comment, CommentDraft, CommentUpdateBuilder, CommentsManager};
 pub use common::{
     duration_from_secs, AudioDesc, DeviceRecord, FileDesc, ImageDesc, LocationDesc, MediaSource,
     OptionBuffer, OptionString, ReactionRecord, TextDesc, ThumbnailInfo, VideoDesc,
 };
 pub use convo::{
     new_convo_settings_builder, Convo, CreateConvoSettings, CreateConvoSettingsBuilder,
+    UnreadSummary,
 };
 pub use core::time::Duration as EfkDuration;
 pub use device::{DeviceChangedEvent, DeviceLeftEvent};
+pub use drafts::{new_compose_draft, ComposeDraft, DraftAttachment};
 pub use invitation::Invitation;
-pub use message::{RoomEventItem, RoomMessage, RoomVirtualItem};
+pub use link_previews::LinkPreview;
+pub use message::{RoomEventItem, RoomMessage, RoomVirtualItem, ThreadSummary};
 pub use news::{NewsEntry, NewsEntryDraft, NewsEntryUpdateBuilder, NewsSlide};
 pub use notifications::{Notification, NotificationListResult};
 pub use pins::{Pin as ActerPin, PinDraft, PinUpdateBuilder};
+pub use polls::{PollAnswerResult, PollDesc};
 pub use profile::{RoomProfile, UserProfile};
 pub use receipt::{ReceiptEvent, ReceiptRecord};
 pub use room::{Member, MemberPermission, MembershipStatus, Room};
 pub use rsvp::{Rsvp, RsvpDraft, RsvpManager};
-pub use search::{PublicSearchResult, PublicSearchResultItem};
+pub use scheduled::{
+    new_scheduled_html_message, new_scheduled_location_message, new_scheduled_markdown_message,
+    new_scheduled_text_message, ScheduledContent, ScheduledMessage,
+};
+pub use search::{MessageSearchResult, PublicSearchResult, PublicSearchResultItem};
 pub use settings::{
     ActerAppSettings, ActerAppSettingsBuilder, EventsSettings, NewsSettings, PinsSettings,
     RoomPowerLevels, SimpleSettingWithTurnOff, SimpleSettingWithTurnOffBuilder,
@@ -87,10 +105,11 @@ pub use spaces::{
     RelationTargetType, Space, SpaceHierarchyListResult, SpaceHierarchyRoomInfo, SpaceRelation,
     SpaceRelations,
 };
-pub use stream::{TimelineDiff, TimelineStream};
+pub use stream::{TimelineDiff, TimelineDiffAction, TimelineStream};
 pub use tasks::{
     Task, TaskDraft, TaskList, TaskListDraft, TaskListUpdateBuilder, TaskUpdateBuilder,
 };
+pub use templates::{builtin_templates, Template, TemplateInput};
 pub use typing::TypingEvent;
 pub use utils::parse_markdown;
 pub use verification::{SessionManager, VerificationEmoji, VerificationEvent};
@@ -0,0 +1,140 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+use super::{auth::login_with_token, client::Client};
+
+/// A stored login of the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredAccount {
+    user_id: String,
+    /// where the client keeps its store
+    base_path: String,
+    /// as given by `Client::restore_token`
+    restore_token: String,
+}
+
+impl StoredAccount {
+    pub fn user_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    pub fn base_path(&self) -> String {
+        self.base_path.clone()
+    }
+
+    /// restore the session of this account
+    pub async fn login(&self) -> Result<Client> {
+        login_with_token(self.base_path.clone(), self.restore_token.clone()).await
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AccountsFile {
+    current: Option<String>,
+    accounts: Vec<StoredAccount>,
+}
+
+// for only cli and tui, not api.rsh
+/// The accounts logged in on this device, kept as json at `path`
+///
+/// The restore tokens in there are as good as a password, so the file is
+/// only readable by the user.
+#[derive(Debug)]
+pub struct AccountRegistry {
+    path: PathBuf,
+    file: AccountsFile,
+}
+
+impl AccountRegistry {
+    /// read the registry at `path`, an empty one if there is none yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = if path.exists() {
+            let content = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Invalid accounts file {}", path.display()))?
+        } else {
+            AccountsFile::default()
+        };
+        Ok(AccountRegistry { path, file })
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&self.path)?;
+        // the mode only counts for new files, older ones might be readable by all
+        #[cfg(unix)]
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        file.write_all(serde_json::to_string_pretty(&self.file)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn accounts(&self) -> &[StoredAccount] {
+        &self.file.accounts
+    }
+
+    pub fn get(&self, user_id: &str) -> Option<&StoredAccount> {
+        self.file.accounts.iter().find(|a| a.user_id == user_id)
+    }
+
+    /// the account to use if none was asked for
+    pub fn current(&self) -> Option<&StoredAccount> {
+        self.file.current.as_deref().and_then(|u| self.get(u))
+    }
+
+    /// store the session of the logged in `client`, the first account
+    /// becomes the current one
+    pub async fn add(&mut self, client: &Client, base_path: String) -> Result<()> {
+        let user_id = client.user_id()?.to_string();
+        let restore_token = client.restore_token().await?;
+        self.file.accounts.retain(|a| a.user_id != user_id);
+        self.file.accounts.push(StoredAccount {
+            user_id: user_id.clone(),
+            base_path,
+            restore_token,
+        });
+        if self.file.current.is_none() {
+            self.file.current = Some(user_id.clone());
+        }
+        self.save()?;
+        info!(user_id, "account stored");
+        Ok(())
+    }
+
+    /// forget the account, its local data is kept. The session stays valid
+    /// on the server, log it out with the returned account if it should end.
+    pub fn remove(&mut self, user_id: &str) -> Result<StoredAccount> {
+        let Some(pos) = self.file.accounts.iter().position(|a| a.user_id == user_id) else {
+            bail!("Unknown account {user_id}");
+        };
+        let account = self.file.accounts.remove(pos);
+        if self.file.current.as_deref() == Some(user_id) {
+            self.file.current = self.file.accounts.first().map(|a| a.user_id.clone());
+        }
+        self.save()?;
+        Ok(account)
+    }
+
+    /// make `user_id` the current account
+    pub fn switch(&mut self, user_id: &str) -> Result<()> {
+        if self.get(user_id).is_none() {
+            bail!("Unknown account {user_id}");
+        }
+        self.file.current = Some(user_id.to_owned());
+        self.save()
+    }
+}
@@ -169,12 +169,10 @@ pub struct AttachmentDraft {
 impl AttachmentDraft {
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let inner = self.inner.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(inner, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, inner).await })
             .await?
     }
 }
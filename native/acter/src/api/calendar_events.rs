@@ -301,12 +301,21 @@ impl CalendarEventDraft {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let inner = self.inner.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(inner, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, inner).await })
+            .await?
+    }
+
+    /// send it at the given time instead, in milliseconds since the unix
+    /// epoch. Returns the id to cancel it with.
+    pub async fn schedule(&self, send_at: u64) -> Result<String> {
+        let room_id = self.room.room_id().to_owned();
+        let client = self.client.clone();
+        let inner = self.inner.build()?;
+        RUNTIME
+            .spawn(async move { client.schedule(room_id, &inner, send_at).await })
             .await?
     }
 }
@@ -394,12 +403,10 @@ impl CalendarEventUpdateBuilder {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let inner = self.inner.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(inner, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, inner).await })
             .await?
     }
 }
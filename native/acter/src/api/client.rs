@@ -1,6 +1,14 @@
 use acter_core::{
-    client::CoreClient, executor::Executor, models::AnyActerModel, spaces::is_acter_space,
-    store::Store, templates::Engine, CustomAuthSession, RestoreToken,
+    client::CoreClient,
+    executor::Executor,
+    models::AnyActerModel,
+    outbox::{is_transient, Outbox, OutboxFailure, OutboxSent, PendingEvent},
+    spaces::is_acter_space,
+    statics::{PURPOSE_FIELD, PURPOSE_FIELD_DEV, PURPOSE_TEAM_VALUE},
+    store::Store,
+    templates::Engine,
+    util::backoff,
+    CustomAuthSession, RestoreToken,
 };
 use anyhow::{bail, Context, Result};
 use core::time::Duration;
@@ -14,26 +22,28 @@ use matrix_sdk::{
     config::SyncSettings,
     event_handler::EventHandlerHandle,
     media::{MediaFormat, MediaRequest},
-    room::Room as SdkRoom,
+    room::{Joined, Room as SdkRoom},
     ruma::{
         api::client::{
             error::{ErrorBody, ErrorKind},
             push::get_notifications::v3::Notification as RumaNotification,
+            sync::sync_events::v4::{RoomSubscription, SyncRequestListFilters},
             Error,
         },
         device_id,
-        events::room::MediaSource,
-        OwnedDeviceId, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedRoomOrAliasId,
-        OwnedServerName, OwnedUserId, RoomOrAliasId, UserId,
+        directory::RoomTypeFilter,
+        events::{room::MediaSource, MessageLikeEventContent, StateEventType},
+        OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId,
+        OwnedRoomOrAliasId, OwnedServerName, OwnedUserId, RoomOrAliasId, UserId,
     },
-    Client as SdkClient, LoopCtrl, RumaApiError,
+    Client as SdkClient, LoopCtrl, RumaApiError, SlidingSyncList, SlidingSyncMode,
 };
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     ops::Deref,
     path::PathBuf,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
     },
 };
@@ -47,6 +57,7 @@ use tokio::{
 };
 use tokio_stream::wrappers::BroadcastStream;
 use tracing::{error, info, trace, warn};
+use url::Url;
 
 use crate::Notification;
 
@@ -56,9 +67,11 @@ use super::{
     convo::{Convo, ConvoController},
     device::DeviceController,
     invitation::InvitationController,
+    link_previews::LinkPreviews,
     profile::UserProfile,
     receipt::ReceiptController,
     room::Room,
+    scheduled::Scheduler,
     spaces::Space,
     typing::TypingController,
     verification::VerificationController,
@@ -90,6 +103,8 @@ pub struct Client {
     pub(crate) typing_controller: TypingController,
     pub(crate) receipt_controller: ReceiptController,
     pub(crate) convo_controller: ConvoController,
+    pub(crate) link_previews: LinkPreviews,
+    pub(crate) scheduler: Scheduler,
     pub(crate) notifications: Arc<Sender<RumaNotification>>,
 }
 
@@ -140,7 +155,10 @@ pub(crate) async fn devide_spaces_from_convos(
         .fold(
             (Vec::new(), Vec::new(), client),
             async move |(mut spaces, mut convos, client), room| {
-                let inner = Room { room: room.clone() };
+                let inner = Room {
+                    room: room.clone(),
+                    scheduler: client.scheduler.clone(),
+                };
 
                 if inner.is_space() {
                     spaces.push(Space::new(client.clone(), inner));
@@ -197,6 +215,21 @@ impl HistoryLoadState {
     }
 }
 
+/// the id the sliding sync of acter runs under
+const SLIDING_SYNC_ID: &str = "acter";
+/// how many rooms the growing sliding sync lists add per request
+const SLIDING_SYNC_BATCH_SIZE: u32 = 50;
+/// how many of the most recent convos sliding sync loads right away
+const RECENT_CONVOS: u32 = 20;
+/// how many events of an acter space sliding sync loads with its subscription
+const ACTER_SPACE_TIMELINE_LIMIT: u32 = 20;
+/// the first wait after a failed sync, doubled with every further failure
+const SYNC_RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+/// the longest we wait between two sync attempts
+const SYNC_RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+/// after how many failed syncs in a row we consider ourselves offline
+const SYNC_OFFLINE_AFTER: u32 = 3;
+
 type RoomHandlers = Arc<Mutex<HashMap<OwnedRoomId, Vec<EventHandlerHandle>>>>;
 
 #[derive(Clone)]
@@ -240,6 +273,40 @@ impl SyncError {
     }
 }
 
+/// How the sync is doing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// the first sync hasn't come back yet
+    #[default]
+    Connecting,
+    /// the last sync went through
+    Online,
+    /// waiting to retry after the sync failed
+    Reconnecting,
+    /// the sync keeps failing, we keep retrying in the background
+    Offline,
+    /// the server asks us to log in again, the sync stopped
+    SoftLogout,
+}
+
+impl ConnectionState {
+    fn ffi_string(&self) -> String {
+        match self {
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Online => "Online",
+            ConnectionState::Reconnecting => "Reconnecting",
+            ConnectionState::Offline => "Offline",
+            ConnectionState::SoftLogout => "SoftLogout",
+        }
+        .to_owned()
+    }
+}
+
+/// How long to wait before the next sync after `failures` failed in a row
+fn sync_backoff(failures: u32) -> Duration {
+    backoff(failures, SYNC_RETRY_MIN_DELAY, SYNC_RETRY_MAX_DELAY)
+}
+
 #[derive(Clone)]
 pub struct SyncState {
     handle: Mutable<Option<JoinHandle<()>>>,
@@ -248,6 +315,9 @@ pub struct SyncState {
     sync_error: Arc<Receiver<SyncError>>,
     history_loading: Mutable<HistoryLoadState>,
     room_handles: RoomHandlers,
+    connection: Mutable<ConnectionState>,
+    outbox_handle: Mutable<Option<JoinHandle<()>>>,
+    scheduler_handle: Mutable<Option<JoinHandle<()>>>,
 }
 
 impl SyncState {
@@ -259,9 +329,25 @@ impl SyncState {
             first_sync_task: Default::default(),
             handle: Default::default(),
             room_handles: Default::default(),
+            connection: Default::default(),
+            outbox_handle: Default::default(),
+            scheduler_handle: Default::default(),
         }
     }
 
+    /// `Connecting`, `Online`, `Reconnecting`, `Offline` or `SoftLogout`
+    pub fn connection_state(&self) -> String {
+        self.connection.get().ffi_string()
+    }
+
+    /// Fires whenever the connection state changes
+    pub fn connection_state_rx(&self) -> impl Stream<Item = String> {
+        self.connection
+            .signal_cloned()
+            .to_stream()
+            .map(|state| state.ffi_string())
+    }
+
     pub fn first_synced_rx(&self) -> impl Stream<Item = bool> {
         BroadcastStream::new(self.first_synced_rx.resubscribe()).map(|o| o.unwrap_or_default())
     }
@@ -300,9 +386,92 @@ impl SyncState {
         if let Some(handle) = self.handle.replace(None) {
             handle.abort();
         }
+        if let Some(handle) = self.outbox_handle.replace(None) {
+            handle.abort();
+        }
+        if let Some(handle) = self.scheduler_handle.replace(None) {
+            handle.abort();
+        }
     }
 }
 
+/// The parts of the `SyncState` the sync loops report to
+#[derive(Clone)]
+struct SyncTargets {
+    first_synced: Arc<Sender<bool>>,
+    sync_error: Arc<Sender<SyncError>>,
+    initial: Arc<AtomicBool>,
+    history_loading: Mutable<HistoryLoadState>,
+    first_sync_task: Mutable<Option<JoinHandle<Result<()>>>>,
+    room_handles: RoomHandlers,
+    connection: Mutable<ConnectionState>,
+    // how many syncs failed in a row
+    failures: Arc<AtomicU32>,
+    outbox: Outbox,
+}
+
+impl SyncTargets {
+    /// a sync response came in
+    fn synced(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        if self.connection.replace(ConnectionState::Online) != ConnectionState::Online {
+            // we are back, send what piled up meanwhile
+            self.outbox.wake();
+        }
+    }
+
+    /// Report the failed sync and wait before the next attempt. `false` if
+    /// retrying is pointless, as the server doesn't accept our token anymore.
+    async fn failed(&self, err: &matrix_sdk::Error) -> bool {
+        let mut retry_after = None;
+        match err.as_ruma_api_error() {
+            Some(RumaApiError::ClientApi(e)) => match &e.body {
+                ErrorBody::Standard {
+                    kind: ErrorKind::UnknownToken { soft_logout },
+                    ..
+                } => {
+                    error!(?e, "Token rejected, stopping sync");
+                    self.connection.set(if *soft_logout {
+                        ConnectionState::SoftLogout
+                    } else {
+                        ConnectionState::Offline
+                    });
+                    self.sync_error.send(e.into());
+                    return false;
+                }
+                ErrorBody::Standard {
+                    kind: ErrorKind::LimitExceeded { retry_after_ms },
+                    ..
+                } => {
+                    warn!(?retry_after_ms, "Rate limited");
+                    retry_after = *retry_after_ms;
+                }
+                _ => error!(?e, "Client error, retrying"),
+            },
+            _ => error!(?err, "Other error, retrying"),
+        }
+
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        self.connection.set_neq(if failures < SYNC_OFFLINE_AFTER {
+            ConnectionState::Reconnecting
+        } else {
+            ConnectionState::Offline
+        });
+        let delay = sync_backoff(failures).max(retry_after.unwrap_or_default());
+        info!(failures, ?delay, "waiting before syncing again");
+        time::sleep(delay).await;
+        true
+    }
+}
+
+/// What a sync response, full or sliding, brought that we act upon
+struct SyncUpdate {
+    /// the rooms we are in that changed
+    joined: Vec<OwnedRoomId>,
+    /// all rooms that changed, joined, left or invited
+    changed: Vec<OwnedRoomId>,
+}
+
 impl Drop for SyncState {
     fn drop(&mut self) {
         self.cancel();
@@ -368,7 +537,10 @@ impl Client {
         futures::future::join_all(
             new_spaces
                 .into_iter()
-                .map(|room| Space::new(self.clone(), Room { room }))
+                .map(|room| {
+                    let scheduler = self.scheduler.clone();
+                    Space::new(self.clone(), Room { room, scheduler })
+                })
                 .map(|mut space| {
                     let history = history.clone();
                     let room_handles = room_handles.clone();
@@ -405,6 +577,328 @@ impl Client {
         Ok(())
     }
 
+    /// register the event handlers and create the state the sync reports to
+    fn prepare_sync(&mut self) -> (SyncState, SyncTargets) {
+        let client = self.core.client().clone();
+
+        self.invitation_controller.add_event_handler(&client);
+        self.typing_controller.add_event_handler(&client);
+        self.receipt_controller.add_event_handler(&client);
+        self.convo_controller.add_event_handler(&client);
+
+        self.verification_controller
+            .add_to_device_event_handler(&client);
+        // sync event is the event that my device was off so it may be timed out possibly
+        // in fact, when user opens app, he sees old verification popup sometimes
+        // in order to avoid this issue, comment out sync event
+        self.verification_controller.add_sync_event_handler(&client);
+
+        let (first_synced_tx, first_synced_rx) = channel(1);
+        let (sync_error_tx, sync_error_rx) = channel(1);
+        let sync_state = SyncState::new(first_synced_rx, sync_error_rx);
+        let targets = SyncTargets {
+            first_synced: Arc::new(first_synced_tx),
+            sync_error: Arc::new(sync_error_tx),
+            initial: Arc::new(AtomicBool::from(true)),
+            history_loading: sync_state.history_loading.clone(),
+            first_sync_task: sync_state.first_sync_task.clone(),
+            room_handles: sync_state.room_handles.clone(),
+            connection: sync_state.connection.clone(),
+            failures: Default::default(),
+            outbox: self.core.outbox().clone(),
+        };
+        let outbox = targets.outbox.clone();
+        sync_state
+            .outbox_handle
+            .set(Some(RUNTIME.spawn(async move { outbox.run().await })));
+        let outbox = targets.outbox.clone();
+        let scheduler = self.scheduler.clone();
+        sync_state.scheduler_handle.set(Some(
+            RUNTIME.spawn(async move { scheduler.run(outbox).await }),
+        ));
+        (sync_state, targets)
+    }
+
+    /// On the first response: load convos and invitations and start loading
+    /// the history. After that: catch up on new spaces among `joined`.
+    async fn handle_synced_rooms(&self, targets: &SyncTargets, joined: Vec<OwnedRoomId>) {
+        if targets
+            .initial
+            .compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
+            == Ok(true)
+        {
+            info!("received first sync");
+            trace!(user_id=?self.user_id_ref(), "initial synced");
+            let filter = SpaceFilterBuilder::default()
+                .build()
+                .expect("Builder SpaceFilter doesn't fail");
+            // divide_spaces_from_convos must be called after first sync
+            let (spaces, convos) = devide_spaces_from_convos(self.clone(), Some(filter)).await;
+            self.convo_controller.clone().load_rooms(&convos).await;
+            // load invitations after first sync
+            self.invitation_controller
+                .load_invitations(self.core.client())
+                .await;
+
+            targets.initial.store(false, Ordering::SeqCst);
+
+            info!("issuing first sync update");
+            targets.first_synced.send(true);
+            if let Ok(mut w) = self.state.try_write() {
+                w.has_first_synced = true;
+            };
+            // background and keep the handle around.
+            let history_first_sync = self.refresh_history_on_start(
+                targets.history_loading.clone(),
+                targets.room_handles.clone(),
+            );
+            targets.first_sync_task.set(Some(history_first_sync)); // keep task in global variable to avoid too early free of temporary varible in release build
+            return;
+        }
+
+        // see if we have new spaces to catch up upon
+        let mut new_spaces = Vec::new();
+        for room_id in joined {
+            if targets.history_loading.lock_mut().knows_room(&room_id) {
+                // we are already loading this room
+                continue;
+            }
+            let Some(full_room) = self.get_room(&room_id) else {
+                error!("room not found. how can that be?");
+                continue;
+            };
+            if is_acter_space(&full_room).await {
+                new_spaces.push(full_room);
+            }
+        }
+
+        if !new_spaces.is_empty() {
+            self.refresh_history_on_way(
+                targets.history_loading.clone(),
+                targets.room_handles.clone(),
+                new_spaces,
+            )
+            .await;
+        }
+    }
+
+    /// Everything to do after a sync response came in, for the full and the
+    /// sliding sync alike. `true` if we should stop syncing.
+    async fn handle_sync_update(&self, targets: &SyncTargets, update: SyncUpdate) -> bool {
+        targets.synced();
+        self.device_controller
+            .process_own_devices(self.core.client())
+            .await;
+        trace!("post device controller");
+
+        self.handle_synced_rooms(targets, update.joined).await;
+
+        let changed_rooms = update
+            .changed
+            .iter()
+            .map(|id| id.to_string()) // FIXME: handle aliases, too?!?
+            .collect();
+        self.handle_changed_rooms(changed_rooms)
+    }
+
+    /// Notify about the changed rooms, `true` if we should stop syncing
+    fn handle_changed_rooms(&self, mut changed_rooms: Vec<String>) -> bool {
+        self.convo_controller.refresh_unread();
+        if (!changed_rooms.is_empty()) {
+            changed_rooms.push("SPACES".to_owned());
+            self.executor().notify(changed_rooms);
+        }
+
+        if let Ok(mut w) = self.state.try_write() {
+            if w.should_stop_syncing {
+                w.is_syncing = false;
+                trace!("Stopping syncing upon user request");
+                return true;
+            }
+        }
+        if let Ok(mut w) = self.state.try_write() {
+            if !w.is_syncing {
+                w.is_syncing = true;
+            }
+        }
+        false
+    }
+
+    async fn full_sync(&self, targets: SyncTargets) {
+        let client = self.core.client().clone();
+        let me = self.clone();
+
+        // fetch the events that received when offline
+        client
+            .clone()
+            .sync_with_result_callback(SyncSettings::new(), |result| async {
+                info!("received sync callback");
+                let me = me.clone();
+                let targets = targets.clone();
+
+                let response = match result {
+                    Ok(response) => response,
+                    Err(err) => {
+                        if targets.failed(&err).await {
+                            return Ok(LoopCtrl::Continue);
+                        }
+                        return Ok(LoopCtrl::Break);
+                    }
+                };
+                trace!(target: "acter::sync_response::full", "sync response: {:#?}", response);
+
+                let update = SyncUpdate {
+                    joined: response.rooms.join.keys().cloned().collect(),
+                    changed: response
+                        .rooms
+                        .join
+                        .keys()
+                        .chain(response.rooms.leave.keys())
+                        .chain(response.rooms.invite.keys())
+                        .cloned()
+                        .collect(),
+                };
+                if me.handle_sync_update(&targets, update).await {
+                    return Ok(LoopCtrl::Break);
+                }
+
+                trace!("ready for the next round");
+                Ok(LoopCtrl::Continue)
+            })
+            .await;
+    }
+
+    /// Sync through the sliding sync proxy, acter spaces and recent convos
+    /// first. Errors if the proxy can't be used, so we can fall back.
+    ///
+    /// The proxy can't filter by the purpose of a space, so the `spaces` list
+    /// brings all spaces and the acter spaces among them get a subscription
+    /// of their own, to be synced in full ahead of everything else.
+    async fn sliding_sync(&self, targets: SyncTargets, proxy: Option<String>) -> Result<()> {
+        let client = self.core.client().clone();
+        let mut builder = client.sliding_sync(SLIDING_SYNC_ID)?;
+        match proxy {
+            Some(proxy) => builder = builder.sliding_sync_proxy(Url::parse(&proxy)?),
+            None if client.sliding_sync_proxy().is_none() => {
+                bail!("The homeserver doesn't announce a sliding sync proxy")
+            }
+            None => {}
+        }
+
+        let room_state = vec![
+            (StateEventType::RoomCreate, "".to_owned()),
+            (StateEventType::RoomName, "".to_owned()),
+            (StateEventType::RoomAvatar, "".to_owned()),
+            (StateEventType::RoomEncryption, "".to_owned()),
+        ];
+        let mut space_state = room_state.clone();
+        space_state.extend([
+            (StateEventType::SpaceChild, "*".to_owned()),
+            (StateEventType::SpaceParent, "*".to_owned()),
+            (PURPOSE_FIELD.into(), PURPOSE_TEAM_VALUE.to_owned()),
+            (PURPOSE_FIELD_DEV.into(), PURPOSE_TEAM_VALUE.to_owned()),
+        ]);
+
+        let mut acter_space_settings = RoomSubscription::default();
+        acter_space_settings.required_state = space_state.clone();
+        acter_space_settings.timeline_limit = Some(ACTER_SPACE_TIMELINE_LIMIT.into());
+
+        let mut space_filters = SyncRequestListFilters::default();
+        space_filters.room_types = vec![RoomTypeFilter::Space];
+        let mut convo_filters = SyncRequestListFilters::default();
+        convo_filters.not_room_types = vec![RoomTypeFilter::Space];
+
+        let sliding_sync = builder
+            .add_list(
+                SlidingSyncList::builder("spaces")
+                    .sync_mode(SlidingSyncMode::new_growing(SLIDING_SYNC_BATCH_SIZE))
+                    .filters(Some(space_filters))
+                    .required_state(space_state)
+                    .timeline_limit(1),
+            )
+            .add_list(
+                SlidingSyncList::builder("recent_convos")
+                    .sync_mode(SlidingSyncMode::new_selective().add_range(0..=RECENT_CONVOS - 1))
+                    .sort(vec!["by_recency".to_owned(), "by_name".to_owned()])
+                    .filters(Some(convo_filters.clone()))
+                    .required_state(room_state.clone())
+                    .timeline_limit(1),
+            )
+            .add_list(
+                SlidingSyncList::builder("convos")
+                    .sync_mode(SlidingSyncMode::new_growing(SLIDING_SYNC_BATCH_SIZE))
+                    .sort(vec!["by_recency".to_owned(), "by_name".to_owned()])
+                    .filters(Some(convo_filters))
+                    .required_state(room_state)
+                    .timeline_limit(1),
+            )
+            .with_common_extensions()
+            .build()
+            .await?;
+
+        // the acter spaces we know from before come first
+        let mut subscribed = HashSet::new();
+        let (spaces, _convos) = devide_spaces_from_convos(self.clone(), None).await;
+        for space in spaces {
+            if space.is_acter_space().await.unwrap_or_default() {
+                let room_id = space.room_id().to_owned();
+                sliding_sync.subscribe_to_room(room_id.clone(), Some(acter_space_settings.clone()));
+                subscribed.insert(room_id);
+            }
+        }
+
+        loop {
+            // the stream ends after an error, we start over once we waited
+            let stream = sliding_sync.sync();
+            pin_mut!(stream);
+            while let Some(update) = stream.next().await {
+                let summary = match update {
+                    Ok(summary) => summary,
+                    Err(err) if targets.initial.load(Ordering::SeqCst) => {
+                        // the proxy doesn't work for us, better use the full sync
+                        return Err(err.into());
+                    }
+                    Err(err) => {
+                        if !targets.failed(&err).await {
+                            return Ok(());
+                        }
+                        break;
+                    }
+                };
+                trace!(lists = ?summary.lists, rooms = summary.rooms.len(), "sliding sync update");
+
+                let joined = summary
+                    .rooms
+                    .iter()
+                    .filter(|room_id| matches!(self.get_room(room_id), Some(SdkRoom::Joined(_))))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for room_id in &joined {
+                    if subscribed.contains(room_id) {
+                        continue;
+                    }
+                    let Some(room) = self.get_room(room_id) else {
+                        continue;
+                    };
+                    if is_acter_space(&room).await {
+                        trace!(?room_id, "subscribing to acter space");
+                        sliding_sync
+                            .subscribe_to_room(room_id.clone(), Some(acter_space_settings.clone()));
+                        subscribed.insert(room_id.clone());
+                    }
+                }
+
+                let update = SyncUpdate {
+                    joined,
+                    changed: summary.rooms,
+                };
+                if self.handle_sync_update(&targets, update).await {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
     pub(crate) async fn source_binary(&self, source: MediaSource) -> Result<FfiBuffer<u8>> {
         // any variable in self can't be called directly in spawn
         let client = self.clone();
@@ -439,16 +933,76 @@ impl Client {
                     .await?;
                 Ok(Room {
                     room: joined.into(),
+                    scheduler: c.scheduler.clone(),
                 })
             })
             .await?
     }
+
+    /// Send the acter event, showing it as local echo right away. If we
+    /// can't reach the server, it is queued in the outbox and the id of the
+    /// local echo returned, `outbox_sent_rx` tells the id it got once sent.
+    /// Once events of the room are queued, new ones queue up behind them to
+    /// keep their order.
+    pub(crate) async fn send_or_queue<C>(&self, room: Joined, content: C) -> Result<OwnedEventId>
+    where
+        C: MessageLikeEventContent,
+    {
+        let outbox = self.core.outbox();
+        let executor = self.core.executor();
+        let room_id = room.room_id().to_owned();
+        let mut event = PendingEvent::new(room_id.clone(), &content)?;
+        // the caller might still hold the id of a local echo sent meanwhile
+        outbox.resolve_references(&mut event).await;
+        outbox.apply_local(&event).await;
+        if !outbox.has_pending(&room_id).await {
+            let txn_id = event.txn_id_typed();
+            let content = event.content().clone();
+            let event_type = event.event_type();
+            match room.send_raw(content, &event_type, Some(txn_id)).await {
+                Ok(resp) => {
+                    let event_id = resp.event_id;
+                    if let Err(error) = executor.confirm_local(txn_id, event_id.clone()).await {
+                        error!(?error, ?txn_id, "confirming local echo failed");
+                    }
+                    return Ok(event_id);
+                }
+                Err(error) if !is_transient(&error) => {
+                    if let Err(error) = executor.rollback_local(txn_id).await {
+                        error!(?error, ?txn_id, "rolling back local echo failed");
+                    }
+                    return Err(error.into());
+                }
+                Err(error) => warn!(?error, ?room_id, "sending failed, queueing"),
+            }
+        }
+        Ok(outbox.enqueue(event).await?)
+    }
 }
 
 // external API
 impl Client {
     pub async fn new(client: SdkClient, state: ClientState) -> Result<Self> {
         let core = CoreClient::new(client).await?;
+        let scheduler = Scheduler::load(core.store().clone()).await;
+        let link_previews = LinkPreviews::new(core.client().clone());
+        let convo_controller = ConvoController::new(
+            core.store().clone(),
+            scheduler.clone(),
+            link_previews.clone(),
+        );
+        let notifications = Arc::new(channel(25).0);
+        // fires for the full and the sliding sync alike
+        let sender = notifications.clone();
+        core.client()
+            .register_notification_handler(move |notification: RumaNotification, _room, _client| {
+                let sender = sender.clone();
+                async move {
+                    trace!("Sending notification");
+                    let _ = sender.send(notification);
+                }
+            })
+            .await;
         let mut cl = Client {
             core,
             state: Arc::new(RwLock::new(state)),
@@ -456,9 +1010,11 @@ impl Client {
             verification_controller: VerificationController::new(),
             device_controller: DeviceController::new(),
             typing_controller: TypingController::new(),
-            receipt_controller: ReceiptController::new(),
-            convo_controller: ConvoController::new(),
-            notifications: Arc::new(channel(25).0),
+            receipt_controller: ReceiptController::new(convo_controller.clone()),
+            convo_controller,
+            link_previews,
+            scheduler,
+            notifications,
         };
 
         let (_spaces, convos) = devide_spaces_from_convos(cl.clone(), None).await;
@@ -481,182 +1037,32 @@ impl Client {
 
     pub fn start_sync(&mut self) -> SyncState {
         info!("starting sync");
-        let state = self.state.clone();
+        let (sync_state, targets) = self.prepare_sync();
         let me = self.clone();
-        let executor = self.executor().clone();
-        let client = self.core.client().clone();
-
-        self.invitation_controller.add_event_handler(&client);
-        self.typing_controller.add_event_handler(&client);
-        self.receipt_controller.add_event_handler(&client);
-        self.convo_controller.add_event_handler(&client);
-
-        self.verification_controller
-            .add_to_device_event_handler(&client);
-        // sync event is the event that my device was off so it may be timed out possibly
-        // in fact, when user opens app, he sees old verification popup sometimes
-        // in order to avoid this issue, comment out sync event
-        self.verification_controller.add_sync_event_handler(&client);
-
-        let mut invitation_controller = self.invitation_controller.clone();
-        let mut device_controller = self.device_controller.clone();
-        let mut convo_controller = self.convo_controller.clone();
-        let notifications = self.notifications.clone();
-
-        let (first_synced_tx, first_synced_rx) = channel(1);
-        let first_synced_arc = Arc::new(first_synced_tx);
-
-        let (sync_error_tx, sync_error_rx) = channel(1);
-        let sync_error_arc = Arc::new(sync_error_tx);
-
-        let initial_arc = Arc::new(AtomicBool::from(true));
-        let sync_state = SyncState::new(first_synced_rx, sync_error_rx);
-        let history_loading = sync_state.history_loading.clone();
-        let first_sync_task = sync_state.first_sync_task.clone();
-        let room_handles = sync_state.room_handles.clone();
-
         let handle = RUNTIME.spawn(async move {
             info!("spawning sync callback");
-            let client = client.clone();
-            let state = state.clone();
-
-            let mut invitation_controller = invitation_controller.clone();
-            let mut device_controller = device_controller.clone();
-            let mut convo_controller = convo_controller.clone();
-
-            let history_loading = history_loading.clone();
-            let first_sync_task = first_sync_task.clone();
-            let room_handles = room_handles.clone();
-            let notifications = notifications.clone();
-
-            // fetch the events that received when offline
-            client
-                .clone()
-                .sync_with_result_callback(SyncSettings::new(), |result| async {
-                    info!("received sync callback");
-                    let client = client.clone();
-                    let me = me.clone();
-                    let executor = executor.clone();
-                    let state = state.clone();
-
-                    let mut invitation_controller = invitation_controller.clone();
-                    let mut device_controller = device_controller.clone();
-                    let mut convo_controller = convo_controller.clone();
-
-                    let first_synced_arc = first_synced_arc.clone();
-                    let sync_error_arc = sync_error_arc.clone();
-                    let history_loading = history_loading.clone();
-                    let initial = initial_arc.clone();
-
-                    let response = match result {
-                        Ok(response) => response,
-                        Err(err) => {
-                            if let Some(RumaApiError::ClientApi(e)) = err.as_ruma_api_error() {
-                                error!(?e, "Client error");
-                                sync_error_arc.send(e.into());
-                                return Ok(LoopCtrl::Break);
-                            }
-                            error!(?err, "Other error, continuing");
-                            return Ok(LoopCtrl::Continue);
-                        }
-                    };
-                    trace!(target: "acter::sync_response::full", "sync response: {:#?}", response);
-
-                    device_controller.process_device_lists(&client, &response);
-                    trace!("post device controller");
-
-                    if initial.compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
-                        == Ok(true)
-                    {
-                        info!("received first sync");
-                        trace!(user_id=?client.user_id(), "initial synced");
-                        let filter = SpaceFilterBuilder::default()
-                            .build()
-                            .expect("Builder SpaceFilter doesn't fail");
-                        // divide_spaces_from_convos must be called after first sync
-                        let (spaces, convos) =
-                            devide_spaces_from_convos(me.clone(), Some(filter)).await;
-                        convo_controller.load_rooms(&convos).await;
-                        // load invitations after first sync
-                        invitation_controller.load_invitations(&client).await;
-
-                        initial.store(false, Ordering::SeqCst);
-
-                        info!("issuing first sync update");
-                        first_synced_arc.send(true);
-                        if let Ok(mut w) = state.try_write() {
-                            w.has_first_synced = true;
-                        };
-                        // background and keep the handle around.
-                        let history_first_sync = me.refresh_history_on_start(
-                            history_loading.clone(),
-                            room_handles.clone(),
-                        );
-                        first_sync_task.set(Some(history_first_sync)); // keep task in global variable to avoid too early free of temporary varible in release build
-                    } else {
-                        // see if we have new spaces to catch up upon
-                        let mut new_spaces = Vec::new();
-                        for room_id in response.rooms.join.keys() {
-                            if history_loading.lock_mut().knows_room(room_id) {
-                                // we are already loading this room
-                                continue;
-                            }
-                            let Some(full_room) = me.get_room(room_id) else {
-                                error!("room not found. how can that be?");
-                                continue;
-                            };
-                            if is_acter_space(&full_room).await {
-                                new_spaces.push(full_room);
-                            }
-                        }
-
-                        if !new_spaces.is_empty() {
-                            me.refresh_history_on_way(
-                                history_loading.clone(),
-                                room_handles.clone(),
-                                new_spaces,
-                            )
-                            .await;
-                        }
-                    }
-
-                    let mut changed_rooms = response
-                        .rooms
-                        .join
-                        .keys()
-                        .chain(response.rooms.leave.keys())
-                        .chain(response.rooms.invite.keys())
-                        .map(|id| id.to_string()) // FIXME: handle aliases, too?!?
-                        .collect::<Vec<String>>();
-
-                    if (!changed_rooms.is_empty()) {
-                        changed_rooms.push("SPACES".to_owned());
-                        me.executor().notify(changed_rooms);
-                    }
-
-                    if let Ok(mut w) = state.try_write() {
-                        if w.should_stop_syncing {
-                            w.is_syncing = false;
-                            trace!("Stopping syncing upon user request");
-                            return Ok(LoopCtrl::Break);
-                        }
-                    }
-                    if let Ok(mut w) = state.try_write() {
-                        if !w.is_syncing {
-                            w.is_syncing = true;
-                        }
-                    }
-                    for ev in response.notifications.into_values() {
-                        for item in ev {
-                            trace!("Sending notification");
-                            notifications.send(item);
-                        }
-                    }
+            me.full_sync(targets).await;
+        });
+        sync_state.handle.set(Some(handle));
+        sync_state
+    }
 
-                    trace!("ready for the next round");
-                    Ok(LoopCtrl::Continue)
-                })
-                .await;
+    /// Start syncing through the sliding sync proxy, acter spaces and the
+    /// most recent convos first. Uses the proxy the homeserver announces if
+    /// none is given and falls back to the full sync of `start_sync` if there
+    /// is no proxy or it fails.
+    pub fn start_sliding_sync(&mut self, proxy: Option<String>) -> SyncState {
+        info!("starting sliding sync");
+        let (sync_state, targets) = self.prepare_sync();
+        let me = self.clone();
+        let handle = RUNTIME.spawn(async move {
+            if let Err(error) = me.sliding_sync(targets.clone(), proxy).await {
+                warn!(
+                    ?error,
+                    "sliding sync unavailable, falling back to full sync"
+                );
+                me.full_sync(targets).await;
+            }
         });
         sync_state.handle.set(Some(handle));
         sync_state
@@ -753,7 +1159,7 @@ impl Client {
     }
 
     pub async fn room(&self, room_id_or_alias: String) -> Result<Room> {
-        let id_or_alias = OwnedRoomOrAliasId::try_from(room_id_or_alias).expect("just checked");
+        let id_or_alias = OwnedRoomOrAliasId::try_from(room_id_or_alias)?;
         self.room_typed(&id_or_alias).await
     }
 
@@ -769,10 +1175,10 @@ impl Client {
     }
 
     pub fn room_by_id_typed(&self, room_id: &OwnedRoomId) -> Option<Room> {
-        self.core
-            .client()
-            .get_room(room_id)
-            .map(|room| Room { room })
+        self.core.client().get_room(room_id).map(|room| Room {
+            room,
+            scheduler: self.scheduler.clone(),
+        })
     }
 
     pub async fn room_by_alias_typed(&self, room_alias: &OwnedRoomAliasId) -> Result<Room> {
@@ -780,12 +1186,18 @@ impl Client {
             // looping locally first
             if let Some(con_alias) = r.canonical_alias() {
                 if &con_alias == room_alias {
-                    return Ok(Room { room: r });
+                    return Ok(Room {
+                        room: r,
+                        scheduler: self.scheduler.clone(),
+                    });
                 }
             }
             for alt_alias in r.alt_aliases() {
                 if &alt_alias == room_alias {
-                    return Ok(Room { room: r });
+                    return Ok(Room {
+                        room: r,
+                        scheduler: self.scheduler.clone(),
+                    });
                 }
             }
         }
@@ -795,6 +1207,56 @@ impl Client {
             .context("Room not found")
     }
 
+    /// the acter events waiting to be sent, oldest first
+    pub async fn pending_events(&self) -> Result<Vec<PendingEvent>> {
+        let outbox = self.core.outbox().clone();
+        RUNTIME
+            .spawn(async move { Ok(outbox.pending().await) })
+            .await?
+    }
+
+    /// Fires whenever sending a queued event failed
+    pub fn outbox_failures_rx(&self) -> impl Stream<Item = OutboxFailure> {
+        BroadcastStream::new(self.core.outbox().subscribe_failures())
+            .filter_map(|r| async move { r.ok() })
+    }
+
+    /// Fires once a queued event was sent, with the id it got instead of
+    /// the pending one returned when it was queued
+    pub fn outbox_sent_rx(&self) -> impl Stream<Item = OutboxSent> {
+        BroadcastStream::new(self.core.outbox().subscribe_sent())
+            .filter_map(|r| async move { r.ok() })
+    }
+
+    /// The id the event got on the server if `event_id` is the pending id of
+    /// a queued event that was sent meanwhile, `event_id` otherwise
+    pub async fn resolve_event_id(&self, event_id: String) -> Result<String> {
+        let outbox = self.core.outbox().clone();
+        RUNTIME
+            .spawn(async move {
+                Ok(outbox
+                    .resolve(&event_id)
+                    .await
+                    .map(|id| id.to_string())
+                    .unwrap_or(event_id))
+            })
+            .await?
+    }
+
+    /// drop the queued event, `false` if it was sent already
+    pub async fn discard_pending_event(&self, txn_id: String) -> Result<bool> {
+        let outbox = self.core.outbox().clone();
+        RUNTIME
+            .spawn(async move { Ok(outbox.discard(&txn_id).await?) })
+            .await?
+    }
+
+    /// whether this model is a local echo, shown before the server
+    /// confirmed the event
+    pub fn is_pending(&self, event_id: String) -> bool {
+        self.store().is_pending(&event_id)
+    }
+
     pub fn notifications_stream(&self) -> impl Stream<Item = Notification> {
         let client = self.clone();
         BroadcastStream::new(self.notifications.subscribe())
@@ -834,9 +1296,15 @@ impl Client {
         timeout: Option<Box<Duration>>,
     ) -> Result<AnyActerModel> {
         let executor = self.core.executor().clone();
+        let outbox = self.core.outbox().clone();
 
         RUNTIME
             .spawn(async move {
+                // the local echo is gone once its event was sent
+                let key = match outbox.resolve(&key).await {
+                    Some(event_id) => event_id.to_string(),
+                    None => key,
+                };
                 let waiter = executor.wait_for(key);
                 let Some(tm) = timeout else {
                     return Ok(waiter.await?);
@@ -917,3 +1385,22 @@ impl Client {
             .await?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_backs_off() {
+        assert_eq!(sync_backoff(1), SYNC_RETRY_MIN_DELAY);
+        assert_eq!(sync_backoff(2), SYNC_RETRY_MIN_DELAY * 2);
+        assert_eq!(sync_backoff(3), SYNC_RETRY_MIN_DELAY * 4);
+        assert_eq!(sync_backoff(100), SYNC_RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn connection_starts_connecting() {
+        assert_eq!(ConnectionState::default(), ConnectionState::Connecting);
+        assert_eq!(ConnectionState::default().ffi_string(), "Connecting");
+    }
+}
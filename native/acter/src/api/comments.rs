@@ -1,5 +1,5 @@
 use acter_core::{
-    events::comments::CommentBuilder,
+    events::comments::{CommentBuilder, CommentUpdateBuilder as CoreCommentUpdateBuilder},
     models::{self, ActerModel, AnyActerModel, Color},
 };
 use anyhow::{bail, Context, Result};
@@ -68,6 +68,17 @@ impl Comment {
         })
     }
 
+    pub fn update_builder(&self) -> Result<CommentUpdateBuilder> {
+        let Room::Joined(joined) = &self.room else {
+            bail!("Can only update comments in joined rooms");
+        };
+        Ok(CommentUpdateBuilder {
+            client: self.client.clone(),
+            room: joined.clone(),
+            inner: self.inner.updater(),
+        })
+    }
+
     pub fn sender(&self) -> OwnedUserId {
         self.inner.meta.sender.clone()
     }
@@ -123,12 +134,39 @@ impl CommentDraft {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let inner = self.inner.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(inner, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, inner).await })
+            .await?
+    }
+}
+
+#[derive(Clone)]
+pub struct CommentUpdateBuilder {
+    client: Client,
+    room: Joined,
+    inner: CoreCommentUpdateBuilder,
+}
+
+impl CommentUpdateBuilder {
+    pub fn content_text(&mut self, body: String) -> &mut Self {
+        self.inner.content(TextMessageEventContent::plain(body));
+        self
+    }
+
+    pub fn content_formatted(&mut self, body: String, html_body: String) -> &mut Self {
+        self.inner
+            .content(TextMessageEventContent::html(body, html_body));
+        self
+    }
+
+    pub async fn send(&self) -> Result<OwnedEventId> {
+        let room = self.room.clone();
+        let client = self.client.clone();
+        let inner = self.inner.build()?;
+        RUNTIME
+            .spawn(async move { client.send_or_queue(room, inner).await })
             .await?
     }
 }
@@ -1,3 +1,4 @@
+use acter_core::events::voice::VoiceContent;
 use core::time::Duration;
 use matrix_sdk::ruma::{
     events::room::{
@@ -205,11 +206,21 @@ pub struct AudioDesc {
     name: String,
     source: SdkMediaSource,
     info: AudioInfo,
+    voice: VoiceContent,
 }
 
 impl AudioDesc {
     pub fn new(name: String, source: SdkMediaSource, info: AudioInfo) -> Self {
-        AudioDesc { name, source, info }
+        AudioDesc {
+            name,
+            source,
+            info,
+            voice: Default::default(),
+        }
+    }
+
+    pub(crate) fn set_voice(&mut self, voice: VoiceContent) {
+        self.voice = voice;
     }
 
     pub fn name(&self) -> String {
@@ -223,19 +234,32 @@ impl AudioDesc {
     }
 
     pub fn duration(&self) -> Option<u32> {
-        self.info.duration.map(|x| {
-            let secs = x.as_secs();
-            if secs > u32::MAX as u64 {
-                panic!("audio duration overflowed");
-            }
-            secs as u32
-        })
+        self.info
+            .duration
+            .or_else(|| self.voice.duration())
+            .map(|x| {
+                let secs = x.as_secs();
+                if secs > u32::MAX as u64 {
+                    panic!("audio duration overflowed");
+                }
+                secs as u32
+            })
     }
 
     pub fn mimetype(&self) -> Option<String> {
         self.info.mimetype.clone()
     }
 
+    /// whether this was recorded as voice message
+    pub fn is_voice(&self) -> bool {
+        self.voice.is_voice()
+    }
+
+    /// the loudness along the recording, each between 0 and 1024
+    pub fn waveform(&self) -> FfiBuffer<u16> {
+        FfiBuffer::new(self.voice.waveform().to_vec())
+    }
+
     pub fn size(&self) -> Option<u32> {
         self.info.size.map(|x| {
             let size = u64::from(x);
@@ -1,61 +1,93 @@
-use acter_core::{statics::default_acter_convo_states, Error};
+use acter_core::{
+    events::{
+        polls::{
+            OriginalSyncPollEndEvent, OriginalSyncPollResponseEvent, OriginalSyncPollStartEvent,
+        },
+        starred::StarredMessagesEvent,
+    },
+    statics::default_acter_convo_states,
+    store::Store,
+    Error,
+};
 use anyhow::{bail, Result};
 use derive_builder::Builder;
 use futures_signals::signal::{Mutable, MutableSignalCloned, SignalExt, SignalStream};
 use matrix_sdk::{
     deserialized_responses::SyncTimelineEvent,
-    event_handler::{Ctx, EventHandlerHandle},
-    room::{MessagesOptions, Room as SdkRoom},
+    event_handler::{Ctx, EventHandlerHandle, RawEvent},
+    room::{MessagesOptions, Receipts, Room as SdkRoom},
     ruma::{
-        api::client::room::{
-            create_room::v3::{CreationContent, Request as CreateRoomRequest},
-            Visibility,
+        api::client::{
+            receipt::create_receipt::v3::ReceiptType as CreateReceiptType,
+            room::{
+                create_room::v3::{CreationContent, Request as CreateRoomRequest},
+                Visibility,
+            },
         },
         assign,
         events::{
+            fully_read::FullyReadEventContent,
             receipt::{ReceiptThread, ReceiptType},
             room::{
                 avatar::{ImageInfo, InitialRoomAvatarEvent, RoomAvatarEventContent},
                 encrypted::OriginalSyncRoomEncryptedEvent,
                 join_rules::{AllowRule, InitialRoomJoinRulesEvent, RoomJoinRulesEventContent},
                 member::{MembershipState, OriginalSyncRoomMemberEvent},
-                message::OriginalSyncRoomMessageEvent,
+                message::{OriginalSyncRoomMessageEvent, Relation},
+                pinned_events::SyncRoomPinnedEventsEvent,
                 redaction::SyncRoomRedactionEvent,
             },
             space::parent::SpaceParentEventContent,
             AnySyncTimelineEvent, InitialStateEvent,
         },
         serde::Raw,
-        MxcUri, OwnedRoomId, OwnedUserId, RoomId, UserId,
+        EventId, MxcUri, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
     },
     Client as SdkClient, RoomMemberships,
 };
 use matrix_sdk_ui::timeline::RoomExt;
-use std::{ops::Deref, path::PathBuf, sync::Arc};
-use tracing::info;
+use std::{collections::HashMap, ops::Deref, path::PathBuf, sync::Arc};
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tracing::{error, info, warn};
 
 use super::{
     client::Client,
-    message::{sync_event_to_message, RoomMessage},
-    receipt::ReceiptRecord,
+    link_previews::LinkPreviews,
+    message::{sync_event_to_message, thread_root, RoomMessage},
+    polls::{load_poll, started_poll, PollStore},
+    receipt::{reads_latest, ReceiptRecord},
     room::Room,
+    scheduled::Scheduler,
+    search::{is_encrypted, MessageIndex},
     stream::TimelineStream,
     RUNTIME,
 };
 
 #[derive(Clone, Debug)]
 pub struct Convo {
-    controller: ConvoController,
+    pub(crate) controller: ConvoController,
     inner: Room,
     latest_message: Option<RoomMessage>,
+    // the latest message of the main timeline, marking as read points there
+    latest_event_id: Option<OwnedEventId>,
+    // as counted by the server, updated with every sync
+    unread: u64,
+    highlights: u64,
+    // replies of others per thread root we haven't read
+    unread_threads: HashMap<OwnedEventId, u64>,
 }
 
 impl Convo {
     pub(crate) fn new(controller: ConvoController, inner: Room) -> Self {
+        let counts = inner.room.unread_notification_counts();
         Convo {
             controller,
             inner,
             latest_message: Default::default(),
+            latest_event_id: Default::default(),
+            unread: counts.notification_count,
+            highlights: counts.highlight_count,
+            unread_threads: Default::default(),
         }
     }
 
@@ -68,15 +100,31 @@ impl Convo {
                 .into_iter()
                 .map(SyncTimelineEvent::from)
                 .collect::<Vec<SyncTimelineEvent>>();
+            let mut found_message = false;
             for event in events {
+                if self.latest_event_id.is_none() && is_main_timeline_message(&event.event) {
+                    self.latest_event_id = event
+                        .event
+                        .get_field::<OwnedEventId>("event_id")
+                        .ok()
+                        .flatten();
+                }
                 // show only message event as latest message in chat room list
                 // skip the state event
                 // if let Ok(AnySyncTimelineEvent::MessageLike(m)) = event.event.deserialize() {
-                if let Some(msg) = sync_event_to_message(&event.event, room.room_id().to_owned()) {
-                    self.set_latest_message(msg);
-                    return;
+                if !found_message {
+                    if let Some(msg) =
+                        sync_event_to_message(&event.event, room.room_id().to_owned())
+                    {
+                        let msg = self.controller.decorate(msg, &room);
+                        self.set_latest_message(msg);
+                        found_message = true;
+                    }
                 }
                 // }
+                if found_message && self.latest_event_id.is_some() {
+                    return;
+                }
             }
         }
     }
@@ -89,6 +137,55 @@ impl Convo {
         self.latest_message.clone()
     }
 
+    /// unread messages, as notified by the server
+    pub fn unread_count(&self) -> u64 {
+        self.unread
+    }
+
+    /// unread messages mentioning us or otherwise highlighted
+    pub fn highlight_count(&self) -> u64 {
+        self.highlights
+    }
+
+    /// unread replies in the thread of `thread_root`
+    pub fn unread_thread_count(&self, thread_root: String) -> u64 {
+        EventId::parse(thread_root)
+            .ok()
+            .and_then(|root| self.unread_threads.get(&root).copied())
+            .unwrap_or_default()
+    }
+
+    /// the roots of the threads with unread replies
+    pub fn unread_threads(&self) -> Vec<String> {
+        self.unread_threads
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(root, _)| root.to_string())
+            .collect()
+    }
+
+    fn count_thread_unread(&mut self, thread_root: OwnedEventId) {
+        *self.unread_threads.entry(thread_root).or_default() += 1;
+    }
+
+    // whether the counts of the server differ from ours
+    fn unread_changed(&self) -> bool {
+        let counts = self.room.unread_notification_counts();
+        counts.notification_count != self.unread || counts.highlight_count != self.highlights
+    }
+
+    fn update_unread(&mut self) {
+        let counts = self.room.unread_notification_counts();
+        self.unread = counts.notification_count;
+        self.highlights = counts.highlight_count;
+    }
+
+    fn clear_unread(&mut self) {
+        self.unread = 0;
+        self.highlights = 0;
+        self.unread_threads.clear();
+    }
+
     pub fn get_room_id(&self) -> OwnedRoomId {
         self.room_id().to_owned()
     }
@@ -129,6 +226,90 @@ impl Convo {
             })
             .await?
     }
+
+    /// the messages of the thread of `thread_root`, root first
+    pub async fn thread_timeline_stream(&self, thread_root: String) -> Result<TimelineStream> {
+        let room = self.room.clone();
+        let controller = self.controller.clone();
+        let thread_root = EventId::parse(thread_root)?;
+
+        RUNTIME
+            .spawn(async move {
+                let timeline = room.timeline().await;
+                let stream =
+                    TimelineStream::new_thread(room, Arc::new(timeline), controller, thread_root);
+                Ok(stream)
+            })
+            .await?
+    }
+
+    /// Move the fully read marker and our read receipt to the latest message
+    /// of the main timeline, which clears all unread messages
+    pub async fn mark_as_read(&self) -> Result<bool> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't mark a room as read we are not in");
+        };
+        let controller = self.controller.clone();
+        // we might be an outdated copy
+        let latest_event_id = controller
+            .latest_event_id(joined.room_id())
+            .or_else(|| self.latest_event_id.clone());
+
+        RUNTIME
+            .spawn(async move {
+                let Some(event_id) = latest_event_id else {
+                    // nothing to read
+                    return Ok(false);
+                };
+                let receipts = Receipts::new()
+                    .fully_read_marker(event_id.clone())
+                    .public_read_receipt(event_id);
+                joined.send_multiple_receipts(receipts).await?;
+                controller.clear_unread(joined.room_id());
+                Ok(true)
+            })
+            .await?
+    }
+
+    /// The event up to which we have read everything
+    pub async fn fully_read_event_id(&self) -> Result<Option<String>> {
+        let room = self.room.clone();
+
+        RUNTIME
+            .spawn(async move {
+                let Some(raw) = room.account_data_static::<FullyReadEventContent>().await? else {
+                    return Ok(None);
+                };
+                let event = raw.deserialize()?;
+                Ok(Some(event.content.event_id.to_string()))
+            })
+            .await?
+    }
+
+    /// Send a read receipt for `event_id` in the thread of `thread_root`,
+    /// which clears its unread replies
+    pub async fn mark_thread_read(&self, thread_root: String, event_id: String) -> Result<bool> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't send receipts to a room we are not in");
+        };
+        let thread_root = EventId::parse(thread_root)?;
+        let event_id = EventId::parse(event_id)?;
+        let controller = self.controller.clone();
+
+        RUNTIME
+            .spawn(async move {
+                joined
+                    .send_single_receipt(
+                        CreateReceiptType::Read,
+                        ReceiptThread::Thread(thread_root.clone()),
+                        event_id,
+                    )
+                    .await?;
+                controller.clear_thread_unread(joined.room_id(), &thread_root);
+                Ok(true)
+            })
+            .await?
+    }
 }
 
 impl Deref for Convo {
@@ -138,6 +319,29 @@ impl Deref for Convo {
     }
 }
 
+/// How much is unread across all convos
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnreadSummary {
+    convos: u64,
+    messages: u64,
+    highlights: u64,
+}
+
+impl UnreadSummary {
+    /// how many convos have unread messages
+    pub fn convos(&self) -> u64 {
+        self.convos
+    }
+
+    pub fn messages(&self) -> u64 {
+        self.messages
+    }
+
+    pub fn highlights(&self) -> u64 {
+        self.highlights
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ConvoController {
     convos: Mutable<Vec<Convo>>,
@@ -145,22 +349,118 @@ pub struct ConvoController {
     message_event_handle: Option<EventHandlerHandle>,
     member_event_handle: Option<EventHandlerHandle>,
     redaction_event_handle: Option<EventHandlerHandle>,
-}
-
-impl Default for ConvoController {
-    fn default() -> Self {
-        Self::new()
-    }
+    pinned_event_handle: Option<EventHandlerHandle>,
+    starred_event_handle: Option<EventHandlerHandle>,
+    poll_start_event_handle: Option<EventHandlerHandle>,
+    poll_response_event_handle: Option<EventHandlerHandle>,
+    poll_end_event_handle: Option<EventHandlerHandle>,
+    message_index: MessageIndex,
+    polls: PollStore,
+    link_previews: LinkPreviews,
+    // where the drafts are kept
+    store: Store,
+    scheduler: Scheduler,
+    unread_summary: Mutable<UnreadSummary>,
+    // the rooms whose pinned messages changed
+    pinned_changed: Arc<Sender<OwnedRoomId>>,
+    starred_changed: Arc<Sender<()>>,
+    // the polls that started, got a vote or ended
+    polls_changed: Arc<Sender<(OwnedRoomId, OwnedEventId)>>,
 }
 
 impl ConvoController {
-    pub fn new() -> Self {
+    pub fn new(store: Store, scheduler: Scheduler, link_previews: LinkPreviews) -> Self {
         ConvoController {
             convos: Default::default(),
             encrypted_event_handle: None,
             message_event_handle: None,
             member_event_handle: None,
             redaction_event_handle: None,
+            pinned_event_handle: None,
+            starred_event_handle: None,
+            poll_start_event_handle: None,
+            poll_response_event_handle: None,
+            poll_end_event_handle: None,
+            message_index: MessageIndex::new(store.clone()),
+            polls: Default::default(),
+            link_previews,
+            store,
+            scheduler,
+            unread_summary: Default::default(),
+            pinned_changed: Arc::new(channel(10).0),
+            starred_changed: Arc::new(channel(1).0),
+            polls_changed: Arc::new(channel(10).0),
+        }
+    }
+
+    pub(crate) fn subscribe_pinned(&self) -> Receiver<OwnedRoomId> {
+        self.pinned_changed.subscribe()
+    }
+
+    pub(crate) fn subscribe_starred(&self) -> Receiver<()> {
+        self.starred_changed.subscribe()
+    }
+
+    pub(crate) fn subscribe_polls(&self) -> Receiver<(OwnedRoomId, OwnedEventId)> {
+        self.polls_changed.subscribe()
+    }
+
+    pub(crate) fn message_index(&self) -> MessageIndex {
+        self.message_index.clone()
+    }
+
+    pub(crate) fn polls(&self) -> PollStore {
+        self.polls.clone()
+    }
+
+    pub(crate) fn store(&self) -> Store {
+        self.store.clone()
+    }
+
+    /// What we know about the message beyond its event: the results of its
+    /// poll and where to get the previews of its links
+    pub(crate) fn decorate(&self, msg: RoomMessage, room: &SdkRoom) -> RoomMessage {
+        self.follow_poll(&msg, room);
+        let client = room.client();
+        let mut msg = self.polls.with_results(msg, client.user_id());
+        if let Some(mut event_item) = msg.event_item() {
+            event_item.set_link_previews(self.link_previews.clone());
+            msg.set_event_item(Some(event_item));
+        }
+        msg
+    }
+
+    /// Fetch the votes of a poll we don't follow yet, e.g. started before
+    /// this session. The poll updates tell when they are in.
+    fn follow_poll(&self, msg: &RoomMessage, room: &SdkRoom) {
+        let Some(poll_id) = started_poll(msg) else {
+            return;
+        };
+        if !self.polls.begin_loading(&poll_id) {
+            return;
+        }
+        let room = room.clone();
+        let polls = self.polls.clone();
+        let polls_changed = self.polls_changed.clone();
+        RUNTIME.spawn(async move {
+            match load_poll(&room, &poll_id).await {
+                Ok(poll) => {
+                    polls.insert(poll);
+                    // nobody listening is fine
+                    let _ = polls_changed.send((room.room_id().to_owned(), poll_id));
+                }
+                Err(error) => {
+                    warn!(?error, ?poll_id, "fetching the votes of the poll failed");
+                    polls.loading_failed(&poll_id);
+                }
+            }
+        });
+    }
+
+    fn room(&self, room: &SdkRoom) -> Room {
+        Room {
+            room: room.clone(),
+            scheduler: self.scheduler.clone(),
         }
     }
 
@@ -183,8 +483,12 @@ impl ConvoController {
         let handle = client.add_event_handler(
             |ev: OriginalSyncRoomMessageEvent,
              room: SdkRoom,
+             raw: RawEvent,
              c: SdkClient,
              Ctx(me): Ctx<ConvoController>| async move {
+                if is_encrypted(&room).await {
+                    me.index_room_message(&ev, raw, &room).await;
+                }
                 me.clone().process_room_message(ev, &room, &c);
             },
         );
@@ -202,16 +506,61 @@ impl ConvoController {
         );
         self.member_event_handle = Some(handle);
 
-        client.add_event_handler_context(me);
+        client.add_event_handler_context(me.clone());
         let handle = client.add_event_handler(
             |ev: SyncRoomRedactionEvent,
              room: SdkRoom,
              c: SdkClient,
              Ctx(me): Ctx<ConvoController>| async move {
+                me.unindex_room_message(&ev, &room).await;
                 me.clone().process_room_redaction(ev, &room, &c);
             },
         );
         self.redaction_event_handle = Some(handle);
+
+        client.add_event_handler_context(me.clone());
+        let handle = client.add_event_handler(
+            |ev: SyncRoomPinnedEventsEvent, room: SdkRoom, Ctx(me): Ctx<ConvoController>| async move {
+                // nobody listening is fine
+                let _ = me.pinned_changed.send(room.room_id().to_owned());
+            },
+        );
+        self.pinned_event_handle = Some(handle);
+
+        client.add_event_handler_context(me.clone());
+        let handle = client.add_event_handler(
+            |ev: OriginalSyncPollStartEvent, room: SdkRoom, Ctx(me): Ctx<ConvoController>| async move {
+                me.process_poll_start(ev, &room);
+            },
+        );
+        self.poll_start_event_handle = Some(handle);
+
+        client.add_event_handler_context(me.clone());
+        let handle =
+            client.add_event_handler(
+                |ev: OriginalSyncPollResponseEvent,
+                 room: SdkRoom,
+                 Ctx(me): Ctx<ConvoController>| async move {
+                    me.process_poll_response(ev, &room);
+                },
+            );
+        self.poll_response_event_handle = Some(handle);
+
+        client.add_event_handler_context(me.clone());
+        let handle = client.add_event_handler(
+            |ev: OriginalSyncPollEndEvent, room: SdkRoom, Ctx(me): Ctx<ConvoController>| async move {
+                me.process_poll_end(ev, &room);
+            },
+        );
+        self.poll_end_event_handle = Some(handle);
+
+        client.add_event_handler_context(me);
+        let handle = client.add_event_handler(
+            |ev: StarredMessagesEvent, Ctx(me): Ctx<ConvoController>| async move {
+                let _ = me.starred_changed.send(());
+            },
+        );
+        self.starred_event_handle = Some(handle);
     }
 
     pub fn remove_event_handler(&mut self, client: &SdkClient) {
@@ -231,6 +580,26 @@ impl ConvoController {
             client.remove_event_handler(handle);
             self.redaction_event_handle = None;
         }
+        if let Some(handle) = self.pinned_event_handle.clone() {
+            client.remove_event_handler(handle);
+            self.pinned_event_handle = None;
+        }
+        if let Some(handle) = self.starred_event_handle.clone() {
+            client.remove_event_handler(handle);
+            self.starred_event_handle = None;
+        }
+        if let Some(handle) = self.poll_start_event_handle.clone() {
+            client.remove_event_handler(handle);
+            self.poll_start_event_handle = None;
+        }
+        if let Some(handle) = self.poll_response_event_handle.clone() {
+            client.remove_event_handler(handle);
+            self.poll_response_event_handle = None;
+        }
+        if let Some(handle) = self.poll_end_event_handle.clone() {
+            client.remove_event_handler(handle);
+            self.poll_end_event_handle = None;
+        }
     }
 
     pub async fn load_rooms(&mut self, convos: &Vec<Convo>) {
@@ -241,6 +610,7 @@ impl ConvoController {
             rooms.push(convo);
         }
         self.convos.lock_mut().clone_from(&rooms);
+        self.update_unread_summary(&rooms);
     }
 
     // reorder room list on OriginalSyncRoomEncryptedEvent
@@ -266,7 +636,7 @@ impl ConvoController {
                     convo.set_latest_message(msg);
                     convos.insert(0, convo);
                 } else {
-                    let mut convo = Convo::new(self.clone(), Room { room: room.clone() });
+                    let mut convo = Convo::new(self.clone(), self.room(room));
                     convo.set_latest_message(msg);
                     convos.insert(0, convo);
                 }
@@ -274,6 +644,61 @@ impl ConvoController {
         }
     }
 
+    fn process_poll_start(&self, ev: OriginalSyncPollStartEvent, room: &SdkRoom) {
+        let poll_id = ev.event_id.clone();
+        if self.polls.start(room.room_id(), ev) {
+            // nobody listening is fine
+            let _ = self
+                .polls_changed
+                .send((room.room_id().to_owned(), poll_id));
+        }
+    }
+
+    fn process_poll_response(&self, ev: OriginalSyncPollResponseEvent, room: &SdkRoom) {
+        if let Some(poll_id) = self.polls.respond(&ev) {
+            let _ = self
+                .polls_changed
+                .send((room.room_id().to_owned(), poll_id));
+        }
+    }
+
+    fn process_poll_end(&self, ev: OriginalSyncPollEndEvent, room: &SdkRoom) {
+        if let Some(poll_id) = self.polls.end(&ev) {
+            let _ = self
+                .polls_changed
+                .send((room.room_id().to_owned(), poll_id));
+        }
+    }
+
+    // the server can't search encrypted messages, so we remember them
+    async fn index_room_message(
+        &self,
+        ev: &OriginalSyncRoomMessageEvent,
+        raw: RawEvent,
+        room: &SdkRoom,
+    ) {
+        let sent_by_me = room.client().user_id() == Some(&ev.sender);
+        let event = Raw::from_json(raw.0);
+        let room_id = room.room_id();
+        if let Err(error) = self.message_index.add(room_id, ev, event, sent_by_me).await {
+            error!(?error, ?room_id, "indexing the message failed");
+        }
+    }
+
+    async fn unindex_room_message(&self, ev: &SyncRoomRedactionEvent, room: &SdkRoom) {
+        let SyncRoomRedactionEvent::Original(o) = ev else {
+            return;
+        };
+        let room_id = room.room_id();
+        if let Err(error) = self.message_index.remove(room_id, &o.redacts).await {
+            error!(
+                ?error,
+                ?room_id,
+                "removing the message from the index failed"
+            );
+        }
+    }
+
     // reorder room list on OriginalSyncRoomMessageEvent
     fn process_room_message(
         &mut self,
@@ -290,17 +715,27 @@ impl ConvoController {
             } else {
                 false
             };
+            let thread_root = match &ev.content.relates_to {
+                Some(Relation::Thread(thread)) => Some(thread.event_id.clone()),
+                _ => None,
+            };
+            let main_event_id = thread_root.is_none().then(|| ev.event_id.clone());
             let msg = RoomMessage::room_message_from_sync_event(ev, room_id.to_owned(), sent_by_me);
+            let msg = self.decorate(msg, room);
 
-            if let Some(idx) = convos.iter().position(|x| x.room_id() == room_id) {
-                let mut convo = convos.remove(idx);
-                convo.set_latest_message(msg);
-                convos.insert(0, convo);
+            let mut convo = if let Some(idx) = convos.iter().position(|x| x.room_id() == room_id) {
+                convos.remove(idx)
             } else {
-                let mut convo = Convo::new(self.clone(), Room { room: room.clone() });
-                convo.set_latest_message(msg);
-                convos.insert(0, convo);
+                Convo::new(self.clone(), self.room(room))
+            };
+            if let (false, Some(thread_root)) = (sent_by_me, thread_root) {
+                convo.count_thread_unread(thread_root);
+            }
+            if main_event_id.is_some() {
+                convo.latest_event_id = main_event_id;
             }
+            convo.set_latest_message(msg);
+            convos.insert(0, convo);
         }
     }
 
@@ -324,7 +759,7 @@ impl ConvoController {
                     convo.set_latest_message(msg);
                     convos.insert(0, convo);
                 } else {
-                    let mut convo = Convo::new(self.clone(), Room { room: room.clone() });
+                    let mut convo = Convo::new(self.clone(), self.room(room));
                     convo.set_latest_message(msg);
                     convos.insert(0, convo);
                 }
@@ -341,7 +776,7 @@ impl ConvoController {
                     // anyway i prevent this event from being called twice
                     if !convos.iter().any(|x| x.room_id() == room_id) {
                         // add new room
-                        let convo = Convo::new(self.clone(), Room { room: room.clone() });
+                        let convo = Convo::new(self.clone(), self.room(room));
                         convos.insert(0, convo);
                     }
                 }
@@ -374,13 +809,68 @@ impl ConvoController {
                 convo.set_latest_message(msg);
                 convos.insert(0, convo);
             } else {
-                let mut convo = Convo::new(self.clone(), Room { room: room.clone() });
+                let mut convo = Convo::new(self.clone(), self.room(room));
                 convo.set_latest_message(msg);
                 convos.insert(0, convo);
             }
         }
     }
 
+    fn update_unread_summary(&self, convos: &[Convo]) {
+        let unread = convos.iter().filter(|c| c.unread > 0);
+        self.unread_summary.set_neq(UnreadSummary {
+            convos: unread.clone().count() as u64,
+            messages: unread.clone().map(|c| c.unread).sum(),
+            highlights: unread.map(|c| c.highlights).sum(),
+        });
+    }
+
+    /// Take over the unread counts the server sent with the last sync
+    pub(crate) fn refresh_unread(&self) {
+        // every write to the convos fires their signal, only do it if needed
+        if self.convos.lock_ref().iter().any(|c| c.unread_changed()) {
+            let mut convos = self.convos.lock_mut();
+            for convo in convos.iter_mut().filter(|c| c.unread_changed()) {
+                convo.update_unread();
+            }
+        }
+        self.update_unread_summary(&self.convos.lock_ref());
+    }
+
+    pub(crate) fn latest_event_id(&self, room_id: &RoomId) -> Option<OwnedEventId> {
+        self.convos
+            .lock_ref()
+            .iter()
+            .find(|x| x.room_id() == room_id)
+            .and_then(|x| x.latest_event_id.clone())
+    }
+
+    /// We read up to `event_ids`, e.g. on another device. Only if one is the
+    /// latest message all is read, otherwise the next sync has the counts.
+    pub(crate) fn read_up_to(&self, room_id: &RoomId, event_ids: &[OwnedEventId]) {
+        if reads_latest(self.latest_event_id(room_id).as_ref(), event_ids) {
+            self.clear_unread(room_id);
+        }
+    }
+
+    /// We read everything in that room
+    pub(crate) fn clear_unread(&self, room_id: &RoomId) {
+        {
+            let mut convos = self.convos.lock_mut();
+            if let Some(convo) = convos.iter_mut().find(|x| x.room_id() == room_id) {
+                convo.clear_unread();
+            }
+        }
+        self.update_unread_summary(&self.convos.lock_ref());
+    }
+
+    fn clear_thread_unread(&self, room_id: &RoomId, thread_root: &EventId) {
+        let mut convos = self.convos.lock_mut();
+        if let Some(convo) = convos.iter_mut().find(|x| x.room_id() == room_id) {
+            convo.unread_threads.remove(thread_root);
+        }
+    }
+
     pub(crate) fn update_latest_message(&mut self, room: &SdkRoom, msg: RoomMessage) {
         if let SdkRoom::Joined(joined) = room {
             let mut convos = self.convos.lock_mut();
@@ -390,7 +880,7 @@ impl ConvoController {
                 let mut convo = convos.get_mut(idx).unwrap();
                 convo.set_latest_message(msg);
             } else {
-                let mut convo = Convo::new(self.clone(), Room { room: room.clone() });
+                let mut convo = Convo::new(self.clone(), self.room(room));
                 convo.set_latest_message(msg);
                 convos.insert(0, convo);
             }
@@ -398,6 +888,12 @@ impl ConvoController {
     }
 }
 
+/// A message of the main timeline, not a state event or a thread reply
+fn is_main_timeline_message(event: &Raw<AnySyncTimelineEvent>) -> bool {
+    matches!(event.get_field::<serde_json::Value>("state_key"), Ok(None))
+        && thread_root(event).is_none()
+}
+
 #[derive(Builder, Default, Clone)]
 pub struct CreateConvoSettings {
     #[builder(setter(into, strip_option), default)]
@@ -564,4 +1060,16 @@ impl Client {
     pub fn convos_rx(&self) -> SignalStream<MutableSignalCloned<Vec<Convo>>> {
         self.convo_controller.convos.signal_cloned().to_stream()
     }
+
+    pub fn unread_summary(&self) -> UnreadSummary {
+        self.convo_controller.unread_summary.get_cloned()
+    }
+
+    /// Fires whenever the unread counts of the convos changed, for badges
+    pub fn unread_summary_rx(&self) -> SignalStream<MutableSignalCloned<UnreadSummary>> {
+        self.convo_controller
+            .unread_summary
+            .signal_cloned()
+            .to_stream()
+    }
 }
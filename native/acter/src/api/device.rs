@@ -3,8 +3,12 @@ use futures::{
     channel::mpsc::{channel, Receiver, Sender},
     stream::StreamExt,
 };
-use matrix_sdk::{ruma::device_id, sync::SyncResponse, Client as SdkClient};
+use matrix_sdk::{
+    ruma::{device_id, OwnedDeviceId},
+    Client as SdkClient,
+};
 use std::{
+    collections::HashSet,
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -172,6 +176,8 @@ pub(crate) struct DeviceController {
     changed_event_rx: Arc<Mutex<Option<Receiver<DeviceChangedEvent>>>>,
     left_event_tx: Sender<DeviceLeftEvent>,
     left_event_rx: Arc<Mutex<Option<Receiver<DeviceLeftEvent>>>>,
+    /// our devices after the last sync
+    known_devices: Arc<Mutex<Option<HashSet<OwnedDeviceId>>>>,
 }
 
 impl DeviceController {
@@ -183,41 +189,44 @@ impl DeviceController {
             changed_event_rx: Arc::new(Mutex::new(Some(changed_event_rx))),
             left_event_tx,
             left_event_rx: Arc::new(Mutex::new(Some(left_event_rx))),
+            known_devices: Default::default(),
         }
     }
 
-    pub fn process_device_lists(&mut self, client: &SdkClient, response: &SyncResponse) {
-        info!("process device lists: {:?}", response);
+    /// Compare our devices as the crypto store knows them with what we saw
+    /// after the last sync. The sliding sync doesn't hand out the device
+    /// lists, this way the full and the sliding sync are treated alike.
+    pub async fn process_own_devices(&self, client: &SdkClient) {
+        let Some(user_id) = client.user_id() else {
+            return; // guests don't have devices to watch
+        };
+        let devices = match client.encryption().get_user_devices(user_id).await {
+            Ok(devices) => devices
+                .devices()
+                .filter(|d| !d.is_deleted())
+                .map(|d| d.device_id().to_owned())
+                .collect::<HashSet<_>>(),
+            Err(e) => {
+                error!("Couldn't get crypto devices: {}", e);
+                return;
+            }
+        };
+        let Some(previous) = self.known_devices.lock().await.replace(devices.clone()) else {
+            return; // the first look, nothing to compare with
+        };
 
-        // avoid device changed event in case that user joined room
-        if response.rooms.join.is_empty() {
-            let current_user_id = client
-                .user_id()
-                .expect("guest user cannot handle the device changed event");
-            for user_id in response.device_lists.changed.clone().into_iter() {
-                info!("device-changed user_id: {}", user_id);
-                if *user_id == *current_user_id {
-                    let evt = DeviceChangedEvent::new(client);
-                    if let Err(e) = self.changed_event_tx.try_send(evt) {
-                        error!("Dropping devices changed event: {}", e);
-                    }
-                }
+        if devices.difference(&previous).next().is_some() {
+            info!("device-changed user_id: {}", user_id);
+            let evt = DeviceChangedEvent::new(client);
+            if let Err(e) = self.changed_event_tx.clone().try_send(evt) {
+                error!("Dropping devices changed event: {}", e);
             }
         }
-
-        // avoid device left event in case that user left room
-        if response.rooms.leave.is_empty() {
-            let current_user_id = client
-                .user_id()
-                .expect("guest user cannot handle the device left event");
-            for user_id in response.device_lists.left.clone().into_iter() {
-                info!("device-left user_id: {}", user_id);
-                if *user_id == *current_user_id {
-                    let evt = DeviceLeftEvent::new(client);
-                    if let Err(e) = self.left_event_tx.try_send(evt) {
-                        error!("Dropping devices left event: {}", e);
-                    }
-                }
+        if previous.difference(&devices).next().is_some() {
+            info!("device-left user_id: {}", user_id);
+            let evt = DeviceLeftEvent::new(client);
+            if let Err(e) = self.left_event_tx.clone().try_send(evt) {
+                error!("Dropping devices left event: {}", e);
             }
         }
     }
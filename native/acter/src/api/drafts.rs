@@ -0,0 +1,210 @@
+use acter_core::{store::Store, Error};
+use anyhow::Result;
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use serde::{Deserialize, Serialize};
+
+use super::{convo::Convo, RUNTIME};
+
+/// A file picked for the message, not uploaded yet
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DraftAttachment {
+    uri: String,
+    name: String,
+    mimetype: String,
+    size: Option<u32>,
+}
+
+impl DraftAttachment {
+    /// the local path of the file
+    pub fn uri(&self) -> String {
+        self.uri.clone()
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn mimetype(&self) -> String {
+        self.mimetype.clone()
+    }
+
+    pub fn size(&self) -> Option<u32> {
+        self.size
+    }
+}
+
+/// What was typed into the composer of a convo but not sent yet
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ComposeDraft {
+    text: String,
+    markdown: bool,
+    reply_to: Option<OwnedEventId>,
+    edit_of: Option<OwnedEventId>,
+    #[serde(default)]
+    attachments: Vec<DraftAttachment>,
+}
+
+impl ComposeDraft {
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+
+    /// whether the text is markdown rather than plain
+    pub fn is_markdown(&self) -> bool {
+        self.markdown
+    }
+
+    pub fn reply_to(&self) -> Option<String> {
+        self.reply_to.as_ref().map(|e| e.to_string())
+    }
+
+    /// the message being edited, if any
+    pub fn edit_of(&self) -> Option<String> {
+        self.edit_of.as_ref().map(|e| e.to_string())
+    }
+
+    pub fn attachments(&self) -> Vec<DraftAttachment> {
+        self.attachments.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty() && self.attachments.is_empty()
+    }
+
+    pub fn set_text(&mut self, text: String, markdown: bool) {
+        self.text = text;
+        self.markdown = markdown;
+    }
+
+    pub fn set_reply_to(&mut self, event_id: Option<String>) -> Result<()> {
+        self.reply_to = event_id.map(EventId::parse).transpose()?;
+        Ok(())
+    }
+
+    pub fn set_edit_of(&mut self, event_id: Option<String>) -> Result<()> {
+        self.edit_of = event_id.map(EventId::parse).transpose()?;
+        Ok(())
+    }
+
+    pub fn add_attachment(
+        &mut self,
+        uri: String,
+        name: String,
+        mimetype: String,
+        size: Option<u32>,
+    ) {
+        self.attachments.push(DraftAttachment {
+            uri,
+            name,
+            mimetype,
+            size,
+        });
+    }
+
+    pub fn clear_attachments(&mut self) {
+        self.attachments.clear();
+    }
+}
+
+pub fn new_compose_draft(text: String, markdown: bool) -> ComposeDraft {
+    ComposeDraft {
+        text,
+        markdown,
+        ..Default::default()
+    }
+}
+
+/// the draft kept under the key, an empty one clears it
+async fn store_draft(store: &Store, key: &str, draft: &ComposeDraft) -> Result<()> {
+    if draft.is_empty() {
+        // the store can't forget keys, an empty one counts as none
+        store.set_raw(key, &None::<ComposeDraft>).await?;
+    } else {
+        store.set_raw(key, &Some(draft)).await?;
+    }
+    Ok(())
+}
+
+async fn load_draft(store: &Store, key: &str) -> Result<Option<ComposeDraft>> {
+    match store.get_raw::<Option<ComposeDraft>>(key).await {
+        Ok(draft) => Ok(draft),
+        Err(Error::ModelNotFound) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+impl Convo {
+    fn draft_key(&self) -> String {
+        format!("{}::draft", self.room_id())
+    }
+
+    /// keep the draft of this convo, replacing the one saved before. An
+    /// empty draft clears it.
+    pub async fn save_draft(&self, draft: Box<ComposeDraft>) -> Result<bool> {
+        let store = self.controller.store();
+        let key = self.draft_key();
+        RUNTIME
+            .spawn(async move {
+                store_draft(&store, &key, &draft).await?;
+                Ok(true)
+            })
+            .await?
+    }
+
+    /// the draft saved for this convo, if any
+    pub async fn draft(&self) -> Result<Option<ComposeDraft>> {
+        let store = self.controller.store();
+        let key = self.draft_key();
+        RUNTIME
+            .spawn(async move { load_draft(&store, &key).await })
+            .await?
+    }
+
+    pub async fn clear_draft(&self) -> Result<bool> {
+        let store = self.controller.store();
+        let key = self.draft_key();
+        RUNTIME
+            .spawn(async move {
+                store_draft(&store, &key, &ComposeDraft::default()).await?;
+                Ok(true)
+            })
+            .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fresh_store;
+
+    #[tokio::test]
+    async fn draft_round_trips() -> Result<()> {
+        let store = fresh_store().await?;
+        let key = "!room:example.org::draft";
+        assert!(load_draft(&store, key).await?.is_none());
+
+        let mut draft = new_compose_draft("hello *world*".to_owned(), true);
+        draft.set_reply_to(Some("$reply:example.org".to_owned()))?;
+        draft.add_attachment(
+            "file:///tmp/cat.png".to_owned(),
+            "cat.png".to_owned(),
+            "image/png".to_owned(),
+            Some(42),
+        );
+        store_draft(&store, key, &draft).await?;
+
+        let saved = load_draft(&store, key).await?.expect("the draft was saved");
+        assert_eq!(saved.text(), "hello *world*");
+        assert!(saved.is_markdown());
+        assert_eq!(saved.reply_to().as_deref(), Some("$reply:example.org"));
+        assert_eq!(saved.edit_of(), None);
+        let attachments = saved.attachments();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].name(), "cat.png");
+        assert_eq!(attachments[0].size(), Some(42));
+
+        store_draft(&store, key, &ComposeDraft::default()).await?;
+        assert!(load_draft(&store, key).await?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::Result;
+use matrix_sdk::{
+    media::{MediaFormat, MediaRequest},
+    ruma::{api::client::media::get_media_preview, events::room::MediaSource, OwnedMxcUri},
+    Client as SdkClient,
+};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tracing::{trace, warn};
+use url::Url;
+
+use super::{client::Client, common::OptionBuffer, RUNTIME};
+
+/// how many previews we keep around
+const CACHE_SIZE: usize = 200;
+
+/// The open graph data the homeserver found on the page
+#[derive(Deserialize)]
+struct OpenGraph {
+    #[serde(rename = "og:title")]
+    title: Option<String>,
+    #[serde(rename = "og:description")]
+    description: Option<String>,
+    #[serde(rename = "og:site_name")]
+    site_name: Option<String>,
+    /// the homeserver keeps a copy of the image for us
+    #[serde(rename = "og:image")]
+    image: Option<String>,
+}
+
+/// What the linked page is about
+#[derive(Clone, Debug)]
+pub struct LinkPreview {
+    url: String,
+    title: Option<String>,
+    description: Option<String>,
+    site_name: Option<String>,
+    image: Option<Vec<u8>>,
+}
+
+impl LinkPreview {
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.title.clone()
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    pub fn site_name(&self) -> Option<String> {
+        self.site_name.clone()
+    }
+
+    pub fn image(&self) -> OptionBuffer {
+        OptionBuffer::new(self.image.clone())
+    }
+}
+
+/// The web links in the text, in order of appearance
+pub(crate) fn find_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        let word = word
+            .trim_start_matches(['(', '<', '"', '\''])
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'']);
+        if !word.starts_with("https://") && !word.starts_with("http://") {
+            continue;
+        }
+        if let Ok(url) = Url::parse(word) {
+            let url = url.to_string();
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// Fetches link previews through the homeserver, so the linked sites don't
+/// learn about us, and keeps the latest ones around
+#[derive(Clone)]
+pub struct LinkPreviews {
+    client: SdkClient,
+    cache: Arc<Mutex<VecDeque<(String, Option<LinkPreview>)>>>,
+}
+
+impl std::fmt::Debug for LinkPreviews {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkPreviews").finish_non_exhaustive()
+    }
+}
+
+impl LinkPreviews {
+    pub fn new(client: SdkClient) -> Self {
+        LinkPreviews {
+            client,
+            cache: Default::default(),
+        }
+    }
+
+    fn cached(&self, url: &str) -> Option<Option<LinkPreview>> {
+        let cache = self.cache.lock().expect("link preview cache poisoned");
+        cache
+            .iter()
+            .find(|(cached, _)| cached == url)
+            .map(|(_, preview)| preview.clone())
+    }
+
+    fn remember(&self, url: String, preview: Option<LinkPreview>) {
+        let mut cache = self.cache.lock().expect("link preview cache poisoned");
+        cache.retain(|(cached, _)| cached != &url);
+        if cache.len() >= CACHE_SIZE {
+            cache.pop_front();
+        }
+        cache.push_back((url, preview));
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Option<LinkPreview>> {
+        let request = get_media_preview::v3::Request::new(url.to_owned());
+        let response = self.client.send(request, None).await?;
+        let Some(data) = response.data else {
+            return Ok(None);
+        };
+        let og = serde_json::from_str::<OpenGraph>(data.get())?;
+        if og.title.is_none() && og.description.is_none() {
+            // nothing worth showing
+            return Ok(None);
+        }
+        let image_uri = og.image.map(OwnedMxcUri::from).filter(|u| u.is_valid());
+        let image = match image_uri {
+            Some(uri) => {
+                let request = MediaRequest {
+                    source: MediaSource::Plain(uri),
+                    format: MediaFormat::File,
+                };
+                match self.client.media().get_media_content(&request, true).await {
+                    Ok(buf) => Some(buf),
+                    Err(error) => {
+                        warn!(?error, url, "fetching preview image failed");
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        Ok(Some(LinkPreview {
+            url: url.to_owned(),
+            title: og.title,
+            description: og.description,
+            site_name: og.site_name,
+            image,
+        }))
+    }
+
+    /// `None` if the page has nothing to show. Failures are not cached,
+    /// asking again tries again.
+    pub async fn preview(&self, url: String) -> Result<Option<LinkPreview>> {
+        if let Some(preview) = self.cached(&url) {
+            trace!(url, "link preview from cache");
+            return Ok(preview);
+        }
+        let me = self.clone();
+        RUNTIME
+            .spawn(async move {
+                let preview = me.fetch(&url).await?;
+                me.remember(url, preview.clone());
+                Ok(preview)
+            })
+            .await?
+    }
+}
+
+impl Client {
+    /// the preview of the web page, as the homeserver sees it
+    pub async fn link_preview(&self, url: String) -> Result<Option<LinkPreview>> {
+        self.link_previews.preview(url).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_web_links_in_order() {
+        let urls = find_urls("see https://acter.global/ and http://example.org/a?b=c too");
+        assert_eq!(urls, ["https://acter.global/", "http://example.org/a?b=c"]);
+    }
+
+    #[test]
+    fn trims_surrounding_punctuation() {
+        let urls = find_urls(
+            "(https://example.org/a), <https://example.org/b>. \"https://example.org/c\"!",
+        );
+        assert_eq!(
+            urls,
+            [
+                "https://example.org/a",
+                "https://example.org/b",
+                "https://example.org/c"
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_each_link_once() {
+        let urls = find_urls("https://example.org/ https://example.org/, https://example.org");
+        assert_eq!(urls, ["https://example.org/"]);
+    }
+
+    #[test]
+    fn ignores_other_schemes_and_broken_links() {
+        assert!(find_urls("mailto:me@example.org ftp://example.org https:// http").is_empty());
+        assert!(find_urls("no links here").is_empty());
+    }
+}
@@ -1,3 +1,9 @@
+use acter_core::events::{
+    polls::{OriginalSyncPollStartEvent, PollResults, PollStartEventContent},
+    voice::VoiceContent,
+    InThread,
+};
+use anyhow::Result;
 use chrono::{DateTime, Utc};
 use core::time::Duration;
 use matrix_sdk::{
@@ -74,24 +80,116 @@ use matrix_sdk::{
                 parent::{OriginalSpaceParentEvent, OriginalSyncSpaceParentEvent},
             },
             sticker::{OriginalStickerEvent, OriginalSyncStickerEvent},
-            AnySyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
-            SyncStateEvent,
+            AnySyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent, StaticEventContent,
+            SyncMessageLikeEvent, SyncStateEvent,
         },
         serde::Raw,
-        OwnedEventId, OwnedRoomId, OwnedUserId,
+        OwnedEventId, OwnedRoomId, OwnedUserId, UserId,
     },
 };
 use matrix_sdk_ui::timeline::{
     EventSendState, EventTimelineItem, MembershipChange, TimelineItem, TimelineItemContent,
     VirtualTimelineItem,
 };
+use serde::Deserialize;
 use std::{collections::HashMap, sync::Arc};
 use tracing::info;
 
-use super::common::{
-    AudioDesc, FileDesc, ImageDesc, LocationDesc, ReactionRecord, TextDesc, VideoDesc,
+use super::{
+    common::{AudioDesc, FileDesc, ImageDesc, LocationDesc, ReactionRecord, TextDesc, VideoDesc},
+    link_previews::{find_urls, LinkPreview, LinkPreviews},
+    polls::PollDesc,
 };
 
+/// The replies to a thread root, as bundled by the server
+#[derive(Clone, Debug)]
+pub struct ThreadSummary {
+    count: u64,
+    latest_reply: Option<Box<RoomMessage>>,
+    participants: Vec<String>,
+}
+
+impl ThreadSummary {
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn latest_reply(&self) -> Option<RoomMessage> {
+        self.latest_reply.as_deref().cloned()
+    }
+
+    /// who replied, as far as we know: the sender of the latest reply and
+    /// ourselves if we took part
+    pub fn participants(&self) -> Vec<String> {
+        self.participants.clone()
+    }
+}
+
+#[derive(Deserialize)]
+struct ThreadedContent {
+    #[serde(rename = "m.relates_to")]
+    relates_to: InThread,
+}
+
+#[derive(Deserialize)]
+struct BundledThread {
+    latest_event: Raw<AnySyncTimelineEvent>,
+    count: u64,
+    current_user_participated: bool,
+}
+
+#[derive(Deserialize)]
+struct ThreadRelations {
+    #[serde(rename = "m.thread")]
+    thread: BundledThread,
+}
+
+#[derive(Deserialize)]
+struct ThreadUnsigned {
+    #[serde(rename = "m.relations")]
+    relations: ThreadRelations,
+}
+
+/// The root of the thread the event was sent in, if any
+pub(crate) fn thread_root(event: &Raw<AnySyncTimelineEvent>) -> Option<OwnedEventId> {
+    let content = event.get_field::<ThreadedContent>("content").ok()??;
+    Some(content.relates_to.event_id)
+}
+
+/// The latest reply the server bundled with the thread root
+pub(crate) fn latest_thread_event(
+    event: &Raw<AnySyncTimelineEvent>,
+) -> Option<Raw<AnySyncTimelineEvent>> {
+    let unsigned = event.get_field::<ThreadUnsigned>("unsigned").ok()??;
+    Some(unsigned.relations.thread.latest_event)
+}
+
+fn thread_summary(
+    event: &Raw<AnySyncTimelineEvent>,
+    room_id: &OwnedRoomId,
+    my_id: Option<&UserId>,
+) -> Option<ThreadSummary> {
+    let thread = event
+        .get_field::<ThreadUnsigned>("unsigned")
+        .ok()??
+        .relations
+        .thread;
+    let mut participants = vec![];
+    if let Ok(Some(sender)) = thread.latest_event.get_field::<OwnedUserId>("sender") {
+        participants.push(sender.to_string());
+    }
+    if let (true, Some(me)) = (thread.current_user_participated, my_id) {
+        if !participants.contains(&me.to_string()) {
+            participants.push(me.to_string());
+        }
+    }
+    Some(ThreadSummary {
+        count: thread.count,
+        latest_reply: sync_event_to_message(&thread.latest_event, room_id.clone()).map(Box::new),
+        participants,
+    })
+}
+
 #[derive(Clone, Debug)]
 pub struct RoomEventItem {
     event_id: String,
@@ -105,7 +203,12 @@ pub struct RoomEventItem {
     video_desc: Option<VideoDesc>,
     file_desc: Option<FileDesc>,
     location_desc: Option<LocationDesc>,
+    poll_desc: Option<PollDesc>,
+    // where to get the previews of the links in the text
+    link_previews: Option<LinkPreviews>,
     in_reply_to: Option<OwnedEventId>,
+    thread_root: Option<OwnedEventId>,
+    thread_summary: Option<ThreadSummary>,
     reactions: HashMap<String, Vec<ReactionRecord>>,
     editable: bool,
 }
@@ -124,7 +227,11 @@ impl RoomEventItem {
             video_desc: None,
             file_desc: None,
             location_desc: None,
+            poll_desc: None,
+            link_previews: None,
             in_reply_to: None,
+            thread_root: None,
+            thread_summary: None,
             reactions: Default::default(),
             editable: false,
         }
@@ -162,6 +269,30 @@ impl RoomEventItem {
         self.text_desc = Some(value);
     }
 
+    /// the web links in the text, to fetch their previews with
+    pub fn link_urls(&self) -> Vec<String> {
+        self.text_desc
+            .as_ref()
+            .map(|desc| find_urls(&desc.body()))
+            .unwrap_or_default()
+    }
+
+    /// the preview of the first web link in the text. Only messages of the
+    /// timeline, threads, pins and stars know where to get it.
+    pub async fn link_preview(&self) -> Result<Option<LinkPreview>> {
+        let Some(link_previews) = self.link_previews.clone() else {
+            return Ok(None);
+        };
+        let Some(url) = self.link_urls().into_iter().next() else {
+            return Ok(None);
+        };
+        link_previews.preview(url).await
+    }
+
+    pub(crate) fn set_link_previews(&mut self, value: LinkPreviews) {
+        self.link_previews = Some(value);
+    }
+
     pub fn image_desc(&self) -> Option<ImageDesc> {
         self.image_desc.clone()
     }
@@ -202,6 +333,15 @@ impl RoomEventItem {
         self.location_desc = Some(value);
     }
 
+    /// the poll with its votes, if this event started one
+    pub fn poll_desc(&self) -> Option<PollDesc> {
+        self.poll_desc.clone()
+    }
+
+    pub(crate) fn set_poll_desc(&mut self, value: PollDesc) {
+        self.poll_desc = Some(value);
+    }
+
     pub fn in_reply_to(&self) -> Option<String> {
         self.in_reply_to.as_ref().map(|x| x.to_string())
     }
@@ -210,6 +350,52 @@ impl RoomEventItem {
         self.in_reply_to = Some(value);
     }
 
+    /// the root of the thread this was sent in
+    pub fn thread_root(&self) -> Option<String> {
+        self.thread_root.as_ref().map(|x| x.to_string())
+    }
+
+    /// the replies, if this is the root of a thread
+    pub fn thread_summary(&self) -> Option<ThreadSummary> {
+        self.thread_summary.clone()
+    }
+
+    fn set_thread_info(
+        &mut self,
+        event: &Raw<AnySyncTimelineEvent>,
+        room_id: &OwnedRoomId,
+        my_id: Option<&UserId>,
+    ) {
+        self.thread_root = thread_root(event);
+        self.thread_summary = thread_summary(event, room_id, my_id);
+    }
+
+    /// ruma drops the voice message fields, so take them from the raw event
+    fn set_voice_info(&mut self, event: &Raw<AnySyncTimelineEvent>) {
+        let Some(audio_desc) = self.audio_desc.as_mut() else {
+            return;
+        };
+        if let Ok(Some(voice)) = event.get_field::<VoiceContent>("content") {
+            audio_desc.set_voice(voice);
+        }
+    }
+
+    /// the timeline doesn't know polls, so take the poll from the raw event.
+    /// Its votes come from the poll store.
+    fn set_poll_info(&mut self, event: &Raw<AnySyncTimelineEvent>, my_id: Option<&UserId>) {
+        if self.event_type != PollStartEventContent::TYPE {
+            return;
+        }
+        let Ok(ev) = event.deserialize_as::<OriginalSyncPollStartEvent>() else {
+            return;
+        };
+        if self.text_desc.is_none() {
+            self.set_text_desc(TextDesc::new(ev.content.text.clone(), None));
+        }
+        let results = PollResults::new(ev.sender, ev.content.poll);
+        self.set_poll_desc(PollDesc::new(&results, my_id));
+    }
+
     pub(crate) fn add_reaction(&mut self, key: String, records: Vec<ReactionRecord>) {
         self.reactions.insert(key, records);
     }
@@ -527,6 +713,23 @@ impl RoomMessage {
         RoomMessage::new_event_item(room_id, event_item)
     }
 
+    pub(crate) fn poll_start_from_sync_event(
+        event: OriginalSyncPollStartEvent,
+        room_id: OwnedRoomId,
+        results: &PollResults,
+        my_id: Option<&UserId>,
+    ) -> Self {
+        let mut event_item = RoomEventItem::new(
+            event.event_id.to_string(),
+            event.sender.to_string(),
+            event.origin_server_ts.get().into(),
+            PollStartEventContent::TYPE.to_string(),
+        );
+        event_item.set_text_desc(TextDesc::new(event.content.text, None));
+        event_item.set_poll_desc(PollDesc::new(results, my_id));
+        RoomMessage::new_event_item(room_id, event_item)
+    }
+
     pub(crate) fn reaction_from_event(event: OriginalReactionEvent, room_id: OwnedRoomId) -> Self {
         let mut event_item = RoomEventItem::new(
             event.event_id.to_string(),
@@ -1663,7 +1866,7 @@ impl RoomMessage {
         let client = room.client();
         let my_user_id = client.user_id();
 
-        let event_item = match event.content() {
+        let mut event_item = match event.content() {
             TimelineItemContent::Message(msg) => {
                 let msg_type = msg.msgtype();
                 let mut result = RoomEventItem::new(
@@ -1948,6 +2151,11 @@ impl RoomMessage {
                 RoomEventItem::new(event_id, sender, origin_server_ts, event_type.to_string())
             }
         };
+        if let Some(raw) = event.original_json() {
+            event_item.set_thread_info(raw, &room_id, my_user_id);
+            event_item.set_voice_info(raw);
+            event_item.set_poll_info(raw, my_user_id);
+        }
         RoomMessage::new_event_item(room_id, event_item)
     }
 
@@ -1995,8 +2203,29 @@ impl RoomMessage {
 pub(crate) fn sync_event_to_message(
     event: &Raw<AnySyncTimelineEvent>,
     room_id: OwnedRoomId,
+) -> Option<RoomMessage> {
+    let mut msg = sync_event_to_message_inner(event, room_id.clone())?;
+    if let Some(event_item) = msg.event_item.as_mut() {
+        event_item.set_thread_info(event, &room_id, None);
+        event_item.set_voice_info(event);
+    }
+    Some(msg)
+}
+
+fn sync_event_to_message_inner(
+    event: &Raw<AnySyncTimelineEvent>,
+    room_id: OwnedRoomId,
 ) -> Option<RoomMessage> {
     info!("sync event to message: {:?}", event);
+    // not known to ruma, so never part of the match below
+    let event_type = event.get_field::<String>("type").ok().flatten();
+    if event_type.as_deref() == Some(PollStartEventContent::TYPE) {
+        let ev = event.deserialize_as::<OriginalSyncPollStartEvent>().ok()?;
+        let results = PollResults::new(ev.sender.clone(), ev.content.poll.clone());
+        return Some(RoomMessage::poll_start_from_sync_event(
+            ev, room_id, &results, None,
+        ));
+    }
     match event.deserialize() {
         Ok(AnySyncTimelineEvent::State(AnySyncStateEvent::PolicyRuleRoom(
             SyncStateEvent::Original(e),
@@ -38,6 +38,7 @@ use super::{
     api::FfiBuffer,
     client::Client,
     common::{AudioDesc, FileDesc, ImageDesc, LocationDesc, VideoDesc},
+    link_previews::{find_urls, LinkPreview},
     spaces::Space,
     RUNTIME,
 };
@@ -186,6 +187,17 @@ impl NewsSlide {
         )
     }
 
+    /// the preview of the first web link in a text slide
+    pub async fn link_preview(&self) -> Result<Option<LinkPreview>> {
+        let NewsContent::Text(TextMessageEventContent { body, .. }) = self.inner.content() else {
+            return Ok(None);
+        };
+        let Some(url) = find_urls(body).into_iter().next() else {
+            return Ok(None);
+        };
+        self.client.link_preview(url).await
+    }
+
     pub fn text(&self) -> String {
         match self.inner.content() {
             NewsContent::Image(ImageMessageEventContent { body, .. }) => body.clone(),
@@ -268,6 +280,33 @@ impl NewsSlide {
                 .map(|info| LocationDesc::new(content.body, content.geo_uri))
         })
     }
+
+    pub async fn download_media(&self, dir_path: String) -> Result<String> {
+        let (source, name) = match self.inner.content() {
+            NewsContent::Image(content) => (content.source.clone(), content.body.clone()),
+            NewsContent::Audio(content) => (content.source.clone(), content.body.clone()),
+            NewsContent::Video(content) => (content.source.clone(), content.body.clone()),
+            NewsContent::File(content) => (content.source.clone(), content.body.clone()),
+            _ => bail!("This slide has no downloadable media"),
+        };
+        let client = self.client.clone();
+        RUNTIME
+            .spawn(async move {
+                let request = MediaRequest {
+                    source,
+                    format: MediaFormat::File,
+                };
+                let data = client.media().get_media_content(&request, true).await?;
+                let mut path = PathBuf::from(dir_path);
+                path.push(name);
+                std::fs::write(&path, data)?;
+                let path_text = path
+                    .to_str()
+                    .context("Path was generated from strings. Must be string")?;
+                Ok(path_text.to_string())
+            })
+            .await?
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -531,15 +570,32 @@ impl NewsEntryDraft {
         self.content.slides(slides);
 
         let room = self.room.clone();
+        let client = self.client.clone();
+
         trace!("send buildin");
         let content = self.content.build()?;
 
         trace!("off we go");
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
+            .await?
+    }
+
+    /// send it at the given time instead, in milliseconds since the unix
+    /// epoch. Returns the id to cancel it with.
+    pub async fn schedule(&mut self, send_at: u64) -> Result<String> {
+        let slides = self
+            .slides
+            .iter()
+            .map(|x| (*x.to_owned()).clone())
+            .collect();
+        self.content.slides(slides);
+
+        let room_id = self.room.room_id().to_owned();
+        let client = self.client.clone();
+        let content = self.content.build()?;
+        RUNTIME
+            .spawn(async move { client.schedule(room_id, &content, send_at).await })
             .await?
     }
 }
@@ -559,6 +615,14 @@ impl NewsEntryUpdateBuilder {
         self
     }
 
+    // replaces all slides with plain text ones
+    // for only cli, not api.rsh
+    pub fn text_slides(&mut self, bodies: Vec<String>) -> &mut Self {
+        let items = bodies.into_iter().map(news::NewsSlide::new_text).collect();
+        self.content.slides(Some(items));
+        self
+    }
+
     pub fn unset_slides(&mut self) -> &mut Self {
         self.content.slides(Some(vec![]));
         self
@@ -586,12 +650,10 @@ impl NewsEntryUpdateBuilder {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
             .await?
     }
 }
@@ -0,0 +1,141 @@
+use anyhow::{bail, Context, Result};
+use futures::{future::ready, Stream, StreamExt};
+use matrix_sdk::{
+    deserialized_responses::SyncOrStrippedState,
+    room::Room as SdkRoom,
+    ruma::{
+        events::{
+            room::pinned_events::RoomPinnedEventsEventContent, StateEventType, SyncStateEvent,
+        },
+        EventId, OwnedEventId,
+    },
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::warn;
+
+use super::{
+    convo::Convo,
+    message::{sync_event_to_message, RoomMessage},
+    RUNTIME,
+};
+
+/// the pinned event ids of the room, oldest first
+async fn pinned_events(room: &SdkRoom) -> Result<Vec<OwnedEventId>> {
+    let Some(raw) = room
+        .get_state_event_static::<RoomPinnedEventsEventContent>()
+        .await?
+    else {
+        return Ok(vec![]);
+    };
+    match raw.deserialize()? {
+        SyncOrStrippedState::Sync(SyncStateEvent::Original(ev)) => Ok(ev.content.pinned),
+        _ => Ok(vec![]),
+    }
+}
+
+impl Convo {
+    /// the event ids of the pinned messages, oldest first
+    pub async fn pinned_event_ids(&self) -> Result<Vec<String>> {
+        let room = self.room.clone();
+        RUNTIME
+            .spawn(async move {
+                let pinned = pinned_events(&room).await?;
+                Ok(pinned.into_iter().map(|e| e.to_string()).collect())
+            })
+            .await?
+    }
+
+    /// the pinned messages, oldest first. Messages we can't fetch anymore
+    /// are left out.
+    pub async fn pinned_messages(&self) -> Result<Vec<RoomMessage>> {
+        let room = self.room.clone();
+        let controller = self.controller.clone();
+        RUNTIME
+            .spawn(async move {
+                let room_id = room.room_id().to_owned();
+                let mut messages = vec![];
+                for event_id in pinned_events(&room).await? {
+                    match room.event(&event_id).await {
+                        Ok(ev) => messages.extend(
+                            sync_event_to_message(&ev.event.cast(), room_id.clone())
+                                .map(|msg| controller.decorate(msg, &room)),
+                        ),
+                        Err(error) => warn!(?error, ?event_id, "fetching pinned message failed"),
+                    }
+                }
+                Ok(messages)
+            })
+            .await?
+    }
+
+    async fn update_pinned(
+        &self,
+        event_id: String,
+        update: impl FnOnce(&mut Vec<OwnedEventId>, OwnedEventId) -> bool + Send + 'static,
+    ) -> Result<bool> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't pin messages in a room we are not in");
+        };
+        let event_id = EventId::parse(event_id)?;
+        let my_id = joined
+            .client()
+            .user_id()
+            .context("User not found")?
+            .to_owned();
+
+        RUNTIME
+            .spawn(async move {
+                let member = joined
+                    .get_member(&my_id)
+                    .await?
+                    .context("Couldn't find me among room members")?;
+                if !member.can_send_state(StateEventType::RoomPinnedEvents) {
+                    bail!("No permission to pin messages in this room");
+                }
+                let mut pinned = pinned_events(&joined).await?;
+                if !update(&mut pinned, event_id) {
+                    return Ok(false);
+                }
+                joined
+                    .send_state_event(RoomPinnedEventsEventContent::new(pinned))
+                    .await?;
+                Ok(true)
+            })
+            .await?
+    }
+
+    /// pin the message for everyone in the convo, `false` if it was
+    /// pinned already
+    pub async fn pin_message(&self, event_id: String) -> Result<bool> {
+        self.update_pinned(event_id, |pinned, event_id| {
+            if pinned.contains(&event_id) {
+                return false;
+            }
+            pinned.push(event_id);
+            true
+        })
+        .await
+    }
+
+    /// `false` if the message wasn't pinned
+    pub async fn unpin_message(&self, event_id: String) -> Result<bool> {
+        self.update_pinned(event_id, |pinned, event_id| {
+            let before = pinned.len();
+            pinned.retain(|e| e != &event_id);
+            pinned.len() != before
+        })
+        .await
+    }
+
+    /// Fires whenever the pinned messages of this convo changed
+    pub fn pinned_messages_rx(&self) -> impl Stream<Item = bool> {
+        let room_id = self.room_id().to_owned();
+        BroadcastStream::new(self.controller.subscribe_pinned()).filter_map(move |r| {
+            ready(match r {
+                Ok(changed) if changed != room_id => None,
+                // after lagging behind we can't tell, better check
+                _ => Some(true),
+            })
+        })
+    }
+}
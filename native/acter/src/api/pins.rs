@@ -23,7 +23,12 @@ use tokio::sync::broadcast::Receiver;
 use tokio_stream::{wrappers::BroadcastStream, Stream};
 use tracing::warn;
 
-use super::{client::Client, spaces::Space, RUNTIME};
+use super::{
+    client::Client,
+    link_previews::{find_urls, LinkPreview},
+    spaces::Space,
+    RUNTIME,
+};
 
 impl Client {
     pub async fn wait_for_pin(&self, key: String, timeout: Option<Box<Duration>>) -> Result<Pin> {
@@ -237,6 +242,14 @@ impl Pin {
         self.content.url.clone()
     }
 
+    /// the preview of the page the pin links to, if it is a web link
+    pub async fn link_preview(&self) -> Result<Option<LinkPreview>> {
+        let Some(url) = self.url().and_then(|u| find_urls(&u).into_iter().next()) else {
+            return Ok(None);
+        };
+        self.client.link_preview(url).await
+    }
+
     pub fn color(&self) -> Option<Color> {
         self.content.display.as_ref().and_then(|t| t.color.clone())
     }
@@ -379,12 +392,21 @@ impl PinDraft {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
+            .await?
+    }
+
+    /// send it at the given time instead, in milliseconds since the unix
+    /// epoch. Returns the id to cancel it with.
+    pub async fn schedule(&self, send_at: u64) -> Result<String> {
+        let room_id = self.room.room_id().to_owned();
+        let client = self.client.clone();
+        let content = self.content.build()?;
+        RUNTIME
+            .spawn(async move { client.schedule(room_id, &content, send_at).await })
             .await?
     }
 }
@@ -447,12 +469,10 @@ impl PinUpdateBuilder {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
             .await?
     }
 }
@@ -0,0 +1,630 @@
+use acter_core::events::polls::{
+    OriginalSyncPollEndEvent, OriginalSyncPollResponseEvent, OriginalSyncPollStartEvent,
+    PollEndEventContent, PollKind, PollResponseEventContent, PollResults, PollStartEventContent,
+};
+use anyhow::{bail, Context, Result};
+use futures::{future::ready, Stream, StreamExt};
+use matrix_sdk::{
+    room::Room as SdkRoom,
+    ruma::{
+        api::client::relations::get_relating_events_with_rel_type,
+        events::{
+            relation::RelationType, room::encrypted::OriginalSyncRoomEncryptedEvent,
+            AnyMessageLikeEvent, MessageLikeEventType, StaticEventContent,
+        },
+        serde::Raw,
+        EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::{trace, warn};
+
+use super::{
+    convo::{Convo, ConvoController},
+    message::RoomMessage,
+    room::Room,
+    RUNTIME,
+};
+
+/// One answer of a poll and how many voted for it
+#[derive(Clone, Debug)]
+pub struct PollAnswerResult {
+    id: String,
+    text: String,
+    votes: u64,
+}
+
+impl PollAnswerResult {
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+
+    /// 0 as long as the results aren't visible
+    pub fn votes(&self) -> u64 {
+        self.votes
+    }
+}
+
+/// A poll with the votes we know of
+#[derive(Clone, Debug)]
+pub struct PollDesc {
+    question: String,
+    disclosed: bool,
+    max_selections: u32,
+    answers: Vec<PollAnswerResult>,
+    my_answers: Vec<String>,
+    voters: u64,
+    ended: bool,
+}
+
+impl PollDesc {
+    pub(crate) fn new(results: &PollResults, my_id: Option<&UserId>) -> Self {
+        let poll = results.poll();
+        let disclosed = poll.kind == PollKind::Disclosed;
+        let visible = disclosed || results.is_ended();
+        let answers = poll
+            .answers
+            .iter()
+            .map(|answer| PollAnswerResult {
+                id: answer.id.clone(),
+                text: answer.text.clone(),
+                votes: if visible {
+                    results.votes_for(&answer.id) as u64
+                } else {
+                    0
+                },
+            })
+            .collect();
+        PollDesc {
+            question: poll.question.text.clone(),
+            disclosed,
+            max_selections: poll.max_selections,
+            answers,
+            my_answers: my_id.map(|me| results.answers_of(me)).unwrap_or_default(),
+            voters: results.voters() as u64,
+            ended: results.is_ended(),
+        }
+    }
+
+    pub fn question(&self) -> String {
+        self.question.clone()
+    }
+
+    /// whether the votes are shown before the poll ended
+    pub fn is_disclosed(&self) -> bool {
+        self.disclosed
+    }
+
+    pub fn max_selections(&self) -> u32 {
+        self.max_selections
+    }
+
+    pub fn answers(&self) -> Vec<PollAnswerResult> {
+        self.answers.clone()
+    }
+
+    /// the ids of the answers we currently vote for
+    pub fn my_answers(&self) -> Vec<String> {
+        self.my_answers.clone()
+    }
+
+    /// how many voted so far
+    pub fn voters(&self) -> u64 {
+        self.voters
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// whether the answers carry their votes yet
+    pub fn results_visible(&self) -> bool {
+        self.disclosed || self.ended
+    }
+}
+
+/// A poll with the votes seen so far
+#[derive(Clone, Debug)]
+pub(crate) struct TrackedPoll {
+    room_id: OwnedRoomId,
+    start: OriginalSyncPollStartEvent,
+    results: PollResults,
+}
+
+impl TrackedPoll {
+    fn new(room_id: OwnedRoomId, start: OriginalSyncPollStartEvent) -> Self {
+        let results = PollResults::new(start.sender.clone(), start.content.poll.clone());
+        TrackedPoll {
+            room_id,
+            start,
+            results,
+        }
+    }
+
+    fn respond(&mut self, ev: &OriginalSyncPollResponseEvent) -> bool {
+        self.results.add_response(
+            ev.sender.clone(),
+            ev.origin_server_ts,
+            ev.content.response.answers.clone(),
+        )
+    }
+
+    fn end(&mut self, ev: &OriginalSyncPollEndEvent) -> bool {
+        self.results.end(&ev.sender, ev.origin_server_ts)
+    }
+
+    pub(crate) fn desc(&self, my_id: Option<&UserId>) -> PollDesc {
+        PollDesc::new(&self.results, my_id)
+    }
+
+    pub(crate) fn to_message(&self, my_id: Option<&UserId>) -> RoomMessage {
+        RoomMessage::poll_start_from_sync_event(
+            self.start.clone(),
+            self.room_id.clone(),
+            &self.results,
+            my_id,
+        )
+    }
+}
+
+/// of how many polls we keep the votes that came before their start
+const EARLY_POLLS_KEPT: usize = 200;
+
+/// A vote or the end of a poll whose start we haven't seen yet
+#[derive(Clone, Debug)]
+enum EarlyPollEvent {
+    Response(OriginalSyncPollResponseEvent),
+    End(OriginalSyncPollEndEvent),
+}
+
+#[derive(Default)]
+struct Polls {
+    tracked: HashMap<OwnedEventId, TrackedPoll>,
+    // the votes and ends that came before their start, e.g. while paginating
+    // backwards
+    early: HashMap<OwnedEventId, Vec<EarlyPollEvent>>,
+    // the polls whose votes are being fetched from the server
+    loading: HashSet<OwnedEventId>,
+}
+
+impl Polls {
+    fn track(&mut self, mut poll: TrackedPoll) {
+        let poll_id = poll.start.event_id.clone();
+        for ev in self.early.remove(&poll_id).unwrap_or_default() {
+            match ev {
+                EarlyPollEvent::Response(ev) => poll.respond(&ev),
+                EarlyPollEvent::End(ev) => poll.end(&ev),
+            };
+        }
+        self.loading.remove(&poll_id);
+        self.tracked.insert(poll_id, poll);
+    }
+
+    fn keep_early(&mut self, poll_id: &EventId, ev: EarlyPollEvent) {
+        if !self.early.contains_key(poll_id) && self.early.len() >= EARLY_POLLS_KEPT {
+            trace!(?poll_id, "too many unknown polls, dropping the event");
+            return;
+        }
+        self.early.entry(poll_id.to_owned()).or_default().push(ev);
+    }
+}
+
+/// The polls we have seen since the start or loaded, kept up to date with
+/// the votes coming in
+#[derive(Clone, Default)]
+pub(crate) struct PollStore {
+    polls: Arc<Mutex<Polls>>,
+}
+
+impl std::fmt::Debug for PollStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PollStore").finish_non_exhaustive()
+    }
+}
+
+impl PollStore {
+    pub(crate) fn get(&self, poll_id: &EventId) -> Option<TrackedPoll> {
+        let polls = self.polls.lock().expect("poll store poisoned");
+        polls.tracked.get(poll_id).cloned()
+    }
+
+    /// Show the results we have on the message, if it starts a poll we follow
+    pub(crate) fn with_results(&self, msg: RoomMessage, my_id: Option<&UserId>) -> RoomMessage {
+        let Some(poll_id) = started_poll(&msg) else {
+            return msg;
+        };
+        match self.get(&poll_id) {
+            Some(poll) => with_poll_desc(&msg, poll.desc(my_id)),
+            None => msg,
+        }
+    }
+
+    pub(crate) fn insert(&self, poll: TrackedPoll) {
+        let mut polls = self.polls.lock().expect("poll store poisoned");
+        polls.track(poll);
+    }
+
+    /// `false` if we follow the poll already or fetch its votes just now
+    pub(crate) fn begin_loading(&self, poll_id: &EventId) -> bool {
+        let mut polls = self.polls.lock().expect("poll store poisoned");
+        if polls.tracked.contains_key(poll_id) {
+            return false;
+        }
+        polls.loading.insert(poll_id.to_owned())
+    }
+
+    /// fetching the votes failed, the next look at the poll tries again
+    pub(crate) fn loading_failed(&self, poll_id: &EventId) {
+        let mut polls = self.polls.lock().expect("poll store poisoned");
+        polls.loading.remove(poll_id);
+    }
+
+    /// `false` if we knew the poll already
+    pub(crate) fn start(&self, room_id: &RoomId, ev: OriginalSyncPollStartEvent) -> bool {
+        let mut polls = self.polls.lock().expect("poll store poisoned");
+        if polls.tracked.contains_key(&ev.event_id) {
+            return false;
+        }
+        polls.track(TrackedPoll::new(room_id.to_owned(), ev));
+        true
+    }
+
+    /// the poll if the response changed its results
+    pub(crate) fn respond(&self, ev: &OriginalSyncPollResponseEvent) -> Option<OwnedEventId> {
+        let poll_id = &ev.content.relates_to.event_id;
+        let mut polls = self.polls.lock().expect("poll store poisoned");
+        let Some(poll) = polls.tracked.get_mut(poll_id) else {
+            trace!(?poll_id, "response to unknown poll, kept for its start");
+            polls.keep_early(poll_id, EarlyPollEvent::Response(ev.clone()));
+            return None;
+        };
+        poll.respond(ev).then(|| poll_id.clone())
+    }
+
+    /// the poll if it just ended
+    pub(crate) fn end(&self, ev: &OriginalSyncPollEndEvent) -> Option<OwnedEventId> {
+        let poll_id = &ev.content.relates_to.event_id;
+        let mut polls = self.polls.lock().expect("poll store poisoned");
+        let Some(poll) = polls.tracked.get_mut(poll_id) else {
+            polls.keep_early(poll_id, EarlyPollEvent::End(ev.clone()));
+            return None;
+        };
+        poll.end(ev).then(|| poll_id.clone())
+    }
+}
+
+/// the id of the poll the message starts, if it does
+pub(crate) fn started_poll(msg: &RoomMessage) -> Option<OwnedEventId> {
+    let event_item = msg.event_item()?;
+    if event_item.event_type() != PollStartEventContent::TYPE {
+        return None;
+    }
+    // local echos don't have an event id yet
+    EventId::parse(event_item.event_id()).ok()
+}
+
+/// the message with `desc` as the results of its poll
+pub(crate) fn with_poll_desc(msg: &RoomMessage, desc: PollDesc) -> RoomMessage {
+    let mut msg = msg.clone();
+    if let Some(mut event_item) = msg.event_item() {
+        event_item.set_poll_desc(desc);
+        msg.set_event_item(Some(event_item));
+    }
+    msg
+}
+
+impl ConvoController {
+    /// The results of the polls of the room we follow, whenever they changed
+    pub(crate) fn poll_results_rx(
+        &self,
+        room_id: OwnedRoomId,
+        my_id: Option<OwnedUserId>,
+    ) -> impl Stream<Item = (OwnedEventId, PollDesc)> {
+        let polls = self.polls();
+        BroadcastStream::new(self.subscribe_polls()).filter_map(move |r| {
+            let results = match r {
+                Ok((changed, poll_id)) if changed == room_id => polls
+                    .get(&poll_id)
+                    .map(|poll| (poll_id, poll.desc(my_id.as_deref()))),
+                _ => None,
+            };
+            ready(results)
+        })
+    }
+}
+
+fn apply_related(poll: &mut TrackedPoll, raw: &Raw<AnyMessageLikeEvent>) -> Result<()> {
+    let event_type = raw
+        .get_field::<String>("type")?
+        .context("event without type")?;
+    if event_type == PollResponseEventContent::TYPE {
+        poll.respond(&raw.deserialize_as()?);
+    } else if event_type == PollEndEventContent::TYPE {
+        poll.end(&raw.deserialize_as()?);
+    }
+    Ok(())
+}
+
+/// The poll with all votes the server knows of
+pub(crate) async fn load_poll(room: &SdkRoom, poll_id: &EventId) -> Result<TrackedPoll> {
+    let room_id = room.room_id().to_owned();
+    let start = room.event(poll_id).await?;
+    let start = start
+        .event
+        .deserialize_as::<OriginalSyncPollStartEvent>()
+        .context("Not a poll")?;
+    let mut poll = TrackedPoll::new(room_id.clone(), start);
+
+    let mut from = None;
+    loop {
+        let mut request = get_relating_events_with_rel_type::v1::Request::new(
+            room_id.clone(),
+            poll_id.to_owned(),
+            RelationType::Reference,
+        );
+        request.from = from;
+        let response = room.client().send(request, None).await?;
+        for raw in response.chunk {
+            let raw = if raw.get_field::<String>("type")?.as_deref() == Some("m.room.encrypted") {
+                match room
+                    .decrypt_event(raw.cast_ref::<OriginalSyncRoomEncryptedEvent>())
+                    .await
+                {
+                    Ok(decrypted) => decrypted.event.cast(),
+                    Err(error) => {
+                        warn!(?error, ?poll_id, "can't decrypt poll event");
+                        continue;
+                    }
+                }
+            } else {
+                raw
+            };
+            if let Err(error) = apply_related(&mut poll, &raw) {
+                warn!(?error, ?poll_id, "ignoring broken poll event");
+            }
+        }
+        if response.next_batch.is_none() {
+            break;
+        }
+        from = response.next_batch;
+    }
+    Ok(poll)
+}
+
+impl Room {
+    /// start a poll, `disclosed` shows the votes before the poll ended
+    pub async fn send_poll(
+        &self,
+        question: String,
+        answers: Vec<String>,
+        max_selections: u32,
+        disclosed: bool,
+    ) -> Result<OwnedEventId> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't start polls in a room we are not in");
+        };
+        if answers.len() < 2 {
+            bail!("A poll needs at least two answers");
+        }
+        let my_id = joined
+            .client()
+            .user_id()
+            .context("User not found")?
+            .to_owned();
+        let kind = if disclosed {
+            PollKind::Disclosed
+        } else {
+            PollKind::Undisclosed
+        };
+
+        RUNTIME
+            .spawn(async move {
+                let member = joined
+                    .get_member(&my_id)
+                    .await?
+                    .context("Couldn't find me among room members")?;
+                if !member.can_send_message(MessageLikeEventType::from(PollStartEventContent::TYPE))
+                {
+                    bail!("No permission to start polls in this room");
+                }
+                let content = PollStartEventContent::new(question, answers, kind, max_selections);
+                let response = joined.send(content, None).await?;
+                Ok(response.event_id)
+            })
+            .await?
+    }
+
+    /// vote for the answers of the poll, replacing our earlier vote. No
+    /// answers take the vote back.
+    pub async fn vote(&self, poll_id: String, answer_ids: Vec<String>) -> Result<OwnedEventId> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't vote in a room we are not in");
+        };
+        let poll_id = EventId::parse(poll_id)?;
+        let my_id = joined
+            .client()
+            .user_id()
+            .context("User not found")?
+            .to_owned();
+
+        RUNTIME
+            .spawn(async move {
+                let member = joined
+                    .get_member(&my_id)
+                    .await?
+                    .context("Couldn't find me among room members")?;
+                if !member
+                    .can_send_message(MessageLikeEventType::from(PollResponseEventContent::TYPE))
+                {
+                    bail!("No permission to vote in this room");
+                }
+                let content = PollResponseEventContent::new(poll_id, answer_ids);
+                let response = joined.send(content, None).await?;
+                Ok(response.event_id)
+            })
+            .await?
+    }
+
+    /// close the poll, only who started it can
+    pub async fn end_poll(&self, poll_id: String) -> Result<OwnedEventId> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't end polls in a room we are not in");
+        };
+        let poll_id = EventId::parse(poll_id)?;
+        let my_id = joined
+            .client()
+            .user_id()
+            .context("User not found")?
+            .to_owned();
+
+        RUNTIME
+            .spawn(async move {
+                let start = joined
+                    .event(&poll_id)
+                    .await?
+                    .event
+                    .deserialize_as::<OriginalSyncPollStartEvent>()
+                    .context("Not a poll")?;
+                if start.sender != my_id {
+                    bail!("Only who started the poll can end it");
+                }
+                let content = PollEndEventContent::new(poll_id);
+                let response = joined.send(content, None).await?;
+                Ok(response.event_id)
+            })
+            .await?
+    }
+}
+
+impl Convo {
+    /// the poll with all votes so far, its `poll_desc` tells the results
+    pub async fn poll(&self, poll_id: String) -> Result<RoomMessage> {
+        let room = self.room.clone();
+        let polls = self.controller.polls();
+        let poll_id = EventId::parse(poll_id)?;
+        RUNTIME
+            .spawn(async move {
+                let poll = load_poll(&room, &poll_id).await?;
+                let msg = poll.to_message(room.client().user_id());
+                polls.insert(poll);
+                Ok(msg)
+            })
+            .await?
+    }
+
+    /// Fires with the poll whenever it started, got a vote or ended, or
+    /// once its votes are fetched after it showed up in the timeline.
+    pub fn poll_updates_rx(&self) -> impl Stream<Item = RoomMessage> {
+        let room_id = self.room_id().to_owned();
+        let my_id = self.room.client().user_id().map(ToOwned::to_owned);
+        let polls = self.controller.polls();
+        BroadcastStream::new(self.controller.subscribe_polls()).filter_map(move |r| {
+            let msg = match r {
+                Ok((changed, poll_id)) if changed == room_id => polls
+                    .get(&poll_id)
+                    .map(|poll| poll.to_message(my_id.as_deref())),
+                _ => None,
+            };
+            ready(msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matrix_sdk::ruma::{event_id, room_id};
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::json;
+
+    fn event<T: DeserializeOwned>(
+        event_type: &str,
+        event_id: &str,
+        sender: &str,
+        content: impl Serialize,
+    ) -> T {
+        let json = json!({
+            "type": event_type,
+            "event_id": event_id,
+            "sender": sender,
+            "origin_server_ts": 1,
+            "content": content,
+        });
+        serde_json::from_str(&json.to_string()).unwrap()
+    }
+
+    fn lunch_poll() -> OriginalSyncPollStartEvent {
+        let content = PollStartEventContent::new(
+            "Lunch?".to_owned(),
+            vec!["Pizza".to_owned(), "Sushi".to_owned()],
+            PollKind::Undisclosed,
+            1,
+        );
+        event(
+            PollStartEventContent::TYPE,
+            "$poll:ds9.org",
+            "@sisko:ds9.org",
+            content,
+        )
+    }
+
+    fn vote(sender: &str, answer: &str) -> OriginalSyncPollResponseEvent {
+        let content = PollResponseEventContent::new(
+            event_id!("$poll:ds9.org").to_owned(),
+            vec![answer.to_owned()],
+        );
+        let event_id = format!("${}", &sender[1..]);
+        event(PollResponseEventContent::TYPE, &event_id, sender, content)
+    }
+
+    #[test]
+    fn responses_before_the_start_count() {
+        let store = PollStore::default();
+        let poll_id = event_id!("$poll:ds9.org");
+        // paginating backwards, the votes and the end come first
+        assert!(store.respond(&vote("@kira:ds9.org", "answer-1")).is_none());
+        assert!(store.respond(&vote("@odo:ds9.org", "answer-0")).is_none());
+        let end = event(
+            PollEndEventContent::TYPE,
+            "$end:ds9.org",
+            "@sisko:ds9.org",
+            PollEndEventContent::new(poll_id.to_owned()),
+        );
+        assert!(store.end(&end).is_none());
+        assert!(store.get(poll_id).is_none());
+
+        assert!(store.start(room_id!("!ops:ds9.org"), lunch_poll()));
+        let desc = store.get(poll_id).unwrap().desc(None);
+        assert!(desc.is_ended());
+        assert_eq!(desc.voters(), 2);
+        let votes = desc
+            .answers()
+            .iter()
+            .map(|answer| answer.votes())
+            .collect::<Vec<_>>();
+        assert_eq!(votes, vec![1, 1]);
+    }
+
+    #[test]
+    fn polls_are_loaded_once() {
+        let store = PollStore::default();
+        let poll_id = event_id!("$poll:ds9.org");
+        assert!(store.begin_loading(poll_id));
+        assert!(!store.begin_loading(poll_id));
+        store.loading_failed(poll_id);
+        assert!(store.begin_loading(poll_id));
+        store.insert(TrackedPoll::new(
+            room_id!("!ops:ds9.org").to_owned(),
+            lunch_poll(),
+        ));
+        assert!(!store.begin_loading(poll_id));
+    }
+}
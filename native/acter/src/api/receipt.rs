@@ -6,8 +6,8 @@ use matrix_sdk::{
     event_handler::{Ctx, EventHandlerHandle},
     room::Room,
     ruma::{
-        events::receipt::{ReceiptType, SyncReceiptEvent},
-        MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId,
+        events::receipt::{ReceiptEventContent, ReceiptThread, ReceiptType, SyncReceiptEvent},
+        MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, UserId,
     },
     Client as SdkClient,
 };
@@ -15,7 +15,7 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{error, info};
 
-use super::client::Client;
+use super::{client::Client, convo::ConvoController};
 
 #[derive(Clone, Debug)]
 pub struct ReceiptRecord {
@@ -92,15 +92,18 @@ pub(crate) struct ReceiptController {
     event_tx: Sender<ReceiptEvent>,
     event_rx: Arc<Mutex<Option<Receiver<ReceiptEvent>>>>,
     event_handle: Option<EventHandlerHandle>,
+    // our own receipts clear the unread counts of the convo
+    convos: ConvoController,
 }
 
 impl ReceiptController {
-    pub fn new() -> Self {
+    pub fn new(convos: ConvoController) -> Self {
         let (tx, rx) = channel::<ReceiptEvent>(10); // dropping after more than 10 items queued
         ReceiptController {
             event_tx: tx,
             event_rx: Arc::new(Mutex::new(Some(rx))),
             event_handle: None,
+            convos,
         }
     }
 
@@ -135,6 +138,12 @@ impl ReceiptController {
                 }
             }
         }
+        if let Some(my_id) = room.client().user_id() {
+            let read_by_me = my_unthreaded_reads(&ev.content, my_id);
+            if !read_by_me.is_empty() {
+                self.convos.read_up_to(room_id, &read_by_me);
+            }
+        }
         if !msg.is_empty() {
             if let Err(e) = self.event_tx.try_send(msg) {
                 error!("Dropping ephemeral event for {}: {}", room_id, e);
@@ -143,6 +152,29 @@ impl ReceiptController {
     }
 }
 
+/// The events our read receipts that aren't for a thread point to
+fn my_unthreaded_reads(content: &ReceiptEventContent, my_id: &UserId) -> Vec<OwnedEventId> {
+    content
+        .iter()
+        .filter(|(_, event_info)| {
+            // a private receipt can only be ours
+            [ReceiptType::Read, ReceiptType::ReadPrivate]
+                .iter()
+                .filter_map(|receipt_type| event_info.get(receipt_type))
+                .flat_map(|receipts| receipts.iter())
+                .any(|(seen_by, receipt)| {
+                    seen_by == my_id && matches!(receipt.thread, ReceiptThread::Unthreaded)
+                })
+        })
+        .map(|(event_id, _)| event_id.clone())
+        .collect()
+}
+
+/// Whether one of the `read` events is the `latest` one
+pub(crate) fn reads_latest(latest: Option<&OwnedEventId>, read: &[OwnedEventId]) -> bool {
+    latest.map_or(false, |latest| read.contains(latest))
+}
+
 impl Client {
     pub fn receipt_event_rx(&self) -> Option<Receiver<ReceiptEvent>> {
         match self.receipt_controller.event_rx.try_lock() {
@@ -151,3 +183,47 @@ impl Client {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matrix_sdk::ruma::{event_id, user_id};
+    use serde_json::json;
+
+    fn content(receipts: serde_json::Value) -> ReceiptEventContent {
+        serde_json::from_str(&receipts.to_string()).unwrap()
+    }
+
+    #[test]
+    fn finds_my_unthreaded_reads() {
+        let content = content(json!({
+            "$public": { "m.read": { "@me:example.org": { "ts": 1 } } },
+            "$private": { "m.read.private": { "@me:example.org": { "ts": 2 } } },
+            "$thread": {
+                "m.read": { "@me:example.org": { "ts": 3, "thread_id": "$root" } }
+            },
+            "$other": { "m.read": { "@other:example.org": { "ts": 4 } } },
+        }));
+        let reads = my_unthreaded_reads(&content, user_id!("@me:example.org"));
+        assert_eq!(
+            reads,
+            [
+                event_id!("$private").to_owned(),
+                event_id!("$public").to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn only_reading_the_latest_clears() {
+        let latest = event_id!("$latest").to_owned();
+        let older = event_id!("$older").to_owned();
+        assert!(reads_latest(
+            Some(&latest),
+            &[older.clone(), latest.clone()]
+        ));
+        assert!(!reads_latest(Some(&latest), &[older.clone()]));
+        // we don't know what is latest, the next sync tells
+        assert!(!reads_latest(None, &[older]));
+    }
+}
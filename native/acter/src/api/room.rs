@@ -4,6 +4,8 @@ use acter_core::{
         news::{NewsContent, NewsEntryEvent, NewsEntryEventContent},
         pins::PinEventContent,
         settings::{ActerAppSettings, ActerAppSettingsContent},
+        templates::TemplateEventContent,
+        voice::{analyze_recording, VoiceContent},
     },
     spaces::is_acter_space,
     statics::PURPOSE_FIELD_DEV,
@@ -15,7 +17,7 @@ use matrix_sdk::{
         AttachmentConfig, AttachmentInfo, BaseAudioInfo, BaseFileInfo, BaseImageInfo, BaseVideoInfo,
     },
     media::{MediaFormat, MediaRequest},
-    room::{Room as SdkRoom, RoomMember},
+    room::{Joined, Room as SdkRoom, RoomMember},
     ruma::{
         api::client::{
             receipt::create_receipt::v3::ReceiptType as CreateReceiptType,
@@ -32,8 +34,9 @@ use matrix_sdk::{
                 message::{
                     AudioInfo, AudioMessageEventContent, FileInfo, FileMessageEventContent,
                     ForwardThread, ImageMessageEventContent, LocationMessageEventContent,
-                    MessageType, Relation, RoomMessageEvent, RoomMessageEventContent,
-                    TextMessageEventContent, VideoInfo, VideoMessageEventContent,
+                    MessageType, OriginalSyncRoomMessageEvent, Relation, ReplyWithinThread,
+                    RoomMessageEvent, RoomMessageEventContent, TextMessageEventContent, VideoInfo,
+                    VideoMessageEventContent,
                 },
                 ImageInfo,
             },
@@ -51,8 +54,9 @@ use tracing::{error, info};
 use super::{
     account::Account,
     api::FfiBuffer,
-    message::RoomMessage,
+    message::{latest_thread_event, RoomMessage},
     profile::{RoomProfile, UserProfile},
+    scheduled::Scheduler,
     RUNTIME,
 };
 
@@ -91,6 +95,7 @@ pub enum MemberPermission {
     CanSetParentSpace,
     CanUpdatePowerLevels,
     CanChangeAppSettings,
+    CanPublishTemplates,
 }
 
 enum PermissionTest {
@@ -231,6 +236,14 @@ impl Member {
                     return false;
                 }
             }
+            MemberPermission::CanPublishTemplates => {
+                if self.acter_app_settings.is_some() {
+                    PermissionTest::StateEvent(TemplateEventContent::TYPE.into())
+                } else {
+                    // not an acter space, templates can't be shared here
+                    return false;
+                }
+            }
         };
         match tester {
             PermissionTest::Message(msg) => self.member.can_send_message(msg),
@@ -262,6 +275,7 @@ impl Member {
 #[derive(Clone, Debug)]
 pub struct Room {
     pub(crate) room: SdkRoom,
+    pub(crate) scheduler: Scheduler,
 }
 
 impl Room {
@@ -1004,6 +1018,52 @@ impl Room {
             .await?
     }
 
+    /// send a recording as voice message (MSC3245), with its waveform
+    /// worked out from the WAV or Ogg Opus file. The given duration wins
+    /// over the one found in the file.
+    pub async fn send_voice_message(
+        &self,
+        uri: String,
+        name: String,
+        mimetype: String,
+        millis: Option<u64>,
+        size: Option<u32>,
+    ) -> Result<OwnedEventId> {
+        let room = if let SdkRoom::Joined(r) = &self.room {
+            r.clone()
+        } else {
+            bail!("Can't send message as voice to a room we are not in")
+        };
+        let client = self.room.client();
+
+        let my_id = client.user_id().context("User not found")?.to_owned();
+
+        let path = PathBuf::from(uri);
+        let mime_type = mimetype.parse::<mime::Mime>()?;
+
+        RUNTIME
+            .spawn(async move {
+                let member = room
+                    .get_member(&my_id)
+                    .await?
+                    .context("Couldn't find me among room members")?;
+                if !member.can_send_message(MessageLikeEventType::RoomMessage) {
+                    bail!("No permission to send message in this room");
+                }
+                let audio_buf = std::fs::read(path).context("File should be read")?;
+                let raw_content =
+                    voice_message_content(&client, &room, audio_buf, name, mime_type, millis, size)
+                        .await?;
+
+                let txn_id = TransactionId::new();
+                let response = room
+                    .send_raw(raw_content, "m.room.message", Some(&txn_id))
+                    .await?;
+                Ok(response.event_id)
+            })
+            .await?
+    }
+
     pub async fn audio_binary(&self, event_id: String) -> Result<FfiBuffer<u8>> {
         let room = if let SdkRoom::Joined(r) = &self.room {
             r.clone()
@@ -2051,6 +2111,63 @@ impl Room {
             .await?
     }
 
+    /// Send a text into the thread of `thread_root`, the thread gets
+    /// started if there was none yet
+    pub async fn send_thread_message(
+        &self,
+        thread_root: String,
+        msg: String,
+        txn_id: Option<String>,
+    ) -> Result<OwnedEventId> {
+        let room = if let SdkRoom::Joined(r) = &self.room {
+            r.clone()
+        } else {
+            bail!("Can't send thread message to a room we are not in")
+        };
+
+        let my_id = room
+            .client()
+            .user_id()
+            .context("User not found")?
+            .to_owned();
+
+        let thread_root = EventId::parse(thread_root)?;
+
+        RUNTIME
+            .spawn(async move {
+                let member = room
+                    .get_member(&my_id)
+                    .await?
+                    .context("Couldn't find me among room members")?;
+                if !member.can_send_message(MessageLikeEventType::RoomMessage) {
+                    bail!("No permission to send message in this room");
+                }
+
+                let root_event = room.event(&thread_root).await?.event.cast();
+                // clients without threads show the message as reply to the
+                // latest one in the thread
+                let latest = latest_thread_event(&root_event)
+                    .and_then(|e| e.deserialize_as::<OriginalSyncRoomMessageEvent>().ok());
+                let previous = match latest {
+                    Some(latest) => latest,
+                    None => root_event
+                        .deserialize_as::<OriginalSyncRoomMessageEvent>()
+                        .context("Thread root is not a message")?,
+                }
+                .into_full_event(room.room_id().to_owned());
+
+                let text_content = TextMessageEventContent::markdown(msg);
+                let content = RoomMessageEventContent::new(MessageType::Text(text_content))
+                    .make_for_thread(&previous, ReplyWithinThread::No);
+
+                let response = room
+                    .send(content, txn_id.as_deref().map(Into::into))
+                    .await?;
+                Ok(response.event_id)
+            })
+            .await?
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn send_image_reply(
         &self,
@@ -2428,3 +2545,49 @@ impl Deref for Room {
         &self.room
     }
 }
+
+/// The `m.audio` content of a voice message, with the MSC3245 fields ruma
+/// doesn't know about. Uploads the recording, encrypted if the room is.
+pub(crate) async fn voice_message_content(
+    client: &Client,
+    room: &Joined,
+    audio_buf: Vec<u8>,
+    name: String,
+    mime_type: mime::Mime,
+    millis: Option<u64>,
+    size: Option<u32>,
+) -> Result<serde_json::Value> {
+    let (duration, waveform) = match (analyze_recording(&audio_buf), millis) {
+        (Ok((found, waveform)), given) => {
+            (given.map(Duration::from_millis).unwrap_or(found), waveform)
+        }
+        (Err(error), Some(given)) => {
+            info!(?error, "no waveform for voice message");
+            (Duration::from_millis(given), vec![])
+        }
+        (Err(error), None) => bail!("Can't tell the duration of the recording: {error}"),
+    };
+
+    let mut audio_content = if room.is_encrypted().await? {
+        let encrypted_file = client
+            .prepare_encrypted_file(&mime_type, &mut audio_buf.as_slice())
+            .await?;
+        AudioMessageEventContent::encrypted(name, encrypted_file)
+    } else {
+        let response = client.media().upload(&mime_type, audio_buf).await?;
+        AudioMessageEventContent::plain(name, response.content_uri)
+    };
+    audio_content.info = Some(Box::new(assign!(AudioInfo::new(), {
+        duration: Some(duration),
+        mimetype: Some(mime_type.to_string()),
+        size: size.map(UInt::from),
+    })));
+    let content = RoomMessageEventContent::new(MessageType::Audio(audio_content));
+
+    let mut raw_content = serde_json::to_value(content)?;
+    let voice = serde_json::to_value(VoiceContent::new(duration, waveform))?;
+    if let (Some(fields), serde_json::Value::Object(voice)) = (raw_content.as_object_mut(), voice) {
+        fields.extend(voice);
+    }
+    Ok(raw_content)
+}
@@ -84,14 +84,15 @@ impl RsvpDraft {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let inner = self.inner.build()?;
         trace!("rsvp draft spawn");
         RUNTIME
             .spawn(async move {
                 trace!("before sending rsvp");
-                let resp = room.send(inner, None).await?;
+                let event_id = client.send_or_queue(room, inner).await?;
                 trace!("after sending rsvp");
-                Ok(resp.event_id)
+                Ok(event_id)
             })
             .await?
     }
@@ -0,0 +1,587 @@
+use acter_core::{
+    models::is_model_type,
+    outbox::{Outbox, PendingEvent},
+    store::Store,
+    Error,
+};
+use anyhow::{bail, Context, Result};
+use futures::{Stream, StreamExt};
+use matrix_sdk::{
+    room::Room as SdkRoom,
+    ruma::{
+        assign,
+        events::{
+            room::{
+                message::{
+                    AudioInfo, AudioMessageEventContent, FileInfo, FileMessageEventContent,
+                    ImageMessageEventContent, LocationMessageEventContent, MessageType,
+                    RoomMessageEventContent, VideoInfo, VideoMessageEventContent,
+                },
+                EncryptedFile, ImageInfo,
+            },
+            MessageLikeEventContent, MessageLikeEventType,
+        },
+        MilliSecondsSinceUnixEpoch, OwnedMxcUri, OwnedRoomId, OwnedTransactionId, RoomId,
+        TransactionId, UInt,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        broadcast::{channel, Receiver, Sender},
+        Mutex, Notify,
+    },
+    time,
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::{error, info};
+
+use super::{
+    client::Client,
+    room::{voice_message_content, Room},
+    RUNTIME,
+};
+
+const SCHEDULED_KEY: &str = "scheduled_messages";
+/// we look again at least this often, in case the clock jumped
+const MAX_NAP: Duration = Duration::from_secs(60);
+
+/// A message to send later, made with one of the `new_scheduled_*`
+/// functions or `prepare_scheduled_media`
+#[derive(Clone, Debug)]
+pub struct ScheduledContent {
+    event_type: String,
+    content: serde_json::Value,
+}
+
+impl ScheduledContent {
+    pub(crate) fn new<C: MessageLikeEventContent>(content: &C) -> Result<Self> {
+        Ok(ScheduledContent {
+            event_type: content.event_type().to_string(),
+            content: serde_json::to_value(content)?,
+        })
+    }
+
+    fn room_message(msgtype: MessageType) -> Result<Self> {
+        ScheduledContent::new(&RoomMessageEventContent::new(msgtype))
+    }
+}
+
+pub fn new_scheduled_text_message(body: String) -> Result<ScheduledContent> {
+    ScheduledContent::new(&RoomMessageEventContent::text_plain(body))
+}
+
+pub fn new_scheduled_markdown_message(body: String) -> Result<ScheduledContent> {
+    ScheduledContent::new(&RoomMessageEventContent::text_markdown(body))
+}
+
+pub fn new_scheduled_html_message(body: String, html_body: String) -> Result<ScheduledContent> {
+    ScheduledContent::new(&RoomMessageEventContent::text_html(body, html_body))
+}
+
+pub fn new_scheduled_location_message(body: String, geo_uri: String) -> Result<ScheduledContent> {
+    ScheduledContent::room_message(MessageType::Location(LocationMessageEventContent::new(
+        body, geo_uri,
+    )))
+}
+
+/// A message waiting for its time to be sent
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScheduledMessage {
+    /// sent with this transaction id later on
+    id: OwnedTransactionId,
+    room_id: OwnedRoomId,
+    event_type: String,
+    content: serde_json::Value,
+    send_at: MilliSecondsSinceUnixEpoch,
+    created_at: MilliSecondsSinceUnixEpoch,
+}
+
+impl ScheduledMessage {
+    pub fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    pub fn room_id_str(&self) -> String {
+        self.room_id.to_string()
+    }
+
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+
+    /// milliseconds since the unix epoch
+    pub fn send_at(&self) -> u64 {
+        self.send_at.get().into()
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at.get().into()
+    }
+
+    /// the text of a message or the title of an acter object, to show in
+    /// the list of scheduled messages
+    pub fn preview(&self) -> Option<String> {
+        ["body", "title"]
+            .iter()
+            .find_map(|key| self.content.get(key)?.as_str().map(ToOwned::to_owned))
+    }
+
+    fn is_due(&self, now: MilliSecondsSinceUnixEpoch) -> bool {
+        self.send_at <= now
+    }
+}
+
+/// Keeps the scheduled messages in the store and hands them to the outbox
+/// once they are due, so they are sent even if we are offline just then
+#[derive(Clone, Debug)]
+pub(crate) struct Scheduler {
+    store: Store,
+    queue: Arc<Mutex<Vec<ScheduledMessage>>>,
+    wake: Arc<Notify>,
+    changed: Arc<Sender<()>>,
+}
+
+impl Scheduler {
+    /// Load what was scheduled before, due ones go out with the next run
+    pub(crate) async fn load(store: Store) -> Self {
+        let queue = match store.get_raw::<Vec<ScheduledMessage>>(SCHEDULED_KEY).await {
+            Ok(queue) => queue,
+            Err(Error::ModelNotFound) => vec![],
+            Err(error) => {
+                error!(
+                    ?error,
+                    "reading the scheduled messages failed, starting empty"
+                );
+                vec![]
+            }
+        };
+        Scheduler {
+            store,
+            queue: Arc::new(Mutex::new(queue)),
+            wake: Default::default(),
+            changed: Arc::new(channel(1).0),
+        }
+    }
+
+    async fn persist(&self, queue: &Vec<ScheduledMessage>) -> Result<()> {
+        self.store.set_raw(SCHEDULED_KEY, queue).await?;
+        let _ = self.changed.send(());
+        Ok(())
+    }
+
+    pub(crate) fn subscribe(&self) -> Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    /// returns the id to cancel it with. A time in the past sends it with
+    /// the next run.
+    pub(crate) async fn schedule(
+        &self,
+        room_id: OwnedRoomId,
+        content: ScheduledContent,
+        send_at: u64,
+    ) -> Result<String> {
+        let message = ScheduledMessage {
+            id: TransactionId::new(),
+            room_id,
+            event_type: content.event_type,
+            content: content.content,
+            send_at: MilliSecondsSinceUnixEpoch(UInt::try_from(send_at)?),
+            created_at: MilliSecondsSinceUnixEpoch::now(),
+        };
+        let id = message.id();
+        {
+            let mut queue = self.queue.lock().await;
+            queue.push(message);
+            self.persist(&queue).await?;
+        }
+        info!(id, send_at, "message scheduled");
+        self.wake.notify_one();
+        Ok(id)
+    }
+
+    /// the scheduled messages, of the room if given, the next due first
+    pub(crate) async fn scheduled(&self, room_id: Option<&RoomId>) -> Vec<ScheduledMessage> {
+        let mut messages = self
+            .queue
+            .lock()
+            .await
+            .iter()
+            .filter(|m| room_id.map(|r| m.room_id == r).unwrap_or(true))
+            .cloned()
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|m| m.send_at);
+        messages
+    }
+
+    /// `false` if it isn't scheduled (anymore), in the room if given
+    pub(crate) async fn cancel(&self, id: &str, room_id: Option<&RoomId>) -> Result<bool> {
+        let mut queue = self.queue.lock().await;
+        let Some(pos) = queue
+            .iter()
+            .position(|m| m.id.as_str() == id && room_id.map(|r| m.room_id == r).unwrap_or(true))
+        else {
+            return Ok(false);
+        };
+        queue.remove(pos);
+        self.persist(&queue).await?;
+        Ok(true)
+    }
+
+    /// Take the due messages out of the queue, the others stay
+    fn take_due(
+        queue: &mut Vec<ScheduledMessage>,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Vec<ScheduledMessage> {
+        let (due, waiting) = queue.drain(..).partition(|m| m.is_due(now));
+        *queue = waiting;
+        due
+    }
+
+    /// Hand the due messages to the outbox. Returns when the next one is
+    /// due, if any.
+    async fn send_due(&self, outbox: &Outbox) -> Option<MilliSecondsSinceUnixEpoch> {
+        let mut queue = self.queue.lock().await;
+        let due = Scheduler::take_due(&mut queue, MilliSecondsSinceUnixEpoch::now());
+        if !due.is_empty() {
+            for message in due {
+                info!(id = message.id(), "scheduled message due");
+                // the outbox keeps the transaction id, so should we send it
+                // again after a crash, the server drops the second one
+                let event = PendingEvent::from_json(
+                    message.id,
+                    message.room_id,
+                    message.event_type,
+                    message.content,
+                );
+                // only acter objects show before they are sent
+                if is_model_type(&event.event_type()) {
+                    outbox.apply_local(&event).await;
+                }
+                if let Err(error) = outbox.enqueue(event).await {
+                    error!(?error, "queueing the scheduled message failed");
+                }
+            }
+            if let Err(error) = self.persist(&queue).await {
+                error!(?error, "persisting the scheduled messages failed");
+            }
+        }
+        queue.iter().map(|m| m.send_at).min()
+    }
+
+    /// Keep sending the messages when they are due. Runs until the task is
+    /// aborted.
+    pub(crate) async fn run(&self, outbox: Outbox) {
+        loop {
+            let nap = match self.send_due(&outbox).await {
+                Some(next) => {
+                    let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+                    let next: u64 = next.get().into();
+                    Duration::from_millis(next.saturating_sub(now)).min(MAX_NAP)
+                }
+                None => MAX_NAP,
+            };
+            tokio::select! {
+                _ = time::sleep(nap) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+    }
+}
+
+/// The uploaded file of a media message
+enum Upload {
+    Plain(OwnedMxcUri),
+    Encrypted(EncryptedFile),
+}
+
+impl Room {
+    /// send the message at the given time, in milliseconds since the unix
+    /// epoch. Returns the id to cancel it with.
+    pub async fn schedule_message(
+        &self,
+        send_at: u64,
+        content: Box<ScheduledContent>,
+    ) -> Result<String> {
+        let SdkRoom::Joined(joined) = self.room.clone() else {
+            bail!("Can't schedule messages in a room we are not in");
+        };
+        let my_id = joined
+            .client()
+            .user_id()
+            .context("User not found")?
+            .to_owned();
+        let scheduler = self.scheduler.clone();
+        RUNTIME
+            .spawn(async move {
+                let member = joined
+                    .get_member(&my_id)
+                    .await?
+                    .context("Couldn't find me among room members")?;
+                let event_type = MessageLikeEventType::from(content.event_type.as_str());
+                if !member.can_send_message(event_type) {
+                    bail!("No permission to send message in this room");
+                }
+                scheduler
+                    .schedule(joined.room_id().to_owned(), *content, send_at)
+                    .await
+            })
+            .await?
+    }
+
+    /// the messages scheduled for this room, the next due first
+    pub async fn scheduled_messages(&self) -> Result<Vec<ScheduledMessage>> {
+        let room_id = self.room_id().to_owned();
+        let scheduler = self.scheduler.clone();
+        RUNTIME
+            .spawn(async move { Ok(scheduler.scheduled(Some(&room_id)).await) })
+            .await?
+    }
+
+    /// `false` if it was sent or cancelled already, or is for another room
+    pub async fn cancel_scheduled_message(&self, id: String) -> Result<bool> {
+        let room_id = self.room_id().to_owned();
+        let scheduler = self.scheduler.clone();
+        RUNTIME
+            .spawn(async move { scheduler.cancel(&id, Some(&room_id)).await })
+            .await?
+    }
+
+    /// Upload the file right away, so only the message is left to send
+    /// later. `kind` is one of `image`, `audio`, `voice`, `video` and
+    /// `file`, the duration only matters for voice messages.
+    pub async fn prepare_scheduled_media(
+        &self,
+        kind: String,
+        uri: String,
+        name: String,
+        mimetype: String,
+        millis: Option<u64>,
+        size: Option<u32>,
+    ) -> Result<ScheduledContent> {
+        let SdkRoom::Joined(room) = self.room.clone() else {
+            bail!("Can't schedule messages in a room we are not in");
+        };
+        let client = self.room.client();
+        let path = PathBuf::from(uri);
+        let mime_type = mimetype.parse::<mime::Mime>()?;
+
+        RUNTIME
+            .spawn(async move {
+                let buf = std::fs::read(path).context("File should be read")?;
+                if kind == "voice" {
+                    let content =
+                        voice_message_content(&client, &room, buf, name, mime_type, millis, size)
+                            .await?;
+                    return Ok(ScheduledContent {
+                        event_type: MessageLikeEventType::RoomMessage.to_string(),
+                        content,
+                    });
+                }
+                let upload = if room.is_encrypted().await? {
+                    let file = client
+                        .prepare_encrypted_file(&mime_type, &mut buf.as_slice())
+                        .await?;
+                    Upload::Encrypted(file)
+                } else {
+                    let response = client.media().upload(&mime_type, buf).await?;
+                    Upload::Plain(response.content_uri)
+                };
+                let size = size.map(UInt::from);
+                let msgtype = match kind.as_str() {
+                    "image" => {
+                        let mut content = match upload {
+                            Upload::Plain(uri) => ImageMessageEventContent::plain(name, uri),
+                            Upload::Encrypted(file) => {
+                                ImageMessageEventContent::encrypted(name, file)
+                            }
+                        };
+                        content.info = Some(Box::new(assign!(ImageInfo::new(), {
+                            mimetype: Some(mimetype),
+                            size,
+                        })));
+                        MessageType::Image(content)
+                    }
+                    "audio" => {
+                        let mut content = match upload {
+                            Upload::Plain(uri) => AudioMessageEventContent::plain(name, uri),
+                            Upload::Encrypted(file) => {
+                                AudioMessageEventContent::encrypted(name, file)
+                            }
+                        };
+                        content.info = Some(Box::new(assign!(AudioInfo::new(), {
+                            mimetype: Some(mimetype),
+                            size,
+                        })));
+                        MessageType::Audio(content)
+                    }
+                    "video" => {
+                        let mut content = match upload {
+                            Upload::Plain(uri) => VideoMessageEventContent::plain(name, uri),
+                            Upload::Encrypted(file) => {
+                                VideoMessageEventContent::encrypted(name, file)
+                            }
+                        };
+                        content.info = Some(Box::new(assign!(VideoInfo::new(), {
+                            mimetype: Some(mimetype),
+                            size,
+                        })));
+                        MessageType::Video(content)
+                    }
+                    "file" => {
+                        let mut content = match upload {
+                            Upload::Plain(uri) => FileMessageEventContent::plain(name, uri),
+                            Upload::Encrypted(file) => {
+                                FileMessageEventContent::encrypted(name, file)
+                            }
+                        };
+                        content.info = Some(Box::new(assign!(FileInfo::new(), {
+                            mimetype: Some(mimetype),
+                            size,
+                        })));
+                        MessageType::File(content)
+                    }
+                    _ => bail!("Unknown kind of media: {kind}"),
+                };
+                ScheduledContent::room_message(msgtype)
+            })
+            .await?
+    }
+}
+
+impl Client {
+    /// Schedule the acter event of a draft, see `Room::schedule_message`
+    pub(crate) async fn schedule<C: MessageLikeEventContent>(
+        &self,
+        room_id: OwnedRoomId,
+        content: &C,
+        send_at: u64,
+    ) -> Result<String> {
+        let content = ScheduledContent::new(content)?;
+        self.scheduler.schedule(room_id, content, send_at).await
+    }
+
+    /// the messages scheduled in all rooms, the next due first
+    pub async fn scheduled_messages(&self) -> Result<Vec<ScheduledMessage>> {
+        let scheduler = self.scheduler.clone();
+        RUNTIME
+            .spawn(async move { Ok(scheduler.scheduled(None).await) })
+            .await?
+    }
+
+    /// `false` if it was sent or cancelled already
+    pub async fn cancel_scheduled_message(&self, id: String) -> Result<bool> {
+        let scheduler = self.scheduler.clone();
+        RUNTIME
+            .spawn(async move { scheduler.cancel(&id, None).await })
+            .await?
+    }
+
+    /// Fires whenever messages were scheduled, cancelled or handed on to
+    /// be sent
+    pub fn scheduled_messages_rx(&self) -> impl Stream<Item = bool> {
+        BroadcastStream::new(self.scheduler.subscribe()).map(|_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::room_id;
+
+    use super::*;
+    use crate::testing::fresh_store;
+
+    fn at(millis: u64) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch(UInt::try_from(millis).unwrap())
+    }
+
+    fn text(body: &str) -> Result<ScheduledContent> {
+        new_scheduled_text_message(body.to_owned())
+    }
+
+    #[tokio::test]
+    async fn due_ones_are_taken_the_others_wait() -> Result<()> {
+        let scheduler = Scheduler::load(fresh_store().await?).await;
+        let room_id = room_id!("!room:example.org");
+        scheduler
+            .schedule(room_id.to_owned(), text("later")?, 3000)
+            .await?;
+        scheduler
+            .schedule(room_id.to_owned(), text("first")?, 1000)
+            .await?;
+        scheduler
+            .schedule(room_id.to_owned(), text("now")?, 2000)
+            .await?;
+
+        let mut queue = scheduler.queue.lock().await;
+        let due = Scheduler::take_due(&mut queue, at(2000));
+        let previews = due.iter().filter_map(|m| m.preview()).collect::<Vec<_>>();
+        assert_eq!(previews, ["first", "now"]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].preview().as_deref(), Some("later"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scheduled_are_sorted_and_filtered_by_room() -> Result<()> {
+        let scheduler = Scheduler::load(fresh_store().await?).await;
+        let room_id = room_id!("!room:example.org");
+        let other_id = room_id!("!other:example.org");
+        scheduler
+            .schedule(room_id.to_owned(), text("b")?, 2000)
+            .await?;
+        scheduler
+            .schedule(other_id.to_owned(), text("c")?, 3000)
+            .await?;
+        scheduler
+            .schedule(room_id.to_owned(), text("a")?, 1000)
+            .await?;
+
+        let all = scheduler.scheduled(None).await;
+        let previews = all.iter().filter_map(|m| m.preview()).collect::<Vec<_>>();
+        assert_eq!(previews, ["a", "b", "c"]);
+        let of_room = scheduler.scheduled(Some(room_id)).await;
+        assert!(of_room.iter().all(|m| m.room_id == room_id));
+        assert_eq!(of_room.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cancelled_ones_are_gone() -> Result<()> {
+        let scheduler = Scheduler::load(fresh_store().await?).await;
+        let room_id = room_id!("!room:example.org");
+        let id = scheduler
+            .schedule(room_id.to_owned(), text("hi")?, 1000)
+            .await?;
+        // not from another room
+        let other_id = room_id!("!other:example.org");
+        assert!(!scheduler.cancel(&id, Some(other_id)).await?);
+        assert!(scheduler.cancel(&id, Some(room_id)).await?);
+        assert!(!scheduler.cancel(&id, None).await?);
+        assert!(scheduler.scheduled(None).await.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scheduled_ones_survive_a_restart() -> Result<()> {
+        let store = fresh_store().await?;
+        let scheduler = Scheduler::load(store.clone()).await;
+        let room_id = room_id!("!room:example.org");
+        let kept = scheduler
+            .schedule(room_id.to_owned(), text("kept")?, 1000)
+            .await?;
+        let cancelled = scheduler
+            .schedule(room_id.to_owned(), text("gone")?, 2000)
+            .await?;
+        scheduler.cancel(&cancelled, None).await?;
+
+        let reloaded = Scheduler::load(store).await.scheduled(None).await;
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded[0].id(), kept);
+        assert_eq!(reloaded[0].preview().as_deref(), Some("kept"));
+        assert_eq!(reloaded[0].send_at(), 1000);
+        Ok(())
+    }
+}
@@ -1,16 +1,48 @@
-use anyhow::Result;
-use matrix_sdk::ruma::{
-    api::client::directory::get_public_rooms_filtered::v3::{
-        Request as FilteredRequest, Response as FilteredResponse,
+use acter_core::{store::Store, Error};
+use anyhow::{Context, Result};
+use matrix_sdk::{
+    room::Room as SdkRoom,
+    ruma::{
+        api::client::{
+            directory::get_public_rooms_filtered::v3::{
+                Request as FilteredRequest, Response as FilteredResponse,
+            },
+            filter::RoomEventFilter,
+            search::search_events::v3::{Categories, Criteria, Request as SearchRequest},
+        },
+        assign,
+        directory::{Filter, PublicRoomJoinRule, PublicRoomsChunk, RoomNetwork, RoomTypeFilter},
+        events::{room::message::OriginalSyncRoomMessageEvent, AnySyncTimelineEvent},
+        room::RoomType,
+        serde::Raw,
+        EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedRoomAliasId,
+        OwnedRoomId, OwnedServerName, RoomId,
     },
-    assign,
-    directory::{Filter, PublicRoomJoinRule, PublicRoomsChunk, RoomNetwork, RoomTypeFilter},
-    room::RoomType,
-    OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedServerName,
+    Client as SdkClient,
 };
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
 use tracing::{error, trace};
 
-use super::{client::Client, RUNTIME};
+use super::{
+    client::Client,
+    convo::Convo,
+    message::{sync_event_to_message, RoomMessage},
+    spaces::Space,
+    RUNTIME,
+};
+
+/// how many messages per room the local index keeps
+const LOCAL_INDEX_SIZE: usize = 10_000;
+/// how many messages of the local index are persisted under one key
+const LOCAL_INDEX_CHUNK_SIZE: usize = 200;
+/// how many matches of the local index are returned per page
+const LOCAL_PAGE_SIZE: usize = 20;
 
 pub struct PublicSearchResultItem {
     chunk: PublicRoomsChunk,
@@ -150,3 +182,635 @@ impl Client {
             .await?
     }
 }
+
+/// A message of the local index
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct IndexedMessage {
+    /// lowercased, to match the query against
+    body: String,
+    event_id: OwnedEventId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    event: Raw<AnySyncTimelineEvent>,
+    sent_by_me: bool,
+}
+
+impl IndexedMessage {
+    fn new(
+        ev: &OriginalSyncRoomMessageEvent,
+        event: Raw<AnySyncTimelineEvent>,
+        sent_by_me: bool,
+    ) -> Self {
+        IndexedMessage {
+            body: ev.content.body().to_lowercase(),
+            event_id: ev.event_id.clone(),
+            origin_server_ts: ev.origin_server_ts,
+            event,
+            sent_by_me,
+        }
+    }
+
+    /// all words of the query have to be found
+    fn matches(&self, words: &[String]) -> bool {
+        words.iter().all(|w| self.body.contains(w.as_str()))
+    }
+
+    fn to_message(&self, room_id: &RoomId) -> Option<RoomMessage> {
+        let event = self
+            .event
+            .deserialize_as::<OriginalSyncRoomMessageEvent>()
+            .ok()?;
+        Some(RoomMessage::room_message_from_sync_event(
+            event,
+            room_id.to_owned(),
+            self.sent_by_me,
+        ))
+    }
+}
+
+/// Which chunks of a room index are persisted, `first..=last`
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+struct IndexChunks {
+    first: u64,
+    last: u64,
+}
+
+/// The local index of one room, oldest first, in the chunks it is
+/// persisted in. There always is a last chunk to add to.
+#[derive(Debug)]
+struct RoomIndex {
+    chunks: IndexChunks,
+    messages: VecDeque<Vec<IndexedMessage>>,
+}
+
+impl Default for RoomIndex {
+    fn default() -> Self {
+        RoomIndex {
+            chunks: Default::default(),
+            messages: VecDeque::from([vec![]]),
+        }
+    }
+}
+
+impl RoomIndex {
+    /// Add the message, returns the numbers of the chunks that changed
+    fn push(&mut self, msg: IndexedMessage) -> Vec<u64> {
+        if self.iter().any(|m| m.event_id == msg.event_id) {
+            return vec![];
+        }
+        if self.messages.back().map(Vec::len).unwrap_or_default() >= LOCAL_INDEX_CHUNK_SIZE {
+            self.chunks.last += 1;
+            self.messages.push_back(vec![]);
+        }
+        self.messages
+            .back_mut()
+            .expect("there always is a last chunk")
+            .push(msg);
+        let mut changed = vec![self.chunks.last];
+        if self.messages.len() > LOCAL_INDEX_SIZE / LOCAL_INDEX_CHUNK_SIZE {
+            // the oldest chunk is dropped, persisted as empty
+            self.messages.pop_front();
+            changed.push(self.chunks.first);
+            self.chunks.first += 1;
+        }
+        changed
+    }
+
+    /// Remove the message, returns the number of the chunk it was in
+    fn remove(&mut self, event_id: &EventId) -> Option<u64> {
+        let first = self.chunks.first;
+        self.messages
+            .iter_mut()
+            .zip(first..)
+            .find_map(|(chunk, number)| {
+                let pos = chunk.iter().position(|m| m.event_id == event_id)?;
+                chunk.remove(pos);
+                Some(number)
+            })
+    }
+
+    /// the messages of the chunk, none if it was dropped
+    fn chunk(&self, number: u64) -> &[IndexedMessage] {
+        number
+            .checked_sub(self.chunks.first)
+            .and_then(|idx| self.messages.get(idx as usize))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &IndexedMessage> {
+        self.messages.iter().flatten()
+    }
+}
+
+/// newest first
+fn rank(mut found: Vec<(OwnedRoomId, IndexedMessage)>) -> Vec<(OwnedRoomId, IndexedMessage)> {
+    found.sort_by_key(|(_, m)| Reverse(m.origin_server_ts));
+    found
+}
+
+/// The messages of encrypted rooms, as the server can't search those for
+/// us. Persisted in the store, a room is loaded when it is first used.
+#[derive(Clone)]
+pub(crate) struct MessageIndex {
+    store: Store,
+    rooms: Arc<Mutex<HashMap<OwnedRoomId, RoomIndex>>>,
+}
+
+impl std::fmt::Debug for MessageIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageIndex").finish_non_exhaustive()
+    }
+}
+
+impl MessageIndex {
+    pub(crate) fn new(store: Store) -> Self {
+        MessageIndex {
+            store,
+            rooms: Default::default(),
+        }
+    }
+
+    fn chunks_key(room_id: &RoomId) -> String {
+        format!("{room_id}::message_index")
+    }
+
+    fn chunk_key(room_id: &RoomId, number: u64) -> String {
+        format!("{room_id}::message_index::{number}")
+    }
+
+    async fn load(&self, room_id: &RoomId) -> Result<RoomIndex> {
+        let chunks = match self
+            .store
+            .get_raw::<IndexChunks>(&Self::chunks_key(room_id))
+            .await
+        {
+            Ok(chunks) => chunks,
+            Err(Error::ModelNotFound) => return Ok(RoomIndex::default()),
+            Err(error) => return Err(error.into()),
+        };
+        let mut messages = VecDeque::new();
+        for number in chunks.first..=chunks.last {
+            let key = Self::chunk_key(room_id, number);
+            messages.push_back(match self.store.get_raw(&key).await {
+                Ok(chunk) => chunk,
+                Err(Error::ModelNotFound) => vec![],
+                Err(error) => return Err(error.into()),
+            });
+        }
+        Ok(RoomIndex { chunks, messages })
+    }
+
+    /// the index of the room, loaded from the store if we don't have it yet
+    async fn room<'a>(
+        &self,
+        rooms: &'a mut HashMap<OwnedRoomId, RoomIndex>,
+        room_id: &RoomId,
+    ) -> Result<&'a mut RoomIndex> {
+        if !rooms.contains_key(room_id) {
+            let index = self.load(room_id).await?;
+            rooms.insert(room_id.to_owned(), index);
+        }
+        Ok(rooms.get_mut(room_id).expect("loaded above"))
+    }
+
+    async fn persist(&self, room_id: &RoomId, index: &RoomIndex, changed: Vec<u64>) -> Result<()> {
+        for number in changed {
+            let key = Self::chunk_key(room_id, number);
+            self.store.set_raw(&key, &index.chunk(number)).await?;
+        }
+        self.store
+            .set_raw(&Self::chunks_key(room_id), &index.chunks)
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn add(
+        &self,
+        room_id: &RoomId,
+        ev: &OriginalSyncRoomMessageEvent,
+        event: Raw<AnySyncTimelineEvent>,
+        sent_by_me: bool,
+    ) -> Result<()> {
+        let mut rooms = self.rooms.lock().await;
+        let index = self.room(&mut rooms, room_id).await?;
+        let changed = index.push(IndexedMessage::new(ev, event, sent_by_me));
+        if changed.is_empty() {
+            return Ok(());
+        }
+        self.persist(room_id, index, changed).await
+    }
+
+    pub(crate) async fn remove(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+        let mut rooms = self.rooms.lock().await;
+        let index = self.room(&mut rooms, room_id).await?;
+        let Some(number) = index.remove(event_id) else {
+            return Ok(());
+        };
+        self.persist(room_id, index, vec![number]).await
+    }
+
+    /// the matches of all rooms, ranked
+    async fn search(
+        &self,
+        room_ids: &[OwnedRoomId],
+        words: &[String],
+    ) -> Result<Vec<(OwnedRoomId, IndexedMessage)>> {
+        let mut rooms = self.rooms.lock().await;
+        let mut found = vec![];
+        for room_id in room_ids {
+            let index = self.room(&mut rooms, room_id).await?;
+            found.extend(
+                index
+                    .iter()
+                    .filter(|m| m.matches(words))
+                    .map(|m| (room_id.clone(), m.clone())),
+            );
+        }
+        Ok(rank(found))
+    }
+}
+
+/// Where the next page of a message search goes on, in the local index
+/// and on the server. Opaque to the caller.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct SearchToken {
+    /// how many local matches were returned already
+    local: usize,
+    /// the `next_batch` of the server
+    server: Option<String>,
+    server_done: bool,
+    /// the estimate of the server
+    server_count: Option<u64>,
+}
+
+impl SearchToken {
+    fn parse(from: Option<String>) -> Result<Self> {
+        match from {
+            Some(from) => serde_json::from_str(&from).context("Invalid search token"),
+            None => Ok(Default::default()),
+        }
+    }
+}
+
+fn query_words(query: &str) -> Vec<String> {
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Put the page of local matches after `token` together with the page of
+/// the server, `None` if it wasn't asked
+fn merge_page(
+    token: &SearchToken,
+    local_count: usize,
+    local_page: Vec<RoomMessage>,
+    words: Vec<String>,
+    server: Option<MessageSearchResult>,
+) -> MessageSearchResult {
+    let mut next = SearchToken {
+        local: (token.local + LOCAL_PAGE_SIZE).min(local_count),
+        ..token.clone()
+    };
+    let mut messages = local_page;
+    let mut highlights = vec![];
+    match server {
+        Some(result) => {
+            messages.extend(result.messages);
+            highlights = result.highlights;
+            next.server_count = result.count.or(token.server_count);
+            next.server_done = result.next_batch.is_none();
+            next.server = result.next_batch;
+        }
+        None => {
+            // nothing (more) to search there
+            next.server_done = true;
+            next.server_count = token.server_count.or(Some(0));
+        }
+    }
+    for word in words {
+        if !highlights.contains(&word) {
+            highlights.push(word);
+        }
+    }
+    let has_more = next.local < local_count || !next.server_done;
+    MessageSearchResult {
+        messages,
+        highlights,
+        count: next.server_count.map(|c| c + local_count as u64),
+        next_batch: has_more
+            .then(|| serde_json::to_string(&next).ok())
+            .flatten(),
+    }
+}
+
+/// A page of messages matching the search
+#[derive(Clone, Debug)]
+pub struct MessageSearchResult {
+    messages: Vec<RoomMessage>,
+    highlights: Vec<String>,
+    next_batch: Option<String>,
+    count: Option<u64>,
+}
+
+impl MessageSearchResult {
+    pub fn messages(&self) -> Vec<RoomMessage> {
+        self.messages.clone()
+    }
+
+    /// the words to highlight in the messages
+    pub fn highlights(&self) -> Vec<String> {
+        self.highlights.clone()
+    }
+
+    /// to be used for the next `from`
+    pub fn next_batch(&self) -> Option<String> {
+        self.next_batch.clone()
+    }
+
+    /// an estimated total of matches
+    pub fn count(&self) -> Option<u64> {
+        self.count
+    }
+}
+
+pub(crate) async fn is_encrypted(room: &SdkRoom) -> bool {
+    match room.is_encrypted().await {
+        Ok(encrypted) => encrypted,
+        Err(error) => {
+            error!(?error, room_id = ?room.room_id(), "checking encryption failed");
+            false
+        }
+    }
+}
+
+async fn search_server(
+    client: &SdkClient,
+    room_ids: Option<Vec<OwnedRoomId>>,
+    query: String,
+    from: Option<String>,
+) -> Result<MessageSearchResult> {
+    let filter = assign!(RoomEventFilter::default(), { rooms: room_ids });
+    let criteria = assign!(Criteria::new(query), { filter });
+    let categories = assign!(Categories::new(), { room_events: Some(criteria) });
+    let request = assign!(SearchRequest::new(categories), { next_batch: from });
+    let response = client.send(request, None).await?;
+    let room_events = response.search_categories.room_events;
+
+    let messages = room_events
+        .results
+        .into_iter()
+        .filter_map(|result| {
+            let event = result.result?.cast();
+            let room_id = event.get_field::<OwnedRoomId>("room_id").ok()??;
+            sync_event_to_message(&event, room_id)
+        })
+        .collect();
+    Ok(MessageSearchResult {
+        messages,
+        highlights: room_events.highlights,
+        next_batch: room_events.next_batch,
+        count: room_events.count.map(Into::into),
+    })
+}
+
+/// Search the messages of `rooms`, of all joined rooms if `None`. The
+/// server searches the unencrypted ones, the local index the others. Each
+/// page has the next local matches, followed by the next ones of the server.
+async fn search_messages(
+    client: SdkClient,
+    index: MessageIndex,
+    rooms: Option<Vec<SdkRoom>>,
+    query: String,
+    from: Option<String>,
+) -> Result<MessageSearchResult> {
+    let token = SearchToken::parse(from)?;
+    let words = query_words(&query);
+    let rooms =
+        rooms.unwrap_or_else(|| client.joined_rooms().into_iter().map(Into::into).collect());
+    let mut encrypted = vec![];
+    let mut unencrypted = vec![];
+    for room in rooms {
+        if is_encrypted(&room).await {
+            encrypted.push(room.room_id().to_owned());
+        } else {
+            unencrypted.push(room.room_id().to_owned());
+        }
+    }
+    trace!(?encrypted, ?unencrypted, ?token, "searching messages");
+
+    let found = index.search(&encrypted, &words).await?;
+    let local_page = found
+        .iter()
+        .skip(token.local)
+        .take(LOCAL_PAGE_SIZE)
+        .filter_map(|(room_id, m)| m.to_message(room_id))
+        .collect();
+    let server = if unencrypted.is_empty() || token.server_done {
+        None
+    } else {
+        let from = token.server.clone();
+        Some(search_server(&client, Some(unencrypted), query, from).await?)
+    };
+    Ok(merge_page(&token, found.len(), local_page, words, server))
+}
+
+impl Client {
+    /// Search the messages of all joined rooms
+    pub async fn search_messages(
+        &self,
+        query: String,
+        from: Option<String>,
+    ) -> Result<MessageSearchResult> {
+        let client = self.core.client().clone();
+        let index = self.convo_controller.message_index();
+        RUNTIME
+            .spawn(async move { search_messages(client, index, None, query, from).await })
+            .await?
+    }
+}
+
+impl Convo {
+    /// Search the messages of this convo
+    pub async fn search_messages(
+        &self,
+        query: String,
+        from: Option<String>,
+    ) -> Result<MessageSearchResult> {
+        let room = self.room.clone();
+        let index = self.controller.message_index();
+        RUNTIME
+            .spawn(async move {
+                let client = room.client();
+                search_messages(client, index, Some(vec![room]), query, from).await
+            })
+            .await?
+    }
+}
+
+impl Space {
+    /// Search the messages of this space and the convos in it we joined
+    pub async fn search_messages(
+        &self,
+        query: String,
+        from: Option<String>,
+    ) -> Result<MessageSearchResult> {
+        let room = self.room.clone();
+        let core = self.client.core.clone();
+        let index = self.client.convo_controller.message_index();
+        RUNTIME
+            .spawn(async move {
+                let client = room.client();
+                let children = core.space_relations(&room).await?.children();
+                let mut rooms = vec![room];
+                rooms.extend(
+                    children
+                        .into_iter()
+                        .filter_map(|child| client.get_joined_room(&child.room_id()))
+                        .map(Into::into),
+                );
+                search_messages(client, index, Some(rooms), query, from).await
+            })
+            .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matrix_sdk::ruma::{event_id, room_id};
+    use serde_json::{json, value::to_raw_value};
+
+    fn indexed(event_id: &str, ts: u64, body: &str) -> IndexedMessage {
+        let event = Raw::from_json(
+            to_raw_value(&json!({
+                "type": "m.room.message",
+                "event_id": event_id,
+                "sender": "@me:example.org",
+                "origin_server_ts": ts,
+                "content": { "msgtype": "m.text", "body": body },
+            }))
+            .unwrap(),
+        );
+        let ev = event
+            .deserialize_as::<OriginalSyncRoomMessageEvent>()
+            .unwrap();
+        IndexedMessage::new(&ev, event, true)
+    }
+
+    fn page_of(count: usize) -> Vec<RoomMessage> {
+        (0..count)
+            .filter_map(|i| indexed(&format!("$e{i}"), 1, "hi").to_message(room_id!("!r:x.org")))
+            .collect()
+    }
+
+    #[test]
+    fn matches_all_words_ignoring_case() {
+        let msg = indexed("$a", 1, "Hello big World");
+        assert!(msg.matches(&query_words("WORLD hello")));
+        assert!(!msg.matches(&query_words("hello moon")));
+    }
+
+    #[test]
+    fn ranks_newest_first() {
+        let room_a = room_id!("!a:example.org").to_owned();
+        let room_b = room_id!("!b:example.org").to_owned();
+        let found = rank(vec![
+            (room_a.clone(), indexed("$old", 1, "hi")),
+            (room_b, indexed("$new", 3, "hi")),
+            (room_a, indexed("$mid", 2, "hi")),
+        ]);
+        let ids = found
+            .iter()
+            .map(|(_, m)| m.event_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["$new", "$mid", "$old"]);
+    }
+
+    #[test]
+    fn room_index_drops_oldest_chunk() {
+        let mut index = RoomIndex::default();
+        for i in 0..LOCAL_INDEX_SIZE {
+            index.push(indexed(&format!("$e{i}"), i as u64, "hi"));
+        }
+        assert_eq!(index.chunks.first, 0);
+        assert_eq!(index.iter().count(), LOCAL_INDEX_SIZE);
+        // already known
+        assert!(index.push(indexed("$e1", 1, "hi")).is_empty());
+
+        let last = (LOCAL_INDEX_SIZE / LOCAL_INDEX_CHUNK_SIZE) as u64;
+        assert_eq!(index.push(indexed("$new", 0, "hi")), [last, 0]);
+        assert_eq!(index.chunks.first, 1);
+        assert!(index.chunk(0).is_empty());
+        assert_eq!(
+            index.iter().count(),
+            LOCAL_INDEX_SIZE - LOCAL_INDEX_CHUNK_SIZE + 1
+        );
+
+        let removed = format!("$e{}", LOCAL_INDEX_CHUNK_SIZE * 2);
+        assert_eq!(index.remove(&EventId::parse(removed).unwrap()), Some(2));
+        assert_eq!(index.remove(event_id!("$e0")), None); // dropped before
+    }
+
+    #[test]
+    fn pages_through_local_matches() -> Result<()> {
+        let local_count = 45;
+        let first = merge_page(
+            &SearchToken::parse(None)?,
+            local_count,
+            page_of(20),
+            query_words("hi"),
+            None,
+        );
+        assert_eq!(first.messages.len(), 20);
+        assert_eq!(first.count, Some(45));
+
+        let token = SearchToken::parse(first.next_batch)?;
+        assert_eq!(token.local, 20);
+        let second = merge_page(&token, local_count, page_of(20), vec![], None);
+        let token = SearchToken::parse(second.next_batch)?;
+        assert_eq!(token.local, 40);
+
+        let last = merge_page(&token, local_count, page_of(5), vec![], None);
+        assert_eq!(last.count, Some(45));
+        assert_eq!(last.next_batch, None);
+        assert!(SearchToken::parse(Some("40".to_owned())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pages_through_local_and_server_matches() -> Result<()> {
+        let server = MessageSearchResult {
+            messages: page_of(10),
+            highlights: vec!["hello".to_owned()],
+            next_batch: Some("server-2".to_owned()),
+            count: Some(30),
+        };
+        let first = merge_page(
+            &SearchToken::default(),
+            3,
+            page_of(3),
+            query_words("Hello World"),
+            Some(server),
+        );
+        assert_eq!(first.messages.len(), 13);
+        assert_eq!(first.highlights, ["hello", "world"]);
+        assert_eq!(first.count, Some(33));
+
+        let token = SearchToken::parse(first.next_batch)?;
+        assert_eq!(token.local, 3);
+        assert_eq!(token.server.as_deref(), Some("server-2"));
+        let server = MessageSearchResult {
+            messages: page_of(10),
+            highlights: vec![],
+            next_batch: None,
+            count: None,
+        };
+        let last = merge_page(&token, 3, vec![], vec![], Some(server));
+        // the estimate of the first page is kept
+        assert_eq!(last.count, Some(33));
+        assert_eq!(last.next_batch, None);
+        Ok(())
+    }
+}
@@ -59,6 +59,7 @@ use super::{
     common::OptionBuffer,
     room::Room,
     search::PublicSearchResult,
+    templates::builtin_template_source,
     RUNTIME,
 };
 
@@ -595,15 +596,15 @@ impl Space {
     }
 
     pub async fn create_onboarding_data(&self) -> Result<()> {
-        let mut engine = Engine::with_template(std::include_str!("../templates/onboarding.toml"))?;
+        let mut engine = Engine::with_template(
+            builtin_template_source("onboarding").expect("onboarding template is builtin"),
+        )?;
         engine
             .add_user("main".to_owned(), self.client.core.clone())
             .await?;
-        engine.add_ref(
-            "space".to_owned(),
-            "space".to_owned(),
-            self.room.room_id().to_string(),
-        )?;
+        engine
+            .add_space("space".to_owned(), self.room.room_id().to_owned())
+            .await?;
 
         let mut executer = engine.execute()?;
         while let Some(i) = executer.next().await {
@@ -811,7 +812,10 @@ impl Client {
             if let Some(room) = self.get_room(&room_id) {
                 let space = Space {
                     client: self.clone(),
-                    inner: Room { room },
+                    inner: Room {
+                        room,
+                        scheduler: self.scheduler.clone(),
+                    },
                 };
                 return Ok(space);
             }
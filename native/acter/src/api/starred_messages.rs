@@ -0,0 +1,112 @@
+use acter_core::events::starred::StarredMessagesEventContent;
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use matrix_sdk::{ruma::EventId, Client as SdkClient};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::warn;
+
+use super::{
+    client::Client,
+    message::{sync_event_to_message, RoomMessage},
+    room::Room,
+    RUNTIME,
+};
+
+async fn starred_content(client: &SdkClient) -> Result<StarredMessagesEventContent> {
+    let Some(raw) = client
+        .account()
+        .account_data::<StarredMessagesEventContent>()
+        .await?
+    else {
+        return Ok(Default::default());
+    };
+    Ok(raw.deserialize()?)
+}
+
+impl Room {
+    /// star the message for ourselves, `false` if it was starred already
+    pub async fn star_message(&self, event_id: String) -> Result<bool> {
+        let room = self.room.clone();
+        let event_id = EventId::parse(event_id)?;
+        RUNTIME
+            .spawn(async move {
+                let client = room.client();
+                let mut starred = starred_content(&client).await?;
+                if !starred.star(room.room_id().to_owned(), event_id) {
+                    return Ok(false);
+                }
+                client.account().set_account_data(starred).await?;
+                Ok(true)
+            })
+            .await?
+    }
+
+    /// `false` if the message wasn't starred
+    pub async fn unstar_message(&self, event_id: String) -> Result<bool> {
+        let room = self.room.clone();
+        let event_id = EventId::parse(event_id)?;
+        RUNTIME
+            .spawn(async move {
+                let client = room.client();
+                let mut starred = starred_content(&client).await?;
+                if !starred.unstar(room.room_id(), &event_id) {
+                    return Ok(false);
+                }
+                client.account().set_account_data(starred).await?;
+                Ok(true)
+            })
+            .await?
+    }
+
+    /// the event ids of the messages of this room we starred
+    pub async fn starred_event_ids(&self) -> Result<Vec<String>> {
+        let room = self.room.clone();
+        RUNTIME
+            .spawn(async move {
+                let starred = starred_content(&room.client()).await?;
+                Ok(starred
+                    .messages
+                    .into_iter()
+                    .filter(|m| m.room_id == room.room_id())
+                    .map(|m| m.event_id.to_string())
+                    .collect())
+            })
+            .await?
+    }
+}
+
+impl Client {
+    /// the messages we starred in all rooms, latest first. Messages we
+    /// can't fetch anymore are left out.
+    pub async fn starred_messages(&self) -> Result<Vec<RoomMessage>> {
+        let client = self.core.client().clone();
+        let convo_controller = self.convo_controller.clone();
+        RUNTIME
+            .spawn(async move {
+                let starred = starred_content(&client).await?;
+                let mut messages = vec![];
+                for message in starred.messages.into_iter().rev() {
+                    let Some(room) = client.get_room(&message.room_id) else {
+                        warn!(room_id = ?message.room_id, "starred message of unknown room");
+                        continue;
+                    };
+                    match room.event(&message.event_id).await {
+                        Ok(ev) => messages.extend(
+                            sync_event_to_message(&ev.event.cast(), message.room_id)
+                                .map(|msg| convo_controller.decorate(msg, &room)),
+                        ),
+                        Err(error) => {
+                            warn!(?error, event_id = ?message.event_id, "fetching starred message failed")
+                        }
+                    }
+                }
+                Ok(messages)
+            })
+            .await?
+    }
+
+    /// Fires whenever we starred or unstarred a message, on any device
+    pub fn starred_messages_rx(&self) -> impl Stream<Item = bool> {
+        BroadcastStream::new(self.convo_controller.subscribe_starred()).map(|_| true)
+    }
+}
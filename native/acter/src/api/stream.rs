@@ -1,58 +1,497 @@
 use anyhow::{bail, Result};
-use eyeball_im::VectorDiff;
-use futures::stream::{Stream, StreamExt};
+use eyeball_im::{Vector, VectorDiff};
+use futures::{
+    future::Either,
+    stream::{self, Stream, StreamExt},
+};
 use matrix_sdk::{
     room::Room,
     ruma::{
+        api::client::relations::get_relating_events_with_rel_type,
         events::{
-            relation::Replacement,
+            relation::{RelationType, Replacement},
             room::message::{MessageType, Relation, RoomMessageEvent, RoomMessageEventContent},
         },
-        EventId,
+        EventId, OwnedEventId,
     },
 };
-use matrix_sdk_ui::timeline::{BackPaginationStatus, PaginationOptions, Timeline};
+use matrix_sdk_ui::timeline::{BackPaginationStatus, PaginationOptions, Timeline, TimelineItem};
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use super::{
     convo::ConvoController,
-    message::{timeline_item_to_message, RoomMessage},
+    message::{sync_event_to_message, thread_root, timeline_item_to_message, RoomMessage},
+    polls::{with_poll_desc, PollDesc},
     RUNTIME,
 };
 
+/// What a `TimelineDiff` does to the list of messages
+#[derive(Eq, PartialEq, Clone, Copy, strum::Display, strum::EnumString, Debug)]
+#[strum(serialize_all = "PascalCase")]
+pub enum TimelineDiffAction {
+    Append,
+    Insert,
+    Set,
+    Remove,
+    PushBack,
+    PushFront,
+    PopBack,
+    PopFront,
+    Clear,
+    /// keep only the first `index` messages
+    Truncate,
+    Reset,
+}
+
+#[derive(Clone, Debug)]
 pub struct TimelineDiff {
-    action: String,
+    action: TimelineDiffAction,
     values: Option<Vec<RoomMessage>>,
     index: Option<usize>,
     value: Option<RoomMessage>,
 }
 
 impl TimelineDiff {
+    fn new(action: TimelineDiffAction) -> Self {
+        TimelineDiff {
+            action,
+            values: None,
+            index: None,
+            value: None,
+        }
+    }
+
+    fn with_values(action: TimelineDiffAction, values: Vec<RoomMessage>) -> Self {
+        TimelineDiff {
+            values: Some(values),
+            ..TimelineDiff::new(action)
+        }
+    }
+
+    fn with_index(action: TimelineDiffAction, index: usize) -> Self {
+        TimelineDiff {
+            index: Some(index),
+            ..TimelineDiff::new(action)
+        }
+    }
+
+    fn with_value(action: TimelineDiffAction, index: Option<usize>, value: RoomMessage) -> Self {
+        TimelineDiff {
+            index,
+            value: Some(value),
+            ..TimelineDiff::new(action)
+        }
+    }
+
+    fn from_vector_diff(
+        diff: VectorDiff<Arc<TimelineItem>>,
+        room: &Room,
+        convo_controller: &ConvoController,
+    ) -> Self {
+        let to_message = |item| to_message(item, room, convo_controller);
+        let to_messages = |items: Vector<Arc<TimelineItem>>| -> Vec<RoomMessage> {
+            items.into_iter().map(to_message).collect()
+        };
+        match diff {
+            VectorDiff::Append { values } => {
+                TimelineDiff::with_values(TimelineDiffAction::Append, to_messages(values))
+            }
+            VectorDiff::Insert { index, value } => {
+                TimelineDiff::with_value(TimelineDiffAction::Insert, Some(index), to_message(value))
+            }
+            VectorDiff::Set { index, value } => {
+                TimelineDiff::with_value(TimelineDiffAction::Set, Some(index), to_message(value))
+            }
+            VectorDiff::Remove { index } => {
+                TimelineDiff::with_index(TimelineDiffAction::Remove, index)
+            }
+            VectorDiff::PushBack { value } => {
+                TimelineDiff::with_value(TimelineDiffAction::PushBack, None, to_message(value))
+            }
+            VectorDiff::PushFront { value } => {
+                TimelineDiff::with_value(TimelineDiffAction::PushFront, None, to_message(value))
+            }
+            VectorDiff::PopBack => TimelineDiff::new(TimelineDiffAction::PopBack),
+            VectorDiff::PopFront => TimelineDiff::new(TimelineDiffAction::PopFront),
+            VectorDiff::Clear => TimelineDiff::new(TimelineDiffAction::Clear),
+            VectorDiff::Reset { values } => {
+                TimelineDiff::with_values(TimelineDiffAction::Reset, to_messages(values))
+            }
+        }
+    }
+
+    /// one of the `TimelineDiffAction` names
     pub fn action(&self) -> String {
-        self.action.clone()
+        self.action.to_string()
+    }
+
+    // for only cli and tui, not api.rsh
+    pub fn action_kind(&self) -> TimelineDiffAction {
+        self.action
+    }
+
+    pub fn is_append(&self) -> bool {
+        self.action == TimelineDiffAction::Append
+    }
+
+    pub fn is_insert(&self) -> bool {
+        self.action == TimelineDiffAction::Insert
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.action == TimelineDiffAction::Set
+    }
+
+    pub fn is_remove(&self) -> bool {
+        self.action == TimelineDiffAction::Remove
+    }
+
+    pub fn is_push_back(&self) -> bool {
+        self.action == TimelineDiffAction::PushBack
+    }
+
+    pub fn is_push_front(&self) -> bool {
+        self.action == TimelineDiffAction::PushFront
+    }
+
+    pub fn is_pop_back(&self) -> bool {
+        self.action == TimelineDiffAction::PopBack
+    }
+
+    pub fn is_pop_front(&self) -> bool {
+        self.action == TimelineDiffAction::PopFront
+    }
+
+    pub fn is_clear(&self) -> bool {
+        self.action == TimelineDiffAction::Clear
+    }
+
+    pub fn is_truncate(&self) -> bool {
+        self.action == TimelineDiffAction::Truncate
     }
 
+    pub fn is_reset(&self) -> bool {
+        self.action == TimelineDiffAction::Reset
+    }
+
+    /// for Append/Reset
     pub fn values(&self) -> Option<Vec<RoomMessage>> {
-        match self.action.as_str() {
-            "Append" | "Reset" => self.values.clone(),
-            _ => None,
-        }
+        self.values.clone()
     }
 
+    /// for Insert/Set/Remove, the new length for Truncate
     pub fn index(&self) -> Option<usize> {
-        match self.action.as_str() {
-            "Insert" | "Set" | "Remove" => self.index,
-            _ => None,
-        }
+        self.index
     }
 
+    /// for Insert/Set/PushBack/PushFront
     pub fn value(&self) -> Option<RoomMessage> {
-        match self.action.as_str() {
-            "Insert" | "Set" | "PushBack" | "PushFront" => self.value.clone(),
-            _ => None,
+        self.value.clone()
+    }
+
+    /// Apply the diff to `messages`, `false` if it doesn't fit them
+    fn apply_to(&self, messages: &mut Vec<RoomMessage>) -> bool {
+        match (self.action, self.index, &self.values, &self.value) {
+            (TimelineDiffAction::Append, _, Some(values), _) => messages.extend(values.clone()),
+            (TimelineDiffAction::Insert, Some(idx), _, Some(value)) if idx <= messages.len() => {
+                messages.insert(idx, value.clone())
+            }
+            (TimelineDiffAction::Set, Some(idx), _, Some(value)) if idx < messages.len() => {
+                messages[idx] = value.clone()
+            }
+            (TimelineDiffAction::Remove, Some(idx), _, _) if idx < messages.len() => {
+                messages.remove(idx);
+            }
+            (TimelineDiffAction::PushBack, _, _, Some(value)) => messages.push(value.clone()),
+            (TimelineDiffAction::PushFront, _, _, Some(value)) => messages.insert(0, value.clone()),
+            (TimelineDiffAction::PopBack, _, _, _) => {
+                messages.pop();
+            }
+            (TimelineDiffAction::PopFront, _, _, _) if !messages.is_empty() => {
+                messages.remove(0);
+            }
+            (TimelineDiffAction::Truncate, Some(len), _, _) => messages.truncate(len),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The messages as the receiver of the diffs has them, to know where the
+/// poll is whose results changed
+#[derive(Default)]
+struct MessageMirror {
+    messages: Vec<RoomMessage>,
+}
+
+impl MessageMirror {
+    fn apply(&mut self, diff: &TimelineDiff) {
+        match diff.action {
+            TimelineDiffAction::Clear => self.messages.clear(),
+            TimelineDiffAction::Reset => self.messages = diff.values.clone().unwrap_or_default(),
+            _ => {
+                if !diff.apply_to(&mut self.messages) {
+                    warn!(action = ?diff.action, "diff doesn't fit the mirrored messages");
+                }
+            }
+        }
+    }
+
+    /// The diff for the change, `None` for the results of a poll we don't show
+    fn update(
+        &mut self,
+        update: TimelineUpdate,
+        room: &Room,
+        convo_controller: &ConvoController,
+    ) -> Option<TimelineDiff> {
+        let diff = match update {
+            Either::Left(diff) => map_room_diff(diff, room, convo_controller),
+            Either::Right((poll_id, desc)) => {
+                let index = self.messages.iter().position(|m| {
+                    ThreadMessages::event_id(m).as_deref() == Some(poll_id.as_str())
+                })?;
+                let msg = with_poll_desc(&self.messages[index], desc);
+                TimelineDiff::with_value(TimelineDiffAction::Set, Some(index), msg)
+            }
+        };
+        self.apply(&diff);
+        Some(diff)
+    }
+}
+
+/// A change of the room timeline or new results of one of its polls
+type TimelineUpdate = Either<VectorDiff<Arc<TimelineItem>>, (OwnedEventId, PollDesc)>;
+
+/// the most diffs merged into one batch
+const DIFF_BATCH_SIZE: usize = 100;
+
+/// Merges the diffs arriving in a burst, e.g. while back-paginating, into
+/// as few as possible. Follows the length of the list for that.
+#[derive(Default)]
+struct DiffBatcher {
+    len: usize,
+}
+
+impl DiffBatcher {
+    fn track(&mut self, diff: &TimelineDiff) {
+        let values = diff.values.as_ref().map(Vec::len).unwrap_or_default();
+        self.len = match diff.action {
+            TimelineDiffAction::Append => self.len + values,
+            TimelineDiffAction::Insert
+            | TimelineDiffAction::PushBack
+            | TimelineDiffAction::PushFront => self.len + 1,
+            TimelineDiffAction::Set => self.len,
+            TimelineDiffAction::Remove
+            | TimelineDiffAction::PopBack
+            | TimelineDiffAction::PopFront => self.len.saturating_sub(1),
+            TimelineDiffAction::Clear => 0,
+            TimelineDiffAction::Truncate => diff.index.unwrap_or(self.len).min(self.len),
+            TimelineDiffAction::Reset => values,
+        };
+    }
+
+    fn coalesce(&mut self, diffs: Vec<TimelineDiff>) -> Vec<TimelineDiff> {
+        let mut batch: Vec<TimelineDiff> = vec![];
+        for diff in diffs {
+            self.track(&diff);
+            if matches!(
+                diff.action,
+                TimelineDiffAction::Clear | TimelineDiffAction::Reset
+            ) {
+                // replaces whatever came before
+                batch.clear();
+                batch.push(diff);
+                continue;
+            }
+            let Some(last) = batch.last_mut() else {
+                batch.push(diff);
+                continue;
+            };
+            match (last.action, diff.action) {
+                (TimelineDiffAction::Clear | TimelineDiffAction::Reset, _) => {
+                    if last.action == TimelineDiffAction::Clear {
+                        *last = TimelineDiff::with_values(TimelineDiffAction::Reset, vec![]);
+                    }
+                    if !diff.apply_to(last.values.get_or_insert_with(Vec::new)) {
+                        batch.push(diff);
+                    }
+                }
+                (
+                    TimelineDiffAction::Append,
+                    TimelineDiffAction::Append | TimelineDiffAction::PushBack,
+                ) => {
+                    diff.apply_to(last.values.get_or_insert_with(Vec::new));
+                }
+                (
+                    TimelineDiffAction::PushBack,
+                    TimelineDiffAction::Append | TimelineDiffAction::PushBack,
+                ) => {
+                    let mut values = last.value.take().into_iter().collect();
+                    diff.apply_to(&mut values);
+                    *last = TimelineDiff::with_values(TimelineDiffAction::Append, values);
+                }
+                (
+                    TimelineDiffAction::PopBack | TimelineDiffAction::Truncate,
+                    TimelineDiffAction::PopBack,
+                ) => {
+                    *last = TimelineDiff::with_index(TimelineDiffAction::Truncate, self.len);
+                }
+                (TimelineDiffAction::Set, TimelineDiffAction::Set) if last.index == diff.index => {
+                    *last = diff;
+                }
+                _ => batch.push(diff),
+            }
+        }
+        batch
+    }
+}
+
+/// The messages of a thread, root first
+#[derive(Default)]
+struct ThreadMessages {
+    messages: Vec<RoomMessage>,
+}
+
+impl ThreadMessages {
+    fn event_id(msg: &RoomMessage) -> Option<String> {
+        msg.event_item().map(|e| e.event_id())
+    }
+
+    /// Add or replace the message, returns the diff for that or `None` if it
+    /// isn't part of the thread
+    fn upsert(&mut self, root: &EventId, msg: RoomMessage) -> Option<TimelineDiff> {
+        let event_item = msg.event_item()?;
+        let event_id = event_item.event_id();
+        if event_id != root.as_str() && event_item.thread_root().as_deref() != Some(root.as_str()) {
+            return None;
+        }
+        if let Some(index) = self
+            .messages
+            .iter()
+            .position(|m| Self::event_id(m).as_ref() == Some(&event_id))
+        {
+            self.messages[index] = msg.clone();
+            return Some(TimelineDiff::with_value(
+                TimelineDiffAction::Set,
+                Some(index),
+                msg,
+            ));
+        }
+        let ts = event_item.origin_server_ts();
+        let index = self
+            .messages
+            .partition_point(|m| m.event_item().map(|e| e.origin_server_ts()) <= Some(ts));
+        self.messages.insert(index, msg.clone());
+        Some(TimelineDiff::with_value(
+            TimelineDiffAction::Insert,
+            Some(index),
+            msg,
+        ))
+    }
+
+    /// Upsert the timeline item if it is an event of the thread
+    fn upsert_item(
+        &mut self,
+        root: &EventId,
+        item: Arc<TimelineItem>,
+        room: &Room,
+        convo_controller: &ConvoController,
+    ) -> Option<TimelineDiff> {
+        if !in_thread(&item, root) {
+            return None;
+        }
+        self.upsert(root, to_message(item, room, convo_controller))
+    }
+
+    /// Show the new results on the poll, if it is part of the thread
+    fn update_poll(
+        &mut self,
+        root: &EventId,
+        poll_id: &EventId,
+        desc: PollDesc,
+    ) -> Option<TimelineDiff> {
+        let msg = self
+            .messages
+            .iter()
+            .find(|m| Self::event_id(m).as_deref() == Some(poll_id.as_str()))?;
+        let msg = with_poll_desc(msg, desc);
+        self.upsert(root, msg)
+    }
+
+    fn reset(&self) -> TimelineDiff {
+        TimelineDiff::with_values(TimelineDiffAction::Reset, self.messages.clone())
+    }
+}
+
+/// The message of the timeline item, with what the controller knows about it
+fn to_message(
+    item: Arc<TimelineItem>,
+    room: &Room,
+    convo_controller: &ConvoController,
+) -> RoomMessage {
+    convo_controller.decorate(timeline_item_to_message(item, room.clone()), room)
+}
+
+/// Whether the timeline item is the root or a reply of the thread
+fn in_thread(item: &TimelineItem, root: &EventId) -> bool {
+    let Some(event) = item.as_event() else {
+        return false;
+    };
+    event.event_id() == Some(root)
+        || event.original_json().and_then(thread_root).as_deref() == Some(root)
+}
+
+/// The root and all replies of the thread the server knows about
+async fn fetch_thread(
+    room: &Room,
+    root: &EventId,
+    convo_controller: &ConvoController,
+) -> Result<Vec<RoomMessage>> {
+    let room_id = room.room_id().to_owned();
+    let mut messages = vec![];
+    let root_event = room.event(root).await?;
+    if let Some(msg) = sync_event_to_message(&root_event.event.cast(), room_id.clone()) {
+        messages.push(convo_controller.decorate(msg, room));
+    }
+    let mut from = None;
+    loop {
+        let mut request = get_relating_events_with_rel_type::v1::Request::new(
+            room_id.clone(),
+            root.to_owned(),
+            RelationType::Thread,
+        );
+        request.from = from;
+        let response = room.client().send(request, None).await?;
+        messages.extend(
+            response
+                .chunk
+                .iter()
+                .filter_map(|e| sync_event_to_message(&e.clone().cast(), room_id.clone()))
+                .map(|msg| convo_controller.decorate(msg, room)),
+        );
+        if response.next_batch.is_none() {
+            break;
         }
+        from = response.next_batch;
+    }
+    Ok(messages)
+}
+
+fn map_room_diff(
+    diff: VectorDiff<Arc<TimelineItem>>,
+    room: &Room,
+    convo_controller: &ConvoController,
+) -> TimelineDiff {
+    let diff = TimelineDiff::from_vector_diff(diff, room, convo_controller);
+    // When unable to decrypt, we get a `Set` after the room key is fetched
+    // and the 2nd decryption succeeded
+    if let (TimelineDiffAction::Set, Some(msg)) = (diff.action, &diff.value) {
+        convo_controller
+            .clone()
+            .update_latest_message(room, msg.clone());
     }
+    diff
 }
 
 #[derive(Clone)]
@@ -60,6 +499,8 @@ pub struct TimelineStream {
     room: Room,
     timeline: Arc<Timeline>,
     convo_controller: ConvoController,
+    // only the events of this thread
+    thread_root: Option<OwnedEventId>,
 }
 
 impl TimelineStream {
@@ -68,110 +509,185 @@ impl TimelineStream {
             room,
             timeline,
             convo_controller,
+            thread_root: None,
+        }
+    }
+
+    pub(crate) fn new_thread(
+        room: Room,
+        timeline: Arc<Timeline>,
+        convo_controller: ConvoController,
+        thread_root: OwnedEventId,
+    ) -> Self {
+        TimelineStream {
+            room,
+            timeline,
+            convo_controller,
+            thread_root: Some(thread_root),
         }
     }
 
+    /// the root of the thread, if this stream only follows that one
+    pub fn thread_root(&self) -> Option<String> {
+        self.thread_root.as_ref().map(|x| x.to_string())
+    }
+
+    /// The changes of the timeline. A thread stream starts with a `Reset` to
+    /// the whole thread, root first, and inserts or sets its messages after.
     pub fn diff_rx(&self) -> impl Stream<Item = TimelineDiff> {
+        match self.thread_root.clone() {
+            Some(root) => self.thread_diff_rx(root).left_stream(),
+            None => self.room_diff_rx().right_stream(),
+        }
+    }
+
+    fn thread_diff_rx(&self, root: OwnedEventId) -> impl Stream<Item = TimelineDiff> {
         let timeline = self.timeline.clone();
         let room = self.room.clone();
-        let mut convo_controller = self.convo_controller.clone();
+        let convo_controller = self.convo_controller.clone();
+        let poll_results = self.poll_results_rx();
 
         async_stream::stream! {
-            let (timeline_items, mut timeline_stream) = timeline.subscribe().await;
-            let mut remap = timeline_stream.map(move |diff| match diff {
-                // Append the given elements at the end of the `Vector` and notify subscribers
-                VectorDiff::Append { values } => TimelineDiff {
-                    action: "Append".to_string(),
-                    values: Some(
-                        values
-                            .iter()
-                            .map(|x| timeline_item_to_message(x.clone(), room.clone()))
-                            .collect(),
-                    ),
-                    index: None,
-                    value: None,
-                },
-                // Insert an element at the given position and notify subscribers
-                VectorDiff::Insert { index, value } => TimelineDiff {
-                    action: "Insert".to_string(),
-                    values: None,
-                    index: Some(index),
-                    value: Some(timeline_item_to_message(value, room.clone())),
-                },
-                // Replace the element at the given position, notify subscribers and return the previous element at that position
-                // When unable to decrypt, we can reach this point after room key is fetched and 2nd decryption is succeeded
-                VectorDiff::Set { index, value } => {
-                    let msg = timeline_item_to_message(value, room.clone());
-                    convo_controller.update_latest_message(&room, msg.clone());
-                    TimelineDiff {
-                        action: "Set".to_string(),
-                        values: None,
-                        index: Some(index),
-                        value: Some(msg),
+            let mut thread = ThreadMessages::default();
+            match fetch_thread(&room, &root, &convo_controller).await {
+                Ok(messages) => {
+                    for msg in messages {
+                        thread.upsert(&root, msg);
+                    }
+                }
+                Err(error) => error!(?error, ?root, "fetching thread failed"),
+            }
+            let (timeline_items, timeline_stream) = timeline.subscribe().await;
+            for item in timeline_items {
+                thread.upsert_item(&root, item, &room, &convo_controller);
+            }
+            yield thread.reset();
+
+            let mut updates = Box::pin(stream::select(
+                timeline_stream.map(Either::Left),
+                poll_results.map(Either::Right),
+            ));
+            while let Some(update) = updates.next().await {
+                let diffs: Vec<TimelineDiff> = match update {
+                    Either::Left(diff) => {
+                        // removals of the room timeline don't remove from the thread
+                        let items: Vec<Arc<TimelineItem>> = match diff {
+                            VectorDiff::Append { values } | VectorDiff::Reset { values } => {
+                                values.into_iter().collect()
+                            }
+                            VectorDiff::Insert { value, .. }
+                            | VectorDiff::Set { value, .. }
+                            | VectorDiff::PushBack { value }
+                            | VectorDiff::PushFront { value } => vec![value],
+                            _ => vec![],
+                        };
+                        items
+                            .into_iter()
+                            .filter_map(|item| thread.upsert_item(&root, item, &room, &convo_controller))
+                            .collect()
+                    }
+                    Either::Right((poll_id, desc)) => {
+                        thread.update_poll(&root, &poll_id, desc).into_iter().collect()
                     }
+                };
+                for diff in diffs {
+                    yield diff;
+                }
+            }
+        }
+    }
+
+    fn room_diff_rx(&self) -> impl Stream<Item = TimelineDiff> {
+        let timeline = self.timeline.clone();
+        let room = self.room.clone();
+        let convo_controller = self.convo_controller.clone();
+        let poll_results = self.poll_results_rx();
+
+        async_stream::stream! {
+            let (timeline_items, timeline_stream) = timeline.subscribe().await;
+            let mut mirror = MessageMirror::default();
+            let messages = timeline_items
+                .into_iter()
+                .map(|item| to_message(item, &room, &convo_controller))
+                .collect();
+            mirror.apply(&TimelineDiff::with_values(TimelineDiffAction::Reset, messages));
+
+            let mut updates = Box::pin(stream::select(
+                timeline_stream.map(Either::Left),
+                poll_results.map(Either::Right),
+            ));
+            while let Some(update) = updates.next().await {
+                if let Some(diff) = mirror.update(update, &room, &convo_controller) {
+                    yield diff;
                 }
-                // Remove the element at the given position, notify subscribers and return the element
-                VectorDiff::Remove { index } => TimelineDiff {
-                    action: "Remove".to_string(),
-                    values: None,
-                    index: Some(index),
-                    value: None,
-                },
-                // Add an element at the back of the list and notify subscribers
-                VectorDiff::PushBack { value } => TimelineDiff {
-                    action: "PushBack".to_string(),
-                    values: None,
-                    index: None,
-                    value: Some(timeline_item_to_message(value, room.clone())),
-                },
-                // Add an element at the front of the list and notify subscribers
-                VectorDiff::PushFront { value } => TimelineDiff {
-                    action: "PushFront".to_string(),
-                    values: None,
-                    index: None,
-                    value: Some(timeline_item_to_message(value, room.clone())),
-                },
-                // Remove the last element, notify subscribers and return the element
-                VectorDiff::PopBack => TimelineDiff {
-                    action: "PopBack".to_string(),
-                    values: None,
-                    index: None,
-                    value: None,
-                },
-                // Remove the first element, notify subscribers and return the element
-                VectorDiff::PopFront => TimelineDiff {
-                    action: "PopFront".to_string(),
-                    values: None,
-                    index: None,
-                    value: None,
-                },
-                // Clear out all of the elements in this `Vector` and notify subscribers
-                VectorDiff::Clear => TimelineDiff {
-                    action: "Clear".to_string(),
-                    values: None,
-                    index: None,
-                    value: None,
-                },
-                VectorDiff::Reset { values } => TimelineDiff {
-                    action: "Reset".to_string(),
-                    values: Some(
-                        values
-                            .iter()
-                            .map(|x| timeline_item_to_message(x.clone(), room.clone()))
-                            .collect(),
-                    ),
-                    index: None,
-                    value: None,
-                },
-            });
-
-            while let Some(d) = remap.next().await {
-                yield d
             }
         }
     }
 
+    /// The new results of the polls of this room we follow
+    fn poll_results_rx(&self) -> impl Stream<Item = (OwnedEventId, PollDesc)> {
+        let my_id = self.room.client().user_id().map(ToOwned::to_owned);
+        self.convo_controller
+            .poll_results_rx(self.room.room_id().to_owned(), my_id)
+    }
+
+    /// Like `diff_rx`, but the diffs arriving at once come merged into as
+    /// few as possible. Starts with a `Reset` to the current messages.
+    pub fn diff_batch_rx(&self) -> impl Stream<Item = Vec<TimelineDiff>> {
+        if let Some(root) = self.thread_root.clone() {
+            let diffs = self.thread_diff_rx(root);
+            let stream = async_stream::stream! {
+                let mut batcher = DiffBatcher::default();
+                let mut batches = Box::pin(diffs.ready_chunks(DIFF_BATCH_SIZE));
+                while let Some(diffs) = batches.next().await {
+                    yield batcher.coalesce(diffs);
+                }
+            };
+            return stream.left_stream();
+        }
+        let timeline = self.timeline.clone();
+        let room = self.room.clone();
+        let convo_controller = self.convo_controller.clone();
+        let poll_results = self.poll_results_rx();
+
+        let stream = async_stream::stream! {
+            let (timeline_items, timeline_stream) = timeline.subscribe().await;
+            let mut batcher = DiffBatcher::default();
+            let mut mirror = MessageMirror::default();
+            let messages = timeline_items
+                .into_iter()
+                .map(|item| to_message(item, &room, &convo_controller))
+                .collect();
+            let reset = TimelineDiff::with_values(TimelineDiffAction::Reset, messages);
+            mirror.apply(&reset);
+            yield batcher.coalesce(vec![reset]);
+
+            let mut batches = Box::pin(
+                stream::select(
+                    timeline_stream.map(Either::Left),
+                    poll_results.map(Either::Right),
+                )
+                .ready_chunks(DIFF_BATCH_SIZE),
+            );
+            while let Some(updates) = batches.next().await {
+                let diffs: Vec<TimelineDiff> = updates
+                    .into_iter()
+                    .filter_map(|update| mirror.update(update, &room, &convo_controller))
+                    .collect();
+                if !diffs.is_empty() {
+                    yield batcher.coalesce(diffs);
+                }
+            }
+        };
+        stream.right_stream()
+    }
+
+    /// Get `count` more messages backwards, whether it has more. A thread
+    /// stream has all of the thread from the start.
     pub async fn paginate_backwards(&self, mut count: u16) -> Result<bool> {
+        if self.thread_root.is_some() {
+            return Ok(false);
+        }
         let timeline = self.timeline.clone();
 
         RUNTIME
@@ -195,9 +711,14 @@ impl TimelineStream {
             .await?
     }
 
+    /// The next message that arrived, of the thread for a thread stream
     pub async fn next(&self) -> Result<RoomMessage> {
         let timeline = self.timeline.clone();
         let room = self.room.clone();
+        let convo_controller = self.convo_controller.clone();
+        let thread = self.thread_root.clone();
+        let wanted =
+            move |item: &TimelineItem| thread.as_deref().map_or(true, |root| in_thread(item, root));
 
         RUNTIME
             .spawn(async move {
@@ -222,13 +743,17 @@ impl TimelineStream {
                             }
                             VectorDiff::PushBack { value } => {
                                 info!("stream forward timeline push_back");
-                                let msg = timeline_item_to_message(value, room.clone());
-                                return Ok(msg);
+                                if wanted(value.as_ref()) {
+                                    let msg = to_message(value, &room, &convo_controller);
+                                    return Ok(msg);
+                                }
                             }
                             VectorDiff::PushFront { value } => {
                                 info!("stream forward timeline push_front");
-                                let msg = timeline_item_to_message(value, room.clone());
-                                return Ok(msg);
+                                if wanted(value.as_ref()) {
+                                    let msg = to_message(value, &room, &convo_controller);
+                                    return Ok(msg);
+                                }
                             }
                             VectorDiff::PopBack => {
                                 info!("stream forward timeline pop_back");
@@ -263,10 +788,17 @@ impl TimelineStream {
         let timeline = self.timeline.clone();
         let event_id = EventId::parse(original_event_id)?;
         let client = self.room.client();
+        let thread = self.thread_root.clone();
 
         RUNTIME
             .spawn(async move {
                 let timeline_event = room.event(&event_id).await?;
+                if let Some(root) = thread {
+                    let event_root = thread_root(&timeline_event.event.clone().cast());
+                    if event_id != root && event_root != Some(root) {
+                        bail!("Can't edit a message of another thread");
+                    }
+                }
                 let event_content = timeline_event.event.deserialize_as::<RoomMessageEvent>()?;
 
                 let mut sent_by_me = false;
@@ -295,3 +827,128 @@ impl TimelineStream {
             .await?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matrix_sdk::ruma::{events::room::message::OriginalSyncRoomMessageEvent, room_id};
+    use serde_json::json;
+
+    fn msg(event_id: &str) -> RoomMessage {
+        let json = json!({
+            "type": "m.room.message",
+            "event_id": event_id,
+            "sender": "@me:example.org",
+            "origin_server_ts": 1,
+            "content": { "msgtype": "m.text", "body": event_id },
+        });
+        let ev = serde_json::from_str::<OriginalSyncRoomMessageEvent>(&json.to_string()).unwrap();
+        RoomMessage::room_message_from_sync_event(ev, room_id!("!r:example.org").to_owned(), false)
+    }
+
+    fn ids(messages: &[RoomMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|m| m.event_item().map(|e| e.event_id()))
+            .collect()
+    }
+
+    fn apply(messages: &mut Vec<RoomMessage>, diff: &TimelineDiff) {
+        match diff.action {
+            TimelineDiffAction::Clear => messages.clear(),
+            TimelineDiffAction::Reset => *messages = diff.values.clone().unwrap_or_default(),
+            action => assert!(diff.apply_to(messages), "{action} doesn't fit"),
+        }
+    }
+
+    /// Apply the diffs as they came and coalesced to `start`, both have to
+    /// end up the same. Returns the coalesced diffs.
+    fn coalesce_from(start: &[&str], diffs: Vec<TimelineDiff>) -> Vec<TimelineDiff> {
+        let start = start.iter().map(|id| msg(id)).collect::<Vec<_>>();
+        let mut batcher = DiffBatcher::default();
+        batcher.coalesce(vec![TimelineDiff::with_values(
+            TimelineDiffAction::Reset,
+            start.clone(),
+        )]);
+
+        let mut raw = start.clone();
+        for diff in &diffs {
+            apply(&mut raw, diff);
+        }
+        let coalesced = batcher.coalesce(diffs);
+        let mut merged = start;
+        for diff in &coalesced {
+            apply(&mut merged, diff);
+        }
+        assert_eq!(ids(&merged), ids(&raw));
+        coalesced
+    }
+
+    fn push_back(event_id: &str) -> TimelineDiff {
+        TimelineDiff::with_value(TimelineDiffAction::PushBack, None, msg(event_id))
+    }
+
+    fn set(index: usize, event_id: &str) -> TimelineDiff {
+        TimelineDiff::with_value(TimelineDiffAction::Set, Some(index), msg(event_id))
+    }
+
+    #[test]
+    fn pushes_merge_into_append() {
+        let append =
+            TimelineDiff::with_values(TimelineDiffAction::Append, vec![msg("$d"), msg("$e")]);
+        let coalesced = coalesce_from(&["$a"], vec![push_back("$b"), push_back("$c"), append]);
+        assert_eq!(coalesced.len(), 1);
+        assert_eq!(coalesced[0].action, TimelineDiffAction::Append);
+    }
+
+    #[test]
+    fn sets_of_the_same_index_merge() {
+        let coalesced = coalesce_from(
+            &["$a", "$b"],
+            vec![set(1, "$x"), set(1, "$y"), set(0, "$z")],
+        );
+        assert_eq!(coalesced.len(), 2);
+    }
+
+    #[test]
+    fn pops_merge_into_truncate() {
+        let pop = || TimelineDiff::new(TimelineDiffAction::PopBack);
+        let coalesced = coalesce_from(&["$a", "$b", "$c", "$d"], vec![pop(), pop(), pop()]);
+        assert_eq!(coalesced.len(), 1);
+        assert_eq!(coalesced[0].action, TimelineDiffAction::Truncate);
+        assert_eq!(coalesced[0].index, Some(1));
+    }
+
+    #[test]
+    fn changes_after_clear_merge_into_reset() {
+        let coalesced = coalesce_from(
+            &["$a", "$b"],
+            vec![
+                TimelineDiff::new(TimelineDiffAction::Clear),
+                push_back("$c"),
+                TimelineDiff::with_value(TimelineDiffAction::Insert, Some(0), msg("$d")),
+                set(1, "$e"),
+                TimelineDiff::new(TimelineDiffAction::PopFront),
+            ],
+        );
+        assert_eq!(coalesced.len(), 1);
+        assert_eq!(coalesced[0].action, TimelineDiffAction::Reset);
+    }
+
+    #[test]
+    fn mixed_diffs_keep_the_order() {
+        coalesce_from(
+            &["$a", "$b", "$c"],
+            vec![
+                TimelineDiff::with_value(TimelineDiffAction::Insert, Some(1), msg("$x")),
+                TimelineDiff::with_index(TimelineDiffAction::Remove, 0),
+                TimelineDiff::with_value(TimelineDiffAction::PushFront, None, msg("$y")),
+                push_back("$z"),
+                TimelineDiff::new(TimelineDiffAction::PopBack),
+                TimelineDiff::new(TimelineDiffAction::PopBack),
+                set(0, "$w"),
+                TimelineDiff::new(TimelineDiffAction::PopFront),
+            ],
+        );
+    }
+}
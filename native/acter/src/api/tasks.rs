@@ -258,12 +258,21 @@ impl TaskListDraft {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
+            .await?
+    }
+
+    /// send it at the given time instead, in milliseconds since the unix
+    /// epoch. Returns the id to cancel it with.
+    pub async fn schedule(&self, send_at: u64) -> Result<String> {
+        let room_id = self.room.room_id().to_owned();
+        let client = self.client.clone();
+        let content = self.content.build()?;
+        RUNTIME
+            .spawn(async move { client.schedule(room_id, &content, send_at).await })
             .await?
     }
 }
@@ -330,6 +339,7 @@ impl TaskList {
             self.client.clone(),
             crate::Room {
                 room: self.room.clone(),
+                scheduler: self.client.scheduler.clone(),
             },
         )
     }
@@ -717,12 +727,21 @@ impl TaskDraft {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
+            .await?
+    }
+
+    /// send it at the given time instead, in milliseconds since the unix
+    /// epoch. Returns the id to cancel it with.
+    pub async fn schedule(&self, send_at: u64) -> Result<String> {
+        let room_id = self.room.room_id().to_owned();
+        let client = self.client.clone();
+        let content = self.content.build()?;
+        RUNTIME
+            .spawn(async move { client.schedule(room_id, &content, send_at).await })
             .await?
     }
 }
@@ -938,12 +957,10 @@ impl TaskUpdateBuilder {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
             .await?
     }
 }
@@ -1053,12 +1070,10 @@ impl TaskListUpdateBuilder {
 
     pub async fn send(&self) -> Result<OwnedEventId> {
         let room = self.room.clone();
+        let client = self.client.clone();
         let content = self.content.build()?;
         RUNTIME
-            .spawn(async move {
-                let resp = room.send(content, None).await?;
-                Ok(resp.event_id)
-            })
+            .spawn(async move { client.send_or_queue(room, content).await })
             .await?
     }
 }
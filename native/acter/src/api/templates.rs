@@ -0,0 +1,234 @@
+use acter_core::{events::templates::TemplateEventContent, templates::Engine};
+use anyhow::{bail, Context, Result};
+use matrix_sdk::{
+    deserialized_responses::SyncOrStrippedState,
+    room::Room as SdkRoom,
+    ruma::{events::SyncStateEvent, OwnedEventId, OwnedRoomId},
+};
+use tracing::warn;
+
+use super::{client::Client, room::Room, RUNTIME};
+
+/// The templates shipped with this library, by key
+static BUILTIN_TEMPLATES: [(&str, &str); 1] = [(
+    "onboarding",
+    std::include_str!("../templates/onboarding.toml"),
+)];
+
+#[derive(Clone, Debug)]
+pub struct TemplateInput {
+    name: String,
+    input_type: String,
+    is_required: bool,
+    is_default: bool,
+    description: Option<String>,
+}
+
+impl TemplateInput {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// one of `user`, `space` or `text`
+    pub fn input_type(&self) -> String {
+        self.input_type.clone()
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.is_required
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    key: String,
+    name: Option<String>,
+    description: Option<String>,
+    revision: u32,
+    origin: Option<OwnedRoomId>,
+    source: String,
+    inputs: Vec<TemplateInput>,
+}
+
+impl Template {
+    pub(crate) fn parse(key: String, source: String, origin: Option<OwnedRoomId>) -> Result<Self> {
+        let engine = Engine::with_template(&source)?;
+        let inputs = engine
+            .requested_inputs()
+            .iter()
+            .map(|(name, input)| TemplateInput {
+                name: name.clone(),
+                input_type: input.input_type().to_owned(),
+                is_required: input.is_required(),
+                is_default: input.is_default(),
+                description: input.description().map(ToOwned::to_owned),
+            })
+            .collect();
+        Ok(Template {
+            key,
+            name: engine.name().map(ToOwned::to_owned),
+            description: engine.description().map(ToOwned::to_owned),
+            revision: engine.revision(),
+            origin,
+            source,
+            inputs,
+        })
+    }
+
+    /// the key this template is registered under
+    pub fn key(&self) -> String {
+        self.key.clone()
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// whether this template ships with the app
+    pub fn is_builtin(&self) -> bool {
+        self.origin.is_none()
+    }
+
+    /// the space this template was published in, if any
+    pub fn origin_room_id(&self) -> Option<OwnedRoomId> {
+        self.origin.clone()
+    }
+
+    pub fn origin_room_id_str(&self) -> Option<String> {
+        self.origin.as_ref().map(ToString::to_string)
+    }
+
+    /// the full toml source, as to be passed to the template engine
+    pub fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    pub fn requested_inputs(&self) -> Vec<TemplateInput> {
+        self.inputs.clone()
+    }
+}
+
+pub fn builtin_templates() -> Vec<Template> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .map(|(key, source)| {
+            Template::parse(key.to_string(), source.to_string(), None)
+                .expect("builtin templates are valid")
+        })
+        .collect()
+}
+
+pub(crate) fn builtin_template_source(key: &str) -> Option<&'static str> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, source)| *source)
+}
+
+impl Room {
+    /// the templates published in this room
+    pub async fn templates(&self) -> Result<Vec<Template>> {
+        let room = self.room.clone();
+        RUNTIME
+            .spawn(async move {
+                let room_id = room.room_id().to_owned();
+                let mut templates = Vec::new();
+                for raw in room
+                    .get_state_events_static::<TemplateEventContent>()
+                    .await?
+                {
+                    let Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(ev))) =
+                        raw.deserialize()
+                    else {
+                        continue;
+                    };
+                    match Template::parse(ev.state_key, ev.content.source, Some(room_id.clone())) {
+                        Ok(t) => templates.push(t),
+                        Err(error) => {
+                            warn!(?error, ?room_id, "ignoring unparsable template");
+                        }
+                    }
+                }
+                Ok(templates)
+            })
+            .await?
+    }
+
+    /// publish the template under the given key. Replaces any former
+    /// template under the same key in this room.
+    pub async fn publish_template(&self, key: String, source: String) -> Result<OwnedEventId> {
+        if key.is_empty() {
+            bail!("Template key can't be empty");
+        }
+        // ensure we only publish templates others can actually use
+        Template::parse(key.clone(), source.clone(), None)?;
+
+        if !self
+            .get_my_membership()
+            .await?
+            .can(crate::MemberPermission::CanPublishTemplates)
+        {
+            bail!("You don't have permissions to publish templates in this space");
+        }
+        let SdkRoom::Joined(joined) = &self.room else {
+            bail!("You can't publish templates in a space you aren't part of");
+        };
+        let room = joined.clone();
+
+        RUNTIME
+            .spawn(async move {
+                let response = room
+                    .send_state_event_for_key(&key, TemplateEventContent { source })
+                    .await?;
+                Ok(response.event_id)
+            })
+            .await?
+    }
+}
+
+impl Client {
+    /// All templates available to the user: the builtin ones
+    /// followed by the ones published in the spaces they are in.
+    pub async fn available_templates(&self) -> Result<Vec<Template>> {
+        let mut templates = builtin_templates();
+        for space in self.spaces().await? {
+            if !space.is_joined() {
+                continue;
+            }
+            match space.templates().await {
+                Ok(found) => templates.extend(found),
+                Err(error) => {
+                    warn!(?error, room_id=?space.room_id(), "fetching templates failed");
+                }
+            }
+        }
+        Ok(templates)
+    }
+
+    /// The template registered under `key`. If multiple are
+    /// available, the one with the highest revision wins.
+    pub async fn template(&self, key: String) -> Result<Template> {
+        self.available_templates()
+            .await?
+            .into_iter()
+            .filter(|t| t.key == key)
+            .max_by_key(|t| t.revision)
+            .context("Template not found")
+    }
+}
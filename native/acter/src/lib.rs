@@ -14,7 +14,7 @@ pub mod platform;
 #[cfg(feature = "cbindgen")]
 pub mod api_generated;
 
-#[cfg(feature = "testing")]
+#[cfg(any(test, feature = "testing"))]
 pub mod testing;
 
 pub use api::*;
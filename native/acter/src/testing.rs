@@ -1,11 +1,15 @@
 //! Testing modules, don't use in production!
 
+use acter_core::store::Store;
 use anyhow::{bail, Result};
 use core::{future::Future, time::Duration};
 use matrix_sdk::{
     ruma::{
-        api::client::{
-            account::register::v3::Request as RegistrationRequest, room::Visibility, uiaa,
+        api::{
+            client::{
+                account::register::v3::Request as RegistrationRequest, room::Visibility, uiaa,
+            },
+            MatrixVersion,
         },
         assign, OwnedUserId,
     },
@@ -54,6 +58,18 @@ where
     Ok(None)
 }
 
+/// An empty store in memory, like after a new login
+pub async fn fresh_store() -> Result<Store> {
+    let config = StoreConfig::default().state_store(MemoryStore::new());
+    let client = SdkClient::builder()
+        .homeserver_url("http://localhost")
+        .server_versions([MatrixVersion::V1_5])
+        .store_config(config)
+        .build()
+        .await?;
+    Ok(Store::new(client).await?)
+}
+
 pub async fn default_client_config(
     homeserver: &str,
     username: &str,
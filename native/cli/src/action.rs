@@ -1,17 +1,34 @@
+use acter::{Client, EfkDuration};
 use anyhow::Result;
 use clap::Subcommand;
 
+use crate::output::OutputFormat;
+
+mod accounts;
+mod comments;
+mod events;
 mod execute;
 mod history;
 mod list;
 mod manage;
 mod mock;
+mod news;
+mod pins;
+mod tasks;
+mod templates;
 
+pub use accounts::Accounts;
+pub use comments::Comments;
+pub use events::Events;
 pub use execute::ExecuteOpts;
 pub use history::HistoryOpts;
 pub use list::List;
 pub use manage::Manage;
 pub use mock::MockOpts;
+pub use news::News;
+pub use pins::Pins;
+pub use tasks::Tasks;
+pub use templates::Templates;
 
 #[derive(Subcommand, Debug)]
 pub enum Action {
@@ -25,17 +42,58 @@ pub enum Action {
     Mock(MockOpts),
     /// Template Execution
     Execute(ExecuteOpts),
+    /// Listing and fetching templates
+    Templates(Templates),
+    /// Task lists and tasks
+    Tasks(Tasks),
+    /// Pins
+    Pins(Pins),
+    /// News entries
+    News(News),
+    /// Calendar events
+    Events(Events),
+    /// Comments on any of the above
+    Comments(Comments),
+    /// The accounts logged in on this device
+    Accounts(Accounts),
 }
 
 impl Action {
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
         match self {
             Action::Manage(config) => config.run().await?,
             Action::Mock(config) => config.run().await?,
-            Action::List(config) => config.run().await?,
-            Action::History(config) => config.run().await?,
+            Action::List(config) => config.run(format).await?,
+            Action::History(config) => config.run(format).await?,
             Action::Execute(config) => config.run().await?,
+            Action::Templates(config) => config.run(format).await?,
+            Action::Tasks(config) => config.run(format).await?,
+            Action::Pins(config) => config.run(format).await?,
+            Action::News(config) => config.run(format).await?,
+            Action::Events(config) => config.run(format).await?,
+            Action::Comments(config) => config.run(format).await?,
+            Action::Accounts(config) => config.run(format).await?,
         };
         Ok(())
     }
 }
+
+/// How long to wait for an object to be found before giving up
+fn object_timeout() -> Option<Box<EfkDuration>> {
+    Some(Box::new(EfkDuration::from_secs(10)))
+}
+
+/// Redact the event of the object in its room
+async fn redact(
+    client: &Client,
+    room_id: String,
+    event_id: String,
+    reason: Option<String>,
+) -> Result<()> {
+    client
+        .room(room_id)
+        .await?
+        .redact_content(event_id, reason)
+        .await?;
+    Ok(())
+}
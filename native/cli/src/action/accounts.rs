@@ -0,0 +1,116 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::fmt;
+use tracing::warn;
+
+use crate::{
+    config::{account_registry, LoginConfig},
+    output::{print_records, OutputFormat, Record},
+};
+
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Log in and store the account, the first one becomes the current
+    Add {
+        #[clap(flatten)]
+        login: LoginConfig,
+    },
+    /// List the stored accounts
+    List,
+    /// Log out and forget a stored account, its local data is kept
+    Remove {
+        /// The user id of the account
+        user: String,
+        /// Don't log out, the session stays valid on the server
+        #[clap(long)]
+        keep_session: bool,
+    },
+    /// Use this account when no user is given
+    Switch {
+        /// The user id of the account
+        user: String,
+    },
+}
+
+/// Managing the stored accounts
+#[derive(Parser, Debug)]
+pub struct Accounts {
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct AccountRecord {
+    user_id: String,
+    base_path: String,
+    current: bool,
+}
+
+impl fmt::Display for AccountRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " * {} [{}]", self.user_id, self.base_path)?;
+        if self.current {
+            write!(f, " (current)")?;
+        }
+        Ok(())
+    }
+}
+
+impl Record for AccountRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["user_id", "base_path", "current"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.user_id.clone(),
+            self.base_path.clone(),
+            self.current.to_string(),
+        ]
+    }
+}
+
+impl Accounts {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        match &self.action {
+            Action::Add { login } => {
+                let client = login.add_account().await?;
+                println!("Stored account {}", client.user_id()?);
+            }
+            Action::List => {
+                let registry = account_registry()?;
+                let current = registry.current().map(|a| a.user_id());
+                let records = registry
+                    .accounts()
+                    .iter()
+                    .map(|a| AccountRecord {
+                        user_id: a.user_id(),
+                        base_path: a.base_path(),
+                        current: current.as_ref() == Some(&a.user_id()),
+                    })
+                    .collect::<Vec<_>>();
+                print_records(&records, format)?;
+            }
+            Action::Remove { user, keep_session } => {
+                let account = account_registry()?.remove(user)?;
+                if !keep_session {
+                    let mut client = account.login().await?;
+                    if !client.logout().await? {
+                        warn!("Logging out {user} failed, the session might still be valid");
+                    }
+                }
+                println!(
+                    "Removed account {}, its data stays in {}",
+                    account.user_id(),
+                    account.base_path()
+                );
+            }
+            Action::Switch { user } => {
+                account_registry()?.switch(user)?;
+                println!("Switched to account {user}");
+            }
+        }
+        Ok(())
+    }
+}
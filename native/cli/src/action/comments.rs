@@ -0,0 +1,174 @@
+use acter::{Client, Comment, CommentsManager};
+use acter_core::models::ActerModel;
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::fmt;
+
+use super::{object_timeout, redact};
+use crate::{
+    config::LoginConfig,
+    output::{print_event_id, print_records, OutputFormat, Record},
+};
+
+/// The kind of object the comments are attached to
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum CommentOn {
+    Task,
+    TaskList,
+    Pin,
+    News,
+    Event,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// List the comments on an object
+    List {
+        #[clap(value_enum)]
+        on: CommentOn,
+        /// The ID of the object
+        id: String,
+    },
+    /// Comment on an object
+    Create {
+        #[clap(value_enum)]
+        on: CommentOn,
+        /// The ID of the object
+        id: String,
+        /// The text of the comment
+        text: String,
+    },
+    /// Change the text of a comment
+    Update {
+        /// The comment to update
+        comment: String,
+        /// The new text of the comment
+        text: String,
+    },
+    /// Delete a comment
+    Delete {
+        /// The comment to delete
+        comment: String,
+        /// Reason given for the deletion
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+/// Reading and writing comments
+#[derive(Parser, Debug)]
+pub struct Comments {
+    #[clap(flatten)]
+    pub login: LoginConfig,
+
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct CommentRecord {
+    id: String,
+    sender: String,
+    origin_server_ts: u64,
+    text: String,
+}
+
+impl From<&Comment> for CommentRecord {
+    fn from(comment: &Comment) -> Self {
+        CommentRecord {
+            id: comment.event_id().to_string(),
+            sender: comment.sender().to_string(),
+            origin_server_ts: comment.origin_server_ts(),
+            text: comment.content_text(),
+        }
+    }
+}
+
+impl fmt::Display for CommentRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " * {} by {}: {}", self.id, self.sender, self.text)
+    }
+}
+
+impl Record for CommentRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "sender", "origin_server_ts", "text"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.sender.clone(),
+            self.origin_server_ts.to_string(),
+            self.text.clone(),
+        ]
+    }
+}
+
+async fn comments_manager(client: &Client, on: CommentOn, id: String) -> Result<CommentsManager> {
+    let timeout = object_timeout();
+    match on {
+        CommentOn::Task => client.wait_for_task(id, timeout).await?.comments().await,
+        CommentOn::TaskList => {
+            client
+                .wait_for_task_list(id, timeout)
+                .await?
+                .comments()
+                .await
+        }
+        CommentOn::Pin => client.wait_for_pin(id, timeout).await?.comments().await,
+        CommentOn::News => client.wait_for_news(id, timeout).await?.comments().await,
+        CommentOn::Event => {
+            client
+                .wait_for_calendar_event(id, timeout)
+                .await?
+                .comments()
+                .await
+        }
+    }
+}
+
+impl Comments {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        let (client, _sync_state) = self.login.synced_client().await?;
+
+        match &self.action {
+            Action::List { on, id } => {
+                let manager = comments_manager(&client, *on, id.clone()).await?;
+                let comments = manager.comments().await?;
+                let records = comments.iter().map(CommentRecord::from).collect::<Vec<_>>();
+                print_records(&records, format)?;
+            }
+            Action::Create { on, id, text } => {
+                let manager = comments_manager(&client, *on, id.clone()).await?;
+                let mut draft = manager.comment_draft()?;
+                draft.content_text(text.clone());
+                let event_id = draft.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Update { comment, text } => {
+                let comment = client
+                    .wait_for_comment(comment.clone(), object_timeout())
+                    .await?;
+                let mut update = comment.update_builder()?;
+                update.content_text(text.clone());
+                let event_id = update.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Delete { comment, reason } => {
+                let comment = client
+                    .wait_for_comment(comment.clone(), object_timeout())
+                    .await?;
+                redact(
+                    &client,
+                    comment.meta.room_id.to_string(),
+                    comment.event_id().to_string(),
+                    reason.clone(),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
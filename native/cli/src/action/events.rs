@@ -0,0 +1,194 @@
+use acter::CalendarEvent;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::fmt;
+
+use super::redact;
+use crate::{
+    config::LoginConfig,
+    output::{print_event_id, print_records, OutputFormat, Record},
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// List all calendar events
+    List {
+        /// Only list the events of this space
+        #[clap(long)]
+        space: Option<String>,
+    },
+    /// Create a new calendar event in a space
+    Create {
+        /// The space to create the event in
+        space: String,
+        /// The title of the event
+        title: String,
+        /// Start as RFC3339, e.g. `2023-06-01T12:00:00+00:00`
+        #[clap(long)]
+        start: String,
+        /// End as RFC3339, e.g. `2023-06-01T14:00:00+00:00`
+        #[clap(long)]
+        end: String,
+        #[clap(long)]
+        description: Option<String>,
+    },
+    /// Update an existing calendar event
+    Update {
+        /// The calendar event to update
+        event: String,
+        #[clap(long)]
+        title: Option<String>,
+        /// Start as RFC3339, e.g. `2023-06-01T12:00:00+00:00`
+        #[clap(long)]
+        start: Option<String>,
+        /// End as RFC3339, e.g. `2023-06-01T14:00:00+00:00`
+        #[clap(long)]
+        end: Option<String>,
+        #[clap(long)]
+        description: Option<String>,
+    },
+    /// Delete a calendar event
+    Delete {
+        /// The calendar event to delete
+        event: String,
+        /// Reason given for the deletion
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+/// Managing calendar events
+#[derive(Parser, Debug)]
+pub struct Events {
+    #[clap(flatten)]
+    pub login: LoginConfig,
+
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct EventRecord {
+    id: String,
+    room_id: String,
+    title: String,
+    description: Option<String>,
+    start: String,
+    end: String,
+}
+
+impl From<&CalendarEvent> for EventRecord {
+    fn from(event: &CalendarEvent) -> Self {
+        EventRecord {
+            id: event.event_id().to_string(),
+            room_id: event.room_id_str(),
+            title: event.title(),
+            description: event.description().map(|d| d.body()),
+            start: event.utc_start().to_rfc3339(),
+            end: event.utc_end().to_rfc3339(),
+        }
+    }
+}
+
+impl fmt::Display for EventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            " * {} [{}]: {} ({} - {})",
+            self.id, self.room_id, self.title, self.start, self.end
+        )
+    }
+}
+
+impl Record for EventRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "room_id", "title", "start", "end"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.room_id.clone(),
+            self.title.clone(),
+            self.start.clone(),
+            self.end.clone(),
+        ]
+    }
+}
+
+impl Events {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        let (client, _sync_state) = self.login.synced_client().await?;
+
+        match &self.action {
+            Action::List { space } => {
+                let events = match space {
+                    Some(space) => {
+                        client
+                            .get_space(space.clone())
+                            .await?
+                            .calendar_events()
+                            .await?
+                    }
+                    None => client.calendar_events().await?,
+                };
+                let records = events.iter().map(EventRecord::from).collect::<Vec<_>>();
+                print_records(&records, format)?;
+            }
+            Action::Create {
+                space,
+                title,
+                start,
+                end,
+                description,
+            } => {
+                let space = client.get_space(space.clone()).await?;
+                let mut draft = space.calendar_event_draft()?;
+                draft.title(title.clone());
+                draft.utc_start_from_rfc3339(start.clone())?;
+                draft.utc_end_from_rfc3339(end.clone())?;
+                if let Some(description) = description {
+                    draft.description_text(description.clone());
+                }
+                let event_id = draft.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Update {
+                event,
+                title,
+                start,
+                end,
+                description,
+            } => {
+                let event = client.calendar_event(event.clone()).await?;
+                let mut update = event.update_builder()?;
+                if let Some(title) = title {
+                    update.title(title.clone());
+                }
+                if let Some(start) = start {
+                    update.utc_start_from_rfc3339(start.clone())?;
+                }
+                if let Some(end) = end {
+                    update.utc_end_from_rfc3339(end.clone())?;
+                }
+                if let Some(description) = description {
+                    update.description_text(description.clone());
+                }
+                let event_id = update.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Delete { event, reason } => {
+                let event = client.calendar_event(event.clone()).await?;
+                redact(
+                    &client,
+                    event.room_id_str(),
+                    event.event_id().to_string(),
+                    reason.clone(),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
+use acter_core::templates::Value;
 use anyhow::{bail, Result};
 use clap::Parser;
 use futures::{pin_mut, stream::StreamExt};
+use matrix_sdk::ruma::OwnedRoomId;
 use std::{collections::HashMap, path::PathBuf};
 use tracing::info;
 
@@ -45,15 +47,24 @@ impl ExecuteOpts {
                 tmpl_engine
                     .requested_inputs()
                     .iter()
-                    .map(|(key, input)| (key.clone(), (input.is_required(), input.is_space())))
+                    .map(|(key, input)| {
+                        (
+                            key.clone(),
+                            (input.is_required(), input.is_space(), input.is_text()),
+                        )
+                    })
                     .collect::<Vec<_>>()
             };
-            for (key, (is_required, is_space)) in input_values {
+            for (key, (is_required, is_space, is_text)) in input_values {
                 if let Some(res) = mapped_inputs.get(key.as_str()) {
-                    if !is_space {
-                        bail!("{key} : non-space input values not yet supported");
+                    if is_space {
+                        let room_id = OwnedRoomId::try_from(res.to_string())?;
+                        tmpl_engine.add_space(key.to_string(), room_id).await?;
+                    } else if is_text {
+                        tmpl_engine.add_context(key.to_string(), Value::from(res.to_string()))?;
+                    } else {
+                        bail!("{key} : user input values not yet supported");
                     }
-                    tmpl_engine.add_ref(key.to_string(), "space".to_owned(), res.to_string())?;
                 } else if is_required {
                     if key != "main" {
                         bail!("Missing required input value {key} for {tmpl_path:?}");
@@ -1,14 +1,32 @@
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use futures::stream::StreamExt;
 use matrix_sdk::{
-    room::{Messages, MessagesOptions},
+    deserialized_responses::TimelineEvent,
+    room::{Messages, MessagesOptions, Room},
     ruma::OwnedRoomId,
 };
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
 use tracing::{info, trace};
 
-use crate::config::{LoginConfig, ENV_ROOM};
+use crate::{
+    config::{LoginConfig, ENV_ROOM},
+    output::{OutputFormat, Record, RecordPrinter},
+};
+
+mod export;
+
+use export::ExportOpts;
+
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Export the room into an archive
+    Export(ExportOpts),
+}
 
+/// Prints all events of the room, unless another action is given
 #[derive(Parser, Debug)]
 pub struct HistoryOpts {
     #[clap(flatten)]
@@ -16,11 +34,74 @@ pub struct HistoryOpts {
 
     /// The room you want to see the history of messages of
     #[clap(env = ENV_ROOM)]
-    pub room: OwnedRoomId,
+    pub room: Option<OwnedRoomId>,
+
+    #[clap(subcommand)]
+    pub action: Option<Action>,
+}
+
+#[derive(Serialize, Debug)]
+struct HistoryRecord {
+    event_id: Option<String>,
+    sender: Option<String>,
+    origin_server_ts: Option<u64>,
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+    /// the full event as received from the server
+    event: Value,
+}
+
+impl HistoryRecord {
+    fn new(event: Value) -> Self {
+        let field = |key: &str| {
+            event
+                .get(key)
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+        };
+        HistoryRecord {
+            event_id: field("event_id"),
+            sender: field("sender"),
+            event_type: field("type"),
+            origin_server_ts: event.get("origin_server_ts").and_then(Value::as_u64),
+            event,
+        }
+    }
+}
+
+impl fmt::Display for HistoryRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "- {}", self.event)
+    }
+}
+
+impl Record for HistoryRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["event_id", "origin_server_ts", "sender", "type"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.event_id.clone().unwrap_or_default(),
+            self.origin_server_ts
+                .map(|ts| ts.to_string())
+                .unwrap_or_default(),
+            self.sender.clone().unwrap_or_default(),
+            self.event_type.clone().unwrap_or_default(),
+        ]
+    }
 }
 
 impl HistoryOpts {
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        match (&self.action, &self.room) {
+            (Some(Action::Export(opts)), _) => opts.run(&self.login, format).await,
+            (None, Some(room)) => self.run_show(room, format).await,
+            (None, None) => bail!("Which room? Give it as argument or via {ENV_ROOM}"),
+        }
+    }
+
+    async fn run_show(&self, room_id: &OwnedRoomId, format: OutputFormat) -> Result<()> {
         let mut client = self.login.client().await?;
 
         info!(" - Syncing -");
@@ -30,34 +111,60 @@ impl HistoryOpts {
         while is_synced.next().await != Some(true) {} // let's wait for it to have synced
         info!(" - First Sync finished - ");
 
-        let Some(room) = client.get_room(self.room.as_ref()) else {
+        let Some(room) = client.get_room(room_id) else {
             bail!("Room not found");
         };
 
-        let mut msg_options = MessagesOptions::forward().from(None);
-        msg_options.limit = 100u32.into();
-
-        loop {
-            let Messages {
-                end,
-                chunk,
-                state: _,
-                ..
-            } = room.messages(msg_options).await?;
-
+        let mut printer = RecordPrinter::new(format);
+        for_each_page(&room, |chunk| {
             for msg in chunk {
-                let evt = msg.event;
-                println!("- {}", evt.into_json());
+                printer.print(HistoryRecord::new(msg.event.deserialize_as()?))?;
             }
+            Ok(())
+        })
+        .await?;
+        printer.finish()
+    }
+}
 
-            if end.is_some() {
-                msg_options = MessagesOptions::forward().from(end.as_deref());
-            } else {
-                // how do we want to understand this case?
-                trace!(room_id = ?room.room_id(), "Done loading");
-                break;
-            }
-        }
+/// Fetch all events of the room from the server, oldest first. Encrypted
+/// events are decrypted where we have the keys for.
+async fn load_timeline(room: &Room) -> Result<Vec<TimelineEvent>> {
+    let mut events = Vec::new();
+    for_each_page(room, |chunk| {
+        events.extend(chunk);
         Ok(())
+    })
+    .await?;
+    Ok(events)
+}
+
+/// Hand the events of the room to `on_page` as the pages come in, oldest
+/// first
+async fn for_each_page<F>(room: &Room, mut on_page: F) -> Result<()>
+where
+    F: FnMut(Vec<TimelineEvent>) -> Result<()>,
+{
+    let mut msg_options = MessagesOptions::forward().from(None);
+    msg_options.limit = 100u32.into();
+
+    loop {
+        let Messages {
+            end,
+            chunk,
+            state: _,
+            ..
+        } = room.messages(msg_options).await?;
+
+        on_page(chunk)?;
+
+        if end.is_some() {
+            msg_options = MessagesOptions::forward().from(end.as_deref());
+        } else {
+            // how do we want to understand this case?
+            trace!(room_id = ?room.room_id(), "Done loading");
+            break;
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,596 @@
+use acter::{Client, CommentsManager};
+use anyhow::{bail, Result};
+use chrono::{TimeZone, Utc};
+use clap::{Parser, ValueEnum};
+use matrix_sdk::{
+    deserialized_responses::TimelineEvent,
+    media::{MediaFormat, MediaRequest},
+    ruma::{
+        events::{
+            room::{message::MessageType, MediaSource},
+            AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent,
+        },
+        OwnedRoomId,
+    },
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+use zip::{write::FileOptions, ZipWriter};
+
+use super::load_timeline;
+use crate::{
+    config::{LoginConfig, ENV_ROOM},
+    output::{print_records, OutputFormat, Record},
+};
+
+/// How the human readable copy of the timeline is rendered
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Rendering {
+    Markdown,
+    Html,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportOpts {
+    /// The room to export
+    #[clap(env = ENV_ROOM)]
+    pub room: OwnedRoomId,
+
+    /// Where to write the archive to. If it ends on `.zip` a zip file
+    /// is created, otherwise a directory
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// How to render the human readable copy of the timeline
+    #[clap(long, value_enum, default_value_t = Rendering::Markdown)]
+    pub render: Rendering,
+
+    /// Don't download any media
+    #[clap(long)]
+    pub skip_media: bool,
+}
+
+/// Where the files of the archive are written to
+trait Archive {
+    fn add(&mut self, path: &str, data: &[u8]) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+struct DirArchive {
+    root: PathBuf,
+}
+
+impl Archive for DirArchive {
+    fn add(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        let target = self.root.join(path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(target, data)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct ZipArchive {
+    writer: ZipWriter<File>,
+}
+
+impl Archive for ZipArchive {
+    fn add(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        self.writer.start_file(path, FileOptions::default())?;
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+fn open_archive(output: &Path) -> Result<Box<dyn Archive>> {
+    if output.extension().map(|e| e == "zip").unwrap_or_default() {
+        if output.exists() {
+            bail!("{} already exists", output.display());
+        }
+        return Ok(Box::new(ZipArchive {
+            writer: ZipWriter::new(File::create(output)?),
+        }));
+    }
+    if output.exists() && output.read_dir()?.next().is_some() {
+        bail!("{} exists and isn't empty", output.display());
+    }
+    std::fs::create_dir_all(output)?;
+    Ok(Box::new(DirArchive {
+        root: output.to_owned(),
+    }))
+}
+
+/// keep only characters that are safe in file names on all platforms
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect()
+}
+
+fn format_ts(ts: u64) -> String {
+    Utc.timestamp_millis_opt(ts as i64)
+        .single()
+        .map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Debug)]
+struct ExportedEvent {
+    event_id: String,
+    sender: String,
+    origin_server_ts: u64,
+    #[serde(rename = "type")]
+    event_type: String,
+    /// the text of messages
+    body: Option<String>,
+    /// set if we didn't have the keys to decrypt this event
+    undecryptable: bool,
+    /// the path of the downloaded media within the archive
+    media: Option<String>,
+}
+
+/// The acter objects of the space, as kept in the store
+#[derive(Serialize, Debug, Default)]
+struct ExportedObjects {
+    news: Vec<Value>,
+    task_lists: Vec<Value>,
+    tasks: Vec<Value>,
+    pins: Vec<Value>,
+    calendar_events: Vec<Value>,
+    comments: Vec<Value>,
+}
+
+/// The machine readable index of the archive, written to `index.json`
+#[derive(Serialize, Debug)]
+struct ArchiveIndex {
+    room_id: String,
+    name: Option<String>,
+    topic: Option<String>,
+    exported_at: String,
+    events: Vec<ExportedEvent>,
+    objects: ExportedObjects,
+}
+
+/// The summary printed once the export is done
+#[derive(Serialize, Debug)]
+struct ExportSummary {
+    output: String,
+    events: usize,
+    media: usize,
+    undecryptable: usize,
+    objects: usize,
+}
+
+impl std::fmt::Display for ExportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Exported {} events ({} undecryptable), {} media files and {} objects to {}",
+            self.events, self.undecryptable, self.media, self.objects, self.output
+        )
+    }
+}
+
+impl Record for ExportSummary {
+    fn headers() -> Vec<&'static str> {
+        vec!["output", "events", "undecryptable", "media", "objects"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.output.clone(),
+            self.events.to_string(),
+            self.undecryptable.to_string(),
+            self.media.to_string(),
+            self.objects.to_string(),
+        ]
+    }
+}
+
+fn media_of(event: &AnyTimelineEvent) -> Option<(MediaSource, String)> {
+    let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(ev),
+    )) = event
+    else {
+        return None;
+    };
+    match &ev.content.msgtype {
+        MessageType::Image(c) => Some((c.source.clone(), c.body.clone())),
+        MessageType::Audio(c) => Some((c.source.clone(), c.body.clone())),
+        MessageType::Video(c) => Some((c.source.clone(), c.body.clone())),
+        MessageType::File(c) => Some((c.source.clone(), c.body.clone())),
+        _ => None,
+    }
+}
+
+impl ExportOpts {
+    pub async fn run(&self, login: &LoginConfig, format: OutputFormat) -> Result<()> {
+        let (client, _sync_state) = login.synced_client().await?;
+
+        let Some(room) = client.get_room(&self.room) else {
+            bail!("Room not found");
+        };
+
+        let mut archive = open_archive(&self.output)?;
+
+        info!(" - Loading timeline - ");
+        let timeline = load_timeline(&room).await?;
+        let events = self
+            .export_events(&client, timeline, archive.as_mut())
+            .await?;
+
+        info!(" - Collecting objects - ");
+        let objects = if room.is_space() {
+            collect_objects(&client, &self.room).await?
+        } else {
+            ExportedObjects::default()
+        };
+
+        let index = ArchiveIndex {
+            room_id: self.room.to_string(),
+            name: room.name(),
+            topic: room.topic(),
+            exported_at: Utc::now().to_rfc3339(),
+            events,
+            objects,
+        };
+
+        match self.render {
+            Rendering::Markdown => archive.add("index.md", render_markdown(&index).as_bytes())?,
+            Rendering::Html => archive.add("index.html", render_html(&index).as_bytes())?,
+        }
+        archive.add("index.json", &serde_json::to_vec_pretty(&index)?)?;
+        archive.finish()?;
+
+        let objects = &index.objects;
+        let summary = ExportSummary {
+            output: self.output.display().to_string(),
+            events: index.events.len(),
+            media: index.events.iter().filter(|e| e.media.is_some()).count(),
+            undecryptable: index.events.iter().filter(|e| e.undecryptable).count(),
+            objects: objects.news.len()
+                + objects.task_lists.len()
+                + objects.tasks.len()
+                + objects.pins.len()
+                + objects.calendar_events.len()
+                + objects.comments.len(),
+        };
+        print_records(&[summary], format)
+    }
+
+    async fn export_events(
+        &self,
+        client: &Client,
+        timeline: Vec<TimelineEvent>,
+        archive: &mut dyn Archive,
+    ) -> Result<Vec<ExportedEvent>> {
+        let mut raw_events = Vec::with_capacity(timeline.len());
+        let mut events = Vec::with_capacity(timeline.len());
+
+        for msg in timeline {
+            raw_events.push(msg.event.deserialize_as::<Value>()?);
+            let event = match msg.event.deserialize() {
+                Ok(event) => event,
+                Err(error) => {
+                    warn!(?error, "skipping event we couldn't parse");
+                    continue;
+                }
+            };
+            let event_id = event.event_id().to_string();
+
+            let media = match media_of(&event) {
+                Some((source, name)) if !self.skip_media => {
+                    let request = MediaRequest {
+                        source,
+                        format: MediaFormat::File,
+                    };
+                    match client.media().get_media_content(&request, true).await {
+                        Ok(data) => {
+                            let path = format!("media/{}-{}", sanitize(&event_id), sanitize(&name));
+                            archive.add(&path, &data)?;
+                            Some(path)
+                        }
+                        Err(error) => {
+                            warn!(?error, event_id, "downloading media failed");
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            let body = match &event {
+                AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+                    MessageLikeEvent::Original(ev),
+                )) => Some(ev.content.body().to_owned()),
+                _ => None,
+            };
+
+            events.push(ExportedEvent {
+                event_id,
+                sender: event.sender().to_string(),
+                origin_server_ts: event.origin_server_ts().get().into(),
+                event_type: event.event_type().to_string(),
+                body,
+                undecryptable: matches!(
+                    event,
+                    AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomEncrypted(_))
+                ),
+                media,
+            });
+        }
+
+        archive.add("events.json", &serde_json::to_vec_pretty(&raw_events)?)?;
+        Ok(events)
+    }
+}
+
+async fn push_comments(manager: CommentsManager, comments: &mut Vec<Value>) -> Result<()> {
+    for comment in manager.comments().await? {
+        comments.push(serde_json::to_value(&*comment)?);
+    }
+    Ok(())
+}
+
+async fn collect_objects(client: &Client, room_id: &OwnedRoomId) -> Result<ExportedObjects> {
+    let space = client.get_space(room_id.to_string()).await?;
+    let mut objects = ExportedObjects::default();
+    if !space.is_acter_space().await? {
+        return Ok(objects);
+    }
+
+    for entry in space.latest_news_entries(u32::MAX).await? {
+        objects.news.push(serde_json::to_value(&*entry)?);
+        push_comments(entry.comments().await?, &mut objects.comments).await?;
+    }
+    for task_list in space.task_lists().await? {
+        objects.task_lists.push(serde_json::to_value(&*task_list)?);
+        push_comments(task_list.comments().await?, &mut objects.comments).await?;
+        for task in task_list.tasks().await? {
+            objects.tasks.push(serde_json::to_value(&*task)?);
+            push_comments(task.comments().await?, &mut objects.comments).await?;
+        }
+    }
+    for pin in space.pins().await? {
+        objects.pins.push(serde_json::to_value(&*pin)?);
+        push_comments(pin.comments().await?, &mut objects.comments).await?;
+    }
+    for event in space.calendar_events().await? {
+        objects.calendar_events.push(serde_json::to_value(&*event)?);
+        push_comments(event.comments().await?, &mut objects.comments).await?;
+    }
+    Ok(objects)
+}
+
+fn object_title(object: &Value, field: &str) -> String {
+    object
+        .get("inner")
+        .and_then(|inner| inner.get(field))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn render_markdown(index: &ArchiveIndex) -> String {
+    let mut out = String::new();
+    let name = index.name.as_deref().unwrap_or(&index.room_id);
+    out.push_str(&format!("# {name}\n\n"));
+    if let Some(topic) = &index.topic {
+        out.push_str(&format!("{topic}\n\n"));
+    }
+    out.push_str(&format!(
+        "_Room `{}` exported at {}_\n\n## Timeline\n\n",
+        index.room_id, index.exported_at
+    ));
+    for event in &index.events {
+        let ts = format_ts(event.origin_server_ts);
+        let text = match (&event.body, event.undecryptable) {
+            (Some(body), _) => body.clone(),
+            (None, true) => "_unable to decrypt_".to_owned(),
+            (None, false) => format!("`{}`", event.event_type),
+        };
+        out.push_str(&format!("- **{}** ({ts}): {text}", event.sender));
+        if let Some(media) = &event.media {
+            out.push_str(&format!(" [attachment]({media})"));
+        }
+        out.push('\n');
+    }
+
+    let objects = &index.objects;
+    for (title, items, field) in [
+        ("Task lists", &objects.task_lists, "name"),
+        ("Tasks", &objects.tasks, "title"),
+        ("Pins", &objects.pins, "title"),
+        ("Calendar events", &objects.calendar_events, "title"),
+    ] {
+        if items.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\n## {title}\n\n"));
+        for item in items {
+            out.push_str(&format!("- {}\n", object_title(item, field)));
+        }
+    }
+    if !objects.news.is_empty() {
+        out.push_str(&format!("\n## News\n\n{} entries\n", objects.news.len()));
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn render_html(index: &ArchiveIndex) -> String {
+    let mut out = String::new();
+    let name = escape_html(index.name.as_deref().unwrap_or(&index.room_id));
+    out.push_str(&format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{name}</title></head><body>\n<h1>{name}</h1>\n"
+    ));
+    if let Some(topic) = &index.topic {
+        out.push_str(&format!("<p>{}</p>\n", escape_html(topic)));
+    }
+    out.push_str(&format!(
+        "<p><em>Room <code>{}</code> exported at {}</em></p>\n<h2>Timeline</h2>\n<ul>\n",
+        escape_html(&index.room_id),
+        escape_html(&index.exported_at)
+    ));
+    for event in &index.events {
+        let ts = format_ts(event.origin_server_ts);
+        let text = match (&event.body, event.undecryptable) {
+            (Some(body), _) => escape_html(body),
+            (None, true) => "<em>unable to decrypt</em>".to_owned(),
+            (None, false) => format!("<code>{}</code>", escape_html(&event.event_type)),
+        };
+        out.push_str(&format!(
+            "<li><strong>{}</strong> ({ts}): {text}",
+            escape_html(&event.sender)
+        ));
+        if let Some(media) = &event.media {
+            out.push_str(&format!(
+                " <a href=\"{}\">attachment</a>",
+                escape_html(media)
+            ));
+        }
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ul>\n");
+
+    let objects = &index.objects;
+    for (title, items, field) in [
+        ("Task lists", &objects.task_lists, "name"),
+        ("Tasks", &objects.tasks, "title"),
+        ("Pins", &objects.pins, "title"),
+        ("Calendar events", &objects.calendar_events, "title"),
+    ] {
+        if items.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("<h2>{title}</h2>\n<ul>\n"));
+        for item in items {
+            out.push_str(&format!(
+                "<li>{}</li>\n",
+                escape_html(&object_title(item, field))
+            ));
+        }
+        out.push_str("</ul>\n");
+    }
+    if !objects.news.is_empty() {
+        out.push_str(&format!(
+            "<h2>News</h2>\n<p>{} entries</p>\n",
+            objects.news.len()
+        ));
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(sender: &str, body: Option<&str>, undecryptable: bool) -> ExportedEvent {
+        ExportedEvent {
+            event_id: "$event".to_owned(),
+            sender: sender.to_owned(),
+            origin_server_ts: 0,
+            event_type: "m.room.message".to_owned(),
+            body: body.map(ToOwned::to_owned),
+            undecryptable,
+            media: None,
+        }
+    }
+
+    fn index() -> ArchiveIndex {
+        let mut with_media = event("@quark:acter.global", Some("the menu"), false);
+        with_media.media = Some("media/menu.png".to_owned());
+        let mut state = event("@odo:acter.global", None, false);
+        state.event_type = "m.room.topic".to_owned();
+        ArchiveIndex {
+            room_id: "!ops:acter.global".to_owned(),
+            name: Some("Ops <DS9>".to_owned()),
+            topic: Some("Running the station".to_owned()),
+            exported_at: "2023-05-01 10:00:00".to_owned(),
+            events: vec![
+                event("@sisko:acter.global", Some("Red alert & shields up"), false),
+                event("@kira:acter.global", None, true),
+                with_media,
+                state,
+            ],
+            objects: ExportedObjects {
+                tasks: vec![json!({ "inner": { "title": "Fix the replicators" } })],
+                news: vec![json!({}), json!({})],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn renders_markdown() {
+        let out = render_markdown(&index());
+        assert!(out.starts_with("# Ops <DS9>\n\nRunning the station\n\n"));
+        assert!(out.contains("_Room `!ops:acter.global` exported at 2023-05-01 10:00:00_"));
+        assert!(out
+            .contains("- **@sisko:acter.global** (1970-01-01 00:00:00): Red alert & shields up\n"));
+        assert!(
+            out.contains("- **@kira:acter.global** (1970-01-01 00:00:00): _unable to decrypt_\n")
+        );
+        assert!(out.contains("the menu [attachment](media/menu.png)\n"));
+        assert!(out.contains("(1970-01-01 00:00:00): `m.room.topic`\n"));
+        assert!(out.contains("\n## Tasks\n\n- Fix the replicators\n"));
+        assert!(!out.contains("## Pins"), "empty sections are left out");
+        assert!(out.ends_with("\n## News\n\n2 entries\n"));
+    }
+
+    #[test]
+    fn renders_html_escaped() {
+        let out = render_html(&index());
+        assert!(out.contains("<title>Ops &lt;DS9&gt;</title>"));
+        assert!(out.contains("<h1>Ops &lt;DS9&gt;</h1>"));
+        assert!(out.contains(
+            "<li><strong>@sisko:acter.global</strong> (1970-01-01 00:00:00): Red alert &amp; shields up</li>"
+        ));
+        assert!(out.contains("<em>unable to decrypt</em></li>"));
+        assert!(out.contains("the menu <a href=\"media/menu.png\">attachment</a></li>"));
+        assert!(out.contains("<code>m.room.topic</code></li>"));
+        assert!(out.contains("<h2>Tasks</h2>\n<ul>\n<li>Fix the replicators</li>\n</ul>\n"));
+        assert!(!out.contains("<h2>Pins</h2>"));
+        assert!(out.ends_with("<h2>News</h2>\n<p>2 entries</p>\n</body></html>\n"));
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+}
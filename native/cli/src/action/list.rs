@@ -1,10 +1,16 @@
+use acter::Space;
 use acter_core::spaces::SpaceRelation;
 use anyhow::Result;
 use clap::Parser;
 use futures::stream::StreamExt;
+use serde::Serialize;
+use std::fmt;
 use tracing::info;
 
-use crate::config::LoginConfig;
+use crate::{
+    config::LoginConfig,
+    output::{print_records, OutputFormat, Record},
+};
 
 /// Posting a news item to a given room
 #[derive(Parser, Debug)]
@@ -23,8 +29,226 @@ pub struct List {
     pub await_history_sync: bool,
 }
 
+#[derive(Serialize, Debug)]
+struct RelationRecord {
+    room_id: String,
+    target_type: String,
+    suggested: bool,
+}
+
+impl From<&SpaceRelation> for RelationRecord {
+    fn from(relation: &SpaceRelation) -> Self {
+        RelationRecord {
+            room_id: relation.room_id().to_string(),
+            target_type: relation.target_type(),
+            suggested: relation.suggested(),
+        }
+    }
+}
+
+impl fmt::Display for RelationRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.room_id, self.target_type)
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ObjectCounts {
+    news: usize,
+    task_lists: usize,
+    calendar_events: usize,
+    pins: usize,
+    pinned_links: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct SpaceDetails {
+    aliases: Vec<String>,
+    topic: Option<String>,
+    avatar_url: Option<String>,
+    canonical_parent: Option<RelationRecord>,
+    other_parents: Vec<RelationRecord>,
+    children: Vec<RelationRecord>,
+    /// only available for acter spaces
+    objects: Option<ObjectCounts>,
+}
+
+impl SpaceDetails {
+    async fn load(space: &Space, is_acter_space: bool) -> Result<Self> {
+        let relations = space.space_relations().await?;
+        let objects = if is_acter_space {
+            Some(ObjectCounts {
+                news: space.latest_news_entries(100).await?.len(),
+                task_lists: space.task_lists().await?.len(),
+                calendar_events: space.calendar_events().await?.len(),
+                pins: space.pins().await?.len(),
+                pinned_links: space.pinned_links().await?.len(),
+            })
+        } else {
+            None
+        };
+        Ok(SpaceDetails {
+            aliases: space
+                .alt_aliases()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            topic: space.topic(),
+            avatar_url: space.avatar_url().map(|url| url.to_string()),
+            canonical_parent: relations.main_parent().as_ref().map(RelationRecord::from),
+            other_parents: relations
+                .other_parents()
+                .iter()
+                .map(RelationRecord::from)
+                .collect(),
+            children: relations
+                .children()
+                .iter()
+                .map(RelationRecord::from)
+                .collect(),
+            objects,
+        })
+    }
+}
+
+fn join(relations: &[&RelationRecord]) -> String {
+    relations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for SpaceDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.aliases.is_empty() {
+            writeln!(f, " - aliases:  ( {} )", self.aliases.join(", "))?;
+        }
+        writeln!(f, " - Topic: {}", self.topic.as_deref().unwrap_or_default())?;
+
+        if let Some(avatar_url) = &self.avatar_url {
+            writeln!(f, " - Avatar: {avatar_url}")?;
+        }
+
+        if let Some(p) = &self.canonical_parent {
+            writeln!(f, " - Canonical parent: {p}")?;
+        }
+
+        if self.other_parents.is_empty() {
+            if self.canonical_parent.is_some() {
+                writeln!(f, " - No other space parents")?;
+            } else {
+                writeln!(f, " - No space parents")?;
+            }
+        } else {
+            let parents = self.other_parents.iter().collect::<Vec<_>>();
+            writeln!(f, " - Other Space parents: {}", join(&parents))?;
+        }
+
+        if self.children.is_empty() {
+            writeln!(f, " - No space children")?;
+        } else {
+            let (suggested, other): (Vec<&RelationRecord>, Vec<&RelationRecord>) =
+                self.children.iter().partition(|p| p.suggested);
+            if !suggested.is_empty() {
+                writeln!(f, " - Suggested space children: {}", join(&suggested))?;
+                if !other.is_empty() {
+                    writeln!(f, " - Other space children: {}", join(&other))?;
+                }
+            } else if !other.is_empty() {
+                writeln!(f, " - Space children: {}", join(&other))?;
+            }
+        }
+
+        if let Some(objects) = &self.objects {
+            writeln!(f, " - Objects: ")?;
+            writeln!(f, "   * {} NewsItems ", objects.news)?;
+            writeln!(f, "   * {} TaskList ", objects.task_lists)?;
+            writeln!(f, "   * {} Calendar Events ", objects.calendar_events)?;
+            writeln!(
+                f,
+                "   * {} Pins of which {} are links",
+                objects.pins, objects.pinned_links
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum RoomRecord {
+    Space {
+        room_id: String,
+        display_name: String,
+        is_acter_space: bool,
+        #[serde(flatten)]
+        details: Option<SpaceDetails>,
+    },
+    Chat {
+        room_id: String,
+        display_name: String,
+    },
+}
+
+impl fmt::Display for RoomRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomRecord::Space {
+                room_id,
+                display_name,
+                is_acter_space,
+                details,
+            } => {
+                let acter_space = if *is_acter_space { 'x' } else { ' ' };
+                write!(f, " ## [{acter_space}] {room_id}: {display_name}")?;
+                if let Some(details) = details {
+                    // details end on a newline, giving it space to breath
+                    write!(f, "\n{details}")?;
+                }
+                Ok(())
+            }
+            RoomRecord::Chat {
+                room_id,
+                display_name,
+            } => write!(f, " * {room_id} : {display_name}"),
+        }
+    }
+}
+
+impl Record for RoomRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["kind", "room_id", "display_name", "acter space"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        match self {
+            RoomRecord::Space {
+                room_id,
+                display_name,
+                is_acter_space,
+                ..
+            } => vec![
+                "space".to_owned(),
+                room_id.clone(),
+                display_name.clone(),
+                is_acter_space.to_string(),
+            ],
+            RoomRecord::Chat {
+                room_id,
+                display_name,
+            } => vec![
+                "chat".to_owned(),
+                room_id.clone(),
+                display_name.clone(),
+                String::new(),
+            ],
+        }
+    }
+}
+
 impl List {
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
         let mut client = self.login.client().await?;
         info!(" - Syncing -");
         let sync_state = client.start_sync();
@@ -39,121 +263,42 @@ impl List {
             info!(" - History synced - ");
         }
 
-        println!("## Spaces:");
+        let mut spaces = Vec::new();
         for sp in client.spaces().await? {
-            let room_id = sp.room_id();
             let is_acter_space = sp.is_acter_space().await?;
-            let acter_space = if is_acter_space { 'x' } else { ' ' };
-            let display_name = sp.display_name().await?;
-            println!(" ## [{acter_space}] {room_id}: {display_name}");
-            if self.details {
-                let aliases = {
-                    let aliases = sp.alt_aliases();
-                    if aliases.is_empty() {
-                        "".to_owned()
-                    } else {
-                        format!(
-                            " ( {} )",
-                            aliases
-                                .iter()
-                                .map(ToString::to_string)
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        )
-                    }
-                };
-                if !aliases.is_empty() {
-                    println!(" - aliases: {aliases}");
-                }
-                let topic = sp.topic().unwrap_or_default();
-                println!(" - Topic: {topic}");
-
-                if let Some(avatar_url) = sp.avatar_url() {
-                    println!(" - Avatar: {avatar_url}");
-                }
-
-                let relations = &sp.space_relations().await?;
-
-                if let Some(p) = relations.main_parent() {
-                    println!(" - Canonical parent: {} ({})", p.room_id(), p.target_type());
-                }
-
-                if relations.other_parents().is_empty() {
-                    if relations.main_parent().is_some() {
-                        println!(" - No other space parents");
-                    } else {
-                        println!(" - No space parents");
-                    }
-                } else {
-                    println!(
-                        " - Other Space parents: {}",
-                        relations
-                            .other_parents()
-                            .iter()
-                            .map(|r| format!("{} ({})", r.room_id(), r.target_type()))
-                            .collect::<Vec<_>>()
-                            .join(", ")
-                    )
-                }
-                let children = relations.children();
-                if children.is_empty() {
-                    println!(" - No space children");
-                } else {
-                    let (suggested, other): (Vec<&SpaceRelation>, Vec<&SpaceRelation>) =
-                        children.iter().partition(|p| p.suggested());
-                    if !suggested.is_empty() {
-                        println!(
-                            " - Suggested space children: {}",
-                            suggested
-                                .iter()
-                                .map(|r| format!("{} ({})", r.room_id(), r.target_type()))
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        );
-                        if !other.is_empty() {
-                            println!(
-                                " - Other space children: {}",
-                                other
-                                    .iter()
-                                    .map(|r| format!("{} ({})", r.room_id(), r.target_type()))
-                                    .collect::<Vec<_>>()
-                                    .join(", ")
-                            );
-                        }
-                    } else if !other.is_empty() {
-                        println!(
-                            " - Space children: {}",
-                            other
-                                .iter()
-                                .map(|r| format!("{} ({})", r.room_id(), r.target_type()))
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        )
-                    };
-                }
-
-                if is_acter_space {
-                    let news_count = sp.latest_news_entries(100).await?.len();
-                    let task_lists = sp.task_lists().await?.len();
-                    let pins = sp.pins().await?.len();
-                    let pinned_links = sp.pinned_links().await?.len();
-                    let events_count = sp.calendar_events().await?.len();
-                    println!(" - Objects: ");
-                    println!("   * {news_count} NewsItems ");
-                    println!("   * {task_lists} TaskList ");
-                    println!("   * {events_count} Calendar Events ");
-                    println!("   * {pins} Pins of which {pinned_links} are links");
-                }
-
-                println!(); // give it space to breath
-            }
+            let details = if self.details {
+                Some(SpaceDetails::load(&sp, is_acter_space).await?)
+            } else {
+                None
+            };
+            spaces.push(RoomRecord::Space {
+                room_id: sp.room_id().to_string(),
+                display_name: sp.display_name().await?.to_string(),
+                is_acter_space,
+                details,
+            });
         }
 
+        let mut chats = Vec::new();
         if self.list_chats {
-            println!("## Chat rooms:");
             for convo in client.convos().await? {
-                println!(" * {} : {}", convo.room_id(), convo.display_name().await?);
+                chats.push(RoomRecord::Chat {
+                    room_id: convo.room_id().to_string(),
+                    display_name: convo.display_name().await?.to_string(),
+                });
+            }
+        }
+
+        if format == OutputFormat::Text {
+            println!("## Spaces:");
+            print_records(&spaces, format)?;
+            if self.list_chats {
+                println!("## Chat rooms:");
+                print_records(&chats, format)?;
             }
+        } else {
+            spaces.append(&mut chats);
+            print_records(&spaces, format)?;
         }
         Ok(())
     }
@@ -0,0 +1,168 @@
+use acter::NewsEntry;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::fmt;
+
+use super::{object_timeout, redact};
+use crate::{
+    config::LoginConfig,
+    output::{print_event_id, print_records, OutputFormat, Record},
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// List the latest news entries
+    List {
+        /// Only list the news of this space
+        #[clap(long)]
+        space: Option<String>,
+        /// How many entries to list at most
+        #[clap(long, default_value = "10")]
+        count: u32,
+    },
+    /// Post a news entry in a space
+    Create {
+        /// The space to post the news in
+        space: String,
+        /// Markdown text of a slide, can be given multiple times
+        #[clap(long = "text", required = true)]
+        texts: Vec<String>,
+    },
+    /// Replace the slides of an existing news entry
+    Update {
+        /// The news entry to update
+        news: String,
+        /// Markdown text of a slide, can be given multiple times
+        #[clap(long = "text", required = true)]
+        texts: Vec<String>,
+    },
+    /// Delete a news entry
+    Delete {
+        /// The news entry to delete
+        news: String,
+        /// Reason given for the deletion
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+/// Posting and managing news
+#[derive(Parser, Debug)]
+pub struct News {
+    #[clap(flatten)]
+    pub login: LoginConfig,
+
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct SlideRecord {
+    #[serde(rename = "type")]
+    type_str: String,
+    text: String,
+}
+
+#[derive(Serialize, Debug)]
+struct NewsRecord {
+    id: String,
+    room_id: String,
+    sender: String,
+    slides: Vec<SlideRecord>,
+}
+
+impl From<&NewsEntry> for NewsRecord {
+    fn from(entry: &NewsEntry) -> Self {
+        let slides = (0..entry.slides_count())
+            .filter_map(|pos| entry.get_slide(pos))
+            .map(|slide| SlideRecord {
+                type_str: slide.type_str(),
+                text: slide.text(),
+            })
+            .collect();
+        NewsRecord {
+            id: entry.event_id().to_string(),
+            room_id: entry.room_id().to_string(),
+            sender: entry.sender().to_string(),
+            slides,
+        }
+    }
+}
+
+impl fmt::Display for NewsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " ## {} [{}] by {}", self.id, self.room_id, self.sender)?;
+        for slide in &self.slides {
+            write!(f, "\n   * ({}) {}", slide.type_str, slide.text)?;
+        }
+        Ok(())
+    }
+}
+
+impl Record for NewsRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "room_id", "sender", "slides"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.room_id.clone(),
+            self.sender.clone(),
+            self.slides.len().to_string(),
+        ]
+    }
+}
+
+impl News {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        let (client, _sync_state) = self.login.synced_client().await?;
+
+        match &self.action {
+            Action::List { space, count } => {
+                let entries = match space {
+                    Some(space) => {
+                        client
+                            .get_space(space.clone())
+                            .await?
+                            .latest_news_entries(*count)
+                            .await?
+                    }
+                    None => client.latest_news_entries(*count).await?,
+                };
+                let records = entries.iter().map(NewsRecord::from).collect::<Vec<_>>();
+                print_records(&records, format)?;
+            }
+            Action::Create { space, texts } => {
+                let space = client.get_space(space.clone()).await?;
+                let mut draft = space.news_draft()?;
+                for text in texts {
+                    draft.add_text_slide(text.clone());
+                }
+                let event_id = draft.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Update { news, texts } => {
+                let entry = client.wait_for_news(news.clone(), object_timeout()).await?;
+                let event_id = entry
+                    .update_builder()?
+                    .text_slides(texts.clone())
+                    .send()
+                    .await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Delete { news, reason } => {
+                let entry = client.wait_for_news(news.clone(), object_timeout()).await?;
+                redact(
+                    &client,
+                    entry.room_id().to_string(),
+                    entry.event_id().to_string(),
+                    reason.clone(),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use acter::ActerPin;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::fmt;
+
+use super::redact;
+use crate::{
+    config::LoginConfig,
+    output::{print_event_id, print_records, OutputFormat, Record},
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// List all pins
+    List {
+        /// Only list the pins of this space
+        #[clap(long)]
+        space: Option<String>,
+    },
+    /// Pin a new link or text in a space
+    Create {
+        /// The space to create the pin in
+        space: String,
+        /// The title of the pin
+        title: String,
+        /// The URL this pin links to
+        #[clap(long)]
+        url: Option<String>,
+        /// Plain text content of the pin
+        #[clap(long, conflicts_with = "markdown")]
+        text: Option<String>,
+        /// Markdown content of the pin
+        #[clap(long)]
+        markdown: Option<String>,
+    },
+    /// Update an existing pin
+    Update {
+        /// The pin to update
+        pin: String,
+        #[clap(long)]
+        title: Option<String>,
+        #[clap(long)]
+        url: Option<String>,
+        #[clap(long, conflicts_with = "markdown")]
+        text: Option<String>,
+        #[clap(long)]
+        markdown: Option<String>,
+    },
+    /// Delete a pin
+    Delete {
+        /// The pin to delete
+        pin: String,
+        /// Reason given for the deletion
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+/// Managing pins
+#[derive(Parser, Debug)]
+pub struct Pins {
+    #[clap(flatten)]
+    pub login: LoginConfig,
+
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct PinRecord {
+    id: String,
+    room_id: String,
+    title: String,
+    url: Option<String>,
+    text: Option<String>,
+}
+
+impl From<&ActerPin> for PinRecord {
+    fn from(pin: &ActerPin) -> Self {
+        PinRecord {
+            id: pin.event_id_str(),
+            room_id: pin.room_id_str(),
+            title: pin.title(),
+            url: pin.url(),
+            text: pin.content_text(),
+        }
+    }
+}
+
+impl fmt::Display for PinRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " * {} [{}]: {}", self.id, self.room_id, self.title)?;
+        if let Some(url) = &self.url {
+            write!(f, " <{url}>")?;
+        }
+        Ok(())
+    }
+}
+
+impl Record for PinRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "room_id", "title", "url"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.room_id.clone(),
+            self.title.clone(),
+            self.url.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Pins {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        let (client, _sync_state) = self.login.synced_client().await?;
+
+        match &self.action {
+            Action::List { space } => {
+                let pins = match space {
+                    Some(space) => client.get_space(space.clone()).await?.pins().await?,
+                    None => client.pins().await?,
+                };
+                let records = pins.iter().map(PinRecord::from).collect::<Vec<_>>();
+                print_records(&records, format)?;
+            }
+            Action::Create {
+                space,
+                title,
+                url,
+                text,
+                markdown,
+            } => {
+                let space = client.get_space(space.clone()).await?;
+                let mut draft = space.pin_draft()?;
+                draft.title(title.clone());
+                if let Some(url) = url {
+                    draft.url(url.clone());
+                }
+                if let Some(text) = text {
+                    draft.content_text(text.clone());
+                } else if let Some(markdown) = markdown {
+                    draft.content_markdown(markdown.clone());
+                }
+                let event_id = draft.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Update {
+                pin,
+                title,
+                url,
+                text,
+                markdown,
+            } => {
+                let pin = client.pin(pin.clone()).await?;
+                let mut update = pin.update_builder()?;
+                if let Some(title) = title {
+                    update.title(title.clone());
+                }
+                if let Some(url) = url {
+                    update.url(url.clone());
+                }
+                if let Some(text) = text {
+                    update.content_text(text.clone());
+                } else if let Some(markdown) = markdown {
+                    update.content_markdown(markdown.clone());
+                }
+                let event_id = update.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Delete { pin, reason } => {
+                let pin = client.pin(pin.clone()).await?;
+                redact(
+                    &client,
+                    pin.room_id_str(),
+                    pin.event_id_str(),
+                    reason.clone(),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
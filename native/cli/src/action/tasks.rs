@@ -0,0 +1,261 @@
+use acter::{Task, TaskList};
+use acter_core::models::{ActerModel, AnyActerModel};
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use matrix_sdk::ruma::OwnedUserId;
+use serde::Serialize;
+use std::fmt;
+
+use super::{object_timeout, redact};
+use crate::{
+    config::LoginConfig,
+    output::{print_event_id, print_records, OutputFormat, Record},
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// List all task lists with their tasks
+    List {
+        /// Only list the task lists of this space
+        #[clap(long)]
+        space: Option<String>,
+    },
+    /// Create a new task list in a space
+    CreateList {
+        /// The space to create the task list in
+        space: String,
+        /// The name of the task list
+        name: String,
+        #[clap(long)]
+        description: Option<String>,
+    },
+    /// Create a new task on a task list
+    Create {
+        /// The task list to add the task to
+        task_list: String,
+        /// The title of the task
+        title: String,
+        #[clap(long)]
+        description: Option<String>,
+        /// Due date as RFC3339, e.g. `2023-06-01T12:00:00+00:00`
+        #[clap(long)]
+        due: Option<String>,
+        /// Assign the task to this user, can be given multiple times
+        #[clap(long = "assignee")]
+        assignees: Vec<OwnedUserId>,
+    },
+    /// Update an existing task
+    Update {
+        /// The task to update
+        task: String,
+        #[clap(long)]
+        title: Option<String>,
+        #[clap(long)]
+        description: Option<String>,
+        /// Due date as RFC3339, e.g. `2023-06-01T12:00:00+00:00`
+        #[clap(long)]
+        due: Option<String>,
+        /// Mark the task as done
+        #[clap(long, conflicts_with = "undone")]
+        done: bool,
+        /// Mark the task as not done
+        #[clap(long)]
+        undone: bool,
+    },
+    /// Delete a task or task list
+    Delete {
+        /// The task or task list to delete
+        id: String,
+        /// Reason given for the deletion
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+/// Managing task lists and tasks
+#[derive(Parser, Debug)]
+pub struct Tasks {
+    #[clap(flatten)]
+    pub login: LoginConfig,
+
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct TaskRecord {
+    id: String,
+    task_list_id: String,
+    title: String,
+    description: Option<String>,
+    is_done: bool,
+    due: Option<String>,
+    assignees: Vec<String>,
+}
+
+impl From<&Task> for TaskRecord {
+    fn from(task: &Task) -> Self {
+        TaskRecord {
+            id: task.event_id().to_string(),
+            task_list_id: task.task_list_id.event_id.to_string(),
+            title: task.title().clone(),
+            description: task.description_text(),
+            is_done: task.is_done(),
+            due: task.utc_due.map(|d| d.to_rfc3339()),
+            assignees: task.assignees.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+impl fmt::Display for TaskRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let done = if self.is_done { 'x' } else { ' ' };
+        write!(f, "   * [{done}] {}: {}", self.id, self.title)?;
+        if let Some(due) = &self.due {
+            write!(f, " (due {due})")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct TaskListRecord {
+    id: String,
+    room_id: String,
+    name: String,
+    description: Option<String>,
+    tasks: Vec<TaskRecord>,
+}
+
+impl TaskListRecord {
+    async fn load(task_list: &TaskList) -> Result<Self> {
+        let tasks = task_list.tasks().await?;
+        Ok(TaskListRecord {
+            id: task_list.event_id().to_string(),
+            room_id: task_list.room_id().to_string(),
+            name: task_list.name.clone(),
+            description: task_list.description_text(),
+            tasks: tasks.iter().map(TaskRecord::from).collect(),
+        })
+    }
+}
+
+impl fmt::Display for TaskListRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " ## {} [{}]: {}", self.id, self.room_id, self.name)?;
+        for task in &self.tasks {
+            write!(f, "\n{task}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Record for TaskListRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "room_id", "name", "tasks done"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let done = self.tasks.iter().filter(|t| t.is_done).count();
+        vec![
+            self.id.clone(),
+            self.room_id.clone(),
+            self.name.clone(),
+            format!("{done}/{}", self.tasks.len()),
+        ]
+    }
+}
+
+impl Tasks {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        let (client, _sync_state) = self.login.synced_client().await?;
+
+        match &self.action {
+            Action::List { space } => {
+                let task_lists = match space {
+                    Some(space) => client.get_space(space.clone()).await?.task_lists().await?,
+                    None => client.task_lists().await?,
+                };
+                let mut records = Vec::with_capacity(task_lists.len());
+                for task_list in &task_lists {
+                    records.push(TaskListRecord::load(task_list).await?);
+                }
+                print_records(&records, format)?;
+            }
+            Action::CreateList {
+                space,
+                name,
+                description,
+            } => {
+                let space = client.get_space(space.clone()).await?;
+                let mut draft = space.task_list_draft()?;
+                draft.name(name.clone());
+                if let Some(description) = description {
+                    draft.description_text(description.clone());
+                }
+                let event_id = draft.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Create {
+                task_list,
+                title,
+                description,
+                due,
+                assignees,
+            } => {
+                let task_list = client
+                    .wait_for_task_list(task_list.clone(), object_timeout())
+                    .await?;
+                let mut draft = task_list.task_builder()?;
+                draft.title(title.clone());
+                if let Some(description) = description {
+                    draft.description_text(description.clone());
+                }
+                if let Some(due) = due {
+                    draft.utc_due_from_rfc3339(due.clone())?;
+                }
+                if !assignees.is_empty() {
+                    draft.assignees(&mut assignees.clone());
+                }
+                let event_id = draft.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Update {
+                task,
+                title,
+                description,
+                due,
+                done,
+                undone,
+            } => {
+                let task = client.wait_for_task(task.clone(), object_timeout()).await?;
+                let mut update = task.update_builder()?;
+                if let Some(title) = title {
+                    update.title(title.clone());
+                }
+                if let Some(description) = description {
+                    update.description_text(description.clone());
+                }
+                if let Some(due) = due {
+                    update.utc_due_from_rfc3339(due.clone())?;
+                }
+                if *done {
+                    update.mark_done();
+                } else if *undone {
+                    update.mark_undone();
+                }
+                let event_id = update.send().await?;
+                print_event_id(event_id.as_str(), format)?;
+            }
+            Action::Delete { id, reason } => {
+                let room_id = match client.store().get(id).await? {
+                    AnyActerModel::Task(task) => task.room_id().to_owned(),
+                    AnyActerModel::TaskList(task_list) => task_list.room_id().to_owned(),
+                    _ => bail!("{id} is neither a task nor a task list"),
+                };
+                redact(&client, room_id.to_string(), id.clone(), reason.clone()).await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use acter::Template;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use futures::stream::StreamExt;
+use serde::Serialize;
+use std::{fmt, path::PathBuf};
+use tracing::info;
+
+use crate::{
+    config::LoginConfig,
+    output::{print_records, OutputFormat, Record},
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// List all templates available to the user
+    List {
+        /// Show the inputs each template requests
+        #[clap(long)]
+        details: bool,
+    },
+    /// Fetch the source of a template
+    Fetch {
+        /// The key the template is registered under
+        key: String,
+
+        /// Write the template to this file rather than stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Discovering templates
+#[derive(Parser, Debug)]
+pub struct Templates {
+    #[clap(flatten)]
+    pub login: LoginConfig,
+
+    #[clap(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct InputRecord {
+    name: String,
+    #[serde(rename = "type")]
+    input_type: String,
+    required: bool,
+    description: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct TemplateRecord {
+    key: String,
+    name: Option<String>,
+    description: Option<String>,
+    revision: u32,
+    /// the space the template was published in, `None` for builtin ones
+    origin: Option<String>,
+    inputs: Vec<InputRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip)]
+    show_inputs: bool,
+}
+
+impl TemplateRecord {
+    fn new(tmpl: &Template, show_inputs: bool) -> Self {
+        TemplateRecord {
+            key: tmpl.key(),
+            name: tmpl.name(),
+            description: tmpl.description(),
+            revision: tmpl.revision(),
+            origin: tmpl.origin_room_id_str(),
+            inputs: tmpl
+                .requested_inputs()
+                .iter()
+                .map(|input| InputRecord {
+                    name: input.name(),
+                    input_type: input.input_type(),
+                    required: input.is_required(),
+                    description: input.description(),
+                })
+                .collect(),
+            source: None,
+            show_inputs,
+        }
+    }
+}
+
+impl fmt::Display for TemplateRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origin = self.origin.as_deref().unwrap_or("builtin");
+        let name = self.name.as_deref().unwrap_or_default();
+        write!(
+            f,
+            " * {} (rev {}) [{origin}]: {name}",
+            self.key, self.revision
+        )?;
+        if let Some(desc) = &self.description {
+            write!(f, "\n   {desc}")?;
+        }
+        if self.show_inputs {
+            for input in &self.inputs {
+                let required = if input.required {
+                    "required"
+                } else {
+                    "optional"
+                };
+                let desc = input.description.as_deref().unwrap_or_default();
+                write!(
+                    f,
+                    "\n   - {} : {} ({required}) {desc}",
+                    input.name, input.input_type
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Record for TemplateRecord {
+    fn headers() -> Vec<&'static str> {
+        vec!["key", "revision", "origin", "name"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.key.clone(),
+            self.revision.to_string(),
+            self.origin.clone().unwrap_or_else(|| "builtin".to_owned()),
+            self.name.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Templates {
+    pub async fn run(&self, format: OutputFormat) -> Result<()> {
+        let mut client = self.login.client().await?;
+        info!(" - Syncing -");
+        let sync_state = client.start_sync();
+
+        let mut is_synced = sync_state.first_synced_rx();
+        while is_synced.next().await != Some(true) {} // let's wait for it to have synced
+        info!(" - First Sync finished - ");
+
+        match self.action {
+            Action::List { details } => {
+                let records = client
+                    .available_templates()
+                    .await?
+                    .iter()
+                    .map(|tmpl| TemplateRecord::new(tmpl, details))
+                    .collect::<Vec<_>>();
+                if format == OutputFormat::Text {
+                    println!("## Templates:");
+                }
+                print_records(&records, format)?;
+            }
+            Action::Fetch {
+                ref key,
+                ref output,
+            } => {
+                let tmpl = client.template(key.clone()).await?;
+                match output {
+                    Some(path) => {
+                        std::fs::write(path, tmpl.source())?;
+                        info!("Template {key} written to {}", path.display());
+                    }
+                    None if format == OutputFormat::Text => println!("{}", tmpl.source()),
+                    None => {
+                        let mut record = TemplateRecord::new(&tmpl, true);
+                        record.source = Some(tmpl.source());
+                        print_records(&[record], format)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,12 @@
-use acter::api::{login_new_client, login_with_token, Client};
-use anyhow::Result;
+use acter::api::{login_new_client, login_with_token, AccountRegistry, Client, SyncState};
+use anyhow::{Context, Result};
 use clap::{crate_version, Parser, ValueHint};
 use dialoguer::{theme::ColorfulTheme, Password};
+use futures::stream::StreamExt;
 use std::path::{Path, PathBuf};
 use tracing::{error, info, warn};
 
-use crate::action::Action;
+use crate::{action::Action, output::OutputFormat};
 
 pub const ENV_DEFAULT_HOMESERVER_URL: &str = "DEFAULT_HOMESERVER_URL";
 pub const ENV_DEFAULT_HOMESERVER_NAME: &str = "DEFAULT_HOMESERVER_NAME";
@@ -14,6 +15,13 @@ pub const ENV_PASSWORD: &str = "ACTER_PASSWORD";
 pub const ENV_REG_TOKEN: &str = "ACTER_REGISTRATIOn_TOKEN";
 pub const ENV_ROOM: &str = "ACTER_ROOM";
 
+/// where `accounts add` keeps the logged in accounts
+const ACCOUNTS_PATH: &str = ".local/accounts.json";
+
+pub fn account_registry() -> Result<AccountRegistry> {
+    AccountRegistry::load(ACCOUNTS_PATH)
+}
+
 /// Generic Login Configuration helper
 #[derive(Parser, Debug)]
 pub struct LoginConfig {
@@ -33,14 +41,15 @@ pub struct LoginConfig {
     )]
     pub server_name: String,
 
-    /// Fully qualified @SOMETHING:server.tld username.
+    /// Fully qualified @SOMETHING:server.tld username. Uses the current
+    /// account of `accounts` if not given.
     #[clap(
         short = 'u',
         long = "user",
         value_hint = ValueHint::Username,
         env = ENV_USER
     )]
-    login_username: String,
+    login_username: Option<String>,
 
     /// optional registration token
     #[clap(
@@ -66,11 +75,22 @@ pub struct LoginConfig {
 }
 
 impl LoginConfig {
+    fn base_path(username: &str) -> String {
+        format!(".local/{username}/")
+    }
+
     pub async fn client(&self) -> Result<Client> {
+        let Some(username) = self.login_username.clone() else {
+            let registry = account_registry()?;
+            let account = registry
+                .current()
+                .context("No user given and no account stored, see `accounts add`")?;
+            warn!("Logging in as stored account {}", account.user_id());
+            return account.login().await;
+        };
         let theme = ColorfulTheme::default();
-        let username = self.login_username.clone();
         warn!("Logging in as {}", username);
-        let base_path = format!(".local/{username}/");
+        let base_path = Self::base_path(&username);
 
         if self.force_login && Path::new(&base_path).exists() {
             std::fs::remove_dir_all(&base_path)?;
@@ -120,6 +140,31 @@ impl LoginConfig {
         }
         Ok(client)
     }
+
+    /// Login and keep the account in the registry
+    pub async fn add_account(&self) -> Result<Client> {
+        let client = self.client().await?;
+        let base_path = Self::base_path(client.user_id()?.as_str());
+        account_registry()?.add(&client, base_path).await?;
+        Ok(client)
+    }
+
+    /// Login and wait until the history of all spaces has been loaded, so
+    /// that all acter objects are available. Syncing stops once the returned
+    /// `SyncState` is dropped.
+    pub async fn synced_client(&self) -> Result<(Client, SyncState)> {
+        let mut client = self.client().await?;
+        info!(" - Syncing -");
+        let sync_state = client.start_sync();
+
+        let mut is_synced = sync_state.first_synced_rx();
+        while is_synced.next().await != Some(true) {} // let's wait for it to have synced
+        info!(" - First Sync finished - ");
+
+        sync_state.await_has_synced_history().await?;
+        info!(" - History synced - ");
+        Ok((client, sync_state))
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -129,6 +174,10 @@ pub struct ActerCliConfig {
     #[clap(short, long, default_value = "acter_cli=info,warn")]
     pub log: String,
 
+    /// How to print results: human readable `text` or machine readable `json`, `ndjson` or `table`
+    #[clap(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
     /// The action to perform
     #[clap(subcommand)]
     pub action: Action,
@@ -2,16 +2,25 @@
 
 mod action;
 mod config;
+mod output;
 
-use anyhow::Result;
 use clap::Parser;
 use config::ActerCliConfig;
 use env_logger::Builder;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = ActerCliConfig::parse();
-    Builder::default().parse_filters(&cli.log).try_init()?;
-    cli.action.run().await?;
-    Ok(())
+    let result = match Builder::default().parse_filters(&cli.log).try_init() {
+        Ok(()) => cli.action.run(cli.format).await,
+        Err(e) => Err(e.into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            output::print_error(&error, cli.format);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,137 @@
+use anyhow::{Error, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::Display;
+use term_table::{row::Row, table_cell::TableCell, Table, TableStyle};
+
+/// How results are printed to stdout
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// A single pretty printed JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// A table of the most important fields
+    Table,
+}
+
+/// A single item of output. The serialized form is the stable schema
+/// used for the `json` and `ndjson` formats, `Display` is used for `text`.
+pub trait Record: Serialize + Display {
+    /// the column names for the `table` format
+    fn headers() -> Vec<&'static str>;
+    /// the cells of this record for the `table` format
+    fn row(&self) -> Vec<String>;
+}
+
+/// Print the given records in the requested format
+pub fn print_records<T: Record>(records: &[T], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for record in records {
+                println!("{record}");
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(records)?),
+        OutputFormat::Ndjson => {
+            for record in records {
+                println!("{}", serde_json::to_string(record)?);
+            }
+        }
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.style = TableStyle::simple();
+            table.add_row(Row::new(
+                T::headers()
+                    .into_iter()
+                    .map(TableCell::new)
+                    .collect::<Vec<_>>(),
+            ));
+            for record in records {
+                table.add_row(Row::new(
+                    record
+                        .row()
+                        .into_iter()
+                        .map(TableCell::new)
+                        .collect::<Vec<_>>(),
+                ));
+            }
+            println!("{}", table.render());
+        }
+    }
+    Ok(())
+}
+
+/// Prints records as they come in. `json` and `table` need all of them
+/// first, those are printed on `finish`.
+pub struct RecordPrinter<T: Record> {
+    format: OutputFormat,
+    pending: Vec<T>,
+}
+
+impl<T: Record> RecordPrinter<T> {
+    pub fn new(format: OutputFormat) -> Self {
+        RecordPrinter {
+            format,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn print(&mut self, record: T) -> Result<()> {
+        match self.format {
+            OutputFormat::Text => println!("{record}"),
+            OutputFormat::Ndjson => println!("{}", serde_json::to_string(&record)?),
+            OutputFormat::Json | OutputFormat::Table => self.pending.push(record),
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.format {
+            OutputFormat::Json | OutputFormat::Table => print_records(&self.pending, self.format),
+            OutputFormat::Text | OutputFormat::Ndjson => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct EventIdRecord<'a> {
+    event_id: &'a str,
+}
+
+/// Print the ID of a freshly created or updated object
+pub fn print_event_id(event_id: &str, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&EventIdRecord { event_id })?
+        ),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(&EventIdRecord { event_id })?),
+        OutputFormat::Text | OutputFormat::Table => println!("{event_id}"),
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorRecord {
+    error: String,
+}
+
+/// Report the error on stderr, structured if a machine readable format was requested
+pub fn print_error(error: &Error, format: OutputFormat) {
+    match format {
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let record = ErrorRecord {
+                error: format!("{error:#}"),
+            };
+            match serde_json::to_string(&record) {
+                Ok(json) => eprintln!("{json}"),
+                Err(_) => eprintln!("Error: {error:?}"),
+            }
+        }
+        OutputFormat::Text | OutputFormat::Table => eprintln!("Error: {error:?}"),
+    }
+}
@@ -1,7 +1,7 @@
 use derive_getters::Getters;
 use matrix_sdk::Client;
 
-use crate::{error::Error, executor::Executor, store::Store};
+use crate::{error::Error, executor::Executor, outbox::Outbox, store::Store};
 
 /// Comment Event
 #[derive(Clone, Debug, Getters)]
@@ -9,6 +9,7 @@ pub struct CoreClient {
     client: Client,
     store: Store,
     executor: Executor,
+    outbox: Outbox,
 }
 
 impl CoreClient {
@@ -16,10 +17,12 @@ impl CoreClient {
         let store = Store::new(client.clone()).await?;
         let executor = Executor::new(store.clone()).await?;
         client.add_event_handler_context(executor.clone());
+        let outbox = Outbox::load(client.clone(), executor.clone()).await?;
 
         Ok(CoreClient {
             store,
             executor,
+            outbox,
             client,
         })
     }
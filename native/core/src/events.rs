@@ -4,13 +4,17 @@ pub mod comments;
 mod common;
 pub mod news;
 pub mod pins;
+pub mod polls;
 pub mod rsvp;
 pub mod settings;
+pub mod starred;
 pub mod tasks;
+pub mod templates;
+pub mod voice;
 
 pub use common::{
-    BelongsTo, BrandIcon, Color, Colorize, Icon, Labels, ObjRef, Position, RefDetails, Reference,
-    References, Update, UtcDateTime,
+    BelongsTo, BrandIcon, Color, Colorize, Icon, InThread, Labels, ObjRef, Position, RefDetails,
+    Reference, References, Update, UtcDateTime,
 };
 use ruma_common::{events::StaticEventContent, exports::serde::de::Error as SerdeDeError};
 
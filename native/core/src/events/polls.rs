@@ -0,0 +1,322 @@
+use matrix_sdk::ruma::{
+    events::macros::EventContent, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::Reference;
+
+/// Whether the votes can be seen before the poll ended
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PollKind {
+    #[default]
+    #[serde(rename = "org.matrix.msc3381.poll.undisclosed")]
+    Undisclosed,
+    #[serde(rename = "org.matrix.msc3381.poll.disclosed")]
+    Disclosed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PollQuestion {
+    #[serde(rename = "org.matrix.msc1767.text")]
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PollAnswer {
+    pub id: String,
+    #[serde(rename = "org.matrix.msc1767.text")]
+    pub text: String,
+}
+
+fn default_max_selections() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PollStart {
+    pub question: PollQuestion,
+    #[serde(default)]
+    pub kind: PollKind,
+    /// how many answers one may pick at most
+    #[serde(default = "default_max_selections")]
+    pub max_selections: u32,
+    pub answers: Vec<PollAnswer>,
+}
+
+/// A new poll, as of MSC3381
+#[derive(Clone, Debug, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "org.matrix.msc3381.poll.start", kind = MessageLike)]
+pub struct PollStartEventContent {
+    #[serde(rename = "org.matrix.msc3381.poll.start")]
+    pub poll: PollStart,
+
+    /// What clients without poll support show instead
+    #[serde(rename = "org.matrix.msc1767.text")]
+    pub text: String,
+}
+
+impl PollStartEventContent {
+    pub fn new(
+        question: String,
+        answers: Vec<String>,
+        kind: PollKind,
+        max_selections: u32,
+    ) -> Self {
+        let mut text = question.clone();
+        for (idx, answer) in answers.iter().enumerate() {
+            text.push_str(&format!("\n{}. {answer}", idx + 1));
+        }
+        let answers = answers
+            .into_iter()
+            .enumerate()
+            .map(|(idx, text)| PollAnswer {
+                id: format!("answer-{idx}"),
+                text,
+            })
+            .collect();
+        PollStartEventContent {
+            poll: PollStart {
+                question: PollQuestion { text: question },
+                kind,
+                max_selections: max_selections.max(1),
+                answers,
+            },
+            text,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PollResponse {
+    #[serde(default)]
+    pub answers: Vec<String>,
+}
+
+/// A vote on a poll, replacing the earlier ones of the sender
+#[derive(Clone, Debug, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "org.matrix.msc3381.poll.response", kind = MessageLike)]
+pub struct PollResponseEventContent {
+    #[serde(rename = "m.relates_to")]
+    pub relates_to: Reference,
+
+    #[serde(rename = "org.matrix.msc3381.poll.response")]
+    pub response: PollResponse,
+}
+
+impl PollResponseEventContent {
+    pub fn new(poll_id: OwnedEventId, answers: Vec<String>) -> Self {
+        PollResponseEventContent {
+            relates_to: poll_id.into(),
+            response: PollResponse { answers },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PollEnd {}
+
+/// Closes the poll, later votes don't count
+#[derive(Clone, Debug, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "org.matrix.msc3381.poll.end", kind = MessageLike)]
+pub struct PollEndEventContent {
+    #[serde(rename = "m.relates_to")]
+    pub relates_to: Reference,
+
+    #[serde(rename = "org.matrix.msc3381.poll.end", default)]
+    pub end: PollEnd,
+
+    /// What clients without poll support show instead
+    #[serde(rename = "org.matrix.msc1767.text")]
+    pub text: String,
+}
+
+impl PollEndEventContent {
+    pub fn new(poll_id: OwnedEventId) -> Self {
+        PollEndEventContent {
+            relates_to: poll_id.into(),
+            end: PollEnd {},
+            text: "The poll has ended.".to_owned(),
+        }
+    }
+}
+
+/// The votes on a poll as far as we have seen them
+///
+/// Only the latest response of each user counts. Unknown answers are
+/// dropped and only the first `max_selections` are kept, a response left
+/// without answers takes the earlier vote back. Only the creator can end
+/// the poll, responses sent after that are ignored.
+#[derive(Clone, Debug)]
+pub struct PollResults {
+    creator: OwnedUserId,
+    poll: PollStart,
+    votes: BTreeMap<OwnedUserId, (MilliSecondsSinceUnixEpoch, Vec<String>)>,
+    ended_at: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl PollResults {
+    pub fn new(creator: OwnedUserId, poll: PollStart) -> Self {
+        PollResults {
+            creator,
+            poll,
+            votes: Default::default(),
+            ended_at: None,
+        }
+    }
+
+    pub fn creator(&self) -> &UserId {
+        &self.creator
+    }
+
+    pub fn poll(&self) -> &PollStart {
+        &self.poll
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.ended_at.is_some()
+    }
+
+    /// `false` if the response doesn't change the results
+    pub fn add_response(
+        &mut self,
+        sender: OwnedUserId,
+        ts: MilliSecondsSinceUnixEpoch,
+        answers: Vec<String>,
+    ) -> bool {
+        if self.ended_at.map(|end| ts > end).unwrap_or_default() {
+            return false;
+        }
+        if let Some((prev_ts, _)) = self.votes.get(&sender) {
+            if *prev_ts > ts {
+                // we got a newer one already
+                return false;
+            }
+        }
+        let mut valid = vec![];
+        for answer in answers {
+            if valid.len() >= self.poll.max_selections as usize {
+                break;
+            }
+            if !valid.contains(&answer) && self.poll.answers.iter().any(|a| a.id == answer) {
+                valid.push(answer);
+            }
+        }
+        self.votes.insert(sender, (ts, valid));
+        true
+    }
+
+    /// `false` if the sender may not end the poll or it ended already
+    pub fn end(&mut self, sender: &UserId, ts: MilliSecondsSinceUnixEpoch) -> bool {
+        if sender != self.creator || self.is_ended() {
+            return false;
+        }
+        self.votes.retain(|_, (vote_ts, _)| *vote_ts <= ts);
+        self.ended_at = Some(ts);
+        true
+    }
+
+    /// the answers the user currently votes for
+    pub fn answers_of(&self, user_id: &UserId) -> Vec<String> {
+        self.votes
+            .get(user_id)
+            .map(|(_, answers)| answers.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn votes_for(&self, answer_id: &str) -> usize {
+        self.votes
+            .values()
+            .filter(|(_, answers)| answers.iter().any(|a| a == answer_id))
+            .count()
+    }
+
+    /// how many users vote for any answer
+    pub fn voters(&self) -> usize {
+        self.votes
+            .values()
+            .filter(|(_, answers)| !answers.is_empty())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matrix_sdk::ruma::{user_id, UInt};
+
+    fn ts(millis: u32) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch(UInt::from(millis))
+    }
+
+    fn lunch_poll(max_selections: u32) -> PollResults {
+        let content = PollStartEventContent::new(
+            "Lunch?".to_owned(),
+            vec!["Pizza".to_owned(), "Sushi".to_owned()],
+            PollKind::Disclosed,
+            max_selections,
+        );
+        PollResults::new(user_id!("@sisko:ds9.org").to_owned(), content.poll)
+    }
+
+    #[test]
+    fn latest_response_counts() {
+        let mut results = lunch_poll(1);
+        let kira = user_id!("@kira:ds9.org");
+        assert!(results.add_response(kira.to_owned(), ts(2), vec!["answer-1".to_owned()]));
+        // arrived late, but is older
+        assert!(!results.add_response(kira.to_owned(), ts(1), vec!["answer-0".to_owned()]));
+        assert_eq!(results.votes_for("answer-0"), 0);
+        assert_eq!(results.votes_for("answer-1"), 1);
+        assert_eq!(results.answers_of(kira), vec!["answer-1".to_owned()]);
+    }
+
+    #[test]
+    fn invalid_answers_are_dropped() {
+        let mut results = lunch_poll(1);
+        let odo = user_id!("@odo:ds9.org");
+        results.add_response(
+            odo.to_owned(),
+            ts(1),
+            vec![
+                "answer-5".to_owned(),
+                "answer-0".to_owned(),
+                "answer-1".to_owned(),
+            ],
+        );
+        assert_eq!(results.answers_of(odo), vec!["answer-0".to_owned()]);
+        results.add_response(odo.to_owned(), ts(2), vec![]);
+        assert_eq!(results.voters(), 0);
+    }
+
+    #[test]
+    fn only_creator_ends_and_later_votes_are_ignored() {
+        let mut results = lunch_poll(2);
+        let quark = user_id!("@quark:ds9.org");
+        results.add_response(quark.to_owned(), ts(5), vec!["answer-0".to_owned()]);
+        assert!(!results.end(quark, ts(3)));
+        assert!(results.end(user_id!("@sisko:ds9.org"), ts(3)));
+        assert!(results.is_ended());
+        assert_eq!(results.voters(), 0);
+        assert!(!results.add_response(quark.to_owned(), ts(4), vec!["answer-1".to_owned()]));
+    }
+
+    #[test]
+    fn start_content_roundtrip() -> crate::Result<()> {
+        let content = PollStartEventContent::new(
+            "Lunch?".to_owned(),
+            vec!["Pizza".to_owned()],
+            PollKind::Undisclosed,
+            0,
+        );
+        let json = serde_json::to_value(&content)?;
+        assert_eq!(
+            json["org.matrix.msc3381.poll.start"]["kind"],
+            "org.matrix.msc3381.poll.undisclosed"
+        );
+        assert_eq!(json["org.matrix.msc1767.text"], "Lunch?\n1. Pizza");
+        let parsed = serde_json::from_value::<PollStartEventContent>(json)?;
+        assert_eq!(parsed.poll.max_selections, 1);
+        Ok(())
+    }
+}
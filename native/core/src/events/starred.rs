@@ -0,0 +1,71 @@
+use matrix_sdk::ruma::{events::macros::EventContent, EventId, OwnedEventId, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+
+/// A message the user starred
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StarredMessage {
+    pub room_id: OwnedRoomId,
+    pub event_id: OwnedEventId,
+}
+
+/// The messages the user starred to find them again, latest last
+///
+/// Kept in the global account data, so it is private to the user but
+/// shared among their devices.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "global.acter.dev.starred_messages", kind = GlobalAccountData)]
+pub struct StarredMessagesEventContent {
+    #[serde(default)]
+    pub messages: Vec<StarredMessage>,
+}
+
+impl StarredMessagesEventContent {
+    pub fn is_starred(&self, room_id: &RoomId, event_id: &EventId) -> bool {
+        self.messages
+            .iter()
+            .any(|m| m.room_id == room_id && m.event_id == event_id)
+    }
+
+    /// `false` if the message was starred already
+    pub fn star(&mut self, room_id: OwnedRoomId, event_id: OwnedEventId) -> bool {
+        if self.is_starred(&room_id, &event_id) {
+            return false;
+        }
+        self.messages.push(StarredMessage { room_id, event_id });
+        true
+    }
+
+    /// `false` if the message wasn't starred
+    pub fn unstar(&mut self, room_id: &RoomId, event_id: &EventId) -> bool {
+        let before = self.messages.len();
+        self.messages
+            .retain(|m| m.room_id != room_id || m.event_id != event_id);
+        self.messages.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matrix_sdk::ruma::{event_id, room_id};
+
+    #[test]
+    fn starring_twice_keeps_one() {
+        let mut starred = StarredMessagesEventContent::default();
+        let room_id = room_id!("!euhIDqDVvVXulrhWgN:ds9.acter.global");
+        let event_id = event_id!("$KwumA4L3M-duXu0I3UA886LvN-BDCKAyxR1skNfnh3c");
+        assert!(starred.star(room_id.to_owned(), event_id.to_owned()));
+        assert!(!starred.star(room_id.to_owned(), event_id.to_owned()));
+        assert_eq!(starred.messages.len(), 1);
+        assert!(starred.unstar(room_id, event_id));
+        assert!(!starred.unstar(room_id, event_id));
+        assert!(starred.messages.is_empty());
+    }
+
+    #[test]
+    fn missing_messages_parse_as_empty() -> crate::Result<()> {
+        let starred = serde_json::from_str::<StarredMessagesEventContent>("{}")?;
+        assert!(starred.messages.is_empty());
+        Ok(())
+    }
+}
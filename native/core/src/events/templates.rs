@@ -0,0 +1,16 @@
+use derive_builder::Builder;
+use matrix_sdk::ruma::events::macros::EventContent;
+use serde::{Deserialize, Serialize};
+
+/// A template shared within a space
+///
+/// Templates are stored as state events, keyed by the template key, so that
+/// a newer revision published under the same key replaces the former one.
+#[derive(Clone, Debug, Deserialize, Serialize, EventContent, Builder)]
+#[ruma_event(type = "global.acter.dev.template", kind = State, state_key_type = String)]
+#[builder(name = "TemplateBuilder", derive(Debug))]
+pub struct TemplateEventContent {
+    /// The full toml source of the template
+    #[builder(setter(into))]
+    pub source: String,
+}
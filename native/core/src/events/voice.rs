@@ -0,0 +1,420 @@
+#[cfg(feature = "opus")]
+use audiopus::{coder::Decoder, Channels, SampleRate};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{Error, Result};
+
+/// the loudest a waveform sample can be
+pub const WAVEFORM_MAX: u16 = 1024;
+
+/// how many samples we put into the waveform of a voice message
+pub const WAVEFORM_SAMPLES: usize = 100;
+
+/// Opus always counts its granules at 48kHz, no matter the input rate
+const OPUS_GRANULE_RATE: u64 = 48_000;
+
+/// the longest an Opus packet can last, in samples per channel at 48kHz
+#[cfg(feature = "opus")]
+const OPUS_MAX_PACKET_SAMPLES: usize = 5760;
+
+/// How long the recording is and how loud along the way
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AudioDetails {
+    /// in milliseconds
+    pub duration: u64,
+    #[serde(default)]
+    pub waveform: Vec<u16>,
+}
+
+/// Marks the audio as recorded voice rather than any audio file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VoiceMarker {}
+
+/// The additions of MSC3245 (v1) to `m.audio` messages
+///
+/// Kept next to the regular audio content, so clients without voice
+/// support still show a playable audio file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VoiceContent {
+    #[serde(
+        rename = "org.matrix.msc1767.audio",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub audio: Option<AudioDetails>,
+
+    #[serde(
+        rename = "org.matrix.msc3245.voice",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub voice: Option<VoiceMarker>,
+}
+
+impl VoiceContent {
+    pub fn new(duration: Duration, waveform: Vec<u16>) -> Self {
+        VoiceContent {
+            audio: Some(AudioDetails {
+                duration: duration.as_millis() as u64,
+                waveform: waveform.into_iter().map(|s| s.min(WAVEFORM_MAX)).collect(),
+            }),
+            voice: Some(VoiceMarker {}),
+        }
+    }
+
+    pub fn is_voice(&self) -> bool {
+        self.voice.is_some()
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.audio
+            .as_ref()
+            .map(|a| Duration::from_millis(a.duration))
+    }
+
+    pub fn waveform(&self) -> &[u16] {
+        self.audio
+            .as_ref()
+            .map(|a| a.waveform.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// Squeezes the levels into `samples` buckets, scaled so the loudest
+/// bucket hits `WAVEFORM_MAX`
+pub fn waveform(levels: &[f32], samples: usize) -> Vec<u16> {
+    let buckets = samples.min(levels.len());
+    if buckets == 0 {
+        return vec![];
+    }
+    let means = (0..buckets)
+        .map(|idx| {
+            let bucket = &levels[idx * levels.len() / buckets..(idx + 1) * levels.len() / buckets];
+            bucket.iter().sum::<f32>() / bucket.len() as f32
+        })
+        .collect::<Vec<_>>();
+    let loudest = means.iter().copied().fold(0f32, f32::max);
+    if loudest <= 0. {
+        return vec![0; buckets];
+    }
+    means
+        .into_iter()
+        .map(|mean| (mean / loudest * WAVEFORM_MAX as f32).round() as u16)
+        .collect()
+}
+
+/// Duration and waveform of a recording, either 16bit PCM in a WAV file or
+/// Opus in an Ogg file. Without the `opus` feature, Ogg Opus recordings come
+/// without a waveform.
+pub fn analyze_recording(data: &[u8]) -> Result<(Duration, Vec<u16>)> {
+    let (duration, levels) = if data.starts_with(b"RIFF") {
+        wav_levels(data)?
+    } else if data.starts_with(b"OggS") {
+        ogg_opus_levels(data)?
+    } else {
+        return Err(Error::Custom(
+            "Only WAV and Ogg Opus recordings are supported".to_owned(),
+        ));
+    };
+    Ok((duration, waveform(&levels, WAVEFORM_SAMPLES)))
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// The mean amplitude of every 10ms
+fn wav_levels(data: &[u8]) -> Result<(Duration, Vec<f32>)> {
+    let broken = || Error::Custom("Broken WAV file".to_owned());
+    if data.get(8..12) != Some(b"WAVE".as_slice()) {
+        return Err(broken());
+    }
+    let mut format = None;
+    let mut samples = None;
+    let mut pos = 12;
+    while let Some(size) = u32_at(data, pos + 4) {
+        let start = pos + 8;
+        let end = (start + size as usize).min(data.len());
+        match &data[pos..pos + 4] {
+            b"fmt " => {
+                let kind = u16_at(data, start).ok_or_else(broken)?;
+                let channels = u16_at(data, start + 2).ok_or_else(broken)?;
+                let rate = u32_at(data, start + 4).ok_or_else(broken)?;
+                let bits = u16_at(data, start + 14).ok_or_else(broken)?;
+                format = Some((kind, channels, rate, bits));
+            }
+            b"data" => samples = Some(&data[start..end]),
+            _ => {}
+        }
+        // chunks are padded to even sizes
+        pos = start + size as usize + size as usize % 2;
+    }
+    let (Some((kind, channels, rate, bits)), Some(samples)) = (format, samples) else {
+        return Err(broken());
+    };
+    if kind != 1 || bits != 16 || channels == 0 || rate == 0 {
+        return Err(Error::Custom(
+            "Only 16bit PCM WAV files are supported".to_owned(),
+        ));
+    }
+    let samples = samples
+        .chunks_exact(2)
+        .map(|s| i16::from_le_bytes([s[0], s[1]]))
+        .collect::<Vec<_>>();
+    let frames = samples.len() / channels as usize;
+    let duration = Duration::from_millis(frames as u64 * 1000 / rate as u64);
+    Ok((duration, pcm_levels(&samples, rate, channels as usize)))
+}
+
+/// The mean amplitude of every 10ms of the interleaved samples
+fn pcm_levels(samples: &[i16], rate: u32, channels: usize) -> Vec<f32> {
+    let window = (rate as usize / 100).max(1) * channels;
+    samples
+        .chunks(window)
+        .map(|chunk| {
+            let sum = chunk
+                .iter()
+                .map(|s| (*s as f32).abs() / i16::MAX as f32)
+                .sum::<f32>();
+            sum / chunk.len() as f32
+        })
+        .collect()
+}
+
+#[cfg(feature = "opus")]
+fn opus_error(error: audiopus::Error) -> Error {
+    Error::Custom(format!("Decoding Opus failed: {error}"))
+}
+
+/// The duration and, with the `opus` feature, the mean amplitude of every
+/// 10ms
+fn ogg_opus_levels(data: &[u8]) -> Result<(Duration, Vec<f32>)> {
+    let broken = || Error::Custom("Broken Ogg file".to_owned());
+    let mut packets: Vec<Vec<u8>> = vec![];
+    let mut packet = vec![];
+    let mut last_granule = 0u64;
+    let mut pos = 0;
+    while pos < data.len() {
+        if data.get(pos..pos + 4) != Some(b"OggS".as_slice()) {
+            return Err(broken());
+        }
+        let granule = data
+            .get(pos + 6..pos + 14)
+            .and_then(|g| g.try_into().ok())
+            .map(u64::from_le_bytes)
+            .ok_or_else(broken)?;
+        // all bits set means no packet ends on this page
+        if granule != u64::MAX {
+            last_granule = granule;
+        }
+        let segments = *data.get(pos + 26).ok_or_else(broken)? as usize;
+        let table = data.get(pos + 27..pos + 27 + segments).ok_or_else(broken)?;
+        let mut body = pos + 27 + segments;
+        for lacing in table {
+            let lacing = *lacing as usize;
+            packet.extend_from_slice(data.get(body..body + lacing).ok_or_else(broken)?);
+            body += lacing;
+            if lacing < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        pos = body;
+    }
+    let Some(head) = packets.first().filter(|p| p.starts_with(b"OpusHead")) else {
+        return Err(Error::Custom("Only Opus in Ogg is supported".to_owned()));
+    };
+    let channel_count = match head.get(9) {
+        Some(count @ (1 | 2)) => *count as usize,
+        _ => {
+            return Err(Error::Custom(
+                "Only mono and stereo Opus is supported".to_owned(),
+            ))
+        }
+    };
+    let pre_skip = u16_at(head, 10).ok_or_else(broken)? as u64;
+    let duration =
+        Duration::from_millis(last_granule.saturating_sub(pre_skip) * 1000 / OPUS_GRANULE_RATE);
+    let levels = opus_levels(&packets, pre_skip as usize, channel_count)?;
+    Ok((duration, levels))
+}
+
+/// Decoding needs libopus, without it there is no waveform
+#[cfg(not(feature = "opus"))]
+fn opus_levels(_packets: &[Vec<u8>], _pre_skip: usize, _channel_count: usize) -> Result<Vec<f32>> {
+    Ok(vec![])
+}
+
+/// The mean amplitude of every 10ms of the Opus packets, decoded with libopus
+#[cfg(feature = "opus")]
+fn opus_levels(packets: &[Vec<u8>], pre_skip: usize, channel_count: usize) -> Result<Vec<f32>> {
+    let channels = if channel_count == 2 {
+        Channels::Stereo
+    } else {
+        Channels::Mono
+    };
+    let mut decoder = Decoder::new(SampleRate::Hz48000, channels).map_err(opus_error)?;
+    let mut pcm = vec![0i16; OPUS_MAX_PACKET_SAMPLES * channel_count];
+    let mut samples = vec![];
+    // after the head and the tags come the audio packets
+    for packet in packets.iter().skip(2) {
+        let decoded = decoder
+            .decode(Some(packet.as_slice()), &mut pcm, false)
+            .map_err(opus_error)?;
+        samples.extend_from_slice(&pcm[..decoded * channel_count]);
+    }
+    // the encoder's warm up isn't part of the recording
+    let skip = (pre_skip * channel_count).min(samples.len());
+    Ok(pcm_levels(
+        &samples[skip..],
+        OPUS_GRANULE_RATE as u32,
+        channel_count,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(rate: u32, samples: &[i16]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(36 + samples.len() as u32 * 2).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        data.extend_from_slice(&1u16.to_le_bytes()); // mono
+        data.extend_from_slice(&rate.to_le_bytes());
+        data.extend_from_slice(&(rate * 2).to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&16u16.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(samples.len() as u32 * 2).to_le_bytes());
+        for sample in samples {
+            data.extend_from_slice(&sample.to_le_bytes());
+        }
+        data
+    }
+
+    fn ogg_page(granule: u64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&[0; 12]); // serial, sequence and checksum
+        let mut table = vec![];
+        for packet in packets {
+            table.resize(table.len() + packet.len() / 255, 255);
+            table.push((packet.len() % 255) as u8);
+        }
+        page.push(table.len() as u8);
+        page.extend(table);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        page
+    }
+
+    #[test]
+    fn waveform_is_scaled_to_the_loudest() {
+        assert_eq!(waveform(&[0.1, 0.1, 0.5, 0.5], 2), vec![205, 1024]);
+        assert_eq!(waveform(&[0.; 3], 10), vec![0; 3]);
+        assert!(waveform(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn wav_recording() -> crate::Result<()> {
+        // half a second of silence, then half a second of noise
+        let mut samples = vec![0i16; 4000];
+        samples.extend((0..4000).map(|i| if i % 2 == 0 { 16000 } else { -16000 }));
+        let (duration, waveform) = analyze_recording(&wav(8000, &samples))?;
+        assert_eq!(duration, Duration::from_secs(1));
+        assert_eq!(waveform.len(), WAVEFORM_SAMPLES);
+        assert_eq!(waveform[0], 0);
+        assert_eq!(waveform[WAVEFORM_SAMPLES - 1], WAVEFORM_MAX);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(feature = "opus"))]
+    fn ogg_opus_recording_without_waveform() -> crate::Result<()> {
+        let mut head = b"OpusHead\x01\x01".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&[0; 7]);
+        let mut data = ogg_page(0, &[&head]);
+        data.extend(ogg_page(0, &[b"OpusTags"]));
+        data.extend(ogg_page(312 + 96_000, &[b"not decoded"]));
+
+        let (duration, waveform) = analyze_recording(&data)?;
+        assert_eq!(duration, Duration::from_secs(2));
+        assert!(waveform.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "opus")]
+    fn ogg_opus_recording() -> crate::Result<()> {
+        use audiopus::{coder::Encoder, Application};
+
+        // a second of silence, then a second of a 1kHz square wave, in 20ms frames
+        let frame = OPUS_GRANULE_RATE as usize / 50;
+        let mut samples = vec![0i16; OPUS_GRANULE_RATE as usize];
+        samples
+            .extend((0..OPUS_GRANULE_RATE).map(|i| if i / 24 % 2 == 0 { 16000 } else { -16000 }));
+        let encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Audio)
+            .map_err(opus_error)?;
+        let mut packets = vec![];
+        for chunk in samples.chunks(frame) {
+            let mut packet = vec![0u8; 4000];
+            let size = encoder.encode(chunk, &mut packet).map_err(opus_error)?;
+            packet.truncate(size);
+            packets.push(packet);
+        }
+        let pre_skip = encoder.lookahead().map_err(opus_error)? as u16;
+
+        let mut head = b"OpusHead\x01\x01".to_vec();
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&[0; 7]);
+        let mut data = ogg_page(0, &[&head]);
+        data.extend(ogg_page(0, &[b"OpusTags"]));
+        let mut granule = pre_skip as u64;
+        for page in packets.chunks(10) {
+            granule += (page.len() * frame) as u64;
+            let page = page.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            data.extend(ogg_page(granule, &page));
+        }
+
+        let (duration, waveform) = analyze_recording(&data)?;
+        assert_eq!(duration, Duration::from_secs(2));
+        assert_eq!(waveform.len(), WAVEFORM_SAMPLES);
+        assert!(waveform[..WAVEFORM_SAMPLES / 2 - 5].iter().all(|s| *s < 50));
+        assert!(waveform[WAVEFORM_SAMPLES / 2 + 5..]
+            .iter()
+            .all(|s| *s > 500));
+        Ok(())
+    }
+
+    #[test]
+    fn voice_content_roundtrip() -> crate::Result<()> {
+        let content = VoiceContent::new(Duration::from_millis(2500), vec![0, 512, 4000]);
+        let json = serde_json::to_value(&content)?;
+        assert_eq!(json["org.matrix.msc1767.audio"]["duration"], 2500);
+        assert_eq!(
+            json["org.matrix.msc1767.audio"]["waveform"],
+            serde_json::json!([0, 512, 1024])
+        );
+        let parsed = serde_json::from_value::<VoiceContent>(json)?;
+        assert!(parsed.is_voice());
+        let plain = serde_json::from_str::<VoiceContent>(r#"{"body": "song.mp3"}"#)?;
+        assert!(!plain.is_voice());
+        assert!(plain.waveform().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_recording_is_refused() {
+        assert!(analyze_recording(b"ID3 some mp3").is_err());
+    }
+}
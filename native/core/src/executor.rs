@@ -1,19 +1,38 @@
 use dashmap::{mapref::entry::Entry, DashMap};
+use matrix_sdk::ruma::{OwnedEventId, TransactionId};
 use ruma_common::events::UnsignedRoomRedactionEvent;
 use std::sync::Arc;
-use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::sync::{
+    broadcast::{channel, Receiver, Sender},
+    Mutex,
+};
 use tracing::{error, trace, trace_span};
 
 use crate::{
-    models::{ActerModel, AnyActerModel, EventMeta, RedactedActerModel},
+    models::{transition_tree, ActerModel, AnyActerModel, EventMeta, RedactedActerModel},
     store::Store,
     Error, Result,
 };
 
+/// A model we sent, shown before its remote echo arrived
+#[derive(Clone, Debug)]
+struct LocalEcho {
+    /// the key of the provisional model
+    local_key: String,
+    /// the model we sent, applied again on top of any remote updates
+    model: AnyActerModel,
+    /// the models it changed, as they were before it was applied last
+    originals: Vec<AnyActerModel>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Executor {
     store: Store,
     notifiers: Arc<DashMap<String, Sender<()>>>,
+    // by transaction id, in the order they were applied
+    local_echos: Arc<Mutex<Vec<(String, LocalEcho)>>>,
+    // event id of a sent local echo to its transaction id
+    awaiting_echo: Arc<DashMap<String, String>>,
 }
 
 impl Executor {
@@ -21,6 +40,8 @@ impl Executor {
         Ok(Executor {
             store,
             notifiers: Default::default(),
+            local_echos: Default::default(),
+            awaiting_echo: Default::default(),
         })
     }
 
@@ -63,6 +84,7 @@ impl Executor {
 
     pub fn notify(&self, mut keys: Vec<String>) -> u32 {
         let mut counter = 0u32;
+        keys.sort_unstable();
         keys.dedup();
         trace!(?keys, "notify");
         for key in keys {
@@ -93,17 +115,160 @@ impl Executor {
     pub async fn handle(&self, model: AnyActerModel) -> Result<()> {
         let event_id = model.event_id().to_string();
         trace!(?event_id, ?model, "handle");
-        self.notify(model.execute(&self.store).await?);
+        let mut echos = self.local_echos.lock().await;
+        let mut keys = self.lift_local(&mut echos).await?;
+        if let Some((_, txn_id)) = self.awaiting_echo.remove(&event_id) {
+            trace!(?event_id, txn_id, "remote echo arrived");
+            keys.extend(self.drop_local(&mut echos, &txn_id).await?);
+        }
+        let executed = model.execute(&self.store).await;
+        keys.extend(self.reapply_local(&mut echos).await);
+        keys.extend(executed?);
+        self.notify(keys);
         trace!(?event_id, "handling done");
         Ok(())
     }
 
+    /// Show the model of an event we are sending right away. It is stored
+    /// under its local event id and the models it belongs to are
+    /// transitioned, but none of it is persisted. Other events are applied
+    /// underneath it until `confirm_local` tells us the remote echo arrived
+    /// or `rollback_local` drops it.
+    pub async fn apply_local(&self, txn_id: &TransactionId, model: AnyActerModel) -> Result<()> {
+        let local_key = model.event_id().to_string();
+        trace!(local_key, ?txn_id, "applying local echo");
+        let mut echos = self.local_echos.lock().await;
+        let mut echo = LocalEcho {
+            local_key,
+            model: model.clone(),
+            originals: vec![],
+        };
+        let mut keys = self.overlay_local(&mut echo).await?;
+        keys.extend(self.store.save_pending(model).await?);
+        echos.push((txn_id.to_string(), echo));
+        self.notify(keys);
+        Ok(())
+    }
+
+    /// The local echo was sent as `event_id`, replace it once that comes in
+    pub async fn confirm_local(
+        &self,
+        txn_id: &TransactionId,
+        event_id: OwnedEventId,
+    ) -> Result<()> {
+        if !self
+            .local_echos
+            .lock()
+            .await
+            .iter()
+            .any(|(t, _)| t == txn_id.as_str())
+        {
+            return Ok(());
+        }
+        let key = event_id.to_string();
+        self.awaiting_echo.insert(key.clone(), txn_id.to_string());
+        // the remote echo might have been faster, it is applied already
+        if self.store.get(&key).await.is_ok() && self.awaiting_echo.remove(&key).is_some() {
+            trace!(event_id = key, ?txn_id, "remote echo arrived before");
+            self.release_local(txn_id.as_str()).await?;
+        }
+        Ok(())
+    }
+
+    /// Sending the local echo failed, drop it
+    pub async fn rollback_local(&self, txn_id: &TransactionId) -> Result<()> {
+        trace!(?txn_id, "rolling back local echo");
+        self.awaiting_echo.retain(|_, v| v != txn_id.as_str());
+        self.release_local(txn_id.as_str()).await
+    }
+
+    async fn release_local(&self, txn_id: &str) -> Result<()> {
+        let mut echos = self.local_echos.lock().await;
+        let mut keys = self.lift_local(&mut echos).await?;
+        let dropped = self.drop_local(&mut echos, txn_id).await;
+        keys.extend(self.reapply_local(&mut echos).await);
+        keys.extend(dropped?);
+        self.notify(keys);
+        Ok(())
+    }
+
+    /// Transition what the local echo belongs to, remembering how it was
+    async fn overlay_local(&self, echo: &mut LocalEcho) -> Result<Vec<String>> {
+        let changed = match echo.model.belongs_to() {
+            Some(belongs_to) => transition_tree(&self.store, belongs_to, &echo.model).await?,
+            None => vec![],
+        };
+        echo.originals = self
+            .store
+            .get_many(changed.iter().map(|m| m.event_id().to_string()).collect())
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        self.store.save_provisional(changed).await
+    }
+
+    /// Put back the models as they were without any local echo, latest
+    /// first. Must be followed by `reapply_local`.
+    async fn lift_local(&self, echos: &mut [(String, LocalEcho)]) -> Result<Vec<String>> {
+        let mut keys = vec![];
+        for (_, echo) in echos.iter_mut().rev() {
+            let originals = std::mem::take(&mut echo.originals);
+            keys.extend(self.store.release_provisional(originals).await?);
+        }
+        Ok(keys)
+    }
+
+    /// Show the lifted local echos again, on top of what we have now
+    async fn reapply_local(&self, echos: &mut [(String, LocalEcho)]) -> Vec<String> {
+        let mut keys = vec![];
+        for (txn_id, echo) in echos.iter_mut() {
+            match self.overlay_local(echo).await {
+                Ok(changed) => keys.extend(changed),
+                Err(error) => error!(txn_id, ?error, "Reapplying local echo failed"),
+            }
+        }
+        keys
+    }
+
+    /// Forget the lifted local echo of `txn_id`
+    async fn drop_local(
+        &self,
+        echos: &mut Vec<(String, LocalEcho)>,
+        txn_id: &str,
+    ) -> Result<Vec<String>> {
+        let Some(pos) = echos.iter().position(|(t, _)| t == txn_id) else {
+            return Ok(vec![]);
+        };
+        let (_, echo) = echos.remove(pos);
+        match self.store.remove_pending(&echo.local_key).await {
+            Ok(keys) => Ok(keys),
+            Err(Error::ModelNotFound) => Ok(vec![]),
+            Err(error) => Err(error),
+        }
+    }
+
     pub async fn redact(
         &self,
         model_type: String,
         event_meta: EventMeta,
         reason: UnsignedRoomRedactionEvent,
     ) -> Result<()> {
+        let mut echos = self.local_echos.lock().await;
+        let mut keys = self.lift_local(&mut echos).await?;
+        let redacted = self.redact_inner(model_type, event_meta, reason).await;
+        keys.extend(self.reapply_local(&mut echos).await);
+        keys.extend(redacted?);
+        self.notify(keys);
+        Ok(())
+    }
+
+    async fn redact_inner(
+        &self,
+        model_type: String,
+        event_meta: EventMeta,
+        reason: UnsignedRoomRedactionEvent,
+    ) -> Result<Vec<String>> {
         match self.store.get(event_meta.event_id.as_str()).await {
             Ok(model) => {
                 let redacted = RedactedActerModel::new(
@@ -112,16 +277,15 @@ impl Executor {
                     event_meta,
                     reason,
                 );
-                self.notify(model.redact(&self.store, redacted).await?);
+                model.redact(&self.store, redacted).await
             }
             Err(Error::ModelNotFound) => {
                 let redacted =
                     RedactedActerModel::new(model_type.to_owned(), vec![], event_meta, reason);
-                self.notify(redacted.execute(&self.store).await?);
+                redacted.execute(&self.store).await
             }
-            Err(error) => return Err(error),
+            Err(error) => Err(error),
         }
-        Ok(())
     }
 }
 
@@ -135,7 +299,10 @@ mod tests {
     };
     use env_logger;
     use matrix_sdk::{
-        ruma::{api::MatrixVersion, event_id, events::room::message::TextMessageEventContent},
+        ruma::{
+            api::MatrixVersion, event_id, events::room::message::TextMessageEventContent,
+            OwnedTransactionId, TransactionId,
+        },
         Client,
     };
     use matrix_sdk_base::store::{MemoryStore, StoreConfig};
@@ -283,4 +450,143 @@ mod tests {
         assert_eq!(inner_model, model);
         Ok(())
     }
+
+    async fn executor_with_local_echo() -> Result<(Executor, String, OwnedTransactionId)> {
+        let executor = fresh_executor().await?;
+        let parent = TestModelBuilder::default().simple().build().unwrap();
+        let parent_id = parent.event_id().to_string();
+        executor.handle(parent.into()).await?;
+
+        let local = TestModelBuilder::default()
+            .event_id(event_id!("$pending-local").to_owned())
+            .belongs_to(vec![parent_id.clone()])
+            .build()
+            .unwrap();
+        let txn_id = TransactionId::new();
+        let mut sub = executor.subscribe(parent_id.clone());
+        executor.apply_local(&txn_id, local.into()).await?;
+        assert!(sub.recv().await.is_ok()); // the parent was transitioned
+        assert!(executor.store().is_pending("$pending-local"));
+        Ok((executor, parent_id, txn_id))
+    }
+
+    #[tokio::test]
+    async fn local_echo_rolled_back() -> Result<()> {
+        let _ = env_logger::try_init();
+        let (executor, parent_id, txn_id) = executor_with_local_echo().await?;
+        let mut sub = executor.subscribe(parent_id.clone());
+
+        executor.rollback_local(&txn_id).await?;
+        assert!(sub.recv().await.is_ok());
+        assert!(executor.store().get("$pending-local").await.is_err());
+        assert!(executor.store().get(&parent_id).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_echo_replaced_by_remote_echo() -> Result<()> {
+        let _ = env_logger::try_init();
+        let (executor, parent_id, txn_id) = executor_with_local_echo().await?;
+        let remote_id = event_id!("$remote");
+        executor
+            .confirm_local(&txn_id, remote_id.to_owned())
+            .await?;
+        // not there yet
+        assert!(executor.store().is_pending("$pending-local"));
+
+        let remote = TestModelBuilder::default()
+            .event_id(remote_id.to_owned())
+            .belongs_to(vec![parent_id])
+            .build()
+            .unwrap();
+        executor.handle(remote.into()).await?;
+        assert!(executor.store().get("$pending-local").await.is_err());
+        assert!(executor.store().get(remote_id.as_str()).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_echo_remote_echo_first() -> Result<()> {
+        let _ = env_logger::try_init();
+        let (executor, parent_id, txn_id) = executor_with_local_echo().await?;
+        let remote_id = event_id!("$remote");
+        let remote = TestModelBuilder::default()
+            .event_id(remote_id.to_owned())
+            .belongs_to(vec![parent_id])
+            .build()
+            .unwrap();
+        executor.handle(remote.into()).await?;
+        // we don't know it is ours yet
+        assert!(executor.store().is_pending("$pending-local"));
+
+        executor
+            .confirm_local(&txn_id, remote_id.to_owned())
+            .await?;
+        assert!(executor.store().get("$pending-local").await.is_err());
+        assert!(executor.store().get(remote_id.as_str()).await.is_ok());
+        Ok(())
+    }
+
+    async fn transitioned_by(executor: &Executor, key: &str) -> Result<Vec<String>> {
+        let AnyActerModel::TestModel(model) = executor.store().get(key).await? else {
+            panic!("Not a test model");
+        };
+        Ok(model.transitioned_by().to_vec())
+    }
+
+    async fn handle_third_party_update(executor: &Executor, parent_id: &str) -> Result<()> {
+        let update = TestModelBuilder::default()
+            .event_id(event_id!("$third-party").to_owned())
+            .belongs_to(vec![parent_id.to_owned()])
+            .build()
+            .unwrap();
+        executor.handle(update.into()).await?;
+        // applied underneath our local echo
+        assert_eq!(
+            transitioned_by(executor, parent_id).await?,
+            ["$third-party", "$pending-local"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_echo_keeps_third_party_update_on_confirm() -> Result<()> {
+        let _ = env_logger::try_init();
+        let (executor, parent_id, txn_id) = executor_with_local_echo().await?;
+        handle_third_party_update(&executor, &parent_id).await?;
+
+        let remote_id = event_id!("$remote");
+        executor
+            .confirm_local(&txn_id, remote_id.to_owned())
+            .await?;
+        let remote = TestModelBuilder::default()
+            .event_id(remote_id.to_owned())
+            .belongs_to(vec![parent_id.clone()])
+            .build()
+            .unwrap();
+        executor.handle(remote.into()).await?;
+
+        assert!(executor.store().get("$pending-local").await.is_err());
+        // the remote echo was applied once, after the update
+        assert_eq!(
+            transitioned_by(&executor, &parent_id).await?,
+            ["$third-party", "$remote"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_echo_keeps_third_party_update_on_rollback() -> Result<()> {
+        let _ = env_logger::try_init();
+        let (executor, parent_id, txn_id) = executor_with_local_echo().await?;
+        handle_third_party_update(&executor, &parent_id).await?;
+
+        executor.rollback_local(&txn_id).await?;
+        assert!(executor.store().get("$pending-local").await.is_err());
+        assert_eq!(
+            transitioned_by(&executor, &parent_id).await?,
+            ["$third-party"]
+        );
+        Ok(())
+    }
 }
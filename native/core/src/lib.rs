@@ -11,6 +11,7 @@ pub mod error;
 pub mod events;
 pub mod executor;
 pub mod models;
+pub mod outbox;
 pub mod spaces;
 pub mod statics;
 pub mod store;
@@ -474,6 +474,11 @@ impl TryFrom<AnyActerEvent> for AnyActerModel {
     }
 }
 
+/// Whether events of the type become acter models
+pub fn is_model_type(event_type: &str) -> bool {
+    event_type.starts_with("global.acter") && event_type != "global.acter.app_settings"
+}
+
 impl TryFrom<&Raw<AnyTimelineEvent>> for AnyActerModel {
     type Error = Error;
     fn try_from(raw: &Raw<AnyTimelineEvent>) -> Result<Self, Self::Error> {
@@ -481,7 +486,7 @@ impl TryFrom<&Raw<AnyTimelineEvent>> for AnyActerModel {
             return Err(Error::UnknownModel(None));
         };
 
-        if !model_type.starts_with("global.acter") || model_type == "global.acter.app_settings" {
+        if !is_model_type(&model_type) {
             return Err(Error::UnknownModel(Some(model_type)));
         }
 
@@ -502,7 +507,7 @@ impl TryFrom<&Raw<AnySyncTimelineEvent>> for AnyActerModel {
             return Err(Error::UnknownModel(None));
         };
 
-        if !model_type.starts_with("global.acter") || model_type == "global.acter.app_settings" {
+        if !is_model_type(&model_type) {
             return Err(Error::UnknownModel(Some(model_type)));
         }
 
@@ -19,6 +19,16 @@ pub struct TestModel {
 
     #[builder(default)]
     transition: bool,
+
+    /// the models it was transitioned with, in order
+    #[builder(default)]
+    transitioned_by: Vec<String>,
+}
+
+impl TestModel {
+    pub fn transitioned_by(&self) -> &[String] {
+        &self.transitioned_by
+    }
 }
 
 impl TestModelBuilder {
@@ -53,7 +63,8 @@ impl ActerModel for TestModel {
         Some(self.belongs_to.clone())
     }
 
-    fn transition(&mut self, _model: &super::AnyActerModel) -> Result<bool> {
+    fn transition(&mut self, model: &super::AnyActerModel) -> Result<bool> {
+        self.transitioned_by.push(model.event_id().to_string());
         Ok(true)
     }
 
@@ -0,0 +1,546 @@
+use matrix_sdk::{
+    ruma::{
+        events::{AnyTimelineEvent, MessageLikeEventContent},
+        serde::Raw,
+        MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedTransactionId, TransactionId,
+        UserId,
+    },
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        broadcast::{channel, Receiver, Sender},
+        Mutex, Notify,
+    },
+    time,
+};
+use tracing::{error, info, warn};
+
+use crate::{executor::Executor, models::AnyActerModel, util::backoff, Error, Result};
+
+const OUTBOX_KEY: &str = "outbox";
+/// the first wait after sending failed, doubled with every further failure
+const RETRY_MIN_DELAY: Duration = Duration::from_secs(2);
+/// the longest we wait before trying again
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(600);
+const SENT_KEY: &str = "outbox_sent";
+/// of how many sent events we remember the real id
+const SENT_KEPT: usize = 500;
+
+/// An acter event waiting to be sent
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingEvent {
+    txn_id: OwnedTransactionId,
+    room_id: OwnedRoomId,
+    event_type: String,
+    content: serde_json::Value,
+    created_at: MilliSecondsSinceUnixEpoch,
+    attempts: u32,
+    last_error: Option<String>,
+}
+
+impl PendingEvent {
+    pub fn new<C: MessageLikeEventContent>(room_id: OwnedRoomId, content: &C) -> Result<Self> {
+        Ok(PendingEvent {
+            txn_id: TransactionId::new(),
+            room_id,
+            event_type: content.event_type().to_string(),
+            content: serde_json::to_value(content)?,
+            created_at: MilliSecondsSinceUnixEpoch::now(),
+            attempts: 0,
+            last_error: None,
+        })
+    }
+
+    /// For content that was serialized before, e.g. to send it later on.
+    /// Keeps the given transaction id, so the server drops it if we ever
+    /// send it twice.
+    pub fn from_json(
+        txn_id: OwnedTransactionId,
+        room_id: OwnedRoomId,
+        event_type: String,
+        content: serde_json::Value,
+    ) -> Self {
+        PendingEvent {
+            txn_id,
+            room_id,
+            event_type,
+            content,
+            created_at: MilliSecondsSinceUnixEpoch::now(),
+            attempts: 0,
+            last_error: None,
+        }
+    }
+
+    pub fn txn_id(&self) -> String {
+        self.txn_id.to_string()
+    }
+
+    pub fn txn_id_typed(&self) -> &TransactionId {
+        &self.txn_id
+    }
+
+    pub fn room_id_str(&self) -> String {
+        self.room_id.to_string()
+    }
+
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+
+    pub fn content(&self) -> &serde_json::Value {
+        &self.content
+    }
+
+    /// how often sending failed so far
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.clone()
+    }
+
+    /// The id the pending model is stored under until the event is sent
+    pub fn pending_event_id(&self) -> OwnedEventId {
+        OwnedEventId::try_from(format!("$pending-{}", self.txn_id))
+            .expect("transaction ids are valid in event ids")
+    }
+
+    /// The model as it will look like once sent by `sender`
+    pub fn model(&self, sender: &UserId) -> Result<AnyActerModel> {
+        let raw = Raw::<AnyTimelineEvent>::from_json(serde_json::value::to_raw_value(
+            &serde_json::json!({
+                "type": self.event_type,
+                "content": self.content,
+                "event_id": self.pending_event_id(),
+                "sender": sender,
+                "origin_server_ts": self.created_at,
+                "room_id": self.room_id,
+                "unsigned": { "transaction_id": self.txn_id },
+            }),
+        )?);
+        AnyActerModel::try_from(&raw)
+    }
+}
+
+/// A queued event went out, the id of its local echo is gone for good
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutboxSent {
+    pending_event_id: OwnedEventId,
+    event_id: OwnedEventId,
+}
+
+impl OutboxSent {
+    /// the id returned when the event was queued
+    pub fn pending_event_id(&self) -> String {
+        self.pending_event_id.to_string()
+    }
+
+    /// the id the server gave the event
+    pub fn event_id(&self) -> String {
+        self.event_id.to_string()
+    }
+}
+
+/// Point references to local echos at the events they became, e.g. of a
+/// comment on a task that was queued before it
+fn replace_pending_ids(value: &mut serde_json::Value, sent: &[OutboxSent]) {
+    match value {
+        serde_json::Value::String(text) if text.starts_with("$pending-") => {
+            if let Some(found) = sent.iter().find(|s| s.pending_event_id == text.as_str()) {
+                *text = found.event_id.to_string();
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                replace_pending_ids(item, sent);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for field in fields.values_mut() {
+                replace_pending_ids(field, sent);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Sending a pending event failed
+#[derive(Clone, Debug)]
+pub struct OutboxFailure {
+    event: PendingEvent,
+    error: String,
+    will_retry: bool,
+}
+
+impl OutboxFailure {
+    pub fn event(&self) -> PendingEvent {
+        self.event.clone()
+    }
+
+    pub fn error(&self) -> String {
+        self.error.clone()
+    }
+
+    /// `false` if the event was dropped, as sending it can't work
+    pub fn will_retry(&self) -> bool {
+        self.will_retry
+    }
+}
+
+fn retry_delay(failures: u32) -> Duration {
+    backoff(failures, RETRY_MIN_DELAY, RETRY_MAX_DELAY)
+}
+
+/// Whether the request may go through if we send it again later, rather
+/// than the server refusing it. Rate limits and server errors pass, as do
+/// network failures, anything else won't get better by waiting.
+pub fn is_transient(error: &matrix_sdk::Error) -> bool {
+    match error.as_client_api_error() {
+        Some(e) => !e.status_code.is_client_error() || e.status_code.as_u16() == 429,
+        None => matches!(error, matrix_sdk::Error::Http(_)),
+    }
+}
+
+/// The persistent queue of acter events to send once we are online again
+#[derive(Clone, Debug)]
+pub struct Outbox {
+    client: Client,
+    executor: Executor,
+    queue: Arc<Mutex<Vec<PendingEvent>>>,
+    wake: Arc<Notify>,
+    failures: Arc<Sender<OutboxFailure>>,
+    /// the latest sent events, oldest first
+    sent: Arc<Mutex<Vec<OutboxSent>>>,
+    sent_tx: Arc<Sender<OutboxSent>>,
+}
+
+impl Outbox {
+    /// Load the queue left from last time and show its pending models
+    pub async fn load(client: Client, executor: Executor) -> Result<Self> {
+        let queue = match executor
+            .store()
+            .get_raw::<Vec<PendingEvent>>(OUTBOX_KEY)
+            .await
+        {
+            Ok(queue) => queue,
+            Err(Error::ModelNotFound) => vec![],
+            Err(error) => {
+                error!(?error, "reading the outbox failed, starting empty");
+                vec![]
+            }
+        };
+        let sent = match executor.store().get_raw::<Vec<OutboxSent>>(SENT_KEY).await {
+            Ok(sent) => sent,
+            Err(Error::ModelNotFound) => vec![],
+            Err(error) => {
+                error!(?error, "reading the sent events failed, starting empty");
+                vec![]
+            }
+        };
+        let outbox = Outbox {
+            client,
+            executor,
+            queue: Arc::new(Mutex::new(queue.clone())),
+            wake: Default::default(),
+            failures: Arc::new(channel(10).0),
+            sent: Arc::new(Mutex::new(sent)),
+            sent_tx: Arc::new(channel(10).0),
+        };
+        for event in queue.iter() {
+            outbox.apply_local(event).await;
+        }
+        Ok(outbox)
+    }
+
+    async fn persist(&self, queue: &Vec<PendingEvent>) -> Result<()> {
+        self.executor.store().set_raw(OUTBOX_KEY, queue).await
+    }
+
+    /// Show the event as local echo until it is sent. Not every event has
+    /// a model, the failure is only logged.
+    pub async fn apply_local(&self, event: &PendingEvent) {
+        let Some(sender) = self.client.user_id() else {
+            warn!(txn_id = ?event.txn_id, "not logged in, no local echo");
+            return;
+        };
+        let result = match event.model(sender) {
+            Ok(model) => self.executor.apply_local(&event.txn_id, model).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            warn!(?error, txn_id = ?event.txn_id, "no local echo");
+        }
+    }
+
+    /// Queue the event to be sent, its local echo should already be
+    /// applied. Returns the id of the local echo.
+    pub async fn enqueue(&self, event: PendingEvent) -> Result<OwnedEventId> {
+        let event_id = event.pending_event_id();
+        {
+            let mut queue = self.queue.lock().await;
+            queue.push(event);
+            self.persist(&queue).await?;
+        }
+        info!(?event_id, "event queued");
+        self.wake();
+        Ok(event_id)
+    }
+
+    /// Whether events of this room are waiting, new ones have to queue up
+    /// behind them to keep the order
+    pub async fn has_pending(&self, room_id: &OwnedRoomId) -> bool {
+        self.queue
+            .lock()
+            .await
+            .iter()
+            .any(|e| &e.room_id == room_id)
+    }
+
+    pub async fn pending(&self) -> Vec<PendingEvent> {
+        self.queue.lock().await.clone()
+    }
+
+    pub fn subscribe_failures(&self) -> Receiver<OutboxFailure> {
+        self.failures.subscribe()
+    }
+
+    /// Fires once a queued event was sent, with the id it got
+    pub fn subscribe_sent(&self) -> Receiver<OutboxSent> {
+        self.sent_tx.subscribe()
+    }
+
+    /// The id the event got, if `event_id` is that of a local echo which
+    /// was sent meanwhile
+    pub async fn resolve(&self, event_id: &str) -> Option<OwnedEventId> {
+        self.sent
+            .lock()
+            .await
+            .iter()
+            .find(|s| s.pending_event_id == event_id)
+            .map(|s| s.event_id.clone())
+    }
+
+    /// Replace the ids of local echos the content refers to with those of
+    /// the sent events, as far as they are sent. Call before sending.
+    pub async fn resolve_references(&self, event: &mut PendingEvent) {
+        replace_pending_ids(&mut event.content, &self.sent.lock().await);
+    }
+
+    async fn record_sent(&self, event: &PendingEvent, event_id: OwnedEventId) {
+        let sent = OutboxSent {
+            pending_event_id: event.pending_event_id(),
+            event_id,
+        };
+        {
+            let mut known = self.sent.lock().await;
+            known.push(sent.clone());
+            let too_many = known.len().saturating_sub(SENT_KEPT);
+            known.drain(..too_many);
+            if let Err(error) = self.executor.store().set_raw(SENT_KEY, &*known).await {
+                error!(?error, "persisting the sent events failed");
+            }
+        }
+        let _ = self.sent_tx.send(sent);
+    }
+
+    /// Try sending right away, e.g. because we are online again
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    async fn rollback_local(&self, event: &PendingEvent) {
+        if let Err(error) = self.executor.rollback_local(&event.txn_id).await {
+            error!(?error, txn_id = ?event.txn_id, "rolling back local echo failed");
+        }
+    }
+
+    /// Drop the event without sending it, `false` if it wasn't queued
+    pub async fn discard(&self, txn_id: &str) -> Result<bool> {
+        let event = {
+            let mut queue = self.queue.lock().await;
+            let Some(pos) = queue.iter().position(|e| e.txn_id.as_str() == txn_id) else {
+                return Ok(false);
+            };
+            let event = queue.remove(pos);
+            self.persist(&queue).await?;
+            event
+        };
+        self.rollback_local(&event).await;
+        Ok(true)
+    }
+
+    async fn send(&self, event: &PendingEvent) -> std::result::Result<OwnedEventId, Error> {
+        let room = self
+            .client
+            .get_joined_room(&event.room_id)
+            .ok_or_else(|| Error::Custom(format!("Not in room {}", event.room_id)))?;
+        // what it refers to might have been sent since it was queued
+        let mut event = event.clone();
+        self.resolve_references(&mut event).await;
+        let response = room
+            .send_raw(
+                event.content.clone(),
+                &event.event_type,
+                Some(&event.txn_id),
+            )
+            .await?;
+        Ok(response.event_id)
+    }
+
+    /// Send the queued events in order. Returns `false` if one failed and
+    /// we should try again later.
+    pub async fn flush(&self) -> bool {
+        loop {
+            let Some(event) = self.queue.lock().await.first().cloned() else {
+                return true;
+            };
+            let result = self.send(&event).await;
+
+            let mut queue = self.queue.lock().await;
+            let Some(pos) = queue.iter().position(|e| e.txn_id == event.txn_id) else {
+                // discarded while we were sending
+                continue;
+            };
+            let failure = match result {
+                Ok(event_id) => {
+                    info!(?event_id, txn_id = ?event.txn_id, "queued event sent");
+                    queue.remove(pos);
+                    if let Err(error) = self.persist(&queue).await {
+                        error!(?error, "persisting the outbox failed");
+                    }
+                    drop(queue);
+                    self.record_sent(&event, event_id.clone()).await;
+                    if let Err(error) = self.executor.confirm_local(&event.txn_id, event_id).await {
+                        error!(?error, txn_id = ?event.txn_id, "confirming local echo failed");
+                    }
+                    continue;
+                }
+                Err(Error::MatrixSdk(error)) if is_transient(&error) => {
+                    let entry = &mut queue[pos];
+                    entry.attempts += 1;
+                    entry.last_error = Some(error.to_string());
+                    OutboxFailure {
+                        event: entry.clone(),
+                        error: error.to_string(),
+                        will_retry: true,
+                    }
+                }
+                Err(error) => {
+                    error!(?error, txn_id = ?event.txn_id, "queued event can't be sent, dropping");
+                    OutboxFailure {
+                        event: queue.remove(pos),
+                        error: error.to_string(),
+                        will_retry: false,
+                    }
+                }
+            };
+            if let Err(error) = self.persist(&queue).await {
+                error!(?error, "persisting the outbox failed");
+            }
+            drop(queue);
+
+            let will_retry = failure.will_retry;
+            if !will_retry {
+                self.rollback_local(&failure.event).await;
+            }
+            let _ = self.failures.send(failure);
+            if will_retry {
+                return false;
+            }
+        }
+    }
+
+    /// Keep sending whatever is queued, waiting longer after every failure.
+    /// Runs until the task is aborted.
+    pub async fn run(&self) {
+        let mut failures = 0;
+        loop {
+            if self.flush().await {
+                failures = 0;
+                self.wake.notified().await;
+                continue;
+            }
+            failures += 1;
+            let delay = retry_delay(failures);
+            info!(failures, ?delay, "sending queued events failed, waiting");
+            tokio::select! {
+                _ = time::sleep(delay) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::pins::PinEventContent, models::ActerModel};
+    use matrix_sdk::ruma::{room_id, user_id};
+
+    #[test]
+    fn pending_pin_model() -> Result<()> {
+        let content = PinEventContent {
+            title: "Seat arrangement".to_owned(),
+            content: None,
+            url: None,
+            display: None,
+        };
+        let event = PendingEvent::new(room_id!("!room:example.org").to_owned(), &content)?;
+        let AnyActerModel::Pin(pin) = event.model(user_id!("@odo:example.org"))? else {
+            panic!("not a pin");
+        };
+        assert_eq!(pin.event_id(), event.pending_event_id());
+        assert_eq!(pin.title, "Seat arrangement");
+        Ok(())
+    }
+
+    #[test]
+    fn pending_ids_are_replaced() {
+        let sent = vec![OutboxSent {
+            pending_event_id: OwnedEventId::try_from("$pending-task").unwrap(),
+            event_id: OwnedEventId::try_from("$task:example.org").unwrap(),
+        }];
+        let mut content = serde_json::json!({
+            "m.relates_to": { "event_id": "$pending-task" },
+            "attachments": ["$pending-task", "$pending-other", "$other:example.org"],
+            "count": 2,
+        });
+        replace_pending_ids(&mut content, &sent);
+        assert_eq!(
+            content,
+            serde_json::json!({
+                "m.relates_to": { "event_id": "$task:example.org" },
+                "attachments": ["$task:example.org", "$pending-other", "$other:example.org"],
+                "count": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn pending_from_json_keeps_txn_id() -> Result<()> {
+        let txn_id = TransactionId::new();
+        let event = PendingEvent::from_json(
+            txn_id.clone(),
+            room_id!("!room:example.org").to_owned(),
+            "global.acter.dev.pin".to_owned(),
+            serde_json::json!({ "title": "Duty roster" }),
+        );
+        assert_eq!(event.txn_id(), txn_id.to_string());
+        let AnyActerModel::Pin(pin) = event.model(user_id!("@odo:example.org"))? else {
+            panic!("not a pin");
+        };
+        assert_eq!(pin.title, "Duty roster");
+        Ok(())
+    }
+
+    #[test]
+    fn retries_back_off() {
+        assert_eq!(retry_delay(1), RETRY_MIN_DELAY);
+        assert_eq!(retry_delay(2), RETRY_MIN_DELAY * 2);
+        assert_eq!(retry_delay(100), RETRY_MAX_DELAY);
+    }
+}
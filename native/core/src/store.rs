@@ -1,4 +1,7 @@
-use dashmap::{mapref::one::RefMut, DashMap, DashSet};
+use dashmap::{
+    mapref::{entry::Entry, one::RefMut},
+    DashMap, DashSet,
+};
 use matrix_sdk::Client;
 use std::{iter::FromIterator, sync::Arc};
 use tracing::{debug, instrument, trace, warn};
@@ -12,9 +15,16 @@ use crate::{
 pub struct Store {
     client: Client,
     fresh: bool,
+    // raw values written since we started fresh, all others are stale
+    fresh_keys: Arc<DashSet<String>>,
     models: Arc<DashMap<String, AnyActerModel>>,
     indizes: Arc<DashMap<String, Vec<String>>>,
     dirty: Arc<DashSet<String>>,
+    // models shown before their event was sent, never persisted
+    pending: Arc<DashSet<String>>,
+    // models changed by local echoes, with how many there are. Not
+    // persisted until all are released.
+    provisional: Arc<DashMap<String, u32>>,
 }
 
 static ALL_MODELS_KEY: &str = "ACTER::ALL";
@@ -32,7 +42,7 @@ async fn get_from_store<T: serde::de::DeserializeOwned>(client: Client, key: &st
 
 impl Store {
     pub async fn get_raw<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<T> {
-        if self.fresh {
+        if self.fresh && !self.fresh_keys.contains(key) {
             return Err(Error::ModelNotFound);
         }
         get_from_store(self.client.clone(), key).await
@@ -47,6 +57,9 @@ impl Store {
                 serde_json::to_vec(value)?,
             )
             .await?;
+        if self.fresh {
+            self.fresh_keys.insert(key.to_owned());
+        }
         Ok(())
     }
 
@@ -77,10 +90,13 @@ impl Store {
 
             return Ok(Store {
                 fresh: true,
+                fresh_keys: Default::default(),
                 client,
                 indizes: Default::default(),
                 models: Default::default(),
                 dirty: Default::default(),
+                pending: Default::default(),
+                provisional: Default::default(),
             });
         }
 
@@ -123,10 +139,13 @@ impl Store {
 
         Ok(Store {
             fresh: false,
+            fresh_keys: Default::default(),
             client,
             indizes: Arc::new(indizes),
             models: Arc::new(models),
             dirty: Default::default(),
+            pending: Default::default(),
+            provisional: Default::default(),
         })
     }
 
@@ -214,6 +233,67 @@ impl Store {
         Ok(keys)
     }
 
+    /// Show a model whose event hasn't been sent yet. It is listed like any
+    /// other model, but not persisted.
+    pub async fn save_pending(&self, mdl: AnyActerModel) -> Result<Vec<String>> {
+        let key = mdl.event_id().to_string();
+        self.pending.insert(key.clone());
+        let keys = self.save_model_inner(mdl).await?;
+        self.dirty.remove(&key);
+        Ok(keys)
+    }
+
+    pub fn is_pending(&self, model_key: &str) -> bool {
+        self.pending.contains(model_key)
+    }
+
+    /// Drop the pending model, returns the keys that changed
+    pub async fn remove_pending(&self, model_key: &str) -> Result<Vec<String>> {
+        if self.pending.remove(model_key).is_none() {
+            return Err(Error::ModelNotFound);
+        }
+        let (key, mdl) = self.models.remove(model_key).ok_or(Error::ModelNotFound)?;
+        let mut keys_changed = vec![key.clone()];
+        for idx in mdl.indizes() {
+            if let Some(mut v) = self.indizes.get_mut(&idx) {
+                v.value_mut().retain(|k| k != &key)
+            }
+            keys_changed.push(idx);
+        }
+        trace!(user=?self.client.user_id(), key, "pending model removed");
+        Ok(keys_changed)
+    }
+
+    /// Keep the models changed by a local echo in memory only, until
+    /// `release_provisional` puts back what we had without it
+    pub async fn save_provisional(&self, models: Vec<AnyActerModel>) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for mdl in models.into_iter() {
+            *self
+                .provisional
+                .entry(mdl.event_id().to_string())
+                .or_default() += 1;
+            keys.extend(self.save_model_inner(mdl).await?);
+        }
+        Ok(keys)
+    }
+
+    /// Put back the `originals` of models changed by a local echo, before
+    /// any other update is applied to them. They are persisted again once no
+    /// other local echo changes them.
+    pub async fn release_provisional(&self, originals: Vec<AnyActerModel>) -> Result<Vec<String>> {
+        for mdl in originals.iter() {
+            let key = mdl.event_id().to_string();
+            if let Entry::Occupied(mut o) = self.provisional.entry(key) {
+                *o.get_mut() -= 1;
+                if *o.get() == 0 {
+                    o.remove();
+                }
+            }
+        }
+        self.save_many(originals).await
+    }
+
     pub async fn sync(&self) -> Result<()> {
         trace!("sync");
         let client_store = self.client.store();
@@ -227,6 +307,14 @@ impl Store {
             keys
         };
         for key in dirty {
+            if self.pending.contains(&key) {
+                continue;
+            }
+            if self.provisional.contains_key(&key) {
+                // persisted once released
+                self.dirty.insert(key);
+                continue;
+            }
             if let Some(r) = self.models.get(&key) {
                 trace!(?key, "syncing");
                 // FIXME: parallize
@@ -246,6 +334,7 @@ impl Store {
             .models
             .iter()
             .map(|v| v.key().clone())
+            .filter(|k| !self.pending.contains(k))
             .collect::<Vec<String>>();
         client_store
             .set_custom_value(ALL_MODELS_KEY.as_bytes(), serde_json::to_vec(&model_keys)?)
@@ -5,7 +5,10 @@ use futures::{
     Stream,
 };
 use indexmap::IndexMap;
-use matrix_sdk::ruma::RoomId;
+use matrix_sdk::{
+    ruma::{OwnedRoomId, RoomId},
+    RoomMemberships,
+};
 pub use minijinja::value::Value;
 use minijinja::Environment;
 use serde::Deserialize;
@@ -15,13 +18,13 @@ use tokio_retry::{
     Retry,
 };
 use toml::{Table, Value as TomlValue};
-use tracing::trace;
+use tracing::{trace, warn};
 
 pub mod filters;
 pub mod functions;
 pub mod values;
 
-use values::{ObjRef, UserValue};
+use values::{ObjRef, SpaceValue, UserValue};
 
 use crate::{
     client::CoreClient,
@@ -123,6 +126,27 @@ impl Input {
     pub fn is_space(&self) -> bool {
         matches!(self, Input::Space { .. })
     }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Input::Text { .. })
+    }
+
+    /// The type of the input as used in the template source
+    pub fn input_type(&self) -> &'static str {
+        match self {
+            Input::Text { .. } => "text",
+            Input::User { .. } => "user",
+            Input::Space { .. } => "space",
+        }
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            Input::Text { description, .. }
+            | Input::User { description, .. }
+            | Input::Space { description, .. } => description.as_deref(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -172,6 +196,10 @@ pub struct Object {
 #[derive(Deserialize)]
 pub struct TemplateV01 {
     name: Option<String>,
+    description: Option<String>,
+    /// the revision of this template, to be bumped whenever it changes
+    #[serde(default)]
+    revision: u32,
     inputs: IndexMap<String, Input>,
     objects: IndexMap<String, Table>,
 }
@@ -221,6 +249,26 @@ fn execute_value_template(
     }
 }
 
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+
+    // functions
+    env.add_function("date", functions::date);
+    env.add_function("future", functions::future);
+    env.add_function("next_weekday", functions::next_weekday);
+    env.add_function("now", functions::now);
+
+    // filters
+    env.add_filter("add_timedelta", filters::add_timedelta);
+    env.add_filter("format_date", filters::format_date);
+    env.add_filter("next_weekday", filters::next_weekday);
+    env.add_filter("rotation", filters::rotation);
+    env.add_filter("slugify", filters::slugify);
+    env.add_filter("start_of_day", filters::start_of_day);
+
+    env
+}
+
 impl ExecutionStream {
     pub fn new(total: u32, stream: Box<dyn Stream<Item = Result<(), Error>> + Unpin>) -> Self {
         ExecutionStream {
@@ -272,6 +320,18 @@ impl Engine {
         })
     }
 
+    pub fn name(&self) -> Option<&str> {
+        self.root.name.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.root.description.as_deref()
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.root.revision
+    }
+
     pub fn requested_inputs(&self) -> &IndexMap<String, Input> {
         &self.root.inputs
     }
@@ -299,6 +359,41 @@ impl Engine {
         }
     }
 
+    /// Add a reference to an existing space, including the list of its
+    /// joined members as `members`. Requires a user to be added first.
+    pub async fn add_space(&mut self, name: String, room_id: OwnedRoomId) -> Result<(), Error> {
+        let client = self
+            .users
+            .values()
+            .next()
+            .ok_or_else(|| Error::NoDefaultSet("user".to_string(), name.clone()))?;
+        let members = match client.client().get_room(&room_id) {
+            Some(room) => room
+                .members(RoomMemberships::JOIN)
+                .await
+                .map_err(|e| Error::Remap(format!("{name} members"), e.to_string()))?
+                .into_iter()
+                .map(|m| m.user_id().to_string())
+                .collect(),
+            None => {
+                warn!(?room_id, name, "space not found locally. no members known");
+                Vec::new()
+            }
+        };
+        if self
+            .context
+            .insert(
+                name.clone(),
+                Value::from_struct_object(SpaceValue::new(room_id.to_string(), members)),
+            )
+            .is_some()
+        {
+            Err(Error::ContextClash(name))
+        } else {
+            Ok(())
+        }
+    }
+
     pub async fn add_user(&mut self, name: String, client: CoreClient) -> Result<(), Error> {
         let cl = Arc::new(client);
         let user_value = UserValue::new(cl.clone()).await?;
@@ -317,17 +412,7 @@ impl Engine {
     pub fn execute(&self) -> Result<ExecutionStream, Error> {
         trace!(name = ?self.root.name, "executing");
 
-        let env = {
-            let mut env = Environment::new();
-
-            // functions
-            env.add_function("future", functions::future);
-            env.add_function("now", functions::now);
-
-            // filters
-
-            env
-        };
+        let env = environment();
 
         let users = self.users.clone();
         let mut context = self.context.clone();
@@ -590,4 +675,118 @@ slides = [
 
         Ok(())
     }
+
+    #[test]
+    fn test_template_metadata() -> Result<()> {
+        let tmpl = r#"
+version = "0.1.1"
+name = "Example Template"
+description = "Just an example"
+revision = 3
+
+[inputs]
+main = { type = "user", is-default = true, required = true, description = "The starting user" }
+space = { type = "space", required = true, description = "The acter space" }
+title = { type = "text" }
+
+[objects]
+start_list = { type = "task-list", name = "{{ title }}" }
+       "#;
+
+        let engine = Engine::with_template(tmpl)?;
+        assert_eq!(engine.name(), Some("Example Template"));
+        assert_eq!(engine.description(), Some("Just an example"));
+        assert_eq!(engine.revision(), 3);
+
+        let inputs = engine.requested_inputs();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs["main"].input_type(), "user");
+        assert_eq!(inputs["main"].description(), Some("The starting user"));
+        assert_eq!(inputs["space"].input_type(), "space");
+        assert!(inputs["title"].is_text());
+        assert!(!inputs["title"].is_required());
+        assert_eq!(inputs["title"].description(), None);
+
+        Ok(())
+    }
+
+    fn render(tmpl: &str, context: Context) -> Result<String> {
+        Ok(environment().render_str(tmpl, context)?)
+    }
+
+    #[test]
+    fn test_date_functions() -> Result<()> {
+        let mut context = Context::new();
+        context.insert("start".to_owned(), Value::from("2023-05-03T10:30:00+00:00"));
+
+        assert_eq!(
+            render("{{ date(start) }}", context.clone())?,
+            "2023-05-03T10:30:00+00:00"
+        );
+        assert_eq!(
+            render(
+                "{{ date(start) | add_timedelta(days=3, hours=2) }}",
+                context.clone()
+            )?,
+            "2023-05-06T12:30:00+00:00"
+        );
+        // 2023-05-03 is a wednesday
+        assert_eq!(
+            render(
+                "{{ date(start) | next_weekday(\"monday\") }}",
+                context.clone()
+            )?,
+            "2023-05-08T10:30:00+00:00"
+        );
+        assert_eq!(
+            render(
+                "{{ (date(start) | next_weekday(\"wed\")).as_date }}",
+                context.clone()
+            )?,
+            "2023-05-03"
+        );
+        assert_eq!(
+            render("{{ date(start) | start_of_day }}", context.clone())?,
+            "2023-05-03T00:00:00+00:00"
+        );
+        assert_eq!(
+            render(
+                "{{ date(\"2023-05-03\") | format_date(\"%d.%m.%Y\") }}",
+                context.clone()
+            )?,
+            "03.05.2023"
+        );
+        assert_eq!(render("{{ date(start).weekday }}", context)?, "wednesday");
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_and_string_filters() -> Result<()> {
+        let mut context = Context::new();
+        context.insert(
+            "space".to_owned(),
+            Value::from_struct_object(SpaceValue::new(
+                "!space:example.org".to_owned(),
+                vec!["@a:example.org".to_owned(), "@b:example.org".to_owned()],
+            )),
+        );
+
+        assert_eq!(
+            render("{{ space.members | rotation(0) }}", context.clone())?,
+            "@a:example.org"
+        );
+        assert_eq!(
+            render("{{ space.members | rotation(3) }}", context.clone())?,
+            "@b:example.org"
+        );
+        assert_eq!(
+            render("{{ space.members | length }}", context.clone())?,
+            "2"
+        );
+        assert_eq!(
+            render("{{ \"  Team #3: Pizza! \" | slugify }}", context)?,
+            "team-3-pizza"
+        );
+        Ok(())
+    }
 }
@@ -1 +1,92 @@
+use chrono::{Duration, Timelike};
+use minijinja::{value::Value, Error, ErrorKind};
 
+use super::functions::{
+    date_value, duration_from_kwargs, parse_date, parse_weekday, snap_to_weekday,
+};
+
+/// add `weeks`, `days`, `hours`, `mins` and `secs` (or any combinations of them) to the date. Example:
+/// ```no_compile
+///     {{ date(start) | add_timedelta(days=3, hours=2) }}
+/// ```
+pub fn add_timedelta(value: Value, kwargs: Value) -> Result<Value, Error> {
+    let date = parse_date(&value)?;
+    let duration = duration_from_kwargs(&kwargs)?;
+    let new_date = date.checked_add_signed(duration).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            "resulting date is out of range",
+        )
+    })?;
+    Ok(date_value(new_date))
+}
+
+/// move the date forward to the given weekday, unless it already is on that day. Example:
+/// ```no_compile
+///     {{ date(start) | next_weekday("friday") }}
+/// ```
+pub fn next_weekday(value: Value, weekday: String) -> Result<Value, Error> {
+    let date = parse_date(&value)?;
+    let weekday = parse_weekday(&weekday)?;
+    Ok(date_value(snap_to_weekday(date, weekday)))
+}
+
+/// reset the time of the date to midnight UTC, as expected for
+/// dates with `show_without_time` set. Example:
+/// ```no_compile
+///     utc_due = "{{ future(days=2) | start_of_day }}"
+///     show_without_time = true
+/// ```
+pub fn start_of_day(value: Value) -> Result<Value, Error> {
+    let date = parse_date(&value)?;
+    let seconds = date.num_seconds_from_midnight() as i64;
+    let nanos = date.nanosecond() as i64;
+    Ok(date_value(
+        date - Duration::seconds(seconds) - Duration::nanoseconds(nanos),
+    ))
+}
+
+/// format the date using the given `strftime` format. Example:
+/// ```no_compile
+///     {{ now() | format_date("%A, %d. %B") }}
+/// ```
+pub fn format_date(value: Value, format: String) -> Result<Value, Error> {
+    let date = parse_date(&value)?;
+    Ok(Value::from(date.format(&format).to_string()))
+}
+
+/// turn the string into a lowercase, dash-separated slug, as used for aliases. Example:
+/// ```no_compile
+///     alias = "{{ title | slugify }}"
+/// ```
+pub fn slugify(value: String) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+/// pick the item at `index` of the list, wrapping around at its end. Useful
+/// for rotations. Example:
+/// ```no_compile
+///     assignees = ["{{ space.members | rotation(3) }}"]
+/// ```
+pub fn rotation(value: Value, index: i64) -> Result<Value, Error> {
+    let items = value.try_iter()?.collect::<Vec<Value>>();
+    if items.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            "can't rotate over an empty list",
+        ));
+    }
+    let pos = index.rem_euclid(items.len() as i64) as usize;
+    Ok(items[pos].clone())
+}
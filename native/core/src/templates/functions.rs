@@ -1,13 +1,103 @@
-use chrono::Duration;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
 use minijinja::{value::Value, Error, ErrorKind};
 use std::time::SystemTime;
 
 use super::values::UtcDateTimeValue;
 use crate::events::UtcDateTime;
 
+pub(crate) fn date_value(date: UtcDateTime) -> Value {
+    Value::from_object(UtcDateTimeValue::new(date))
+}
+
+fn int_kwarg(kwargs: &Value, name: &str) -> Result<Option<i64>, Error> {
+    let Some(value) = kwargs.get_attr(name).ok().filter(|x| !x.is_undefined()) else {
+        return Ok(None);
+    };
+    let parsed = match value.as_str() {
+        Some(s) => s.parse::<i64>().ok(),
+        None => i64::try_from(value).ok(),
+    };
+    parsed.map(Some).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("{name} must be a number"),
+        )
+    })
+}
+
+/// sum up the `weeks`, `days`, `hours`, `mins` and `secs` given as keyword arguments
+pub(crate) fn duration_from_kwargs(kwargs: &Value) -> Result<Duration, Error> {
+    let mut duration = Duration::zero();
+    for (name, to_duration) in [
+        ("weeks", Duration::weeks as fn(i64) -> Duration),
+        ("days", Duration::days),
+        ("hours", Duration::hours),
+        ("mins", Duration::minutes),
+        ("secs", Duration::seconds),
+    ] {
+        if let Some(amount) = int_kwarg(kwargs, name)? {
+            duration = duration.checked_add(&to_duration(amount)).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("{name} couldn't be added"),
+                )
+            })?;
+        }
+    }
+    Ok(duration)
+}
+
+/// read a date from a date value, an RFC3339 string, a plain `YYYY-MM-DD`
+/// date string (midnight UTC) or a unix timestamp in seconds
+pub(crate) fn parse_date(value: &Value) -> Result<UtcDateTime, Error> {
+    if let Some(d) = value.downcast_object_ref::<UtcDateTimeValue>() {
+        return Ok(*d.date());
+    }
+    if let Some(s) = value.as_str() {
+        if let Ok(d) = DateTime::parse_from_rfc3339(s) {
+            return Ok(d.with_timezone(&Utc));
+        }
+        if let Some(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+        {
+            return Ok(Utc.from_utc_datetime(&d));
+        }
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("'{s}' is not a known date format"),
+        ));
+    }
+    if let Ok(ts) = i64::try_from(value.clone()) {
+        if let Some(d) = Utc.timestamp_opt(ts, 0).single() {
+            return Ok(d);
+        }
+    }
+    Err(Error::new(
+        ErrorKind::InvalidOperation,
+        format!("{value} can't be read as a date"),
+    ))
+}
+
+pub(crate) fn parse_weekday(weekday: &str) -> Result<Weekday, Error> {
+    weekday.parse::<Weekday>().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("'{weekday}' is not a weekday"),
+        )
+    })
+}
+
+/// move the date forward to the given weekday, unless it already is that day
+pub(crate) fn snap_to_weekday(date: UtcDateTime, weekday: Weekday) -> UtcDateTime {
+    let current = date.weekday().num_days_from_monday() as i64;
+    let target = weekday.num_days_from_monday() as i64;
+    date + Duration::days((target - current).rem_euclid(7))
+}
+
 /// create a date using the current date time
 pub fn now() -> Value {
-    Value::from_struct_object(UtcDateTimeValue::new(UtcDateTime::from(SystemTime::now())))
+    date_value(UtcDateTime::from(SystemTime::now()))
 }
 
 /// create a date in the future add `days`, `weeks`, `hours`, `mins`, `secs` (or any combinations of them) to create
@@ -17,62 +107,24 @@ pub fn now() -> Value {
 /// ```
 pub fn future(kwargs: Value) -> Result<Value, Error> {
     let date = UtcDateTime::from(SystemTime::now());
-    let mut duration = Duration::zero();
-    if let Some(Ok(days)) = kwargs
-        .get_attr("days")
-        .ok()
-        .filter(|x| !x.is_undefined())
-        .and_then(|f| f.as_str().map(str::parse::<i64>))
-    {
-        duration = duration
-            .checked_add(&Duration::days(days))
-            .ok_or_else(|| Error::new(ErrorKind::InvalidOperation, "days couldn't be added"))?;
-    }
-
-    if let Some(Ok(weeks)) = kwargs
-        .get_attr("weeks")
-        .ok()
-        .filter(|x| !x.is_undefined())
-        .and_then(|f| f.as_str().map(str::parse::<i64>))
-    {
-        duration = duration
-            .checked_add(&Duration::weeks(weeks))
-            .ok_or_else(|| Error::new(ErrorKind::InvalidOperation, "weeks couldn't be added"))?;
-    }
-
-    if let Some(Ok(hours)) = kwargs
-        .get_attr("hours")
-        .ok()
-        .filter(|x| !x.is_undefined())
-        .and_then(|f| f.as_str().map(str::parse::<i64>))
-    {
-        duration = duration
-            .checked_add(&Duration::hours(hours))
-            .ok_or_else(|| Error::new(ErrorKind::InvalidOperation, "hours couldn't be added"))?;
-    }
-
-    if let Some(Ok(minutes)) = kwargs
-        .get_attr("mins")
-        .ok()
-        .filter(|x| !x.is_undefined())
-        .and_then(|f| f.as_str().map(str::parse::<i64>))
-    {
-        duration = duration
-            .checked_add(&Duration::minutes(minutes))
-            .ok_or_else(|| Error::new(ErrorKind::InvalidOperation, "minutes couldn't be added"))?;
-    }
+    let duration = duration_from_kwargs(&kwargs)?;
+    Ok(date_value(date + duration))
+}
 
-    if let Some(Ok(seconds)) = kwargs
-        .get_attr("secs")
-        .ok()
-        .filter(|x| !x.is_undefined())
-        .and_then(|f| f.as_str().map(str::parse::<i64>))
-    {
-        duration = duration
-            .checked_add(&Duration::seconds(seconds))
-            .ok_or_else(|| Error::new(ErrorKind::InvalidOperation, "seconds couldn't be added"))?;
-    }
+/// read the given value as a date, to use it with the date filters. Example:
+/// ```no_compile
+///     {{ date(start) | add_timedelta(days=3) }}
+/// ```
+pub fn date(value: Value) -> Result<Value, Error> {
+    Ok(date_value(parse_date(&value)?))
+}
 
-    let val = Value::from_struct_object(UtcDateTimeValue::new(date + duration));
-    Ok(val)
+/// the next date (starting today) falling on the given weekday. Example:
+/// ```no_compile
+///     {{ next_weekday("monday") }}
+/// ```
+pub fn next_weekday(weekday: String) -> Result<Value, Error> {
+    let weekday = parse_weekday(&weekday)?;
+    let date = UtcDateTime::from(SystemTime::now());
+    Ok(date_value(snap_to_weekday(date, weekday)))
 }
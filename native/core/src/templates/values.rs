@@ -1,5 +1,5 @@
-use minijinja::value::{StructObject, Value};
-use std::sync::Arc;
+use minijinja::value::{Object, ObjectKind, StructObject, Value};
+use std::{fmt, sync::Arc};
 
 use super::Error;
 use crate::{client::CoreClient, events::UtcDateTime};
@@ -76,8 +76,38 @@ impl StructObject for ObjRef {
     }
 }
 
-/// Hold a UtcDateTime for templates
+/// Reference to a space, including its members at the time of execution
 #[derive(Debug)]
+pub struct SpaceValue {
+    id: String,
+    members: Vec<String>,
+}
+
+impl SpaceValue {
+    pub(crate) fn new(id: String, members: Vec<String>) -> Self {
+        SpaceValue { id, members }
+    }
+}
+
+impl StructObject for SpaceValue {
+    fn get_field(&self, name: &str) -> Option<Value> {
+        match name {
+            "id" => Some(Value::from(self.id.clone())),
+            "type" => Some(Value::from("space")),
+            "members" => Some(Value::from(self.members.clone())),
+            _ => None,
+        }
+    }
+
+    fn static_fields(&self) -> Option<&'static [&'static str]> {
+        Some(&["id", "type", "members"][..])
+    }
+}
+
+/// Hold a UtcDateTime for templates
+///
+/// Renders as RFC3339 when used directly
+#[derive(Debug, Clone)]
 pub struct UtcDateTimeValue {
     date: UtcDateTime,
 }
@@ -86,6 +116,22 @@ impl UtcDateTimeValue {
     pub(crate) fn new(date: UtcDateTime) -> Self {
         UtcDateTimeValue { date }
     }
+
+    pub(crate) fn date(&self) -> &UtcDateTime {
+        &self.date
+    }
+}
+
+impl fmt::Display for UtcDateTimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.date.to_rfc3339())
+    }
+}
+
+impl Object for UtcDateTimeValue {
+    fn kind(&self) -> ObjectKind<'_> {
+        ObjectKind::Struct(self)
+    }
 }
 
 impl StructObject for UtcDateTimeValue {
@@ -93,11 +139,15 @@ impl StructObject for UtcDateTimeValue {
         match name {
             "as_timestamp" => Some(Value::from(self.date.timestamp())),
             "as_rfc3339" => Some(Value::from(self.date.to_rfc3339())),
+            "as_date" => Some(Value::from(self.date.format("%Y-%m-%d").to_string())),
+            "weekday" => Some(Value::from(
+                self.date.format("%A").to_string().to_lowercase(),
+            )),
             _ => None,
         }
     }
 
     fn static_fields(&self) -> Option<&'static [&'static str]> {
-        Some(&["as_timestamp"][..])
+        Some(&["as_timestamp", "as_rfc3339", "as_date", "weekday"][..])
     }
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Deserializer};
+use std::time::Duration;
 
 /// Any value that is present is considered Some value, including null.
 /// from https://github.com/serde-rs/serde/issues/984#issuecomment-314143738
@@ -9,3 +10,27 @@ where
 {
     Deserialize::deserialize(deserializer).map(Some)
 }
+
+/// How long to wait before the next attempt after `failures` failed in a
+/// row: `min` after the first, doubled with every further one, at most `max`
+pub fn backoff(failures: u32, min: Duration, max: Duration) -> Duration {
+    min.saturating_mul(2u32.pow(failures.saturating_sub(1).min(16)))
+        .min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let min = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+        assert_eq!(backoff(0, min, max), min);
+        assert_eq!(backoff(1, min, max), min);
+        assert_eq!(backoff(2, min, max), min * 2);
+        assert_eq!(backoff(6, min, max), min * 32);
+        assert_eq!(backoff(7, min, max), max);
+        assert_eq!(backoff(u32::MAX, min, max), max);
+    }
+}
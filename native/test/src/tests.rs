@@ -1,7 +1,9 @@
+mod accounts;
 mod auth;
 mod calendar;
 mod formatted_body;
 mod invitation;
+mod link_previews;
 mod news;
 mod onboarding;
 mod pins;
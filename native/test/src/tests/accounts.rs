@@ -0,0 +1,91 @@
+use acter::api::{login_with_token, AccountRegistry};
+use anyhow::Result;
+use tempfile::TempDir;
+
+/// Stores an account whose session is never used against a server
+async fn add_account(registry: &mut AccountRegistry, dir: &TempDir, name: &str) -> Result<()> {
+    let base_path = dir.path().join(name).to_string_lossy().to_string();
+    let token = format!(
+        r#"{{
+            "is_guest": false,
+            "homeurl": "http://localhost:1",
+            "session": {{
+                "user_id": "@{name}:localhost",
+                "device_id": "DS9",
+                "access_token": "secret"
+            }}
+        }}"#
+    );
+    let client = login_with_token(base_path.clone(), token).await?;
+    registry.add(&client, base_path).await
+}
+
+#[tokio::test]
+async fn accounts_are_stored_and_switched() -> Result<()> {
+    let _ = env_logger::try_init();
+    let dir = TempDir::new()?;
+    let path = dir.path().join("accounts.json");
+    let mut registry = AccountRegistry::load(&path)?;
+    assert!(registry.current().is_none());
+
+    add_account(&mut registry, &dir, "sisko").await?;
+    add_account(&mut registry, &dir, "kira").await?;
+    // the first one stays the current one
+    assert_eq!(registry.current().unwrap().user_id(), "@sisko:localhost");
+    // adding again replaces the stored session
+    add_account(&mut registry, &dir, "sisko").await?;
+    assert_eq!(registry.accounts().len(), 2);
+
+    registry.switch("@kira:localhost")?;
+    assert!(registry.switch("@quark:localhost").is_err());
+
+    let reloaded = AccountRegistry::load(&path)?;
+    let users = reloaded
+        .accounts()
+        .iter()
+        .map(|a| a.user_id())
+        .collect::<Vec<_>>();
+    assert_eq!(users, ["@kira:localhost", "@sisko:localhost"]);
+    assert_eq!(reloaded.current().unwrap().user_id(), "@kira:localhost");
+    Ok(())
+}
+
+#[tokio::test]
+async fn removing_the_current_account_falls_back() -> Result<()> {
+    let _ = env_logger::try_init();
+    let dir = TempDir::new()?;
+    let path = dir.path().join("accounts.json");
+    let mut registry = AccountRegistry::load(&path)?;
+    add_account(&mut registry, &dir, "sisko").await?;
+    add_account(&mut registry, &dir, "kira").await?;
+
+    let removed = registry.remove("@sisko:localhost")?;
+    assert_eq!(removed.user_id(), "@sisko:localhost");
+    assert_eq!(registry.current().unwrap().user_id(), "@kira:localhost");
+    assert!(registry.remove("@sisko:localhost").is_err());
+
+    registry.remove("@kira:localhost")?;
+    let reloaded = AccountRegistry::load(&path)?;
+    assert!(reloaded.accounts().is_empty());
+    assert!(reloaded.current().is_none());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn accounts_file_is_private() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _ = env_logger::try_init();
+    let dir = TempDir::new()?;
+    let path = dir.path().join("accounts.json");
+    // as written by older versions
+    std::fs::write(&path, "{\"accounts\": []}")?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))?;
+
+    let mut registry = AccountRegistry::load(&path)?;
+    add_account(&mut registry, &dir, "sisko").await?;
+    let mode = std::fs::metadata(&path)?.permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use acter::{api::login_with_token_under_config, matrix_sdk::Client as SdkClient, Client};
+use anyhow::Result;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Answers just enough of the client-server API for link previews
+async fn stand_in_homeserver(previews_asked: Arc<AtomicUsize>) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let homeurl = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(answer(stream, previews_asked.clone()));
+        }
+    });
+    Ok(homeurl)
+}
+
+async fn answer(mut stream: TcpStream, previews_asked: Arc<AtomicUsize>) -> Result<()> {
+    let mut request = vec![];
+    let mut buf = [0u8; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, content_type, body): (&str, &str, Vec<u8>) =
+        if path.starts_with("/_matrix/client/versions") {
+            (
+                "200 OK",
+                "application/json",
+                br#"{"versions":["v1.5"]}"#.to_vec(),
+            )
+        } else if path.contains("/preview_url") {
+            previews_asked.fetch_add(1, Ordering::SeqCst);
+            let body = if path.contains("empty") {
+                "{}".to_owned()
+            } else {
+                r#"{
+                    "og:title": "Deep Space Nine",
+                    "og:description": "A space station near the wormhole",
+                    "og:site_name": "Memory Alpha",
+                    "og:image": "mxc://localhost/preview-image"
+                }"#
+                .to_owned()
+            };
+            ("200 OK", "application/json", body.into_bytes())
+        } else if path.contains("/download/localhost/preview-image") {
+            ("200 OK", "image/png", b"not really a png".to_vec())
+        } else {
+            (
+                "404 Not Found",
+                "application/json",
+                br#"{"errcode":"M_UNRECOGNIZED","error":"Unrecognized request"}"#.to_vec(),
+            )
+        };
+
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn client_for(homeurl: &str) -> Result<Client> {
+    let token = format!(
+        r#"{{
+            "is_guest": false,
+            "homeurl": "{homeurl}",
+            "session": {{
+                "user_id": "@sisko:localhost",
+                "device_id": "DS9",
+                "access_token": "secret"
+            }}
+        }}"#
+    );
+    login_with_token_under_config(token, SdkClient::builder()).await
+}
+
+#[tokio::test]
+async fn link_preview_is_fetched_and_cached() -> Result<()> {
+    let _ = env_logger::try_init();
+    let previews_asked = Arc::new(AtomicUsize::new(0));
+    let homeurl = stand_in_homeserver(previews_asked.clone()).await?;
+    let client = client_for(&homeurl).await?;
+
+    let url = "https://memory-alpha.fandom.com/wiki/Deep_Space_9".to_owned();
+    let preview = client
+        .link_preview(url.clone())
+        .await?
+        .expect("the page has a preview");
+    assert_eq!(preview.url(), url);
+    assert_eq!(preview.title().as_deref(), Some("Deep Space Nine"));
+    assert_eq!(
+        preview.description().as_deref(),
+        Some("A space station near the wormhole")
+    );
+    assert_eq!(preview.site_name().as_deref(), Some("Memory Alpha"));
+    assert!(
+        preview.image().data().is_some(),
+        "the image wasn't downloaded"
+    );
+
+    // asking again doesn't bother the homeserver
+    let again = client.link_preview(url).await?.expect("still there");
+    assert_eq!(again.title(), preview.title());
+    assert_eq!(previews_asked.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn link_preview_without_content_is_none() -> Result<()> {
+    let _ = env_logger::try_init();
+    let previews_asked = Arc::new(AtomicUsize::new(0));
+    let homeurl = stand_in_homeserver(previews_asked.clone()).await?;
+    let client = client_for(&homeurl).await?;
+
+    let url = "https://example.org/empty".to_owned();
+    assert!(client.link_preview(url.clone()).await?.is_none());
+    // nothing to show is remembered too
+    assert!(client.link_preview(url).await?.is_none());
+    assert_eq!(previews_asked.load(Ordering::SeqCst), 1);
+    Ok(())
+}
@@ -4,7 +4,7 @@ use tokio_retry::{
     Retry,
 };
 
-use crate::utils::random_user_with_template;
+use crate::utils::{random_user_with_random_space, random_user_with_template};
 
 const TMPL: &str = r#"
 version = "0.1"
@@ -68,3 +68,58 @@ async fn template_creates_space() -> Result<()> {
     assert_eq!(main_space.task_lists().await?.len(), 1);
     Ok(())
 }
+
+const SHARED_TMPL: &str = r#"
+version = "0.1"
+name = "Shared Template"
+description = "A template shared within the space"
+revision = 2
+
+[inputs]
+main = { type = "user", is-default = true, required = true, description = "The starting user" }
+space = { type = "space", is-default = true, required = true, description = "The acter space" }
+
+[objects]
+start_list = { type = "task-list", name = "{{ main.display_name }}'s shared list" }
+"#;
+
+#[tokio::test]
+async fn template_publish_and_discover() -> Result<()> {
+    let _ = env_logger::try_init();
+    let (mut user, room_id) = random_user_with_random_space("template-publish").await?;
+    let state_sync = user.start_sync();
+    state_sync.await_has_synced_history().await?;
+    let space = user.get_space(room_id.to_string()).await?;
+
+    assert!(space.templates().await?.is_empty());
+    space
+        .publish_template("shared".to_owned(), SHARED_TMPL.to_owned())
+        .await?;
+
+    let retry_strategy = FibonacciBackoff::from_millis(100).map(jitter).take(10);
+    let fetcher_space = space.clone();
+    Retry::spawn(retry_strategy, move || {
+        let space = fetcher_space.clone();
+        async move {
+            if space.templates().await?.is_empty() {
+                bail!("template not found");
+            } else {
+                Ok(())
+            }
+        }
+    })
+    .await?;
+
+    let templates = user.available_templates().await?;
+    assert!(templates
+        .iter()
+        .any(|t| t.key() == "onboarding" && t.is_builtin()));
+
+    let shared = user.template("shared".to_owned()).await?;
+    assert!(!shared.is_builtin());
+    assert_eq!(shared.name(), Some("Shared Template".to_owned()));
+    assert_eq!(shared.revision(), 2);
+    assert_eq!(shared.requested_inputs().len(), 2);
+    assert_eq!(shared.origin_room_id_str(), Some(room_id.to_string()));
+    Ok(())
+}
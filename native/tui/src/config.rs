@@ -1,23 +1,44 @@
-use acter::{api::login_new_client, matrix_sdk::ruma::OwnedUserId, Client};
-use anyhow::Result;
+use acter::{
+    api::{login_new_client, AccountRegistry},
+    matrix_sdk::ruma::OwnedUserId,
+    Client,
+};
+use anyhow::{Context, Result};
 use clap::Parser;
 use dialoguer::{theme::ColorfulTheme, Password};
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use tracing::info;
 
+use crate::ui::{KeyBindings, KeyList, Preset, ThemeConfig};
+
 pub const ENV_USER: &str = "ACTER_USER";
 pub const ENV_PASSWORD: &str = "ACTER_PASSWORD";
 
+/// The accounts logged in so far, kept in the data dir
+pub fn accounts_path(app_dir: &Path) -> PathBuf {
+    app_dir.join("accounts.json")
+}
+
+/// The keybindings and theme, next to the rest of our data
+pub fn ui_config_path(app_dir: &Path) -> PathBuf {
+    app_dir.join("config.toml")
+}
+
 /// Generic Login Configuration helper
 #[derive(Parser, Debug)]
 pub struct LoginConfig {
-    /// Fully qualified @SOMETHING:server.tld username.
+    /// Fully qualified @SOMETHING:server.tld username. Uses the last account
+    /// if not given.
     #[clap(
         short = 'u',
         long = "user",
         env = ENV_USER
     )]
-    login_username: OwnedUserId,
+    login_username: Option<OwnedUserId>,
 
     #[clap(
         short = 'p',
@@ -28,9 +49,25 @@ pub struct LoginConfig {
 }
 
 impl LoginConfig {
-    pub async fn client(&self, path: PathBuf) -> Result<Client> {
+    /// Restore the session of a stored account or log in and store it, the
+    /// account becomes the current one
+    pub async fn client(&self, app_dir: &Path) -> Result<Client> {
+        let mut registry = AccountRegistry::load(accounts_path(app_dir))?;
+        let Some(username) = self.login_username.clone() else {
+            let account = registry
+                .current()
+                .context("No user given and no account stored yet, use `--user`")?;
+            info!("Restoring {}", account.user_id());
+            return account.login().await;
+        };
+        if let Some(account) = registry.get(username.as_str()) {
+            info!("Restoring {}", username);
+            let client = account.login().await?;
+            registry.switch(username.as_str())?;
+            return Ok(client);
+        }
+
         let theme = ColorfulTheme::default();
-        let username = self.login_username.clone();
         info!("Logging in as {}", username);
         let password = match self.login_password {
             Some(ref pw) => pw.clone(),
@@ -45,8 +82,9 @@ impl LoginConfig {
             .unwrap_or("https://matrix.acter.global")
             .to_string();
 
+        let base_path = app_dir.join(username.as_str());
         let client = login_new_client(
-            String::from(path.to_string_lossy()),
+            String::from(base_path.to_string_lossy()),
             username.to_string(),
             password,
             default_homeserver_name,
@@ -55,6 +93,10 @@ impl LoginConfig {
         )
         .await?;
 
+        registry
+            .add(&client, String::from(base_path.to_string_lossy()))
+            .await?;
+        registry.switch(username.as_str())?;
         Ok(client)
     }
 }
@@ -78,7 +120,51 @@ pub struct ActerTuiConfig {
     #[clap(long)]
     pub local: bool,
 
+    /// Sync through the sliding sync proxy, falls back to the full sync
+    #[clap(long)]
+    pub sliding_sync: bool,
+
+    /// Keybindings and theme, defaults to `config.toml` in the data dir (or `.local` with `--local`)
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
     /// The action to perform
     #[clap(flatten)]
     pub login: LoginConfig,
 }
+
+/// The `config.toml` of the tui
+///
+/// ```toml
+/// preset = "vi"
+///
+/// [theme]
+/// primary = "#ec2758"
+///
+/// [keys.chat]
+/// reply = ["ctrl+r", "r"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// extra bindings to start from
+    pub preset: Preset,
+    pub theme: ThemeConfig,
+    /// action to key(s) per scope, replacing the default keys of that action
+    pub keys: HashMap<String, HashMap<String, KeyList>>,
+}
+
+impl UiConfig {
+    /// read the config at `path`, a missing file means the defaults
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    pub fn key_bindings(&self) -> Result<KeyBindings> {
+        KeyBindings::new(self.preset, &self.keys)
+    }
+}
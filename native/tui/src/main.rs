@@ -3,21 +3,29 @@
 mod config;
 mod ui;
 
-use anyhow::Result;
+use acter::{api::AccountRegistry, Client};
+use anyhow::{Context, Result};
 use app_dirs2::{app_root, AppDataType, AppInfo};
 use clap::Parser;
-use config::ActerTuiConfig;
+use config::{accounts_path, ui_config_path, ActerTuiConfig, UiConfig};
 use futures::{future::Either, pin_mut, stream::StreamExt};
-use std::{path::PathBuf, sync::mpsc::channel};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+};
+use tokio::{sync::mpsc::unbounded_channel, task::JoinHandle};
 use tracing::{error, info, warn};
 use tui_logger::Drain;
-use ui::AppUpdate;
+use ui::{AppCommand, AppUpdate};
 
 const APP_INFO: AppInfo = AppInfo {
     name: "acter-tui",
     author: "Acter Team",
 };
 
+/// how many of the latest news entries the news tool pages through
+const NEWS_COUNT: u32 = 50;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = ActerTuiConfig::parse();
@@ -35,80 +43,227 @@ async fn main() -> Result<()> {
             })
         .init(); // make this the global logger
 
-    let (sender, rx) = channel::<AppUpdate>();
     let app_dir = if cli.local {
         PathBuf::new().join(".local")
     } else {
         app_root(AppDataType::UserData, &APP_INFO)?
     };
 
+    let config_path = match cli.config {
+        Some(ref path) => path.clone(),
+        None => ui_config_path(&app_dir),
+    };
+    let ui_config = UiConfig::load(&config_path)?;
+    ui::set_theme(ui_config.theme.theme()?);
+    let keys = ui_config.key_bindings()?;
+
+    let (sender, rx) = channel::<AppUpdate>();
+
     if cli.fresh {
-        std::fs::remove_dir_all(app_dir.clone())?;
+        remove_data(&app_dir, &config_path)?;
     }
 
-    let mut client = cli.login.client(app_dir).await?;
-    let sync_state = client.start_sync();
-
+    let client = cli.login.client(&app_dir).await?;
+    let sliding_sync = cli.sliding_sync;
+    let (commands, mut commands_rx) = unbounded_channel::<AppCommand>();
     tokio::spawn(async move {
-        let username = client.user_id().expect("You seem to be not logged in");
-        sender
-            .send(AppUpdate::SetUsername(username.to_string()))
-            .unwrap();
-
-        let dp = client.account().unwrap().display_name().await.unwrap();
-        let name = format!("{:?} ({username:})", dp.text());
-        sender.send(AppUpdate::SetUsername(name)).unwrap();
-
-        let sync_stream = sync_state.first_synced_rx();
-        let history_loaded = sync_state.get_history_loading_rx();
-
-        let main_stream = futures::stream::select(
-            history_loaded.map(Either::Right),
-            sync_stream.map(Either::Left),
-        );
-
-        pin_mut!(main_stream);
-
-        loop {
-            match main_stream.next().await {
-                Some(Either::Left(synced)) => {
-                    sender.send(AppUpdate::SetSynced(synced)).unwrap();
-                    if synced {
-                        // let's update the chats;
-                        let conversastions = client.convos().await.unwrap();
-                        sender
-                            .send(AppUpdate::UpdateConvos(conversastions))
-                            .unwrap();
-
-                        // let's update the spaces;
-                        let spaces = client.spaces().await.unwrap();
-                        sender.send(AppUpdate::UpdateSpaces(spaces)).unwrap();
+        let registry_path = accounts_path(&app_dir);
+        let mut session = Session::start(client, sliding_sync, &registry_path, sender.clone());
+        while let Some(command) = commands_rx.recv().await {
+            match command {
+                AppCommand::SwitchAccount(user_id) => {
+                    let client = match restore_account(&registry_path, &user_id).await {
+                        Ok(client) => client,
+                        Err(error) => {
+                            error!(?error, user_id, "Switching account failed");
+                            continue;
+                        }
+                    };
+                    session.stop().await;
+                    if sender.send(AppUpdate::Reset).is_err() {
+                        break; // the ui is gone
                     }
+                    session = Session::start(client, sliding_sync, &registry_path, sender.clone());
                 }
-                Some(Either::Right(history)) => {
-                    info!("History updated. Done? {:}", history.is_done_loading());
-                    if history.is_done_loading() {
-                        match client.task_lists().await {
-                            Ok(task_lists) => {
-                                if task_lists.is_empty() {
-                                    warn!("No task lists found");
+            }
+        }
+    });
+    ui::run_ui(rx, commands, cli.fullscreen_logs, keys).await?;
+
+    Ok(())
+}
+
+/// drop all data of `app_dir`, but keep the config if it lives there
+fn remove_data(app_dir: &Path, config_path: &Path) -> Result<()> {
+    if !app_dir.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(app_dir)? {
+        let path = entry?.path();
+        if path == config_path {
+            continue;
+        }
+        if path.is_dir() {
+            std::fs::remove_dir_all(path)?;
+        } else {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// make `user_id` the current account and restore its session
+async fn restore_account(registry_path: &Path, user_id: &str) -> Result<Client> {
+    let mut registry = AccountRegistry::load(registry_path)?;
+    let account = registry.get(user_id).context("Unknown account")?;
+    let client = account.login().await?;
+    registry.switch(user_id)?;
+    Ok(client)
+}
+
+/// Everything running for the logged in account
+struct Session {
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Session {
+    fn start(
+        mut client: Client,
+        sliding_sync: bool,
+        registry_path: &Path,
+        sender: Sender<AppUpdate>,
+    ) -> Self {
+        match AccountRegistry::load(registry_path) {
+            Ok(registry) => {
+                let accounts = registry.accounts().iter().map(|a| a.user_id()).collect();
+                let current = registry.current().map(|a| a.user_id()).unwrap_or_default();
+                sender
+                    .send(AppUpdate::SetAccounts(accounts, current))
+                    .unwrap();
+            }
+            Err(error) => error!(?error, "Accounts couldn't be read"),
+        }
+
+        let sync_state = if sliding_sync {
+            client.start_sliding_sync(None)
+        } else {
+            client.start_sync()
+        };
+
+        let connection_stream = sync_state.connection_state_rx();
+        let connection_sender = sender.clone();
+        let connection_handle = tokio::spawn(async move {
+            pin_mut!(connection_stream);
+            while let Some(connection) = connection_stream.next().await {
+                if connection_sender
+                    .send(AppUpdate::SetConnection(connection))
+                    .is_err()
+                {
+                    break; // the ui is gone
+                }
+            }
+        });
+
+        let convos_client = client.clone();
+        let convos_sender = sender.clone();
+        let convos_handle = tokio::spawn(async move {
+            // keep the chat list in sync with the rooms we are in
+            let convos_stream = convos_client.convos_rx();
+            pin_mut!(convos_stream);
+            while let Some(convos) = convos_stream.next().await {
+                if convos_sender.send(AppUpdate::UpdateConvos(convos)).is_err() {
+                    break; // the ui is gone
+                }
+            }
+        });
+
+        let main_handle = tokio::spawn(async move {
+            let username = client.user_id().expect("You seem to be not logged in");
+            sender
+                .send(AppUpdate::SetUsername(username.to_string()))
+                .unwrap();
+
+            let dp = client.account().unwrap().display_name().await.unwrap();
+            let name = format!("{:?} ({username:})", dp.text());
+            sender.send(AppUpdate::SetUsername(name)).unwrap();
+
+            let sync_stream = sync_state.first_synced_rx();
+            let history_loaded = sync_state.get_history_loading_rx();
+
+            let main_stream = futures::stream::select(
+                history_loaded.map(Either::Right),
+                sync_stream.map(Either::Left),
+            );
+
+            pin_mut!(main_stream);
+
+            loop {
+                match main_stream.next().await {
+                    Some(Either::Left(synced)) => {
+                        sender.send(AppUpdate::SetSynced(synced)).unwrap();
+                        if synced {
+                            // let's update the spaces;
+                            let spaces = client.spaces().await.unwrap();
+                            sender.send(AppUpdate::UpdateSpaces(spaces)).unwrap();
+                        }
+                    }
+                    Some(Either::Right(history)) => {
+                        info!("History updated. Done? {:}", history.is_done_loading());
+                        if history.is_done_loading() {
+                            match client.task_lists().await {
+                                Ok(task_lists) => {
+                                    if task_lists.is_empty() {
+                                        warn!("No task lists found");
+                                    }
+                                    sender.send(AppUpdate::SetTasksList(task_lists)).unwrap();
+                                }
+                                Err(error) => {
+                                    error!(?error, "TaskList couldn't be read");
                                 }
-                                sender.send(AppUpdate::SetTasksList(task_lists)).unwrap();
                             }
-                            Err(error) => {
-                                error!(?error, "TaskList couldn't be read");
+                            match client.latest_news_entries(NEWS_COUNT).await {
+                                Ok(entries) => {
+                                    sender.send(AppUpdate::SetNews(entries)).unwrap();
+                                }
+                                Err(error) => {
+                                    error!(?error, "News couldn't be read");
+                                }
+                            }
+                            match client.pins().await {
+                                Ok(pins) => {
+                                    sender.send(AppUpdate::SetPins(pins)).unwrap();
+                                }
+                                Err(error) => {
+                                    error!(?error, "Pins couldn't be read");
+                                }
+                            }
+                            match client.calendar_events().await {
+                                Ok(events) => {
+                                    sender.send(AppUpdate::SetCalendarEvents(events)).unwrap();
+                                }
+                                Err(error) => {
+                                    error!(?error, "Calendar events couldn't be read");
+                                }
                             }
                         }
+                        sender
+                            .send(AppUpdate::SetHistoryLoadState(history))
+                            .unwrap();
                     }
-                    sender
-                        .send(AppUpdate::SetHistoryLoadState(history))
-                        .unwrap();
+                    None => {}
                 }
-                None => {}
             }
+        });
+        Session {
+            handles: vec![connection_handle, convos_handle, main_handle],
         }
-    });
-    ui::run_ui(rx, cli.fullscreen_logs).await?;
+    }
 
-    Ok(())
+    /// stops syncing and all updates of this account
+    async fn stop(self) {
+        for handle in self.handles {
+            handle.abort();
+            let _ = handle.await;
+        }
+    }
 }
@@ -1,9 +1,9 @@
 #![allow(dead_code)]
-use acter::{Convo, HistoryLoadState, Space, Task, TaskList};
+use acter::{ActerPin, CalendarEvent, Convo, HistoryLoadState, NewsEntry, Space, TaskList};
 use anyhow::Result;
 use clap::crate_version;
 use crossterm::{
-    event::{self, DisableMouseCapture, Event, KeyCode, KeyEvent},
+    event::{self, DisableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -11,32 +11,55 @@ use std::{
     sync::mpsc::{Receiver as MpscReceiver, TryRecvError},
     time::{Duration, Instant},
 };
-use tokio::sync::broadcast::Receiver as BroadcastReceiver;
-use tracing::{error, info, trace};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{error, info};
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
-    text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, ListState, Tabs},
+    text::{Span, Spans},
+    widgets::{Block, Borders, ListState, Tabs},
     Frame, Terminal,
 };
 use tui_logger::{TuiLoggerWidget, TuiWidgetEvent};
 
-const PRIMARY: Color = Color::Rgb(236, 39, 88);
-const SECONDARY: Color = Color::Rgb(35, 175, 194);
-const TERTIARY: Color = Color::Rgb(92, 42, 128);
-const BG_GRAY: Color = Color::Rgb(151, 151, 151);
-const BG_DARK: Color = Color::Rgb(51, 53, 64);
-const BG_DARKER: Color = Color::Rgb(47, 49, 62);
+mod calendar;
+mod chat;
+mod keys;
+mod news;
+mod pins;
+mod tasks;
+mod theme;
+
+use calendar::CalendarState;
+use chat::ChatState;
+use keys::Scope;
+use news::NewsState;
+use pins::PinsState;
+use tasks::TasksState;
+use theme::theme;
+
+pub use keys::{KeyBindings, KeyList, Preset};
+pub use theme::{set_theme, ThemeConfig};
 
 pub enum AppUpdate {
     SetUsername(String), // set the username
     SetSynced(bool),     // set the synced state
+    SetConnection(String),
     UpdateConvos(Vec<Convo>),
     UpdateSpaces(Vec<Space>),
     SetHistoryLoadState(HistoryLoadState),
     SetTasksList(Vec<TaskList>),
+    SetNews(Vec<NewsEntry>),
+    SetPins(Vec<ActerPin>),
+    SetCalendarEvents(Vec<CalendarEvent>),
+    SetAccounts(Vec<String>, String), // all stored accounts and the current one
+    Reset,                            // the account changed, drop everything
+}
+
+/// What the ui asks of the session
+pub enum AppCommand {
+    SwitchAccount(String),
 }
 
 #[derive(PartialEq, Eq)]
@@ -46,23 +69,6 @@ enum Widget {
     Logs,
 }
 
-#[derive(Clone, Debug)]
-struct ChatStats {
-    total: u32,
-    unread: u32,
-    notifications: u32,
-}
-
-#[derive(Debug, Default)]
-struct TasksState {
-    task_lists_list_state: ListState,
-    tasks_list_state: ListState,
-    selected: Option<TaskList>,
-    task_lists: Vec<TaskList>,
-    receivers: Vec<BroadcastReceiver<()>>,
-    tasks: Vec<Task>,
-}
-
 pub fn next(list_state: &mut ListState, list_len: usize) {
     if list_len == 0 {
         return;
@@ -85,245 +91,90 @@ pub fn prev(list_state: &mut ListState) {
     }
 }
 
-impl TasksState {
-    fn fresh(task_lists: Vec<TaskList>) -> Self {
-        TasksState {
-            task_lists_list_state: Default::default(),
-            tasks_list_state: Default::default(),
-            selected: None,
-            task_lists,
-            receivers: Default::default(),
-            tasks: Default::default(),
-        }
-    }
-
-    async fn tick(&mut self) {
-        let mut update = false;
-        for t in self.receivers.iter_mut() {
-            if t.try_recv().is_ok() {
-                // consume
-                update = true;
-            }
-        }
+#[derive(Debug)]
+enum Tool {
+    News(NewsState),
+    Pins(PinsState),
+    Tasks(TasksState),
+    Calendar(CalendarState),
+    Chat(ChatState),
+}
 
-        if update {
-            trace!("refreshing upon tick");
-            if let Some(t) = &self.selected.clone() {
-                self.refresh(t).await;
-            }
+impl Tool {
+    fn name(&self) -> String {
+        match self {
+            Tool::News(news_state) => news_state.name(),
+            Tool::Pins(pins_state) => pins_state.name(),
+            Tool::Tasks(tasks_state) => tasks_state.name(),
+            Tool::Calendar(calendar_state) => calendar_state.name(),
+            Tool::Chat(chat_state) => chat_state.name(),
         }
     }
 
-    async fn refresh(&mut self, task_list: &TaskList) {
-        self.tasks = task_list.tasks().await.unwrap();
-        self.receivers = self.tasks.iter().map(|t| t.subscribe()).collect();
-        // self.receivers.push(task_list.subscribe());
-    }
-
-    fn select_next(&mut self) {
-        if self.selected.is_some() {
-            next(&mut self.tasks_list_state, self.tasks.len());
-        } else {
-            next(&mut self.task_lists_list_state, self.task_lists.len());
+    fn scope(&self) -> Scope {
+        match self {
+            Tool::News(_) => Scope::News,
+            Tool::Pins(_) => Scope::Pins,
+            Tool::Tasks(_) => Scope::Tasks,
+            Tool::Calendar(_) => Scope::Calendar,
+            Tool::Chat(_) => Scope::Chat,
         }
     }
 
-    async fn select(&mut self) {
-        if self.selected.is_some() {
-            if let Some(idx) = self.tasks_list_state.selected() {
-                let Some(task) = self.tasks.get(idx) else {
-                    panic!("can't be readed");
-                };
-
-                let resp = if task.is_done() {
-                    trace!(?task, "marking undone");
-                    task.update_builder().unwrap().mark_undone().send().await
-                } else {
-                    trace!(?task, "marking done");
-                    task.update_builder().unwrap().mark_done().send().await
-                };
-
-                match resp {
-                    Err(error) => {
-                        error!(?task, ?error, "updating task failed");
-                    }
-                    Ok(event_id) => {
-                        trace!(?task, ?event_id, "updating accepted");
-                    }
-                }
-            }
-        } else if let Some(selected) = self
-            .task_lists_list_state
-            .selected()
-            .and_then(|idx| self.task_lists.get(idx).cloned())
-        {
-            trace!(?selected, "selecting");
-            self.refresh(&selected).await;
-            self.selected = Some(selected.clone());
+    fn is_typing(&self) -> bool {
+        match self {
+            Tool::Tasks(tasks_state) => tasks_state.is_typing(),
+            Tool::Chat(chat_state) => chat_state.is_typing(),
+            _ => false,
         }
     }
 
-    fn select_prev(&mut self) {
-        if self.selected.is_some() {
-            if self.tasks.is_empty() {
-                return;
-            }
-            prev(&mut self.tasks_list_state);
-        } else {
-            if self.task_lists.is_empty() {
-                return;
-            }
-            prev(&mut self.task_lists_list_state);
-        }
+    fn is_tasks(&self) -> bool {
+        matches!(self, Tool::Tasks(_))
     }
 
-    async fn handle_key(&mut self, key: KeyEvent) -> bool {
-        match key.code {
-            KeyCode::Down => {
-                self.select_next();
-                true
-            }
-            KeyCode::Enter => {
-                self.select().await;
-                true
-            }
-            KeyCode::Up => {
-                self.select_prev();
-                true
-            }
-            KeyCode::Right => {
-                if self.selected.is_none() {
-                    self.select().await;
-                }
-                true
-            }
-            KeyCode::Esc | KeyCode::Left => {
-                if self.selected.is_some() {
-                    self.selected = None;
-                    true
-                } else {
-                    false
-                }
-            }
-            // KeyCode::Char('n') => {
-            //     let spaces = &self.spaces;
-            //     let names: Vec<String> =
-            //         join_all(spaces.iter().map(|g| g.display_name()))
-            //             .await
-            //             .into_iter()
-            //             .map(|d| d.map(|i| format!("{}", i)))
-            //             .collect::<Result<Vec<_>, StoreError>>()
-            //             .unwrap();
-
-            //     let list_title = Input::<String>::new()
-            //         .with_prompt("List Title")
-            //         .interact_text()
-            //         .unwrap();
-
-            //     let chosen: usize = Select::new()
-            //         .with_prompt("Under which Space?")
-            //         .items(&names)
-            //         .interact()
-            //         .unwrap();
-
-            //     let space = &spaces[chosen];
-            //     let mut tl_draft = space.task_list_draft().unwrap();
-            //     tl_draft.name(list_title);
-
-            //     tl_draft.send().await.unwrap();
-            //     true
-            // }
-            _ => false,
-        }
+    fn is_chat(&self) -> bool {
+        matches!(self, Tool::Chat(_))
     }
 
-    fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
-        if let Some(selected) = &self.selected {
-            let ls = List::new(
-                self.tasks
-                    .iter()
-                    .map(|t| {
-                        if t.is_done() {
-                            format!(" [x] {:}", t.title())
-                        } else if let Some(p) = t.percent() {
-                            format!(" {p}% {:}", t.title())
-                        } else {
-                            format!(" [ ] {:}", t.title())
-                        }
-                    })
-                    .map(|s| ListItem::new(Text::from(s)))
-                    .collect::<Vec<_>>(),
-            )
-            .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(PRIMARY))
-            .block(
-                Block::default()
-                    .title(format!(" [🗹] > {:}", selected.name()))
-                    .borders(Borders::ALL)
-                    .border_style(block_border_style),
-            );
-
-            f.render_stateful_widget(ls, area, &mut self.tasks_list_state);
-        } else {
-            let ls = List::new(
-                self.task_lists
-                    .iter()
-                    .map(|l| ListItem::new(Text::from(l.name().as_str())))
-                    .collect::<Vec<_>>(),
-            )
-            .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(PRIMARY))
-            .block(
-                Block::default()
-                    .title(" Tasks 🗹")
-                    .borders(Borders::ALL)
-                    .border_style(block_border_style),
-            );
-
-            f.render_stateful_widget(ls, area, &mut self.task_lists_list_state);
-        }
+    fn is_news(&self) -> bool {
+        matches!(self, Tool::News(_))
     }
-}
 
-#[derive(Debug)]
-enum Tool {
-    News,
-    Tasks(TasksState),
-    Chat(Option<ChatStats>),
-}
+    fn is_pins(&self) -> bool {
+        matches!(self, Tool::Pins(_))
+    }
 
-impl Tool {
-    fn name(&self) -> String {
-        match self {
-            Tool::News => "News".to_owned(),
-            Tool::Tasks(TasksState { task_lists, .. }) => {
-                if task_lists.is_empty() {
-                    "Tasks".to_owned()
-                } else {
-                    format!("Tasks ({:})", task_lists.len())
-                }
-            }
-            Tool::Chat(stats) => match stats {
-                Some(s) => {
-                    format!("Chat ({}/{})", s.unread, s.total)
-                }
-                None => "Chat".to_owned(),
-            },
-        }
+    fn is_calendar(&self) -> bool {
+        matches!(self, Tool::Calendar(_))
     }
 
-    fn is_tasks(&self) -> bool {
-        matches!(self, Tool::Tasks(_))
+    fn set_news(&mut self, entries: Vec<NewsEntry>) {
+        let Tool::News(news_state) = self else {
+            unimplemented!("What are you doing here?")
+        };
+        news_state.set_entries(entries)
     }
 
-    fn is_chat(&self) -> bool {
-        matches!(self, Tool::Chat(_))
+    fn set_pins(&mut self, pins: Vec<ActerPin>) {
+        let Tool::Pins(pins_state) = self else {
+            unimplemented!("What are you doing here?")
+        };
+        pins_state.set_pins(pins)
     }
 
-    fn update_chat_stats(&mut self, new_stats: ChatStats) {
-        if !self.is_chat() {
+    fn set_calendar_events(&mut self, events: Vec<CalendarEvent>) {
+        let Tool::Calendar(calendar_state) = self else {
             unimplemented!("What are you doing here?")
-        }
+        };
+        calendar_state.set_events(events)
+    }
 
-        *self = Tool::Chat(Some(new_stats))
+    fn set_convos(&mut self, convos: Vec<Convo>) {
+        let Tool::Chat(chat_state) = self else {
+            unimplemented!("What are you doing here?")
+        };
+        chat_state.set_convos(convos)
     }
 
     fn set_tasks(&mut self, t: Vec<TaskList>) {
@@ -337,15 +188,20 @@ impl Tool {
     fn all() -> Vec<Self> {
         vec![
             Tool::Tasks(Default::default()),
-            Tool::News,
-            Tool::Chat(None),
+            Tool::News(Default::default()),
+            Tool::Pins(Default::default()),
+            Tool::Calendar(Default::default()),
+            Tool::Chat(Default::default()),
         ]
     }
 
     async fn handle_key(&mut self, key: KeyEvent) -> bool {
         match self {
             Tool::Tasks(task_state) => task_state.handle_key(key).await,
-            _ => false,
+            Tool::Chat(chat_state) => chat_state.handle_key(key).await,
+            Tool::News(news_state) => news_state.handle_key(key).await,
+            Tool::Pins(pins_state) => pins_state.handle_key(key).await,
+            Tool::Calendar(calendar_state) => calendar_state.handle_key(key).await,
         }
     }
 }
@@ -371,10 +227,15 @@ struct App {
     pub history_load_state: HistoryLoadState,
     pub index: usize,
     pub synced: bool,
+    pub connection: Option<String>,
+    pub keys: KeyBindings,
+    pub accounts: Vec<String>,
+    pub account: Option<String>,
+    pub commands: UnboundedSender<AppCommand>,
 }
 
 impl App {
-    fn new(logs_fullscreen: bool) -> App {
+    fn new(logs_fullscreen: bool, keys: KeyBindings, commands: UnboundedSender<AppCommand>) -> App {
         let selected_widget = if logs_fullscreen {
             Widget::Logs
         } else {
@@ -391,13 +252,21 @@ impl App {
             history_load_state: Default::default(),
             username: None,
             synced: false,
+            connection: None,
+            keys,
+            accounts: Default::default(),
+            account: None,
+            commands,
         }
     }
 
     async fn on_tick(&mut self) {
-        if let Tool::Tasks(t) = self.selected_tool_mut() {
-            t.tick().await;
-        };
+        match self.selected_tool_mut() {
+            Tool::Tasks(t) => t.tick().await,
+            Tool::Chat(c) => c.tick(),
+            Tool::Calendar(c) => c.tick().await,
+            _ => {}
+        }
     }
 
     pub fn next_widget(&mut self) {
@@ -408,6 +277,20 @@ impl App {
         match update {
             AppUpdate::SetUsername(u) => self.username = Some(u),
             AppUpdate::SetSynced(synced) => self.synced = synced,
+            AppUpdate::SetConnection(connection) => self.connection = Some(connection),
+            AppUpdate::SetAccounts(accounts, current) => {
+                self.accounts = accounts;
+                self.account = Some(current);
+            }
+            AppUpdate::Reset => {
+                self.tools = Tool::all();
+                self.spaces = Default::default();
+                self.convos = Default::default();
+                self.history_load_state = Default::default();
+                self.username = None;
+                self.synced = false;
+                self.connection = None;
+            }
             AppUpdate::UpdateSpaces(spaces) => {
                 self.spaces = spaces;
             }
@@ -419,17 +302,37 @@ impl App {
                     }
                 }
             }
+            AppUpdate::SetNews(entries) => {
+                for m in self.tools.iter_mut() {
+                    if m.is_news() {
+                        m.set_news(entries);
+                        break;
+                    }
+                }
+            }
+            AppUpdate::SetPins(pins) => {
+                for m in self.tools.iter_mut() {
+                    if m.is_pins() {
+                        m.set_pins(pins);
+                        break;
+                    }
+                }
+            }
+            AppUpdate::SetCalendarEvents(events) => {
+                for m in self.tools.iter_mut() {
+                    if m.is_calendar() {
+                        m.set_calendar_events(events);
+                        break;
+                    }
+                }
+            }
             AppUpdate::SetHistoryLoadState(state) => {
                 self.history_load_state = state;
             }
             AppUpdate::UpdateConvos(convos) => {
                 for m in self.tools.iter_mut() {
                     if m.is_chat() {
-                        m.update_chat_stats(ChatStats {
-                            total: convos.len() as u32,
-                            unread: 0,
-                            notifications: 0,
-                        });
+                        m.set_convos(convos.clone());
                         break;
                     }
                 }
@@ -446,6 +349,19 @@ impl App {
         &mut self.tools[self.index]
     }
     async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let (scope, typing) = match self.selected_widget {
+            Widget::Tools => (Scope::Navigation, false),
+            Widget::Logs => (Scope::Logs, false),
+            Widget::Main => (
+                self.selected_tool().scope(),
+                self.selected_tool().is_typing(),
+            ),
+        };
+        let Some(key) = self.keys.translate(scope, key, typing) else {
+            // unbound
+            return false;
+        };
+
         let handled = match self.selected_widget {
             Widget::Tools => match key.code {
                 KeyCode::Right => {
@@ -484,6 +400,10 @@ impl App {
                     self.next_widget();
                     return false;
                 }
+                KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.switch_account();
+                    return false;
+                }
                 _ => {}
             }
         }
@@ -491,6 +411,24 @@ impl App {
         false
     }
 
+    /// ask for the session of the next stored account
+    fn switch_account(&mut self) {
+        if self.accounts.len() < 2 {
+            info!("No other account stored");
+            return;
+        }
+        let current = self
+            .account
+            .as_ref()
+            .and_then(|a| self.accounts.iter().position(|s| s == a))
+            .unwrap_or_default();
+        let next = self.accounts[(current + 1) % self.accounts.len()].clone();
+        info!(account = next, "switching account");
+        if self.commands.send(AppCommand::SwitchAccount(next)).is_err() {
+            error!("session is gone, can't switch account");
+        }
+    }
+
     pub fn next_tool(&mut self) {
         self.index = (self.index + 1) % self.tools.len();
     }
@@ -504,7 +442,12 @@ impl App {
     }
 }
 
-pub async fn run_ui(rx: MpscReceiver<AppUpdate>, logs_fullscreen: bool) -> Result<()> {
+pub async fn run_ui(
+    rx: MpscReceiver<AppUpdate>,
+    commands: UnboundedSender<AppCommand>,
+    logs_fullscreen: bool,
+    keys: KeyBindings,
+) -> Result<()> {
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -513,7 +456,7 @@ pub async fn run_ui(rx: MpscReceiver<AppUpdate>, logs_fullscreen: bool) -> Resul
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = App::new(logs_fullscreen);
+    let app = App::new(logs_fullscreen, keys, commands);
     let res = run_app(&mut terminal, app, rx).await;
 
     // restore terminal
@@ -594,7 +537,7 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         .split(size);
 
     let block = Block::default()
-        .style(Style::default().bg(BG_DARKER).fg(SECONDARY))
+        .style(Style::default().bg(theme().darker).fg(theme().secondary))
         .title_alignment(Alignment::Center)
         .title(format!(" acter {:}", crate_version!()));
     f.render_widget(block, size);
@@ -602,26 +545,39 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     let titles = app
         .tools
         .iter()
-        .map(|t| Spans::from(vec![Span::styled(t.name(), Style::default().fg(TERTIARY))]))
+        .map(|t| {
+            Spans::from(vec![Span::styled(
+                t.name(),
+                Style::default().fg(theme().tertiary),
+            )])
+        })
         .collect();
 
     let mut block = Block::default().borders(Borders::ALL).title(" Tool ");
     if app.selected_widget == Widget::Tools {
-        block = block.border_style(Style::default().fg(PRIMARY));
+        block = block.border_style(Style::default().fg(theme().primary));
     }
     let tabs = Tabs::new(titles)
         .block(block)
         .select(app.index)
-        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(PRIMARY));
+        .highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(theme().primary),
+        );
 
     let mut titles = vec![
         Spans::from(vec![Span::styled(
             app.username.clone().unwrap_or("".to_owned()),
-            Style::default().fg(BG_GRAY),
+            Style::default().fg(theme().gray),
         )]),
         Spans::from(vec![Span::styled(
             format!("synced: {}", app.synced),
-            Style::default().fg(BG_GRAY),
+            Style::default().fg(theme().gray),
+        )]),
+        Spans::from(vec![Span::styled(
+            app.connection.clone().unwrap_or_default(),
+            Style::default().fg(theme().gray),
         )]),
     ];
     if !app.history_load_state.is_done_loading() {
@@ -631,22 +587,33 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
                 app.history_load_state.known_spaces.len(),
                 app.history_load_state.total_spaces()
             ),
-            Style::default().fg(BG_GRAY),
+            Style::default().fg(theme().gray),
         )]));
     } else {
         titles.push(Spans::from(vec![Span::styled(
             format!("{} Spaces", app.spaces.len()),
-            Style::default().fg(BG_GRAY),
+            Style::default().fg(theme().gray),
+        )]));
+    }
+
+    if app.accounts.len() > 1 {
+        titles.push(Spans::from(vec![Span::styled(
+            format!(
+                "{} accounts ({}: switch)",
+                app.accounts.len(),
+                app.keys.hint(Scope::Global, "switch_account")
+            ),
+            Style::default().fg(theme().gray),
         )]));
     }
 
     let status = Tabs::new(titles)
         .block(Block::default().borders(Borders::ALL).title(" Status "))
-        .style(Style::default().fg(BG_GRAY));
+        .style(Style::default().fg(theme().gray));
 
     let mut block = Block::default().borders(Borders::ALL).title(" Logs ");
     if app.selected_widget == Widget::Logs {
-        block = block.border_style(Style::default().fg(PRIMARY));
+        block = block.border_style(Style::default().fg(theme().primary));
     }
 
     let logger = TuiLoggerWidget::default()
@@ -667,15 +634,27 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     f.render_widget(logger, chunks[3]);
     let border_style = if app.selected_widget == Widget::Main {
-        Style::default().fg(PRIMARY)
+        Style::default().fg(theme().primary)
     } else {
         Style::default()
     };
 
     match app.selected_tool_mut() {
-        Tool::Tasks(tasks_state) if !tasks_state.task_lists.is_empty() => {
+        Tool::Tasks(tasks_state) if !tasks_state.is_empty() => {
             tasks_state.render(f, chunks[1], border_style);
         }
+        Tool::Chat(chat_state) => {
+            chat_state.render(f, chunks[1], border_style);
+        }
+        Tool::News(news_state) => {
+            news_state.render(f, chunks[1], border_style);
+        }
+        Tool::Pins(pins_state) => {
+            pins_state.render(f, chunks[1], border_style);
+        }
+        Tool::Calendar(calendar_state) => {
+            calendar_state.render(f, chunks[1], border_style);
+        }
         t => {
             let default_block = Block::default()
                 .title(format!(" {:} ", t.name()))
@@ -0,0 +1,352 @@
+use acter::CalendarEvent;
+use acter_core::events::calendar::EventLocation;
+use anyhow::Result;
+use chrono::{Datelike, Duration, TimeZone, Utc};
+use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::HashMap;
+use tracing::{error, trace};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use super::theme::theme;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+enum View {
+    /// everything that hasn't ended yet
+    #[default]
+    Agenda,
+    /// monday to sunday of the current week
+    Week,
+}
+
+#[derive(Debug)]
+enum Row {
+    Day(String),
+    Event(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct CalendarState {
+    events: Vec<CalendarEvent>,
+    // my rsvp per event id, `None` if I haven't answered yet
+    statuses: HashMap<String, Option<String>>,
+    rows: Vec<Row>,
+    list_state: ListState,
+    view: View,
+}
+
+fn location_lines(location: &EventLocation) -> Vec<Spans<'static>> {
+    let label = Style::default().fg(theme().gray);
+    let mut lines = vec![];
+    match location {
+        EventLocation::Physical {
+            name,
+            description,
+            coordinates,
+            uri,
+            ..
+        } => {
+            lines.push(Spans::from(vec![
+                Span::styled("📍 ", label),
+                Span::raw(name.clone().unwrap_or_else(|| "Somewhere".to_owned())),
+            ]));
+            if let Some(coordinates) = coordinates {
+                lines.push(Spans::from(Span::styled(
+                    format!("   {coordinates}"),
+                    label,
+                )));
+            }
+            if let Some(uri) = uri {
+                lines.push(Spans::from(Span::styled(
+                    format!("   {uri}"),
+                    Style::default().fg(theme().secondary),
+                )));
+            }
+            if let Some(description) = description {
+                lines.push(Spans::from(format!("   {}", description.body)));
+            }
+        }
+        EventLocation::Virtual {
+            uri,
+            name,
+            description,
+            ..
+        } => {
+            lines.push(Spans::from(vec![
+                Span::styled("🌐 ", label),
+                Span::raw(name.clone().unwrap_or_else(|| "Online".to_owned())),
+            ]));
+            lines.push(Spans::from(Span::styled(
+                format!("   {uri}"),
+                Style::default().fg(theme().secondary),
+            )));
+            if let Some(description) = description {
+                lines.push(Spans::from(format!("   {}", description.body)));
+            }
+        }
+    }
+    lines
+}
+
+impl CalendarState {
+    pub fn name(&self) -> String {
+        if self.events.is_empty() {
+            "Calendar".to_owned()
+        } else {
+            format!("Calendar ({:})", self.events.len())
+        }
+    }
+
+    pub fn set_events(&mut self, mut events: Vec<CalendarEvent>) {
+        events.sort_by_key(|e| e.utc_start());
+        self.events = events;
+        self.rebuild();
+    }
+
+    /// loads the rsvp status of any event we haven't checked yet
+    pub async fn tick(&mut self) {
+        for event in self.events.iter() {
+            let event_id = event.event_id().to_string();
+            if self.statuses.contains_key(&event_id) {
+                continue;
+            }
+            let status = match event.rsvp_manager().await {
+                Ok(manager) => match manager.my_status().await {
+                    Ok(status) => status.text(),
+                    Err(error) => {
+                        error!(?error, event_id, "reading rsvp status failed");
+                        None
+                    }
+                },
+                Err(error) => {
+                    error!(?error, event_id, "loading rsvp failed");
+                    None
+                }
+            };
+            self.statuses.insert(event_id, status);
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let selected = self.selected().map(|e| e.event_id());
+        let now = Utc::now();
+        let monday = now - Duration::days(now.weekday().num_days_from_monday() as i64);
+        let week_start = Utc.from_utc_datetime(
+            &monday
+                .date_naive()
+                .and_hms_opt(0, 0, 0)
+                .expect("midnight is a valid time"),
+        );
+        let week_end = week_start + Duration::weeks(1);
+
+        let mut rows = vec![];
+        let mut day = None;
+        for (idx, event) in self.events.iter().enumerate() {
+            let visible = match self.view {
+                View::Agenda => event.utc_end() >= now,
+                View::Week => event.utc_start() < week_end && event.utc_end() >= week_start,
+            };
+            if !visible {
+                continue;
+            }
+            let event_day = event.utc_start().format("%A, %Y-%m-%d").to_string();
+            if day.as_ref() != Some(&event_day) {
+                rows.push(Row::Day(event_day.clone()));
+                day = Some(event_day);
+            }
+            rows.push(Row::Event(idx));
+        }
+
+        let row = selected.and_then(|event_id| {
+            rows.iter().position(|r| match r {
+                Row::Event(idx) => self.events[*idx].event_id() == event_id,
+                Row::Day(_) => false,
+            })
+        });
+        self.rows = rows;
+        self.list_state.select(row);
+    }
+
+    fn selected(&self) -> Option<&CalendarEvent> {
+        match self.list_state.selected().and_then(|r| self.rows.get(r)) {
+            Some(Row::Event(idx)) => self.events.get(*idx),
+            _ => None,
+        }
+    }
+
+    // moves the selection, jumping over the day titles
+    fn select_next(&mut self) {
+        let start = self
+            .list_state
+            .selected()
+            .map(|r| r + 1)
+            .unwrap_or_default();
+        if let Some(offset) = self.rows[start.min(self.rows.len())..]
+            .iter()
+            .position(|r| matches!(r, Row::Event(_)))
+        {
+            self.list_state.select(Some(start + offset));
+        }
+    }
+
+    fn select_prev(&mut self) {
+        let Some(current) = self.list_state.selected() else {
+            return;
+        };
+        if let Some(row) = self.rows[..current]
+            .iter()
+            .rposition(|r| matches!(r, Row::Event(_)))
+        {
+            self.list_state.select(Some(row));
+        }
+    }
+
+    async fn send_rsvp(event: &CalendarEvent, status: &str) -> Result<()> {
+        let manager = event.rsvp_manager().await?;
+        let event_id = manager
+            .rsvp_draft()?
+            .status(status.to_owned())
+            .send()
+            .await?;
+        trace!(?event_id, status, "rsvp sent");
+        Ok(())
+    }
+
+    async fn rsvp(&mut self, status: &str) {
+        let Some(event) = self.selected().cloned() else {
+            return;
+        };
+        match Self::send_rsvp(&event, status).await {
+            Ok(()) => {
+                self.statuses
+                    .insert(event.event_id().to_string(), Some(status.to_owned()));
+            }
+            Err(error) => error!(?error, status, "sending rsvp failed"),
+        }
+    }
+
+    pub async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Down => self.select_next(),
+            KeyCode::Up => self.select_prev(),
+            KeyCode::Char('y') => self.rsvp("Yes").await,
+            KeyCode::Char('m') => self.rsvp("Maybe").await,
+            KeyCode::Char('n') => self.rsvp("No").await,
+            KeyCode::Char('w') => {
+                self.view = match self.view {
+                    View::Agenda => View::Week,
+                    View::Week => View::Agenda,
+                };
+                self.rebuild();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn status_of(&self, event: &CalendarEvent) -> Option<&str> {
+        self.statuses
+            .get(&event.event_id().to_string())
+            .and_then(|s| s.as_deref())
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(area);
+
+        let items = self
+            .rows
+            .iter()
+            .map(|row| match row {
+                Row::Day(day) => ListItem::new(Spans::from(Span::styled(
+                    day.clone(),
+                    Style::default()
+                        .fg(theme().secondary)
+                        .add_modifier(Modifier::BOLD),
+                ))),
+                Row::Event(idx) => {
+                    let event = &self.events[*idx];
+                    let marker = match self.status_of(event) {
+                        Some("Yes") => "✔",
+                        Some("Maybe") => "?",
+                        Some("No") => "✘",
+                        _ => " ",
+                    };
+                    let time = if event.show_without_time() {
+                        "all day".to_owned()
+                    } else {
+                        event.utc_start().format("%H:%M").to_string()
+                    };
+                    ListItem::new(Text::from(format!("  [{marker}] {time} {}", event.title())))
+                }
+            })
+            .collect::<Vec<_>>();
+        let title = match self.view {
+            View::Agenda => " Agenda 📅 (w: this week, y/m/n: rsvp) ",
+            View::Week => " This week 📅 (w: agenda, y/m/n: rsvp) ",
+        };
+        let ls = List::new(items)
+            .highlight_style(
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(theme().primary),
+            )
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(block_border_style),
+            );
+        f.render_stateful_widget(ls, chunks[0], &mut self.list_state);
+
+        let label = Style::default().fg(theme().gray);
+        let mut lines = vec![];
+        if let Some(event) = self.selected() {
+            lines.push(Spans::from(Span::styled(
+                event.title(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            lines.push(Spans::from(Span::styled(
+                format!(
+                    "{} - {}",
+                    event.utc_start().format("%Y-%m-%d %H:%M"),
+                    event.utc_end().format("%Y-%m-%d %H:%M UTC")
+                ),
+                label,
+            )));
+            let status = self
+                .status_of(event)
+                .map(|s| s.to_owned())
+                .unwrap_or_else(|| "not answered".to_owned());
+            lines.push(Spans::from(vec![
+                Span::styled("RSVP: ", label),
+                Span::styled(status, Style::default().fg(theme().tertiary)),
+            ]));
+            lines.push(Spans::from(""));
+            if let Some(description) = event.description() {
+                lines.extend(
+                    description
+                        .body()
+                        .lines()
+                        .map(|l| Spans::from(l.to_owned())),
+                );
+                lines.push(Spans::from(""));
+            }
+            for location in event.locations.iter() {
+                lines.extend(location_lines(location));
+            }
+        }
+        let details = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(details, chunks[1]);
+    }
+}
@@ -0,0 +1,683 @@
+use acter::{
+    new_compose_draft, Convo, RoomMessage, TimelineDiff, TimelineDiffAction, TimelineStream,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use futures::{pin_mut, stream::StreamExt};
+use std::{collections::HashSet, fmt};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    task::JoinHandle,
+};
+use tracing::{error, trace};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use super::{next, prev, theme::theme};
+
+/// how many events to ask the server for per back-pagination
+const PAGINATION_SIZE: u16 = 20;
+/// the reaction sent with `ctrl+t`
+const QUICK_REACTION: &str = "👍";
+
+struct OpenConvo {
+    convo: Convo,
+    stream: TimelineStream,
+    diffs: UnboundedReceiver<Vec<TimelineDiff>>,
+    listener: JoinHandle<()>,
+    messages: Vec<RoomMessage>,
+    messages_list_state: ListState,
+    // keep the latest message selected as new ones come in
+    follow: bool,
+    has_more: bool,
+    // event ids of the messages pinned in the convo and starred by us
+    pinned: HashSet<String>,
+    starred: HashSet<String>,
+}
+
+impl fmt::Debug for OpenConvo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenConvo")
+            .field("convo", &self.convo.get_room_id_str())
+            .field("messages", &self.messages.len())
+            .field("has_more", &self.has_more)
+            .finish()
+    }
+}
+
+impl Drop for OpenConvo {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+impl OpenConvo {
+    async fn new(convo: Convo) -> anyhow::Result<Self> {
+        let stream = convo.timeline_stream().await?;
+        let (sender, diffs) = unbounded_channel();
+        let diff_stream = stream.diff_batch_rx();
+        let listener = tokio::spawn(async move {
+            pin_mut!(diff_stream);
+            while let Some(diffs) = diff_stream.next().await {
+                if sender.send(diffs).is_err() {
+                    // the convo was closed
+                    break;
+                }
+            }
+        });
+        let mut open = OpenConvo {
+            convo,
+            stream,
+            diffs,
+            listener,
+            messages: Default::default(),
+            messages_list_state: Default::default(),
+            follow: true,
+            has_more: true,
+            pinned: Default::default(),
+            starred: Default::default(),
+        };
+        open.paginate().await;
+        open.refresh_marks().await;
+        Ok(open)
+    }
+
+    async fn refresh_marks(&mut self) {
+        match self.convo.pinned_event_ids().await {
+            Ok(pinned) => self.pinned = pinned.into_iter().collect(),
+            Err(error) => error!(?error, "fetching pinned messages failed"),
+        }
+        match self.convo.starred_event_ids().await {
+            Ok(starred) => self.starred = starred.into_iter().collect(),
+            Err(error) => error!(?error, "fetching starred messages failed"),
+        }
+    }
+
+    async fn paginate(&mut self) {
+        if !self.has_more {
+            return;
+        }
+        match self.stream.paginate_backwards(PAGINATION_SIZE).await {
+            Ok(has_more) => self.has_more = has_more,
+            Err(error) => error!(?error, "back-paginating failed"),
+        }
+    }
+
+    fn tick(&mut self) {
+        let mut update = false;
+        while let Ok(diffs) = self.diffs.try_recv() {
+            for diff in diffs {
+                self.apply(diff);
+            }
+            update = true;
+        }
+        if update && self.follow && !self.messages.is_empty() {
+            self.messages_list_state
+                .select(Some(self.messages.len() - 1));
+        }
+    }
+
+    // changes before the selection move it along
+    fn shift_selection(&mut self, idx: usize, by: isize) {
+        if let Some(selected) = self.messages_list_state.selected() {
+            if idx <= selected {
+                let moved = (selected as isize + by).max(0) as usize;
+                self.messages_list_state.select(Some(moved));
+            }
+        }
+    }
+
+    fn apply(&mut self, diff: TimelineDiff) {
+        match diff.action_kind() {
+            TimelineDiffAction::Append => {
+                if let Some(values) = diff.values() {
+                    self.messages.extend(values);
+                }
+            }
+            TimelineDiffAction::Insert => {
+                if let (Some(idx), Some(value)) = (diff.index(), diff.value()) {
+                    if idx <= self.messages.len() {
+                        self.messages.insert(idx, value);
+                        self.shift_selection(idx, 1);
+                    }
+                }
+            }
+            TimelineDiffAction::Set => {
+                if let (Some(idx), Some(value)) = (diff.index(), diff.value()) {
+                    if let Some(msg) = self.messages.get_mut(idx) {
+                        *msg = value;
+                    }
+                }
+            }
+            TimelineDiffAction::Remove => {
+                if let Some(idx) = diff.index() {
+                    if idx < self.messages.len() {
+                        self.messages.remove(idx);
+                        self.shift_selection(idx, -1);
+                    }
+                }
+            }
+            TimelineDiffAction::PushBack => {
+                if let Some(value) = diff.value() {
+                    self.messages.push(value);
+                }
+            }
+            TimelineDiffAction::PushFront => {
+                if let Some(value) = diff.value() {
+                    self.messages.insert(0, value);
+                    self.shift_selection(0, 1);
+                }
+            }
+            TimelineDiffAction::PopBack => {
+                self.messages.pop();
+            }
+            TimelineDiffAction::PopFront => {
+                if !self.messages.is_empty() {
+                    self.messages.remove(0);
+                    self.shift_selection(0, -1);
+                }
+            }
+            TimelineDiffAction::Truncate => {
+                if let Some(len) = diff.index() {
+                    self.messages.truncate(len);
+                }
+            }
+            TimelineDiffAction::Clear => {
+                self.messages.clear();
+                self.messages_list_state.select(None);
+            }
+            TimelineDiffAction::Reset => {
+                self.messages = diff.values().unwrap_or_default();
+                self.messages_list_state.select(None);
+            }
+        }
+        if let Some(s) = self.messages_list_state.selected() {
+            if s >= self.messages.len() {
+                self.messages_list_state
+                    .select(self.messages.len().checked_sub(1));
+            }
+        }
+    }
+
+    fn selected_event_id(&self) -> Option<String> {
+        self.messages_list_state
+            .selected()
+            .and_then(|idx| self.messages.get(idx))
+            .and_then(|msg| msg.event_item())
+            .map(|item| item.event_id())
+    }
+
+    async fn select_prev(&mut self) {
+        self.follow = false;
+        if self.messages_list_state.selected().unwrap_or_default() == 0 {
+            // reached the top, load more
+            self.paginate().await;
+        } else {
+            prev(&mut self.messages_list_state);
+        }
+    }
+
+    fn select_next(&mut self) {
+        next(&mut self.messages_list_state, self.messages.len());
+        self.follow = self.messages_list_state.selected() == self.messages.len().checked_sub(1);
+    }
+}
+
+#[derive(Debug)]
+struct ReplyTo {
+    event_id: String,
+    preview: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ChatState {
+    convos_list_state: ListState,
+    convos: Vec<Convo>,
+    open: Option<OpenConvo>,
+    compose: String,
+    reply_to: Option<ReplyTo>,
+}
+
+fn convo_name(convo: &Convo) -> String {
+    convo.name().unwrap_or_else(|| convo.get_room_id_str())
+}
+
+fn convo_item(convo: &Convo) -> ListItem<'static> {
+    let mut spans = vec![Span::raw(convo_name(convo))];
+    if convo.unread_count() > 0 {
+        let color = if convo.highlight_count() > 0 {
+            theme().primary
+        } else {
+            theme().tertiary
+        };
+        spans.push(Span::styled(
+            format!(" ({})", convo.unread_count()),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ));
+    }
+    ListItem::new(Spans::from(spans))
+}
+
+fn message_body(msg: &RoomMessage) -> Option<(String, String)> {
+    let item = msg.event_item()?;
+    let body = if let Some(text) = item.text_desc() {
+        text.body()
+    } else if let Some(image) = item.image_desc() {
+        format!("[image] {}", image.name())
+    } else if let Some(audio) = item.audio_desc() {
+        format!("[audio] {}", audio.name())
+    } else if let Some(video) = item.video_desc() {
+        format!("[video] {}", video.name())
+    } else if let Some(file) = item.file_desc() {
+        format!("[file] {}", file.name())
+    } else if let Some(location) = item.location_desc() {
+        format!("[location] {}", location.body())
+    } else if let Some(poll) = item.poll_desc() {
+        format!("[poll] {} ({} voted)", poll.question(), poll.voters())
+    } else {
+        format!("[{}]", item.event_type())
+    };
+    Some((item.sender(), body))
+}
+
+fn reply_preview(msg: &RoomMessage) -> String {
+    message_body(msg)
+        .map(|(sender, body)| format!("{sender}: {}", body.lines().next().unwrap_or_default()))
+        .unwrap_or_default()
+}
+
+fn message_item(
+    msg: &RoomMessage,
+    pinned: &HashSet<String>,
+    starred: &HashSet<String>,
+) -> ListItem<'static> {
+    if let Some(item) = msg.virtual_item() {
+        let line = match item.event_type().as_str() {
+            "ReadMarker" => "── new ──".to_owned(),
+            _ => format!("── {} ──", item.desc().unwrap_or_default()),
+        };
+        return ListItem::new(Spans::from(Span::styled(
+            line,
+            Style::default().fg(theme().gray),
+        )));
+    }
+    let Some(item) = msg.event_item() else {
+        return ListItem::new("");
+    };
+    let (sender, body) = message_body(msg).unwrap_or_default();
+    let mut lines = body.lines();
+    let event_id = item.event_id();
+    let mut first = vec![];
+    if pinned.contains(&event_id) {
+        first.push(Span::raw("📌 "));
+    }
+    if starred.contains(&event_id) {
+        first.push(Span::raw("⭐ "));
+    }
+    first.push(Span::styled(
+        sender,
+        Style::default()
+            .fg(theme().secondary)
+            .add_modifier(Modifier::BOLD),
+    ));
+    if item.in_reply_to().is_some() {
+        first.push(Span::styled(" ↪", Style::default().fg(theme().gray)));
+    }
+    first.push(Span::raw(": "));
+    first.push(Span::raw(lines.next().unwrap_or_default().to_owned()));
+
+    let mut text = Text::from(Spans::from(first));
+    for line in lines {
+        text.lines.push(Spans::from(format!("  {line}")));
+    }
+
+    let reactions = item
+        .reaction_keys()
+        .into_iter()
+        .map(|key| {
+            let count = item
+                .reaction_records(key.clone())
+                .map(|r| r.len())
+                .unwrap_or_default();
+            format!("{key} {count}")
+        })
+        .collect::<Vec<_>>();
+    if !reactions.is_empty() {
+        text.lines.push(Spans::from(Span::styled(
+            format!("  [{}]", reactions.join("] [")),
+            Style::default().fg(theme().tertiary),
+        )));
+    }
+    ListItem::new(text)
+}
+
+impl ChatState {
+    pub fn name(&self) -> String {
+        if self.convos.is_empty() {
+            "Chat".to_owned()
+        } else {
+            format!("Chat ({:})", self.convos.len())
+        }
+    }
+
+    /// whether keys go into the compose box
+    pub fn is_typing(&self) -> bool {
+        self.open.is_some()
+    }
+
+    pub fn set_convos(&mut self, convos: Vec<Convo>) {
+        // keep the selection on the same convo
+        let selected = self
+            .convos_list_state
+            .selected()
+            .and_then(|idx| self.convos.get(idx))
+            .map(|c| c.get_room_id_str());
+        self.convos = convos;
+        let idx = selected.and_then(|room_id| {
+            self.convos
+                .iter()
+                .position(|c| c.get_room_id_str() == room_id)
+        });
+        self.convos_list_state.select(idx);
+    }
+
+    pub fn tick(&mut self) {
+        if let Some(open) = self.open.as_mut() {
+            open.tick();
+        }
+    }
+
+    async fn open_selected(&mut self) {
+        let Some(convo) = self
+            .convos_list_state
+            .selected()
+            .and_then(|idx| self.convos.get(idx).cloned())
+        else {
+            return;
+        };
+        trace!(room_id = convo.get_room_id_str(), "opening convo");
+        if convo.unread_count() > 0 {
+            if let Err(error) = convo.mark_as_read().await {
+                error!(?error, "marking convo as read failed");
+            }
+        }
+        match OpenConvo::new(convo).await {
+            Ok(open) => {
+                self.compose.clear();
+                self.reply_to = None;
+                match open.convo.draft().await {
+                    Ok(Some(draft)) => {
+                        self.compose = draft.text();
+                        self.reply_to = draft.reply_to().map(|event_id| {
+                            let preview = open
+                                .messages
+                                .iter()
+                                .find(|m| {
+                                    m.event_item().map(|i| i.event_id()).as_ref() == Some(&event_id)
+                                })
+                                .map(reply_preview)
+                                .unwrap_or_else(|| event_id.clone());
+                            ReplyTo { event_id, preview }
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(error) => error!(?error, "loading draft failed"),
+                }
+                self.open = Some(open);
+            }
+            Err(error) => error!(?error, "opening convo failed"),
+        }
+    }
+
+    // keeps what was typed as draft for next time
+    async fn close(&mut self) {
+        if let Some(open) = self.open.take() {
+            let mut draft = new_compose_draft(self.compose.clone(), true);
+            let reply_to = self.reply_to.as_ref().map(|r| r.event_id.clone());
+            if let Err(error) = draft.set_reply_to(reply_to) {
+                error!(?error, "invalid reply in draft");
+            }
+            if let Err(error) = open.convo.save_draft(Box::new(draft)).await {
+                error!(?error, "saving draft failed");
+            }
+        }
+        self.compose.clear();
+        self.reply_to = None;
+    }
+
+    fn start_reply(&mut self) {
+        let Some(open) = &self.open else {
+            return;
+        };
+        let Some(msg) = open
+            .messages_list_state
+            .selected()
+            .and_then(|idx| open.messages.get(idx))
+        else {
+            return;
+        };
+        let Some(event_id) = msg.event_item().map(|item| item.event_id()) else {
+            return;
+        };
+        let preview = reply_preview(msg);
+        self.reply_to = Some(ReplyTo { event_id, preview });
+    }
+
+    async fn react(&mut self) {
+        let Some(open) = &self.open else {
+            return;
+        };
+        let Some(event_id) = open.selected_event_id() else {
+            return;
+        };
+        if let Err(error) = open
+            .convo
+            .send_reaction(event_id, QUICK_REACTION.to_owned())
+            .await
+        {
+            error!(?error, "sending reaction failed");
+        }
+    }
+
+    async fn toggle_pin(&mut self) {
+        let Some(open) = self.open.as_mut() else {
+            return;
+        };
+        let Some(event_id) = open.selected_event_id() else {
+            return;
+        };
+        let resp = if open.pinned.contains(&event_id) {
+            open.convo.unpin_message(event_id).await
+        } else {
+            open.convo.pin_message(event_id).await
+        };
+        if let Err(error) = resp {
+            error!(?error, "toggling pin failed");
+        }
+        open.refresh_marks().await;
+    }
+
+    async fn toggle_star(&mut self) {
+        let Some(open) = self.open.as_mut() else {
+            return;
+        };
+        let Some(event_id) = open.selected_event_id() else {
+            return;
+        };
+        let resp = if open.starred.contains(&event_id) {
+            open.convo.unstar_message(event_id).await
+        } else {
+            open.convo.star_message(event_id).await
+        };
+        if let Err(error) = resp {
+            error!(?error, "toggling star failed");
+        }
+        open.refresh_marks().await;
+    }
+
+    async fn send(&mut self) {
+        let Some(open) = self.open.as_mut() else {
+            return;
+        };
+        if self.compose.trim().is_empty() {
+            return;
+        }
+        let msg = self.compose.clone();
+        let resp = match &self.reply_to {
+            Some(reply) => {
+                open.convo
+                    .send_text_reply(msg, reply.event_id.clone(), None)
+                    .await
+            }
+            None => open.convo.send_formatted_message(msg).await,
+        };
+        match resp {
+            Ok(event_id) => {
+                trace!(?event_id, "message sent");
+                self.compose.clear();
+                self.reply_to = None;
+                open.follow = true;
+                if let Err(error) = open.convo.clear_draft().await {
+                    error!(?error, "clearing draft failed");
+                }
+            }
+            Err(error) => {
+                // keep the composed text around for another try
+                error!(?error, "sending message failed");
+            }
+        }
+    }
+
+    pub async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.open.is_none() {
+            return match key.code {
+                KeyCode::Down => {
+                    next(&mut self.convos_list_state, self.convos.len());
+                    true
+                }
+                KeyCode::Up => {
+                    prev(&mut self.convos_list_state);
+                    true
+                }
+                KeyCode::Enter | KeyCode::Right => {
+                    self.open_selected().await;
+                    true
+                }
+                _ => false,
+            };
+        }
+
+        let Some(open) = self.open.as_mut() else {
+            return false;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Up => open.select_prev().await,
+            KeyCode::Down => open.select_next(),
+            KeyCode::PageUp => {
+                open.follow = false;
+                open.paginate().await;
+            }
+            KeyCode::Char('r') if ctrl => self.start_reply(),
+            KeyCode::Char('t') if ctrl => self.react().await,
+            KeyCode::Char('p') if ctrl => self.toggle_pin().await,
+            KeyCode::Char('s') if ctrl => self.toggle_star().await,
+            KeyCode::Char(c) => self.compose.push(c),
+            KeyCode::Backspace => {
+                self.compose.pop();
+            }
+            KeyCode::Enter => self.send().await,
+            KeyCode::Esc => {
+                if self.reply_to.is_some() {
+                    self.reply_to = None;
+                } else {
+                    self.close().await;
+                }
+            }
+            KeyCode::Left if self.compose.is_empty() => self.close().await,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
+            .split(area);
+
+        let convos = List::new(self.convos.iter().map(convo_item).collect::<Vec<_>>())
+            .highlight_style(
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(theme().primary),
+            )
+            .block(
+                Block::default()
+                    .title(" Chats 💬")
+                    .borders(Borders::ALL)
+                    .border_style(if self.open.is_none() {
+                        block_border_style
+                    } else {
+                        Style::default()
+                    }),
+            );
+        f.render_stateful_widget(convos, chunks[0], &mut self.convos_list_state);
+
+        let Some(open) = self.open.as_mut() else {
+            let hint = Paragraph::new("Select a chat and press enter")
+                .style(Style::default().fg(theme().gray))
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(hint, chunks[1]);
+            return;
+        };
+
+        let compose_height = if self.reply_to.is_some() { 4 } else { 3 };
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(compose_height)].as_ref())
+            .split(chunks[1]);
+
+        let mut title = format!(" {:} ", convo_name(&open.convo));
+        if open.has_more {
+            title.push_str("(↑ for more) ");
+        }
+        let items = open
+            .messages
+            .iter()
+            .map(|msg| message_item(msg, &open.pinned, &open.starred))
+            .collect::<Vec<_>>();
+        let timeline = List::new(items)
+            .highlight_style(Style::default().bg(theme().dark))
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(block_border_style),
+            );
+        f.render_stateful_widget(timeline, right[0], &mut open.messages_list_state);
+
+        let mut lines = vec![];
+        if let Some(reply) = &self.reply_to {
+            lines.push(Spans::from(Span::styled(
+                format!("↪ {}", reply.preview),
+                Style::default().fg(theme().gray),
+            )));
+        }
+        lines.push(Spans::from(format!("{}▏", self.compose)));
+        let compose = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .title(
+                    " Message (enter: send, ctrl+r: reply, ctrl+t: 👍, ctrl+p: pin, ctrl+s: star) ",
+                )
+                .borders(Borders::ALL)
+                .border_style(block_border_style),
+        );
+        f.render_widget(compose, right[1]);
+    }
+}
@@ -0,0 +1,405 @@
+use anyhow::{bail, Context, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// Where a key binding applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// everywhere, unless the focused tool handles the key
+    Global,
+    /// moving around lists, shared by all tools
+    Navigation,
+    Logs,
+    Tasks,
+    Chat,
+    News,
+    Pins,
+    Calendar,
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "global" => Scope::Global,
+            "navigation" => Scope::Navigation,
+            "logs" => Scope::Logs,
+            "tasks" => Scope::Tasks,
+            "chat" => Scope::Chat,
+            "news" => Scope::News,
+            "pins" => Scope::Pins,
+            "calendar" => Scope::Calendar,
+            _ => bail!("Unknown key scope `{s}`"),
+        })
+    }
+}
+
+/// Additional bindings on top of the defaults
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    /// `hjkl` navigation, `ctrl+u` for older messages and `q` to quit
+    Vi,
+}
+
+/// A key combination, as written in the config: `j`, `ctrl+r`, `shift+up`, `enter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn of(event: &KeyEvent) -> Self {
+        let mut modifiers = event.modifiers;
+        if let KeyCode::Char(_) = event.code {
+            // the case of the character already tells us about shift
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+
+    fn event(self) -> KeyEvent {
+        KeyEvent::new(self.code, self.modifiers)
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_))
+            && !self
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts = s.split('+').collect::<Vec<_>>();
+        // `+` on its own is a key, too
+        let name = match parts.pop() {
+            Some("") if s.ends_with('+') => {
+                parts.pop();
+                "+"
+            }
+            Some(name) => name,
+            None => bail!("Empty key"),
+        };
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("Unknown modifier `{modifier}` in `{s}`"),
+            };
+        }
+        let code = match name.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            f if f.len() > 1 && f.starts_with('f') => KeyCode::F(
+                f[1..]
+                    .parse()
+                    .with_context(|| format!("Unknown key `{s}`"))?,
+            ),
+            _ => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => bail!("Unknown key `{s}`"),
+                }
+            }
+        };
+        Ok(Key { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            code => {
+                let name = format!("{code:?}").to_lowercase();
+                f.write_str(&name)
+            }
+        }
+    }
+}
+
+/// One key or a list of keys for an action
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    fn keys(&self) -> Result<Vec<Key>> {
+        match self {
+            KeyList::One(key) => Ok(vec![key.parse()?]),
+            KeyList::Many(keys) => keys.iter().map(|k| k.parse()).collect(),
+        }
+    }
+}
+
+/// The actions of every scope and the key the tools react to
+const DEFAULTS: &[(Scope, &str, &str)] = &[
+    (Scope::Global, "quit", "esc"),
+    (Scope::Global, "next_widget", "tab"),
+    (Scope::Global, "switch_account", "ctrl+a"),
+    (Scope::Navigation, "up", "up"),
+    (Scope::Navigation, "down", "down"),
+    (Scope::Navigation, "left", "left"),
+    (Scope::Navigation, "right", "right"),
+    (Scope::Navigation, "select", "enter"),
+    (Scope::Navigation, "back", "esc"),
+    (Scope::Logs, "fullscreen", "f"),
+    (Scope::Tasks, "toggle", "space"),
+    (Scope::Tasks, "new", "n"),
+    (Scope::Tasks, "edit", "e"),
+    (Scope::Tasks, "move_up", "shift+up"),
+    (Scope::Tasks, "move_down", "shift+down"),
+    (Scope::Chat, "reply", "ctrl+r"),
+    (Scope::Chat, "react", "ctrl+t"),
+    (Scope::Chat, "pin", "ctrl+p"),
+    (Scope::Chat, "star", "ctrl+s"),
+    (Scope::Chat, "older", "pageup"),
+    (Scope::News, "next_slide", "space"),
+    (Scope::News, "download", "d"),
+    (Scope::Pins, "open", "o"),
+    (Scope::Calendar, "rsvp_yes", "y"),
+    (Scope::Calendar, "rsvp_maybe", "m"),
+    (Scope::Calendar, "rsvp_no", "n"),
+    (Scope::Calendar, "toggle_week", "w"),
+];
+
+const VI_PRESET: &[(Scope, &str, &str)] = &[
+    (Scope::Global, "quit", "q"),
+    (Scope::Navigation, "up", "k"),
+    (Scope::Navigation, "down", "j"),
+    (Scope::Navigation, "left", "h"),
+    (Scope::Navigation, "right", "l"),
+    (Scope::Chat, "older", "ctrl+u"),
+    (Scope::Tasks, "move_up", "K"),
+    (Scope::Tasks, "move_down", "J"),
+];
+
+#[derive(Debug)]
+struct Binding {
+    scope: Scope,
+    action: &'static str,
+    /// what the tools understand
+    canonical: Key,
+    /// what the user presses
+    keys: Vec<Key>,
+}
+
+/// Translates the keys pressed into those the tools react to
+#[derive(Debug)]
+pub struct KeyBindings {
+    bindings: Vec<Binding>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::new(Preset::Default, &Default::default())
+            .expect("default key bindings are valid")
+    }
+}
+
+impl KeyBindings {
+    pub fn new(
+        preset: Preset,
+        overrides: &HashMap<String, HashMap<String, KeyList>>,
+    ) -> Result<Self> {
+        let mut bindings = DEFAULTS
+            .iter()
+            .map(|(scope, action, key)| {
+                let key = key.parse::<Key>()?;
+                Ok(Binding {
+                    scope: *scope,
+                    action: *action,
+                    canonical: key,
+                    keys: vec![key],
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Preset::Vi = preset {
+            for (scope, action, key) in VI_PRESET {
+                let binding = bindings
+                    .iter_mut()
+                    .find(|b| b.scope == *scope && b.action == *action)
+                    .expect("presets only use known actions");
+                binding.keys.push(key.parse()?);
+            }
+        }
+
+        for (scope_name, actions) in overrides {
+            let scope = scope_name.parse::<Scope>()?;
+            for (action, keys) in actions {
+                let Some(binding) = bindings
+                    .iter_mut()
+                    .find(|b| b.scope == scope && b.action == action.as_str())
+                else {
+                    bail!("Unknown action `{action}` in keys.{scope_name}");
+                };
+                binding.keys = keys
+                    .keys()
+                    .with_context(|| format!("Invalid keys for keys.{scope_name}.{action}"))?;
+            }
+        }
+
+        Ok(KeyBindings { bindings })
+    }
+
+    /// The key the tool of `scope` should handle for what was pressed, `None`
+    /// if the key has been unbound. While `typing`, plain characters are
+    /// passed on as they are.
+    pub fn translate(&self, scope: Scope, event: KeyEvent, typing: bool) -> Option<KeyEvent> {
+        let key = Key::of(&event);
+        if typing && key.is_text() {
+            return Some(event);
+        }
+        let in_scope = |b: &&Binding| {
+            b.scope == scope || b.scope == Scope::Navigation || b.scope == Scope::Global
+        };
+        // the tool's own bindings win over navigation, which wins over global
+        let mut candidates = self.bindings.iter().filter(in_scope).collect::<Vec<_>>();
+        candidates.sort_by_key(|b| match b.scope {
+            Scope::Global => 2,
+            Scope::Navigation => 1,
+            _ => 0,
+        });
+
+        if let Some(binding) = candidates.iter().find(|b| b.keys.contains(&key)) {
+            return Some(binding.canonical.event());
+        }
+        if candidates.iter().any(|b| b.canonical == key) {
+            // a default key that has been bound to something else
+            return None;
+        }
+        Some(event)
+    }
+
+    /// The keys bound to `action`, for hints in the ui
+    pub fn hint(&self, scope: Scope, action: &str) -> String {
+        self.bindings
+            .iter()
+            .find(|b| b.scope == scope && b.action == action)
+            .map(|b| {
+                b.keys
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_keys() -> Result<()> {
+        assert_eq!(
+            "ctrl+r".parse::<Key>()?,
+            Key {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL
+            }
+        );
+        assert_eq!(
+            "shift+up".parse::<Key>()?,
+            Key {
+                code: KeyCode::Up,
+                modifiers: KeyModifiers::SHIFT
+            }
+        );
+        assert_eq!("space".parse::<Key>()?.code, KeyCode::Char(' '));
+        assert_eq!("f5".parse::<Key>()?.code, KeyCode::F(5));
+        assert_eq!("ctrl++".parse::<Key>()?.code, KeyCode::Char('+'));
+        assert!("hyper+x".parse::<Key>().is_err());
+        assert!("nope".parse::<Key>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn vi_preset_navigates() -> Result<()> {
+        let keys = KeyBindings::new(Preset::Vi, &Default::default())?;
+        let j = press(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(
+            keys.translate(Scope::Tasks, j, false).map(|e| e.code),
+            Some(KeyCode::Down)
+        );
+        // but not while writing a message
+        assert_eq!(
+            keys.translate(Scope::Chat, j, true).map(|e| e.code),
+            Some(KeyCode::Char('j'))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn overrides_replace_defaults() -> Result<()> {
+        let overrides = toml::from_str("[tasks]\nnew = [\"a\"]")?;
+        let keys = KeyBindings::new(Preset::Default, &overrides)?;
+        let a = press(KeyCode::Char('a'), KeyModifiers::NONE);
+        let n = press(KeyCode::Char('n'), KeyModifiers::NONE);
+        assert_eq!(
+            keys.translate(Scope::Tasks, a, false).map(|e| e.code),
+            Some(KeyCode::Char('n'))
+        );
+        assert_eq!(keys.translate(Scope::Tasks, n, false), None);
+        // other tools still get their `n`
+        assert_eq!(
+            keys.translate(Scope::Calendar, n, false).map(|e| e.code),
+            Some(KeyCode::Char('n'))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_actions_are_rejected() {
+        let overrides = toml::from_str("[tasks]\nfly = \"x\"").unwrap();
+        assert!(KeyBindings::new(Preset::Default, &overrides).is_err());
+    }
+}
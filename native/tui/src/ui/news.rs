@@ -0,0 +1,236 @@
+use acter::{NewsEntry, NewsSlide};
+use crossterm::event::{KeyCode, KeyEvent};
+use std::env;
+use tracing::{error, info};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+
+use super::theme::theme;
+
+#[derive(Debug, Default)]
+pub struct NewsState {
+    entries: Vec<NewsEntry>,
+    entry_idx: usize,
+    slide_idx: u8,
+    // the outcome of the last download
+    status: Option<String>,
+}
+
+fn media_lines(name: String, mimetype: Option<String>, size: Option<u32>) -> Vec<Spans<'static>> {
+    let label = Style::default().fg(theme().gray);
+    let mut lines = vec![Spans::from(vec![
+        Span::styled("name: ", label),
+        Span::raw(name),
+    ])];
+    if let Some(mimetype) = mimetype {
+        lines.push(Spans::from(vec![
+            Span::styled("type: ", label),
+            Span::raw(mimetype),
+        ]));
+    }
+    if let Some(size) = size {
+        lines.push(Spans::from(vec![
+            Span::styled("size: ", label),
+            Span::raw(format!("{size} bytes")),
+        ]));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled("press d to download", label)));
+    lines
+}
+
+fn slide_lines(slide: &NewsSlide) -> Vec<Spans<'static>> {
+    if let Some(image) = slide.image_desc() {
+        media_lines(image.name(), image.mimetype(), image.size())
+    } else if let Some(video) = slide.video_desc() {
+        media_lines(video.name(), video.mimetype(), video.size())
+    } else if let Some(audio) = slide.audio_desc() {
+        media_lines(audio.name(), audio.mimetype(), audio.size())
+    } else if let Some(file) = slide.file_desc() {
+        media_lines(file.name(), file.mimetype(), file.size())
+    } else if let Some(location) = slide.location_desc() {
+        vec![
+            Spans::from(location.body()),
+            Spans::from(Span::styled(
+                location.geo_uri(),
+                Style::default().fg(theme().gray),
+            )),
+        ]
+    } else {
+        slide
+            .text()
+            .lines()
+            .map(|l| Spans::from(l.to_owned()))
+            .collect()
+    }
+}
+
+impl NewsState {
+    pub fn name(&self) -> String {
+        if self.entries.is_empty() {
+            "News".to_owned()
+        } else {
+            format!("News ({:})", self.entries.len())
+        }
+    }
+
+    pub fn set_entries(&mut self, entries: Vec<NewsEntry>) {
+        let current = self.current().map(|e| e.event_id());
+        self.entries = entries;
+        match current.and_then(|id| self.entries.iter().position(|e| e.event_id() == id)) {
+            Some(idx) => self.entry_idx = idx,
+            None => {
+                self.entry_idx = 0;
+                self.slide_idx = 0;
+            }
+        }
+    }
+
+    fn current(&self) -> Option<&NewsEntry> {
+        self.entries.get(self.entry_idx)
+    }
+
+    fn current_slide(&self) -> Option<NewsSlide> {
+        self.current().and_then(|e| e.get_slide(self.slide_idx))
+    }
+
+    fn next_slide(&mut self) {
+        let Some(entry) = self.current() else {
+            return;
+        };
+        if self.slide_idx + 1 < entry.slides_count() {
+            self.slide_idx += 1;
+        } else if self.entry_idx + 1 < self.entries.len() {
+            // continue with the next entry
+            self.entry_idx += 1;
+            self.slide_idx = 0;
+        }
+        self.status = None;
+    }
+
+    fn prev_slide(&mut self) {
+        if self.slide_idx > 0 {
+            self.slide_idx -= 1;
+        } else if self.entry_idx > 0 {
+            self.entry_idx -= 1;
+            self.slide_idx = self
+                .current()
+                .map(|e| e.slides_count().saturating_sub(1))
+                .unwrap_or_default();
+        }
+        self.status = None;
+    }
+
+    fn next_entry(&mut self) {
+        if self.entry_idx + 1 < self.entries.len() {
+            self.entry_idx += 1;
+            self.slide_idx = 0;
+            self.status = None;
+        }
+    }
+
+    fn prev_entry(&mut self) {
+        if self.entry_idx > 0 {
+            self.entry_idx -= 1;
+            self.slide_idx = 0;
+            self.status = None;
+        }
+    }
+
+    async fn download(&mut self) {
+        let Some(slide) = self.current_slide() else {
+            return;
+        };
+        let dir = match env::current_dir() {
+            Ok(dir) => dir,
+            Err(error) => {
+                error!(?error, "no directory to download to");
+                return;
+            }
+        };
+        self.status = Some(
+            match slide
+                .download_media(dir.to_string_lossy().to_string())
+                .await
+            {
+                Ok(path) => {
+                    info!(path, "news media downloaded");
+                    format!("saved to {path}")
+                }
+                Err(error) => {
+                    error!(?error, "downloading news media failed");
+                    format!("download failed: {error}")
+                }
+            },
+        );
+    }
+
+    pub async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Right | KeyCode::Char(' ') => self.next_slide(),
+            KeyCode::Left => self.prev_slide(),
+            KeyCode::Down => self.next_entry(),
+            KeyCode::Up => self.prev_entry(),
+            KeyCode::Char('d') => self.download().await,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(block_border_style);
+        let Some(entry) = self.current() else {
+            let empty = Paragraph::new("No news yet")
+                .style(Style::default().fg(theme().gray))
+                .block(block.title(" News "));
+            f.render_widget(empty, area);
+            return;
+        };
+
+        let title = format!(
+            " News {}/{} · slide {}/{} ",
+            self.entry_idx + 1,
+            self.entries.len(),
+            self.slide_idx + 1,
+            entry.slides_count()
+        );
+        let mut lines = vec![
+            Spans::from(vec![
+                Span::styled(
+                    entry.sender().to_string(),
+                    Style::default()
+                        .fg(theme().secondary)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    format!(" in {}", entry.room_id()),
+                    Style::default().fg(theme().gray),
+                ),
+            ]),
+            Spans::from(""),
+        ];
+        if let Some(slide) = self.current_slide() {
+            lines.extend(slide_lines(&slide));
+        }
+        if let Some(status) = &self.status {
+            lines.push(Spans::from(""));
+            lines.push(Spans::from(Span::styled(
+                status.clone(),
+                Style::default().fg(theme().primary),
+            )));
+        }
+
+        let news = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block.title(title));
+        f.render_widget(news, area);
+    }
+}
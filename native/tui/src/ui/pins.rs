@@ -0,0 +1,199 @@
+use acter::ActerPin;
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use std::process::Command;
+use tracing::{error, info};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use super::theme::theme;
+
+/// pins without a section are listed last, under this title
+const NO_SECTION: &str = "Other";
+
+#[derive(Debug)]
+enum Row {
+    Section(String),
+    Pin(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct PinsState {
+    pins: Vec<ActerPin>,
+    rows: Vec<Row>,
+    list_state: ListState,
+}
+
+/// hand the url over to whatever the system opens links with
+fn open_url(url: &str) -> Result<()> {
+    let mut cmd = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else {
+        Command::new("xdg-open")
+    };
+    cmd.arg(url).spawn()?;
+    Ok(())
+}
+
+impl PinsState {
+    pub fn name(&self) -> String {
+        if self.pins.is_empty() {
+            "Pins".to_owned()
+        } else {
+            format!("Pins ({:})", self.pins.len())
+        }
+    }
+
+    pub fn set_pins(&mut self, mut pins: Vec<ActerPin>) {
+        let selected = self.selected().map(|p| p.event_id_str());
+        pins.sort_by_key(|p| (p.section().is_none(), p.section(), p.title()));
+
+        let mut rows = vec![];
+        let mut section = None;
+        for (idx, pin) in pins.iter().enumerate() {
+            let pin_section = pin.section().unwrap_or_else(|| NO_SECTION.to_owned());
+            if section.as_ref() != Some(&pin_section) {
+                rows.push(Row::Section(pin_section.clone()));
+                section = Some(pin_section);
+            }
+            rows.push(Row::Pin(idx));
+        }
+
+        let row = selected.and_then(|event_id| {
+            rows.iter().position(|r| match r {
+                Row::Pin(idx) => pins[*idx].event_id_str() == event_id,
+                Row::Section(_) => false,
+            })
+        });
+        self.pins = pins;
+        self.rows = rows;
+        self.list_state.select(row);
+    }
+
+    fn selected(&self) -> Option<&ActerPin> {
+        match self.list_state.selected().and_then(|r| self.rows.get(r)) {
+            Some(Row::Pin(idx)) => self.pins.get(*idx),
+            _ => None,
+        }
+    }
+
+    // moves the selection, jumping over the section titles
+    fn select_next(&mut self) {
+        let start = self
+            .list_state
+            .selected()
+            .map(|r| r + 1)
+            .unwrap_or_default();
+        if let Some(offset) = self.rows[start.min(self.rows.len())..]
+            .iter()
+            .position(|r| matches!(r, Row::Pin(_)))
+        {
+            self.list_state.select(Some(start + offset));
+        }
+    }
+
+    fn select_prev(&mut self) {
+        let Some(current) = self.list_state.selected() else {
+            return;
+        };
+        if let Some(row) = self.rows[..current]
+            .iter()
+            .rposition(|r| matches!(r, Row::Pin(_)))
+        {
+            self.list_state.select(Some(row));
+        }
+    }
+
+    fn open_selected(&self) {
+        let Some(pin) = self.selected() else {
+            return;
+        };
+        let Some(url) = pin.url() else {
+            info!(pin = pin.title(), "pin has no url to open");
+            return;
+        };
+        if let Err(error) = open_url(&url) {
+            error!(?error, url, "opening url failed");
+        }
+    }
+
+    pub async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Down => self.select_next(),
+            KeyCode::Up => self.select_prev(),
+            KeyCode::Enter | KeyCode::Char('o') => self.open_selected(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+            .split(area);
+
+        let items = self
+            .rows
+            .iter()
+            .map(|row| match row {
+                Row::Section(title) => ListItem::new(Spans::from(Span::styled(
+                    title.clone(),
+                    Style::default()
+                        .fg(theme().secondary)
+                        .add_modifier(Modifier::BOLD),
+                ))),
+                Row::Pin(idx) => {
+                    let pin = &self.pins[*idx];
+                    let marker = if pin.url().is_some() { "🔗" } else { "📌" };
+                    ListItem::new(Text::from(format!("  {marker} {}", pin.title())))
+                }
+            })
+            .collect::<Vec<_>>();
+        let ls = List::new(items)
+            .highlight_style(
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(theme().primary),
+            )
+            .block(
+                Block::default()
+                    .title(" Pins 📌")
+                    .borders(Borders::ALL)
+                    .border_style(block_border_style),
+            );
+        f.render_stateful_widget(ls, chunks[0], &mut self.list_state);
+
+        let mut lines = vec![];
+        if let Some(pin) = self.selected() {
+            lines.push(Spans::from(Span::styled(
+                pin.title(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            if let Some(url) = pin.url() {
+                lines.push(Spans::from(vec![
+                    Span::styled(url, Style::default().fg(theme().secondary)),
+                    Span::styled(" (enter to open)", Style::default().fg(theme().gray)),
+                ]));
+            }
+            lines.push(Spans::from(""));
+            if let Some(text) = pin.content_text() {
+                lines.extend(text.lines().map(|l| Spans::from(l.to_owned())));
+            }
+        }
+        let details = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(details, chunks[1]);
+    }
+}
@@ -0,0 +1,540 @@
+use acter::{matrix_sdk::ruma::OwnedUserId, Task, TaskList};
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use futures::stream::{select_all, StreamExt};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    task::JoinHandle,
+};
+use tracing::{error, trace};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use super::{next, prev, theme::theme};
+
+const FORM_FIELDS: [&str; 3] = ["Title", "Due", "Assignees"];
+
+/// Creating a new or editing an existing task
+#[derive(Debug, Default)]
+struct TaskForm {
+    editing: Option<Task>,
+    title: String,
+    due: String,
+    assignees: String,
+    focus: usize,
+}
+
+impl TaskForm {
+    fn edit(task: Task) -> Self {
+        TaskForm {
+            title: task.title().clone(),
+            due: task.utc_due.map(|d| d.to_rfc3339()).unwrap_or_default(),
+            assignees: task
+                .assignees
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            editing: Some(task),
+            focus: 0,
+        }
+    }
+
+    fn focused_mut(&mut self) -> &mut String {
+        match self.focus {
+            0 => &mut self.title,
+            1 => &mut self.due,
+            _ => &mut self.assignees,
+        }
+    }
+
+    /// accepts full RFC3339 or just the date
+    fn due_rfc3339(&self) -> Option<String> {
+        let due = self.due.trim();
+        if due.is_empty() {
+            None
+        } else if due.len() == 10 {
+            Some(format!("{due}T00:00:00+00:00"))
+        } else {
+            Some(due.to_owned())
+        }
+    }
+
+    fn assignees(&self) -> Result<Vec<OwnedUserId>> {
+        let mut assignees = vec![];
+        for user_id in self.assignees.split(',') {
+            let user_id = user_id.trim();
+            if !user_id.is_empty() {
+                assignees.push(OwnedUserId::try_from(user_id)?);
+            }
+        }
+        Ok(assignees)
+    }
+
+    async fn create(&self, task_list: &TaskList, sort_order: u32) -> Result<()> {
+        let mut draft = task_list.task_builder()?;
+        draft.title(self.title.clone()).sort_order(sort_order);
+        if let Some(due) = self.due_rfc3339() {
+            draft.utc_due_from_rfc3339(due)?;
+        }
+        let mut assignees = self.assignees()?;
+        if !assignees.is_empty() {
+            draft.assignees(&mut assignees);
+        }
+        let event_id = draft.send().await?;
+        trace!(?event_id, "task created");
+        Ok(())
+    }
+
+    async fn update(&self, task: &Task) -> Result<()> {
+        let mut update = task.update_builder()?;
+        update.title(self.title.clone());
+        match self.due_rfc3339() {
+            Some(due) => update.utc_due_from_rfc3339(due)?,
+            None => {
+                update.unset_utc_due();
+            }
+        }
+        let mut assignees = self.assignees()?;
+        if assignees.is_empty() {
+            update.unset_assignees();
+        } else {
+            update.assignees(&mut assignees);
+        }
+        let event_id = update.send().await?;
+        trace!(?event_id, "task updated");
+        Ok(())
+    }
+
+    async fn submit(&self, task_list: &TaskList, sort_order: u32) -> Result<()> {
+        if self.title.trim().is_empty() {
+            anyhow::bail!("A task needs a title");
+        }
+        match &self.editing {
+            Some(task) => self.update(task).await,
+            None => self.create(task_list, sort_order).await,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Tab | KeyCode::Down => self.focus = (self.focus + 1) % FORM_FIELDS.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                self.focus = (self.focus + FORM_FIELDS.len() - 1) % FORM_FIELDS.len()
+            }
+            KeyCode::Backspace => {
+                self.focused_mut().pop();
+            }
+            KeyCode::Char(c) => self.focused_mut().push(c),
+            _ => {}
+        }
+    }
+
+    fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
+        let values = [&self.title, &self.due, &self.assignees];
+        let lines = FORM_FIELDS
+            .iter()
+            .zip(values)
+            .enumerate()
+            .map(|(idx, (label, value))| {
+                let style = if idx == self.focus {
+                    Style::default()
+                        .fg(theme().primary)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(theme().gray)
+                };
+                let cursor = if idx == self.focus { "▏" } else { "" };
+                Spans::from(vec![
+                    Span::styled(format!("{label:>10}: "), style),
+                    Span::raw(format!("{value}{cursor}")),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let title = if self.editing.is_some() {
+            " Edit task (enter: save, esc: cancel) "
+        } else {
+            " New task (enter: create, esc: cancel) "
+        };
+        let form = Paragraph::new(lines).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(block_border_style),
+        );
+        f.render_widget(form, area);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TasksState {
+    task_lists_list_state: ListState,
+    tasks_list_state: ListState,
+    selected: Option<TaskList>,
+    task_lists: Vec<TaskList>,
+    updates: Option<UnboundedReceiver<()>>,
+    listener: Option<JoinHandle<()>>,
+    tasks: Vec<Task>,
+    form: Option<TaskForm>,
+}
+
+impl Drop for TasksState {
+    fn drop(&mut self) {
+        self.stop_listening();
+    }
+}
+
+impl TasksState {
+    pub fn fresh(task_lists: Vec<TaskList>) -> Self {
+        TasksState {
+            task_lists_list_state: Default::default(),
+            tasks_list_state: Default::default(),
+            selected: None,
+            task_lists,
+            updates: None,
+            listener: None,
+            tasks: Default::default(),
+            form: None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        if self.task_lists.is_empty() {
+            "Tasks".to_owned()
+        } else {
+            format!("Tasks ({:})", self.task_lists.len())
+        }
+    }
+
+    /// whether keys go into the task form
+    pub fn is_typing(&self) -> bool {
+        self.form.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.task_lists.is_empty()
+    }
+
+    pub async fn tick(&mut self) {
+        let mut update = false;
+        if let Some(updates) = self.updates.as_mut() {
+            while updates.try_recv().is_ok() {
+                // consume
+                update = true;
+            }
+        }
+
+        if update {
+            trace!("refreshing upon update");
+            if let Some(t) = &self.selected.clone() {
+                self.refresh(t).await;
+            }
+        }
+    }
+
+    fn stop_listening(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+        }
+        self.updates = None;
+    }
+
+    async fn refresh(&mut self, task_list: &TaskList) {
+        let task_list = match task_list.refresh().await {
+            Ok(t) => t,
+            Err(error) => {
+                error!(?error, "refreshing task list failed");
+                task_list.clone()
+            }
+        };
+        let mut tasks = match task_list.tasks().await {
+            Ok(tasks) => tasks,
+            Err(error) => {
+                error!(?error, "loading tasks failed");
+                return;
+            }
+        };
+        tasks.sort_by_key(|t| t.sort_order());
+        self.tasks = tasks;
+        self.listen(&task_list);
+        self.selected = Some(task_list);
+    }
+
+    /// forward changes of the list and any of its tasks to the next tick
+    fn listen(&mut self, task_list: &TaskList) {
+        self.stop_listening();
+        let mut streams = vec![task_list.subscribe_stream().boxed()];
+        streams.extend(self.tasks.iter().map(|t| t.subscribe_stream().boxed()));
+        let (sender, updates) = unbounded_channel();
+        let listener = tokio::spawn(async move {
+            let mut changes = select_all(streams);
+            while changes.next().await.is_some() {
+                if sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        self.updates = Some(updates);
+        self.listener = Some(listener);
+    }
+
+    fn selected_task(&self) -> Option<&Task> {
+        self.tasks_list_state
+            .selected()
+            .and_then(|idx| self.tasks.get(idx))
+    }
+
+    fn select_next(&mut self) {
+        if self.selected.is_some() {
+            next(&mut self.tasks_list_state, self.tasks.len());
+        } else {
+            next(&mut self.task_lists_list_state, self.task_lists.len());
+        }
+    }
+
+    async fn toggle_done(&self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+
+        let resp = match task.update_builder() {
+            Ok(mut update) => {
+                if task.is_done() {
+                    trace!(?task, "marking undone");
+                    update.mark_undone().send().await
+                } else {
+                    trace!(?task, "marking done");
+                    update.mark_done().send().await
+                }
+            }
+            Err(error) => Err(error),
+        };
+
+        match resp {
+            Err(error) => {
+                error!(?task, ?error, "updating task failed");
+            }
+            Ok(event_id) => {
+                trace!(?task, ?event_id, "updating accepted");
+            }
+        }
+    }
+
+    async fn select(&mut self) {
+        if self.selected.is_some() {
+            self.toggle_done().await;
+        } else if let Some(selected) = self
+            .task_lists_list_state
+            .selected()
+            .and_then(|idx| self.task_lists.get(idx).cloned())
+        {
+            trace!(?selected, "selecting");
+            self.tasks_list_state.select(None);
+            self.refresh(&selected).await;
+        }
+    }
+
+    fn select_prev(&mut self) {
+        if self.selected.is_some() {
+            if self.tasks.is_empty() {
+                return;
+            }
+            prev(&mut self.tasks_list_state);
+        } else {
+            if self.task_lists.is_empty() {
+                return;
+            }
+            prev(&mut self.task_lists_list_state);
+        }
+    }
+
+    /// move the selected task one up (`-1`) or down (`1`) and store the
+    /// resulting `sort_order` of every task that changed position
+    async fn move_selected(&mut self, by: isize) {
+        let Some(from) = self.tasks_list_state.selected() else {
+            return;
+        };
+        let to = from as isize + by;
+        if to < 0 || to as usize >= self.tasks.len() {
+            return;
+        }
+        let to = to as usize;
+        let mut ordered = self.tasks.iter().collect::<Vec<_>>();
+        ordered.swap(from, to);
+
+        for (pos, task) in ordered.into_iter().enumerate() {
+            if task.sort_order() == pos as u32 {
+                continue;
+            }
+            let resp = match task.update_builder() {
+                Ok(mut update) => update.sort_order(pos as u32).send().await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = resp {
+                error!(?task, ?error, "reordering task failed");
+                return;
+            }
+        }
+        self.tasks.swap(from, to);
+        self.tasks_list_state.select(Some(to));
+    }
+
+    async fn submit_form(&mut self) {
+        let (Some(form), Some(task_list)) = (&self.form, &self.selected) else {
+            return;
+        };
+        let sort_order = self
+            .tasks
+            .iter()
+            .map(|t| t.sort_order() + 1)
+            .max()
+            .unwrap_or_default();
+        match form.submit(task_list, sort_order).await {
+            Ok(()) => self.form = None,
+            Err(error) => {
+                // keep the form open to fix the input
+                error!(?error, "saving task failed");
+            }
+        }
+    }
+
+    pub async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if let Some(form) = self.form.as_mut() {
+            match key.code {
+                KeyCode::Esc => self.form = None,
+                KeyCode::Enter => self.submit_form().await,
+                _ => form.handle_key(key),
+            }
+            return true;
+        }
+
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Down if shift && self.selected.is_some() => {
+                self.move_selected(1).await;
+                true
+            }
+            KeyCode::Up if shift && self.selected.is_some() => {
+                self.move_selected(-1).await;
+                true
+            }
+            KeyCode::Down => {
+                self.select_next();
+                true
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                self.select().await;
+                true
+            }
+            KeyCode::Up => {
+                self.select_prev();
+                true
+            }
+            KeyCode::Right => {
+                if self.selected.is_none() {
+                    self.select().await;
+                }
+                true
+            }
+            KeyCode::Char('n') if self.selected.is_some() => {
+                self.form = Some(TaskForm::default());
+                true
+            }
+            KeyCode::Char('e') => {
+                if let Some(task) = self.selected_task().cloned() {
+                    self.form = Some(TaskForm::edit(task));
+                }
+                true
+            }
+            KeyCode::Esc | KeyCode::Left => {
+                if self.selected.is_some() {
+                    self.selected = None;
+                    self.stop_listening();
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, block_border_style: Style) {
+        if let Some(selected) = &self.selected {
+            let area = if let Some(form) = &self.form {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(5)].as_ref())
+                    .split(area);
+                form.render(f, chunks[1], block_border_style);
+                chunks[0]
+            } else {
+                area
+            };
+            let ls = List::new(
+                self.tasks
+                    .iter()
+                    .map(|t| {
+                        let mut line = if t.is_done() {
+                            format!(" [x] {:}", t.title())
+                        } else if let Some(p) = t.percent() {
+                            format!(" {p}% {:}", t.title())
+                        } else {
+                            format!(" [ ] {:}", t.title())
+                        };
+                        if let Some(due) = t.utc_due {
+                            line.push_str(&format!(" (due {})", due.format("%Y-%m-%d")));
+                        }
+                        if !t.assignees.is_empty() {
+                            line.push_str(&format!(" 👤{}", t.assignees.len()));
+                        }
+                        line
+                    })
+                    .map(|s| ListItem::new(Text::from(s)))
+                    .collect::<Vec<_>>(),
+            )
+            .highlight_style(
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(theme().primary),
+            )
+            .block(
+                Block::default()
+                    .title(format!(
+                        " [🗹] > {:} (n: new, e: edit, shift+↑↓: move) ",
+                        selected.name()
+                    ))
+                    .borders(Borders::ALL)
+                    .border_style(block_border_style),
+            );
+
+            f.render_stateful_widget(ls, area, &mut self.tasks_list_state);
+        } else {
+            let ls = List::new(
+                self.task_lists
+                    .iter()
+                    .map(|l| ListItem::new(Text::from(l.name().as_str())))
+                    .collect::<Vec<_>>(),
+            )
+            .highlight_style(
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .fg(theme().primary),
+            )
+            .block(
+                Block::default()
+                    .title(" Tasks 🗹")
+                    .borders(Borders::ALL)
+                    .border_style(block_border_style),
+            );
+
+            f.render_stateful_widget(ls, area, &mut self.task_lists_list_state);
+        }
+    }
+}
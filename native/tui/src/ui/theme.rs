@@ -0,0 +1,116 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::sync::OnceLock;
+use tui::style::Color;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// The palette all tools draw with
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub primary: Color,
+    pub secondary: Color,
+    pub tertiary: Color,
+    pub gray: Color,
+    pub dark: Color,
+    pub darker: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            primary: Color::Rgb(236, 39, 88),
+            secondary: Color::Rgb(35, 175, 194),
+            tertiary: Color::Rgb(92, 42, 128),
+            gray: Color::Rgb(151, 151, 151),
+            dark: Color::Rgb(51, 53, 64),
+            darker: Color::Rgb(47, 49, 62),
+        }
+    }
+}
+
+/// The `[theme]` section of the config, colors are `#rrggbb` or a terminal color name
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    primary: Option<String>,
+    secondary: Option<String>,
+    tertiary: Option<String>,
+    gray: Option<String>,
+    dark: Option<String>,
+    darker: Option<String>,
+}
+
+fn parse_color(color: &str) -> Result<Color> {
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 {
+            bail!("Colors need to be `#rrggbb`, not `{color}`");
+        }
+        let channel = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16);
+        return Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    Ok(match color.to_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        "reset" => Color::Reset,
+        _ => bail!("Unknown color `{color}`"),
+    })
+}
+
+impl ThemeConfig {
+    pub fn theme(&self) -> Result<Theme> {
+        let mut theme = Theme::default();
+        let fields = [
+            (&self.primary, &mut theme.primary),
+            (&self.secondary, &mut theme.secondary),
+            (&self.tertiary, &mut theme.tertiary),
+            (&self.gray, &mut theme.gray),
+            (&self.dark, &mut theme.dark),
+            (&self.darker, &mut theme.darker),
+        ];
+        for (config, color) in fields {
+            if let Some(config) = config {
+                *color = parse_color(config)?;
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// Set the palette, only the first call has any effect
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() -> Result<()> {
+        assert_eq!(parse_color("#ec2758")?, Color::Rgb(236, 39, 88));
+        assert_eq!(parse_color("LightBlue")?, Color::LightBlue);
+        assert!(parse_color("#ec27").is_err());
+        assert!(parse_color("#gg0000").is_err());
+        assert!(parse_color("chartreuse").is_err());
+        Ok(())
+    }
+}